@@ -0,0 +1,45 @@
+//! Annotating & saving a batch of 32 large frames, one by one vs. as a batch.
+//!
+//! cargo bench --bench annotate
+
+use geo::polygon;
+use std::time::Instant;
+use usls::{Annotator, Bbox, Polygon, Y};
+
+fn main() {
+    let n = 32;
+    let (w, h) = (1920, 1080);
+    let imgs = vec![image::DynamicImage::new_rgb8(w, h); n];
+    let y = Y::default()
+        .with_bboxes(
+            &(0..50)
+                .map(|i| {
+                    let x = (i * 37 % (w - 200)) as f32;
+                    let y = (i * 53 % (h - 200)) as f32;
+                    Bbox::from((x, y, 160., 120., i as isize % 80, 0.5))
+                })
+                .collect::<Vec<_>>(),
+        )
+        .with_polygons(&[Polygon::default().with_polygon(polygon![
+            (x: 100., y: 100.), (x: 900., y: 150.), (x: 700., y: 800.), (x: 150., y: 600.)
+        ])]);
+    let ys = vec![y; n];
+    let annotator = Annotator::default().with_saveout("bench-annotate");
+
+    let t = Instant::now();
+    for (img, y) in imgs.iter().zip(ys.iter()) {
+        annotator.annotate(std::slice::from_ref(img), std::slice::from_ref(y));
+    }
+    let t_serial = t.elapsed();
+
+    let t = Instant::now();
+    annotator.annotate(&imgs, &ys);
+    let t_batch = t.elapsed();
+
+    println!(
+        "\nannotate x{n} ({w}x{h}) | one by one: {:?} | batch: {:?} | speedup: {:.2}x",
+        t_serial,
+        t_batch,
+        t_serial.as_secs_f64() / t_batch.as_secs_f64()
+    );
+}
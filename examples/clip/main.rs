@@ -44,6 +44,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             None => continue,
         };
 
+        // zero-shot classification
+        for (y, path) in model.classify(&images, &texts)?.iter().zip(paths.iter()) {
+            if let Some(probs) = y.probs() {
+                println!("[zero-shot] {} => {:?}", path.display(), probs.top1());
+            }
+        }
+
         // summary
         for i in 0..paths.len() {
             let probs = &matrix[i];
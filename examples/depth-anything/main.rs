@@ -17,7 +17,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // annotate
     let annotator = Annotator::default()
-        .with_colormap("Turbo")
+        .with_colormap("Turbo")?
+        .with_heatmap("Turbo", 0.6)? // depth overlay
         .with_saveout("Depth-Anything");
     annotator.annotate(&x, &y);
 
@@ -0,0 +1,43 @@
+use usls::{models::OCR, Annotator, DataLoader, Options};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // text detection
+    let options_det = Options::default()
+        .with_i00((1, 1, 4).into())
+        .with_i02((608, 960, 1280).into())
+        .with_i03((608, 960, 1280).into())
+        .with_confs(&[0.4])
+        .with_min_width(5.0)
+        .with_min_height(12.0)
+        .with_model("ppocr-v4-db-dyn.onnx")?;
+
+    // text recognition
+    let options_rec = Options::default()
+        .with_i00((1, 4, 8).into())
+        .with_i03((320, 960, 1600).into())
+        .with_confs(&[0.2])
+        .with_vocab("ppocr_rec_vocab.txt")?
+        .with_model("ppocr-v4-svtr-ch-dyn.onnx")?;
+
+    // build pipeline
+    let mut model = OCR::new(options_det, options_rec)?;
+
+    // load image
+    let x = vec![DataLoader::try_read("./assets/db.png")?];
+
+    // run
+    let y = model.run(&x)?;
+    for text in y[0].texts().into_iter().flatten() {
+        println!("{text}");
+    }
+
+    // annotate
+    let annotator = Annotator::default()
+        .without_bboxes(true)
+        .without_mbrs(true)
+        .with_polygons_name(true)
+        .with_saveout("OCR");
+    annotator.annotate(&x, &y);
+
+    Ok(())
+}
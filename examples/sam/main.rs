@@ -0,0 +1,41 @@
+use usls::{
+    models::{SamPrompt, SAM},
+    Annotator, DataLoader, Options,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // encoder
+    let options_encoder = Options::default()
+        .with_model("mobile-sam-vit-t-encoder.onnx")?
+        .with_i00((1, 1, 1).into())
+        .with_i02((1024, 1024, 1024).into())
+        .with_i03((1024, 1024, 1024).into());
+
+    // decoder
+    let options_decoder = Options::default()
+        .with_model("mobile-sam-vit-t-decoder.onnx")?
+        .with_i11((1, 2, 8).into()) // point_coords: num_points
+        .with_i21((1, 2, 8).into()); // point_labels: num_points
+
+    // build model
+    let mut model = SAM::new(options_encoder, options_decoder)?;
+
+    // load image
+    let xs = vec![
+        DataLoader::try_read("./assets/bus.jpg")?,
+        DataLoader::try_read("./assets/bus.jpg")?,
+    ];
+
+    // a point on the bus, and a box around the man on the left
+    let prompts = [
+        SamPrompt::default().with_positive_point(400., 350.),
+        SamPrompt::default().with_xyxy(48., 398., 246., 905.),
+    ];
+    let ys = model.run(&xs, &prompts)?;
+
+    // annotate
+    let annotator = Annotator::default().with_saveout("SAM");
+    annotator.annotate(&xs, &ys);
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+use usls::{models::YOLO, EnginePool, Options};
+
+// POST an image, get detections back as JSON:
+// curl --data-binary @assets/bus.jpg http://127.0.0.1:8080/detect
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // two sessions, so at most two requests run inference at once, the rest queue up
+    let options = Options::default()
+        .with_model("yolov8m-dyn.onnx")?
+        .with_i00((1, 1, 4).into())
+        .with_i02((224, 640, 800).into())
+        .with_i03((224, 640, 800).into());
+    let pool = Arc::new(EnginePool::new(2, || YOLO::new(options.clone()))?);
+
+    let listener = TcpListener::bind("127.0.0.1:8080")?;
+    println!("Listening on http://127.0.0.1:8080/detect");
+    for stream in listener.incoming() {
+        let pool = Arc::clone(&pool);
+        std::thread::spawn(move || {
+            if let Err(err) = stream.map_err(|e| e.into()).and_then(|x| handle(x, &pool)) {
+                eprintln!("{err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle(stream: TcpStream, pool: &EnginePool<YOLO>) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(&stream);
+
+    // request line and headers
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((k, v)) = header.split_once(':') {
+            if k.eq_ignore_ascii_case("content-length") {
+                content_length = v.trim().parse()?;
+            }
+        }
+    }
+    if !line.starts_with("POST /detect") {
+        return respond(&stream, "404 Not Found", "{}");
+    }
+
+    // body
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    let image = match image::load_from_memory(&body) {
+        Ok(x) => x,
+        Err(err) => {
+            return respond(
+                &stream,
+                "400 Bad Request",
+                &format!("{{\"error\": {:?}}}", err.to_string()),
+            )
+        }
+    };
+
+    // inference on a free session, blocking this connection's thread only
+    let ys = pool.run(move |model| model.run(&[image])).wait()??;
+    respond(&stream, "200 OK", &ys[0].to_json().to_string())
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
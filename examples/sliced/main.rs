@@ -0,0 +1,27 @@
+use usls::{models::YOLO, Annotator, DataLoader, Options, SlicedRunner};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // build model
+    let options = Options::default()
+        .with_model("yolov8m-dyn.onnx")?
+        .with_i00((1, 1, 4).into())
+        .with_i02((224, 640, 800).into())
+        .with_i03((224, 640, 800).into())
+        .with_confs(&[0.3]);
+    let mut model = YOLO::new(options)?;
+
+    // 640 x 640 tiles sharing 20% with their neighbours, plus one full-frame pass
+    let runner = SlicedRunner::new(640)
+        .with_overlap(0.2)
+        .with_full_frame(true);
+
+    // run & annotate
+    let x = DataLoader::try_read("./assets/dota.png")?;
+    let y = runner.run(&x, |xs| model.run(xs))?;
+    println!("{y:?}");
+    Annotator::default()
+        .with_saveout("Sliced")
+        .annotate(&[x], &[y]);
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use usls::{coco, models::YOLO, Annotator, DataLoader, Options, VideoWriter};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // input: a video file, stream url or camera
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: cargo run -r --example video --features video -- <video file | stream url | camera>");
+            std::process::exit(1);
+        }
+    };
+
+    // build model
+    let options = Options::default()
+        .with_model("yolov8m-dyn.onnx")?
+        .with_i00((1, 1, 4).into())
+        .with_i02((224, 640, 800).into())
+        .with_i03((224, 640, 800).into())
+        .with_confs(&[0.4, 0.15])
+        .with_names(&coco::NAMES_80);
+    let mut model = YOLO::new(options)?;
+
+    // decode every 5th frame of a file, or every frame of a stream / camera
+    let mut dl = match usls::is_stream(&path) {
+        true => DataLoader::from_stream(&path)?,
+        false => DataLoader::from_video(&path, 5)?,
+    }
+    .with_batch(1);
+    println!(
+        "fps: {}, frames: {:?}, output fps: {}",
+        dl.fps(),
+        dl.frames(),
+        dl.output_fps()
+    );
+
+    // build annotate, results go to a video at the decoded frame rate
+    let annotator = Annotator::default();
+    let mut writer = VideoWriter::new("runs/Video/annotated.mp4", dl.output_fps())?;
+
+    // run & annotate
+    while let Some((xs, indices)) = dl.next() {
+        let ys = model.run(&xs)?;
+        for (i, y) in indices.iter().zip(ys.iter()) {
+            println!(
+                "frame {i} ({:.2?}): {:?}",
+                dl.timestamp(*i).unwrap_or_default(),
+                y.bboxes().map(|x| x.len())
+            );
+        }
+        annotator.annotate_to_video(&mut writer, &xs, &ys)?;
+    }
+    writer.finish()?;
+    dl.status()?; // the stream may have ended on a decode error
+
+    Ok(())
+}
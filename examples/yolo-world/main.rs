@@ -8,6 +8,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_i02((416, 640, 800).into())
         .with_i03((416, 640, 800).into())
         .with_confs(&[0.3])
+        .with_text_prompts(&["shoes"])
         .with_profile(false);
     let mut model = YOLO::new(options)?;
 
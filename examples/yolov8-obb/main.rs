@@ -0,0 +1,30 @@
+use usls::{models::YOLO, Annotator, DataLoader, Options};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // build model, DOTA-v1 classes
+    let options = Options::default()
+        .with_model("yolov8m-obb-dyn.onnx")?
+        .with_i00((1, 1, 4).into())
+        .with_i02((416, 1024, 1024).into())
+        .with_i03((416, 1024, 1024).into())
+        .with_confs(&[0.3]);
+    let mut model = YOLO::new(options)?;
+
+    // build dataloader
+    let dl = DataLoader::default()
+        .with_batch(1)
+        .load("./assets/dota.png")?;
+
+    // build annotate, rotated boxes are drawn as their four corners
+    let annotator = Annotator::default()
+        .without_bboxes(true)
+        .with_saveout("YOLOv8-OBB");
+
+    // run & annotate
+    for (xs, _paths) in dl {
+        let ys = model.run(&xs)?;
+        annotator.annotate(&xs, &ys);
+    }
+
+    Ok(())
+}
@@ -12,7 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // .with_model("yolov8m-oiv7-dyn.onnx")?
         // .with_trt(0)
         // .with_fp16(true)
-        // .with_coreml(0)
+        // .with_coreml(0) // or .with_device(Device::CoreML(0)) on Apple Silicon, CPU if unavailable
         // .with_cuda(3)
         .with_i00((1, 1, 4).into())
         .with_i02((224, 640, 800).into())
@@ -1,19 +1,28 @@
 use crate::{
-    auto_load, colormap256, string_now, Bbox, Keypoint, Mask, Mbr, Polygon, Prob, CHECK_MARK,
-    CROSS_MARK, Y,
+    auto_load, colormap256, ops, ops::RedactMode, string_now, Bbox, Keypoint, Mask, Mbr, Polygon,
+    Prob, CHECK_MARK, CROSS_MARK, Y,
 };
 use ab_glyph::{FontVec, PxScale};
 use anyhow::Result;
-use image::{DynamicImage, GenericImage, Rgba, RgbaImage};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{DynamicImage, GenericImage, Pixel, Rgba, RgbaImage};
 use imageproc::map::map_colors;
+use ndarray::{Array, Ix2};
+use rayon::prelude::*;
+use std::collections::HashMap;
 
 /// Annotator for struct `Y`
 #[derive(Debug)]
 pub struct Annotator {
-    font: FontVec,
-    _scale: f32, // Cope with ab_glyph & imageproc=0.24.0
+    font: Option<FontVec>, // texts are skipped without one
+    _scale: f32,           // Cope with ab_glyph & imageproc=0.24.0
     scale_dy: f32,
+    auto_scale: bool,
+    line_width: Option<usize>,
+    text_scale: Option<f32>,
+    font_size: Option<f32>,
     saveout: Option<String>,
+    output_resize: Option<u32>,
     decimal_places: usize,
 
     // About mbrs
@@ -22,6 +31,7 @@ pub struct Annotator {
     without_mbrs_name: bool,
     without_mbrs_text_bg: bool,
     mbrs_text_color: Rgba<u8>,
+    with_mbrs_text_rotated: bool,
 
     // About bboxes
     without_bboxes: bool,
@@ -37,8 +47,11 @@ pub struct Annotator {
     without_keypoints_text_bg: bool,
     keypoints_text_color: Rgba<u8>,
     skeletons: Option<Vec<(usize, usize)>>,
+    skeletons_colors: Option<Vec<(u8, u8, u8, u8)>>, // one per limb
     keypoints_radius: usize,
     keypoints_palette: Option<Vec<(u8, u8, u8, u8)>>,
+    keypoints_depth_shading: bool, // darken keypoints with larger z
+    class_colors: Option<HashMap<String, (u8, u8, u8, u8)>>, // by class name
 
     // About polygons
     without_polygons: bool,
@@ -54,18 +67,38 @@ pub struct Annotator {
     without_masks: bool,
     colormap: Option<[[u8; 3]; 256]>,
 
+    // About heatmaps
+    without_heatmap: bool,
+    heatmap_colormap: [[u8; 3]; 256],
+    heatmap_alpha: f32,
+    heatmap_threshold: f32,
+    without_depth: bool,
+
     // About probs
     probs_topk: usize,
+
+    // About redaction
+    redact: Option<RedactMode>,
+    redact_classes: Option<Vec<usize>>,
 }
 
 impl Default for Annotator {
     fn default() -> Self {
         Self {
-            font: Self::load_font(None).unwrap(),
+            font: Self::load_font(None)
+                .map_err(|err| {
+                    println!("{CROSS_MARK} No font, texts won't be drawn: {err}");
+                })
+                .ok(),
             _scale: 6.666667,
             scale_dy: 28.,
+            auto_scale: false,
+            line_width: None,
+            text_scale: None,
+            font_size: None,
             polygons_alpha: 179,
             saveout: None,
+            output_resize: None,
             decimal_places: 4,
             without_bboxes: false,
             without_bboxes_conf: false,
@@ -77,12 +110,16 @@ impl Default for Annotator {
             without_mbrs_name: false,
             without_mbrs_text_bg: false,
             mbrs_text_color: Rgba([0, 0, 0, 255]),
+            with_mbrs_text_rotated: false,
             without_keypoints: false,
             with_keypoints_conf: false,
             with_keypoints_name: false,
             keypoints_radius: 3,
             skeletons: None,
+            skeletons_colors: None,
             keypoints_palette: None,
+            keypoints_depth_shading: false,
+            class_colors: None,
             without_keypoints_text_bg: false,
             keypoints_text_color: Rgba([0, 0, 0, 255]),
             without_polygons: false,
@@ -95,6 +132,13 @@ impl Default for Annotator {
             probs_topk: 5usize,
             without_masks: false,
             colormap: None,
+            without_heatmap: false,
+            heatmap_colormap: colormap256::JET,
+            heatmap_alpha: 0.5,
+            heatmap_threshold: 0.05,
+            without_depth: false,
+            redact: None,
+            redact_classes: None,
         }
     }
 }
@@ -146,6 +190,40 @@ impl Annotator {
         self
     }
 
+    /// Limbs as pairs of keypoint indices of an `nk`-keypoint model, e.g. 21 for hands
+    pub fn with_skeleton(mut self, x: &[(usize, usize)], nk: usize) -> Result<Self> {
+        if let Some(&(i, ii)) = x.iter().find(|&&(i, ii)| i >= nk || ii >= nk) {
+            anyhow::bail!("Skeleton limb ({i}, {ii}) is out of range for {nk} keypoints");
+        }
+        self.skeletons = Some(x.to_vec());
+        Ok(self)
+    }
+
+    /// One color per limb of the skeleton, in the same order
+    pub fn with_skeleton_colors(mut self, x: &[(u8, u8, u8, u8)]) -> Result<Self> {
+        let n = self.skeletons.as_ref().map_or(0, |x| x.len());
+        if x.len() != n {
+            anyhow::bail!("Got {} skeleton colors for {n} limbs", x.len());
+        }
+        self.skeletons_colors = Some(x.to_vec());
+        Ok(self)
+    }
+
+    fn skeleton_color(&self, i: usize) -> (u8, u8, u8, u8) {
+        self.skeletons_colors
+            .as_ref()
+            .and_then(|x| x.get(i).copied())
+            .unwrap_or((255, 51, 255, 255))
+    }
+
+    /// Colors by class name, matched against the names of the results so they carry
+    /// over to models with another class order. Other classes keep the palette colors.
+    #[allow(clippy::type_complexity)]
+    pub fn with_class_colors_named(mut self, x: &[(&str, (u8, u8, u8, u8))]) -> Self {
+        self.class_colors = Some(x.iter().map(|(k, v)| (k.to_string(), *v)).collect());
+        self
+    }
+
     pub fn with_keypoints_palette(mut self, x: &[(u8, u8, u8, u8)]) -> Self {
         self.keypoints_palette = Some(x.to_vec());
         self
@@ -156,6 +234,12 @@ impl Annotator {
         self
     }
 
+    /// Shade keypoints that have a depth, the farthest of an instance at 40% brightness
+    pub fn with_keypoints_depth_shading(mut self, x: bool) -> Self {
+        self.keypoints_depth_shading = x;
+        self
+    }
+
     pub fn with_keypoints_conf(mut self, x: bool) -> Self {
         self.with_keypoints_conf = x;
         self
@@ -212,6 +296,12 @@ impl Annotator {
         self
     }
 
+    /// Align MBR labels with the box orientation, flipped to stay upright
+    pub fn with_mbrs_text_rotated(mut self, x: bool) -> Self {
+        self.with_mbrs_text_rotated = x;
+        self
+    }
+
     pub fn without_polygons(mut self, x: bool) -> Self {
         self.without_polygons = x;
         self
@@ -237,8 +327,40 @@ impl Annotator {
         self
     }
 
-    pub fn with_colormap(mut self, x: &str) -> Self {
-        let x = match x {
+    /// Colormap of masks, unknown colormap names are an error
+    pub fn with_colormap(mut self, x: &str) -> Result<Self> {
+        self.colormap = Some(Self::colormap_of(x)?);
+        Ok(self)
+    }
+
+    /// Blend heatmaps over the image with `colormap` (see `with_colormap`, plus `jet`) and
+    /// `alpha` in [0, 1], unknown colormap names are an error
+    pub fn with_heatmap(mut self, colormap: &str, alpha: f32) -> Result<Self> {
+        self.heatmap_colormap = Self::colormap_of(colormap)?;
+        self.heatmap_alpha = alpha.clamp(0., 1.);
+        Ok(self)
+    }
+
+    /// Heatmap values below `x` are not drawn, 0.05 by default
+    pub fn with_heatmap_threshold(mut self, x: f32) -> Self {
+        self.heatmap_threshold = x;
+        self
+    }
+
+    pub fn without_heatmap(mut self, x: bool) -> Self {
+        self.without_heatmap = x;
+        self
+    }
+
+    /// Skip depth maps, drawn like heatmaps after min-max normalization otherwise
+    pub fn without_depth(mut self, x: bool) -> Self {
+        self.without_depth = x;
+        self
+    }
+
+    fn colormap_of(x: &str) -> Result<[[u8; 3]; 256]> {
+        Ok(match x {
+            "jet" | "Jet" | "JET" => colormap256::JET,
             "turbo" | "Turbo" | "TURBO" => colormap256::TURBO,
             "inferno" | "Inferno" | "INFERNO" => colormap256::INFERNO,
             "plasma" | "Plasma" | "PLASMA" => colormap256::PLASMA,
@@ -251,10 +373,8 @@ impl Annotator {
             }
             "kindlmann" | "KindLmann" | "KINDLMANN" => colormap256::KINDLMANN,
             "smoothcoolwarm" | "SmoothCoolWarm" | "SMOOTHCOOLWARM" => colormap256::SMOOTHCOOLWARM,
-            _ => todo!(),
-        };
-        self.colormap = Some(x);
-        self
+            _ => anyhow::bail!("Unknown colormap: {x}"),
+        })
     }
 
     pub fn with_polygons_text_color(mut self, rgba: [u8; 4]) -> Self {
@@ -282,23 +402,107 @@ impl Annotator {
         self
     }
 
+    /// Blur or pixelate bboxes instead of drawing them
+    pub fn with_redact(mut self, x: RedactMode) -> Self {
+        self.redact = Some(x);
+        self
+    }
+
+    /// Only redact bboxes of these class ids, all classes by default
+    pub fn with_redact_classes(mut self, x: &[usize]) -> Self {
+        self.redact_classes = Some(x.to_vec());
+        self
+    }
+
+    /// Scale line width and text size with the image diagonal
+    pub fn with_auto_scale(mut self, x: bool) -> Self {
+        self.auto_scale = x;
+        self
+    }
+
+    /// Line width in pixels, overrides auto scaling
+    pub fn with_line_width(mut self, x: usize) -> Self {
+        self.line_width = Some(x.max(1));
+        self
+    }
+
+    /// Text size relative to the default, overrides auto scaling
+    pub fn with_text_scale(mut self, x: f32) -> Self {
+        self.text_scale = Some(x);
+        self
+    }
+
+    /// Text height in pixels, overrides `with_text_scale` and auto scaling.
+    /// Without it, bbox labels shrink to fit small boxes.
+    pub fn with_font_size(mut self, x: f32) -> Self {
+        self.font_size = Some(x.max(1.));
+        self
+    }
+
+    /// Same as `with_line_width`
+    pub fn with_line_thickness(self, x: u32) -> Self {
+        self.with_line_width(x as usize)
+    }
+
+    /// Draw a filled background behind every label, or none at all
+    pub fn with_text_background(mut self, x: bool) -> Self {
+        self.without_bboxes_text_bg = !x;
+        self.without_mbrs_text_bg = !x;
+        self.without_keypoints_text_bg = !x;
+        self.with_polygons_text_bg = x;
+        self
+    }
+
+    pub fn font_size(&self) -> Option<f32> {
+        self.font_size
+    }
+
+    pub fn line_width(&self) -> Option<usize> {
+        self.line_width
+    }
+
     pub fn with_saveout(mut self, saveout: &str) -> Self {
         self.saveout = Some(saveout.to_string());
         self
     }
 
+    /// Downscale saved images so the longer side is at most `max_side`, after drawing
+    pub fn with_output_resize(mut self, max_side: u32) -> Self {
+        self.output_resize = Some(max_side.max(1));
+        self
+    }
+
+    /// Font file to draw texts with, keeps the current font if it can't be loaded
     pub fn with_font(mut self, path: &str) -> Self {
-        self.font = Self::load_font(Some(path)).unwrap();
+        match Self::load_font(Some(path)) {
+            Ok(font) => self.font = Some(font),
+            Err(err) => println!(
+                "{} Failed to load font {:?}: {err}, using the default font",
+                crate::CROSS_MARK,
+                path
+            ),
+        }
         self
     }
 
     /// Save annotated images to `runs` folder
     pub fn save(&self, image: &RgbaImage, saveout: &str) {
+        match self.output_resize {
+            Some(_) => self.save_as(
+                &self.resize_output(image.clone()),
+                saveout,
+                &string_now("-"),
+            ),
+            None => self.save_as(image, saveout, &string_now("-")),
+        }
+    }
+
+    fn save_as(&self, image: &RgbaImage, saveout: &str, stem: &str) {
         let mut saveout = std::path::PathBuf::from("runs").join(saveout);
         if !saveout.exists() {
             std::fs::create_dir_all(&saveout).unwrap();
         }
-        saveout.push(string_now("-"));
+        saveout.push(stem);
         let saveout = format!("{}.png", saveout.to_str().unwrap());
         match image.save(&saveout) {
             Err(err) => println!("{} Saving failed: {:?}", CROSS_MARK, err),
@@ -306,68 +510,332 @@ impl Annotator {
         }
     }
 
-    /// Annotate images
+    /// Downscale to `output_resize` if set
+    fn resize_output(&self, image: RgbaImage) -> RgbaImage {
+        match self.output_resize {
+            Some(max_side) if image.width().max(image.height()) > max_side => {
+                let ratio = max_side as f32 / image.width().max(image.height()) as f32;
+                image::imageops::resize(
+                    &image,
+                    ((image.width() as f32 * ratio).round() as u32).max(1),
+                    ((image.height() as f32 * ratio).round() as u32).max(1),
+                    image::imageops::FilterType::Lanczos3,
+                )
+            }
+            _ => image,
+        }
+    }
+
+    /// Annotated images in memory, exactly what `annotate` saves, drawn in parallel
+    pub fn annotate_to_image(&self, imgs: &[DynamicImage], ys: &[Y]) -> Vec<DynamicImage> {
+        imgs.par_iter()
+            .zip(ys.par_iter())
+            .map(|(img, y)| DynamicImage::ImageRgba8(self.resize_output(self.plot(img, y))))
+            .collect()
+    }
+
+    /// Annotate images, drawing and encoding run in parallel across the batch.
+    /// Files of one call share a timestamp and are suffixed with the batch index.
     pub fn annotate(&self, imgs: &[DynamicImage], ys: &[Y]) {
-        for (img, y) in imgs.iter().zip(ys.iter()) {
-            let mut img_rgba = img.to_rgba8();
+        let stem = string_now("-");
+        let images = self.annotate_to_image(imgs, ys);
+        let Some(saveout) = &self.saveout else {
+            return;
+        };
+        let n = images.len();
+        images.par_iter().enumerate().for_each(|(i, image)| {
+            let Some(image) = image.as_rgba8() else {
+                return;
+            };
+            match n {
+                1 => self.save_as(image, saveout, &stem),
+                _ => self.save_as(image, saveout, &format!("{stem}-{i}")),
+            }
+        });
+    }
 
-            // polygons
-            if !self.without_polygons {
-                if let Some(xs) = &y.polygons() {
-                    self.plot_polygons(&mut img_rgba, xs)
+    /// Draw one result onto a copy of `img`, without saving
+    pub fn plot(&self, img: &DynamicImage, y: &Y) -> RgbaImage {
+        let mut img_rgba = img.to_rgba8();
+
+        // redaction, before any drawing
+        let mut bboxes = y.bboxes().cloned();
+        if let (Some(mode), Some(xs)) = (self.redact, &mut bboxes) {
+            xs.retain(|bbox| {
+                if !self.is_redacted(bbox.id()) {
+                    return true;
                 }
+                ops::redact(
+                    &mut img_rgba,
+                    bbox.xmin(),
+                    bbox.ymin(),
+                    bbox.xmax(),
+                    bbox.ymax(),
+                    mode,
+                );
+                false
+            });
+        }
+
+        // heatmap and depth, under everything else
+        if !self.without_depth {
+            if let Some(x) = y.depth() {
+                self.plot_depth(&mut img_rgba, x)
             }
+        }
+        if !self.without_heatmap {
+            if let Some(x) = y.heatmap() {
+                self.plot_heatmap(&mut img_rgba, x)
+            }
+        }
 
-            // bboxes
-            if !self.without_bboxes {
-                if let Some(xs) = &y.bboxes() {
-                    self.plot_bboxes(&mut img_rgba, xs)
-                }
+        // polygons
+        if !self.without_polygons {
+            if let Some(xs) = &y.polygons() {
+                self.plot_polygons(&mut img_rgba, xs)
             }
+        }
 
-            // mbrs
-            if !self.without_mbrs {
-                if let Some(xs) = &y.mbrs() {
-                    self.plot_mbrs(&mut img_rgba, xs)
-                }
+        // bboxes
+        if !self.without_bboxes {
+            if let Some(xs) = &bboxes {
+                self.plot_bboxes(&mut img_rgba, xs)
+            }
+        }
+
+        // mbrs
+        if !self.without_mbrs {
+            if let Some(xs) = &y.mbrs() {
+                self.plot_mbrs(&mut img_rgba, xs)
             }
+        }
 
-            // keypoints
-            if !self.without_keypoints {
-                if let Some(xs) = &y.keypoints() {
-                    self.plot_keypoints(&mut img_rgba, xs)
+        // keypoints
+        if !self.without_keypoints {
+            if let Some(xs) = &y.keypoints() {
+                self.plot_keypoints(&mut img_rgba, xs)
+            }
+        }
+
+        // probs
+        if let Some(xs) = &y.probs() {
+            self.plot_probs(&mut img_rgba, xs)
+        }
+
+        // masks
+        if !self.without_masks {
+            if let Some(xs) = &y.masks() {
+                self.plot_masks(&mut img_rgba, xs)
+            }
+        }
+
+        img_rgba
+    }
+
+    /// Vector version of `annotate` for one result: `<polygon>`s, `<rect>`s, mbrs, `<circle>`
+    /// keypoints with skeletons and `<text>` labels, in palette colors.
+    pub fn to_svg(&self, y: &Y, image_w: u32, image_h: u32) -> String {
+        self.svg(y, image_w, image_h, None)
+    }
+
+    /// `to_svg` with the source image embedded underneath as a base64 PNG `<image>`
+    pub fn to_svg_with_image(&self, y: &Y, image: &DynamicImage) -> Result<String> {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut buf, image::ImageFormat::Png)?;
+        let href = format!("data:image/png;base64,{}", BASE64.encode(buf.into_inner()));
+        Ok(self.svg(y, image.width(), image.height(), Some(&href)))
+    }
+
+    fn svg(&self, y: &Y, w: u32, h: u32, href: Option<&str>) -> String {
+        let escape = |x: &str| {
+            x.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        };
+        let rgb = |c: (u8, u8, u8, u8)| format!("rgb({},{},{})", c.0, c.1, c.2);
+        let points = |xs: &mut dyn Iterator<Item = (f64, f64)>| {
+            xs.map(|(x, y)| format!("{:.1},{:.1}", x, y))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let lw = self.line_width_for(w, h);
+        let fs = self.scale_dy_for(w, h);
+        let label = |svg: &mut String, text: &str, x: f32, y: f32, color: (u8, u8, u8, u8)| {
+            svg.push_str(&format!(
+                "  <text x=\"{:.1}\" y=\"{:.1}\" font-family=\"Arial, sans-serif\" font-size=\"{:.1}\" fill=\"{}\">{}</text>\n",
+                x,
+                y,
+                fs,
+                rgb(color),
+                escape(text)
+            ));
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n"
+        );
+        if let Some(href) = href {
+            svg.push_str(&format!(
+                "  <image href=\"{href}\" x=\"0\" y=\"0\" width=\"{w}\" height=\"{h}\"/>\n"
+            ));
+        }
+
+        // polygons
+        if !self.without_polygons {
+            for polygon in y.polygons().into_iter().flatten() {
+                let color = self.color_of(polygon.id(), polygon.name());
+                let stroke = match self.without_contours {
+                    true => "none".to_string(),
+                    false => format!(
+                        "rgb({},{},{})",
+                        self.contours_color[0], self.contours_color[1], self.contours_color[2]
+                    ),
+                };
+                svg.push_str(&format!(
+                    "  <polygon points=\"{}\" fill=\"{}\" fill-opacity=\"{:.3}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                    points(&mut polygon.polygon().exterior().coords().map(|c| (c.x, c.y))),
+                    rgb(color),
+                    self.polygons_alpha as f32 / 255.,
+                    stroke,
+                    lw
+                ));
+                if self.with_polygons_name || self.with_polygons_conf {
+                    if let Some((x, y)) = polygon.centroid() {
+                        let text = polygon.label(
+                            self.with_polygons_name,
+                            self.with_polygons_conf,
+                            self.decimal_places,
+                        );
+                        label(&mut svg, &text, x, y, color);
+                    }
                 }
             }
+        }
 
-            // probs
-            if let Some(xs) = &y.probs() {
-                self.plot_probs(&mut img_rgba, xs)
+        // bboxes
+        if !self.without_bboxes {
+            for bbox in y.bboxes().into_iter().flatten() {
+                let color = self.color_of(bbox.id(), bbox.name());
+                svg.push_str(&format!(
+                    "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                    bbox.xmin(),
+                    bbox.ymin(),
+                    bbox.width(),
+                    bbox.height(),
+                    rgb(color),
+                    lw
+                ));
+                if !self.without_bboxes_name || !self.without_bboxes_conf {
+                    let text = bbox.label(
+                        !self.without_bboxes_name,
+                        !self.without_bboxes_conf,
+                        self.decimal_places,
+                    );
+                    label(&mut svg, &text, bbox.xmin(), bbox.ymin() - lw as f32, color);
+                }
             }
+        }
 
-            // masks
-            if !self.without_masks {
-                if let Some(xs) = &y.masks() {
-                    self.plot_masks(&mut img_rgba, xs)
+        // mbrs
+        if !self.without_mbrs {
+            for mbr in y.mbrs().into_iter().flatten() {
+                let color = self.color_of(mbr.id(), mbr.name());
+                svg.push_str(&format!(
+                    "  <polygon points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                    points(&mut mbr.vertices().iter().map(|c| (c.x, c.y))),
+                    rgb(color),
+                    lw
+                ));
+                if !self.without_mbrs_name || !self.without_mbrs_conf {
+                    let text = mbr.label(
+                        !self.without_mbrs_name,
+                        !self.without_mbrs_conf,
+                        self.decimal_places,
+                    );
+                    let top = mbr.top();
+                    label(&mut svg, &text, top.x as f32, top.y as f32, color);
                 }
             }
+        }
 
-            // save
-            if let Some(saveout) = &self.saveout {
-                self.save(&img_rgba, saveout);
+        // keypoints
+        if !self.without_keypoints {
+            for kpts in y.keypoints().into_iter().flatten() {
+                if let Some(skeletons) = &self.skeletons {
+                    for (n, &(i, ii)) in skeletons.iter().enumerate() {
+                        let (a, b) = match (kpts.get(i), kpts.get(ii)) {
+                            (Some(a), Some(b)) => (a, b),
+                            _ => continue,
+                        };
+                        if a.confidence() == 0.0 || b.confidence() == 0.0 {
+                            continue;
+                        }
+                        svg.push_str(&format!(
+                            "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                            a.x(),
+                            a.y(),
+                            b.x(),
+                            b.y(),
+                            rgb(self.skeleton_color(n)),
+                            lw
+                        ));
+                    }
+                }
+                for (i, kpt) in kpts.iter().enumerate() {
+                    if kpt.confidence() == 0.0 {
+                        continue;
+                    }
+                    let color = self.keypoint_color(kpts, i);
+                    svg.push_str(&format!(
+                        "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\"/>\n",
+                        kpt.x(),
+                        kpt.y(),
+                        self.keypoints_radius,
+                        rgb(color)
+                    ));
+                    if self.with_keypoints_name || self.with_keypoints_conf {
+                        let text = kpt.label(
+                            self.with_keypoints_name,
+                            self.with_keypoints_conf,
+                            self.decimal_places,
+                        );
+                        label(&mut svg, &text, kpt.x(), kpt.y(), color);
+                    }
+                }
             }
         }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn is_redacted(&self, id: isize) -> bool {
+        match &self.redact_classes {
+            None => true,
+            Some(classes) => usize::try_from(id).is_ok_and(|id| classes.contains(&id)),
+        }
     }
 
     /// Plot bounding bboxes and labels
     pub fn plot_bboxes(&self, img: &mut RgbaImage, bboxes: &[Bbox]) {
         for bbox in bboxes.iter() {
             // bbox
-            imageproc::drawing::draw_hollow_rect_mut(
-                img,
-                imageproc::rect::Rect::at(bbox.xmin().round() as i32, bbox.ymin().round() as i32)
-                    .of_size(bbox.width().round() as u32, bbox.height().round() as u32),
-                image::Rgba(self.get_color(bbox.id() as usize).into()),
-            );
+            let lw = self.line_width_of(img) as i32;
+            for i in 0..lw {
+                imageproc::drawing::draw_hollow_rect_mut(
+                    img,
+                    imageproc::rect::Rect::at(
+                        bbox.xmin().round() as i32 - i,
+                        bbox.ymin().round() as i32 - i,
+                    )
+                    .of_size(
+                        (bbox.width().round() as i32 + 2 * i).max(1) as u32,
+                        (bbox.height().round() as i32 + 2 * i).max(1) as u32,
+                    ),
+                    image::Rgba(self.color_of(bbox.id(), bbox.name()).into()),
+                );
+            }
 
             // label
             if !self.without_bboxes_name || !self.without_bboxes_conf {
@@ -376,14 +844,16 @@ impl Annotator {
                     !self.without_bboxes_conf,
                     self.decimal_places,
                 );
-                self.put_text(
+                let scale_dy = self.scale_dy_for_box(img, bbox.height());
+                self.put_text_sized(
                     img,
                     &label,
                     bbox.xmin(),
                     bbox.ymin(),
-                    image::Rgba(self.get_color(bbox.id() as usize).into()),
+                    image::Rgba(self.color_of(bbox.id(), bbox.name()).into()),
                     self.bboxes_text_color,
                     self.without_bboxes_text_bg,
+                    scale_dy,
                 );
             }
         }
@@ -396,11 +866,11 @@ impl Annotator {
             for i in 0..mbr.vertices().len() {
                 let p1 = mbr.vertices()[i];
                 let p2 = mbr.vertices()[(i + 1) % mbr.vertices().len()];
-                imageproc::drawing::draw_line_segment_mut(
+                self.draw_line(
                     img,
                     (p1.x.round() as f32, p1.y.round() as f32),
                     (p2.x.round() as f32, p2.y.round() as f32),
-                    image::Rgba(self.get_color(mbr.id() as usize).into()),
+                    image::Rgba(self.color_of(mbr.id(), mbr.name()).into()),
                 );
             }
 
@@ -411,15 +881,26 @@ impl Annotator {
                     !self.without_mbrs_conf,
                     self.decimal_places,
                 );
-                self.put_text(
-                    img,
-                    &label,
-                    mbr.top().x as f32,
-                    mbr.top().y as f32,
-                    image::Rgba(self.get_color(mbr.id() as usize).into()),
-                    self.mbrs_text_color,
-                    self.without_mbrs_text_bg,
-                );
+                if self.with_mbrs_text_rotated {
+                    self.put_text_along_mbr(
+                        img,
+                        &label,
+                        mbr,
+                        image::Rgba(self.color_of(mbr.id(), mbr.name()).into()),
+                        self.mbrs_text_color,
+                        self.without_mbrs_text_bg,
+                    );
+                } else {
+                    self.put_text(
+                        img,
+                        &label,
+                        mbr.top().x as f32,
+                        mbr.top().y as f32,
+                        image::Rgba(self.color_of(mbr.id(), mbr.name()).into()),
+                        self.mbrs_text_color,
+                        self.without_mbrs_text_bg,
+                    );
+                }
             }
         }
     }
@@ -440,7 +921,7 @@ impl Annotator {
                 })
                 .map(|p| imageproc::point::Point::new(p.x() as i32, p.y() as i32))
                 .collect::<Vec<_>>();
-            let mut color_ = self.get_color(polygon.id() as usize);
+            let mut color_ = self.color_of(polygon.id(), polygon.name());
             color_.3 = self.polygons_alpha;
             imageproc::drawing::draw_polygon_mut(&mut convas, &polygon_i32, Rgba(color_.into()));
 
@@ -457,7 +938,18 @@ impl Annotator {
                     })
                     .map(|p| imageproc::point::Point::new(p.x() as f32, p.y() as f32))
                     .collect::<Vec<_>>();
-                imageproc::drawing::draw_hollow_polygon_mut(img, &polygon_f32, self.contours_color);
+                if self.line_width_of(img) <= 1 {
+                    imageproc::drawing::draw_hollow_polygon_mut(
+                        img,
+                        &polygon_f32,
+                        self.contours_color,
+                    );
+                } else {
+                    for (i, p1) in polygon_f32.iter().enumerate() {
+                        let p2 = polygon_f32[(i + 1) % polygon_f32.len()];
+                        self.draw_line(img, (p1.x, p1.y), (p2.x, p2.y), self.contours_color);
+                    }
+                }
             }
         }
         image::imageops::overlay(img, &convas, 0, 0);
@@ -476,7 +968,7 @@ impl Annotator {
                         &label,
                         x,
                         y,
-                        image::Rgba(self.get_color(polygon.id() as usize).into()),
+                        image::Rgba(self.color_of(polygon.id(), polygon.name()).into()),
                         self.polygons_text_color,
                         !self.with_polygons_text_bg,
                     );
@@ -485,6 +977,31 @@ impl Annotator {
         }
     }
 
+    /// Palette color of the `i`-th keypoint, shaded by its depth within `kpts` if enabled
+    fn keypoint_color(&self, kpts: &[Keypoint], i: usize) -> (u8, u8, u8, u8) {
+        let (r, g, b, a) = match &self.keypoints_palette {
+            None => self.get_color(i),
+            Some(keypoints_palette) => keypoints_palette[i],
+        };
+        let z = match kpts[i].z() {
+            Some(z) if self.keypoints_depth_shading => z,
+            _ => return (r, g, b, a),
+        };
+        let (min, max) = kpts
+            .iter()
+            .filter(|kpt| kpt.confidence() > 0.)
+            .filter_map(|kpt| kpt.z())
+            .fold((z, z), |(min, max), z| (min.min(z), max.max(z)));
+        let t = if max > min {
+            (z - min) / (max - min)
+        } else {
+            0.
+        };
+        let k = 1. - 0.6 * t;
+        let shade = |x: u8| (x as f32 * k).round() as u8;
+        (shade(r), shade(g), shade(b), a)
+    }
+
     /// Plot keypoints and texts
     pub fn plot_keypoints(&self, img: &mut RgbaImage, keypoints: &[Vec<Keypoint>]) {
         for kpts in keypoints.iter() {
@@ -494,10 +1011,7 @@ impl Annotator {
                 }
 
                 // keypoint
-                let color = match &self.keypoints_palette {
-                    None => self.get_color(i),
-                    Some(keypoints_palette) => keypoints_palette[i],
-                };
+                let color = self.keypoint_color(kpts, i);
                 imageproc::drawing::draw_filled_circle_mut(
                     img,
                     (kpt.x() as i32, kpt.y() as i32),
@@ -526,23 +1040,71 @@ impl Annotator {
 
             // skeletons
             if let Some(skeletons) = &self.skeletons {
-                for &(i, ii) in skeletons.iter() {
-                    let kpt1 = &kpts[i];
-                    let kpt2 = &kpts[ii];
+                for (n, &(i, ii)) in skeletons.iter().enumerate() {
+                    let (kpt1, kpt2) = match (kpts.get(i), kpts.get(ii)) {
+                        (Some(a), Some(b)) => (a, b),
+                        _ => continue,
+                    };
                     if kpt1.confidence() == 0.0 || kpt2.confidence() == 0.0 {
                         continue;
                     }
-                    imageproc::drawing::draw_line_segment_mut(
+                    self.draw_line(
                         img,
                         (kpt1.x(), kpt1.y()),
                         (kpt2.x(), kpt2.y()),
-                        image::Rgba([255, 51, 255, 255]),
+                        image::Rgba(self.skeleton_color(n).into()),
                     );
                 }
             }
         }
     }
 
+    /// Blend a [0, 1] confidence map, resized to the image, in colormap colors
+    pub fn plot_heatmap(&self, img: &mut RgbaImage, heatmap: &Array<f32, Ix2>) {
+        self.blend_map(img, heatmap, self.heatmap_threshold)
+    }
+
+    /// Blend a depth map, min-max normalized, in the heatmap colormap
+    pub fn plot_depth(&self, img: &mut RgbaImage, depth: &Array<f32, Ix2>) {
+        let (min, max) = depth
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold((f32::MAX, f32::MIN), |(a, b), &x| (a.min(x), b.max(x)));
+        if min > max {
+            return;
+        }
+        let range = (max - min).max(f32::EPSILON);
+        self.blend_map(img, &depth.mapv(|x| (x - min) / range), f32::MIN)
+    }
+
+    fn blend_map(&self, img: &mut RgbaImage, heatmap: &Array<f32, Ix2>, threshold: f32) {
+        let (w, h) = img.dimensions();
+        let (hh, hw) = heatmap.dim();
+        let Some(map) = image::ImageBuffer::<image::Luma<f32>, Vec<f32>>::from_raw(
+            hw as u32,
+            hh as u32,
+            heatmap.iter().copied().collect(),
+        ) else {
+            return;
+        };
+        let map = if (hw as u32, hh as u32) == (w, h) {
+            map
+        } else {
+            image::imageops::resize(&map, w, h, image::imageops::FilterType::Triangle)
+        };
+        let a = self.heatmap_alpha;
+        for (p, v) in img.pixels_mut().zip(map.pixels()) {
+            let v = v.0[0];
+            if v.is_nan() || v < threshold {
+                continue;
+            }
+            let c = self.heatmap_colormap[(v.clamp(0., 1.) * 255.).round() as usize];
+            for (x, c) in p.0.iter_mut().zip(c) {
+                *x = (*x as f32 * (1. - a) + c as f32 * a).round() as u8;
+            }
+        }
+    }
+
     /// Plot masks
     pub fn plot_masks(&self, img: &mut RgbaImage, masks: &[Mask]) {
         let (w, h) = img.dimensions();
@@ -615,11 +1177,15 @@ impl Annotator {
 
     /// Plot probs
     pub fn plot_probs(&self, img: &mut RgbaImage, probs: &Prob) {
+        let Some(font) = &self.font else {
+            return;
+        };
         let (x, mut y) = (img.width() as i32 / 20, img.height() as i32 / 20);
         for k in probs.topk(self.probs_topk).iter() {
             let legend = format!("{}: {:.4}", k.2.as_ref().unwrap_or(&k.0.to_string()), k.1);
-            let scale = PxScale::from(self.scale_dy);
-            let (text_w, text_h) = imageproc::drawing::text_size(scale, &self.font, &legend);
+            let scale_dy = self.scale_dy_of(img);
+            let scale = PxScale::from(scale_dy);
+            let (text_w, text_h) = imageproc::drawing::text_size(scale, font, &legend);
             let text_h = text_h + text_h / 3;
             y += text_h as i32;
             imageproc::drawing::draw_filled_rect_mut(
@@ -631,14 +1197,85 @@ impl Annotator {
                 img,
                 image::Rgba([0, 0, 0, 255]),
                 x,
-                y - (self.scale_dy / self._scale).floor() as i32 + 2,
+                y - (scale_dy / self._scale).floor() as i32 + 2,
                 scale,
-                &self.font,
+                font,
                 &legend,
             );
         }
     }
 
+    /// Line width for this image
+    fn line_width_of(&self, img: &RgbaImage) -> usize {
+        self.line_width_for(img.width(), img.height())
+    }
+
+    fn line_width_for(&self, w: u32, h: u32) -> usize {
+        match self.line_width {
+            Some(x) => x,
+            None if self.auto_scale => {
+                (Self::diagonal(w, h) / 800.).round().clamp(1., 12.) as usize
+            }
+            None => 1,
+        }
+    }
+
+    /// Text height in pixels for this image, 28 at 1280x720 when auto scaling
+    fn scale_dy_of(&self, img: &RgbaImage) -> f32 {
+        self.scale_dy_for(img.width(), img.height())
+    }
+
+    fn scale_dy_for(&self, w: u32, h: u32) -> f32 {
+        if let Some(x) = self.font_size {
+            return x;
+        }
+        match self.text_scale {
+            Some(x) => self.scale_dy * x,
+            None if self.auto_scale => {
+                (self.scale_dy * Self::diagonal(w, h) / 1468.6).clamp(12., 96.)
+            }
+            None => self.scale_dy,
+        }
+    }
+
+    /// Text height for the label of a box `box_h` pixels high, at most half the box
+    /// (but not below 12 pixels) unless a size was given
+    fn scale_dy_for_box(&self, img: &RgbaImage, box_h: f32) -> f32 {
+        let scale_dy = self.scale_dy_of(img);
+        if self.font_size.is_some() || self.text_scale.is_some() {
+            scale_dy
+        } else {
+            scale_dy.min((box_h / 2.).max(12.))
+        }
+    }
+
+    fn diagonal(w: u32, h: u32) -> f32 {
+        (w as f32).hypot(h as f32)
+    }
+
+    /// Line segment drawn as parallel 1px lines to reach the line width
+    fn draw_line(&self, img: &mut RgbaImage, p1: (f32, f32), p2: (f32, f32), color: Rgba<u8>) {
+        let lw = self.line_width_of(img);
+        let (dx, dy) = (p2.0 - p1.0, p2.1 - p1.1);
+        let len = dx.hypot(dy);
+        if lw <= 1 || len == 0. {
+            imageproc::drawing::draw_line_segment_mut(img, p1, p2, color);
+            return;
+        }
+        let (nx, ny) = (-dy / len, dx / len);
+        let half = (lw - 1) as f32 / 2.;
+        let mut t = -half;
+        while t <= half {
+            imageproc::drawing::draw_line_segment_mut(
+                img,
+                (p1.0 + nx * t, p1.1 + ny * t),
+                (p2.0 + nx * t, p2.1 + ny * t),
+                color,
+            );
+            t += 0.5;
+        }
+    }
+
     /// Helper for putting texts
     #[allow(clippy::too_many_arguments)]
     fn put_text(
@@ -651,9 +1288,34 @@ impl Annotator {
         text_color: Rgba<u8>,
         without_text_bg: bool,
     ) {
-        if !legend.is_empty() {
-            let scale = PxScale::from(self.scale_dy);
-            let (text_w, text_h) = imageproc::drawing::text_size(scale, &self.font, legend);
+        let scale_dy = self.scale_dy_of(img);
+        self.put_text_sized(
+            img,
+            legend,
+            x,
+            y,
+            color,
+            text_color,
+            without_text_bg,
+            scale_dy,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn put_text_sized(
+        &self,
+        img: &mut RgbaImage,
+        legend: &str,
+        x: f32,
+        y: f32,
+        color: Rgba<u8>,
+        text_color: Rgba<u8>,
+        without_text_bg: bool,
+        scale_dy: f32,
+    ) {
+        if let (false, Some(font)) = (legend.is_empty(), &self.font) {
+            let scale = PxScale::from(scale_dy);
+            let (text_w, text_h) = imageproc::drawing::text_size(scale, font, legend);
             let text_h = text_h + text_h / 3;
             let top = if y > text_h as f32 {
                 (y.round() as u32 - text_h) as i32
@@ -679,22 +1341,122 @@ impl Annotator {
                 img,
                 text_color,
                 left,
-                top - (self.scale_dy / self._scale).floor() as i32 + 2,
+                top - (scale_dy / self._scale).floor() as i32 + 2,
                 scale,
-                &self.font,
+                font,
                 legend,
             );
         }
     }
 
+    /// Draw text rotated along the longest edge of an MBR, outside the box
+    fn put_text_along_mbr(
+        &self,
+        img: &mut RgbaImage,
+        legend: &str,
+        mbr: &Mbr,
+        color: Rgba<u8>,
+        text_color: Rgba<u8>,
+        without_text_bg: bool,
+    ) {
+        let vs = mbr.vertices();
+        let Some(font) = &self.font else {
+            return;
+        };
+        if legend.is_empty() || vs.len() < 2 {
+            return;
+        }
+
+        // upper one of the longest edges, angle kept in (-90°, 90°] so text is never upside-down
+        let edges: Vec<_> = (0..vs.len())
+            .map(|i| (vs[i], vs[(i + 1) % vs.len()]))
+            .collect();
+        let length = |(p, q): &(geo::Coord, geo::Coord)| (q.x - p.x).hypot(q.y - p.y);
+        let longest = edges.iter().map(length).fold(0., f64::max);
+        let (p1, p2) = edges
+            .iter()
+            .filter(|e| length(e) >= longest * 0.999)
+            .min_by(|a, b| (a.0.y + a.1.y).total_cmp(&(b.0.y + b.1.y)))
+            .copied()
+            .unwrap();
+        let mut theta = (p2.y - p1.y).atan2(p2.x - p1.x) as f32;
+        if theta > std::f32::consts::FRAC_PI_2 {
+            theta -= std::f32::consts::PI;
+        } else if theta <= -std::f32::consts::FRAC_PI_2 {
+            theta += std::f32::consts::PI;
+        }
+
+        // render text onto a square patch so rotation never crops it
+        let scale_dy = self.scale_dy_of(img);
+        let scale = PxScale::from(scale_dy);
+        let (text_w, text_h) = imageproc::drawing::text_size(scale, font, legend);
+        let text_h = text_h + text_h / 3;
+        let side = ((text_w * text_w + text_h * text_h) as f32).sqrt().ceil() as u32 + 2;
+        let mut patch = RgbaImage::new(side, side);
+        let (left, top) = ((side - text_w) as i32 / 2, (side - text_h) as i32 / 2);
+        if !without_text_bg {
+            imageproc::drawing::draw_filled_rect_mut(
+                &mut patch,
+                imageproc::rect::Rect::at(left, top).of_size(text_w, text_h),
+                color,
+            );
+        }
+        imageproc::drawing::draw_text_mut(
+            &mut patch,
+            text_color,
+            left,
+            top - (scale_dy / self._scale).floor() as i32 + 2,
+            scale,
+            font,
+            legend,
+        );
+        let patch = imageproc::geometric_transformations::rotate_about_center(
+            &patch,
+            theta,
+            imageproc::geometric_transformations::Interpolation::Bilinear,
+            Rgba([0, 0, 0, 0]),
+        );
+
+        // center on the edge midpoint, pushed outwards by half the text height
+        let (mx, my) = ((p1.x + p2.x) as f32 / 2., (p1.y + p2.y) as f32 / 2.);
+        let (cx, cy) = vs.iter().fold((0., 0.), |(x, y), v| (x + v.x, y + v.y));
+        let (cx, cy) = (cx as f32 / vs.len() as f32, cy as f32 / vs.len() as f32);
+        let (mut nx, mut ny) = (-theta.sin(), theta.cos());
+        if nx * (mx - cx) + ny * (my - cy) < 0. {
+            (nx, ny) = (-nx, -ny);
+        }
+        let half = text_h as f32 / 2.;
+        let x0 = (mx + nx * half - side as f32 / 2.).round() as i64;
+        let y0 = (my + ny * half - side as f32 / 2.).round() as i64;
+        for (x, y, p) in patch.enumerate_pixels() {
+            let (xx, yy) = (x0 + x as i64, y0 + y as i64);
+            if p.0[3] == 0 || xx < 0 || yy < 0 {
+                continue;
+            }
+            let (xx, yy) = (xx as u32, yy as u32);
+            if xx < img.width() && yy < img.height() {
+                img.get_pixel_mut(xx, yy).blend(p);
+            }
+        }
+    }
+
     /// Load custom font
     fn load_font(path: Option<&str>) -> Result<FontVec> {
         let path_font = match path {
             None => auto_load("Arial.ttf", Some("fonts"))?,
             Some(p) => p.into(),
         };
-        let buffer = std::fs::read(path_font)?;
-        Ok(FontVec::try_from_vec(buffer.to_owned()).unwrap())
+        let buffer = std::fs::read(&path_font)?;
+        FontVec::try_from_vec(buffer)
+            .map_err(|err| anyhow::anyhow!("Failed to load font {:?}: {err}", path_font))
+    }
+
+    /// Color of a class: its named color if any, or the palette color of its id
+    fn color_of(&self, id: isize, name: Option<&String>) -> (u8, u8, u8, u8) {
+        match (&self.class_colors, name) {
+            (Some(colors), Some(name)) if colors.contains_key(name) => colors[name],
+            _ => self.get_color(id as usize),
+        }
     }
 
     /// Pick color from pallette
@@ -728,3 +1490,118 @@ impl Annotator {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests_annotator {
+    use super::Annotator;
+    use crate::{Bbox, Keypoint, Y};
+    use image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn styling() {
+        let annotator = Annotator::default()
+            .with_font("no/such/font.ttf")
+            .with_font_size(40.)
+            .with_line_thickness(5)
+            .with_text_background(false);
+        assert_eq!(annotator.font_size(), Some(40.));
+        assert_eq!(annotator.line_width(), Some(5));
+
+        let image = DynamicImage::ImageRgb8(RgbImage::new(4000, 3000));
+        let y = Y::default().with_bboxes(&[
+            Bbox::default()
+                .with_xyxy(100., 100., 3900., 2900.)
+                .with_id(0)
+                .with_confidence(0.9),
+            Bbox::default()
+                .with_xyxy(3990., 0., 4000., 8.)
+                .with_id(1)
+                .with_confidence(0.5),
+        ]);
+        let images = annotator.annotate_to_image(&[image], &[y]);
+        assert_eq!(images[0].width(), 4000);
+
+        let path = std::env::temp_dir().join(format!("usls-font-{}.ttf", std::process::id()));
+        std::fs::write(&path, b"not a font").unwrap();
+        assert!(Annotator::load_font(path.to_str()).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // without a font the boxes are still drawn, their texts skipped
+        let annotator = Annotator {
+            font: None,
+            ..Annotator::default()
+        };
+        let image = DynamicImage::ImageRgb8(RgbImage::new(64, 64));
+        let y = Y::default().with_bboxes(&[Bbox::from((8., 8., 40., 40., 0, 0.9))]);
+        let images = annotator.annotate_to_image(&[image], &[y]);
+        assert_ne!(images[0].to_rgb8().get_pixel(8, 20).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn skeleton() {
+        let limbs = [(0, 1), (1, 2), (2, 3), (3, 4), (0, 5), (5, 6), (0, 20)];
+        assert!(Annotator::default().with_skeleton(&limbs, 20).is_err());
+        let annotator = Annotator::default().with_skeleton(&limbs, 21).unwrap();
+        assert!(annotator
+            .with_skeleton_colors(&[(0, 255, 0, 255); 6])
+            .is_err());
+        let annotator = Annotator::default()
+            .with_skeleton(&limbs, 21)
+            .unwrap()
+            .with_skeleton_colors(&[(0, 255, 0, 255); 7])
+            .unwrap();
+
+        // a full hand and a truncated one
+        let mut hand: Vec<Keypoint> = (0..21)
+            .map(|i| Keypoint::from((3. * i as f32, 5.)).with_confidence(0.9))
+            .collect();
+        hand[0] = Keypoint::from((5., 50.)).with_confidence(0.9);
+        hand[20] = Keypoint::from((60., 50.)).with_confidence(0.9);
+        let y = Y::default().with_keypoints(&[hand.clone(), hand[..3].to_vec()]);
+        let image = DynamicImage::ImageRgb8(RgbImage::new(64, 64));
+        let images = annotator.annotate_to_image(&[image], &[y]);
+        assert_eq!(images[0].to_rgb8().get_pixel(32, 50).0, [0, 255, 0]);
+    }
+
+    #[test]
+    fn heatmap() {
+        // 0 on the left to 1 on the right
+        let map = ndarray::Array::from_shape_fn((10, 10), |(_, x)| x as f32 / 9.);
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(10, 10, image::Rgb([0, 0, 255])));
+        let y = Y::default().with_heatmap(map.clone());
+
+        let plot = Annotator::default()
+            .with_heatmap("jet", 1.)
+            .unwrap()
+            .plot(&image, &y);
+        assert_eq!(plot.get_pixel(0, 0).0, [0, 0, 255, 255]); // below the threshold
+        assert_eq!(plot.get_pixel(9, 9).0, [128, 0, 0, 255]); // jet at 1
+        assert_eq!(plot.get_pixel(9, 0).0, [128, 0, 0, 255]);
+
+        // half transparent, resized from a smaller map
+        let y = Y::default().with_heatmap(map.slice(ndarray::s![..5, ..]).to_owned());
+        let plot = Annotator::default()
+            .with_heatmap("viridis", 0.5)
+            .unwrap()
+            .plot(&image, &y);
+        let [r, g, b] = crate::colormap256::VIRIDIS[255];
+        let blend = |x: u8, c: u8| ((x as f32 + c as f32) / 2.).round() as u8;
+        assert_eq!(
+            plot.get_pixel(9, 9).0,
+            [blend(0, r), blend(0, g), blend(255, b), 255]
+        );
+
+        // depth in any range, the nearest drawn too
+        let y = Y::default().with_depth(map.mapv(|x| 2. + 8. * x));
+        let plot = Annotator::default()
+            .with_heatmap("jet", 1.)
+            .unwrap()
+            .plot(&image, &y);
+        assert_eq!(plot.get_pixel(0, 0).0, [0, 0, 128, 255]); // jet at 0
+        assert_eq!(plot.get_pixel(9, 0).0, [128, 0, 0, 255]);
+        let plot = Annotator::default().without_depth(true).plot(&image, &y);
+        assert_eq!(plot.get_pixel(9, 0).0, [0, 0, 255, 255]);
+        assert!(Annotator::default().with_heatmap("rainbow", 1.).is_err());
+        assert!(Annotator::default().with_colormap("rainbow").is_err());
+    }
+}
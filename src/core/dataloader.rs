@@ -1,8 +1,12 @@
 use crate::{CHECK_MARK, SAFE_CROSS_MARK};
 use anyhow::{anyhow, bail, Result};
-use image::DynamicImage;
+use image::{DynamicImage, ImageDecoder};
+use rayon::prelude::*;
 use std::collections::VecDeque;
+use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::JoinHandle;
 use walkdir::{DirEntry, WalkDir};
 
 /// Dataloader for load images
@@ -11,6 +15,7 @@ pub struct DataLoader {
     pub paths: VecDeque<PathBuf>,
     pub recursive: bool,
     pub batch: usize,
+    pub progress: bool, // count and ETA on stderr in `run_with_sink`
 }
 
 impl Iterator for DataLoader {
@@ -47,6 +52,7 @@ impl Default for DataLoader {
         Self {
             batch: 1,
             recursive: false,
+            progress: false,
             paths: Default::default(),
         }
     }
@@ -58,6 +64,9 @@ impl DataLoader {
         let mut paths = VecDeque::new();
 
         match source {
+            s if s.is_file() && Self::is_video(s) => {
+                bail!("{s:?} is a video, decode it with `DataLoader::from_video` (`video` feature)")
+            }
             s if s.is_file() => paths.push_back(s.to_path_buf()),
             s if s.is_dir() => {
                 for entry in WalkDir::new(s)
@@ -71,6 +80,9 @@ impl DataLoader {
                     if !self.recursive && entry.depth() > 1 {
                         continue;
                     }
+                    if Self::is_video(entry.path()) {
+                        continue;
+                    }
                     paths.push_back(entry.path().to_path_buf());
                 }
             }
@@ -88,14 +100,111 @@ impl DataLoader {
             paths: self.paths.to_owned(),
             batch: self.batch,
             recursive: self.recursive,
+            progress: self.progress,
+        })
+    }
+
+    /// Images listed in a newline-separated manifest file
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = std::fs::File::open(path.as_ref())
+            .map_err(|_| anyhow!("Failed to open manifest at {:?}", path.as_ref()))?;
+        Self::from_reader(std::io::BufReader::new(f))
+    }
+
+    /// Images listed on stdin, one path per line, e.g. `find ./images -name "*.jpg" | ...`
+    pub fn from_stdin() -> Result<Self> {
+        Self::from_reader(std::io::stdin().lock())
+    }
+
+    /// One image path per line, blank lines ignored and missing files skipped with a warning
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut paths = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let p = PathBuf::from(line);
+            if p.is_file() {
+                paths.push_back(p);
+            } else {
+                println!("{SAFE_CROSS_MARK} {p:?} Not Exists, skipped");
+            }
+        }
+        println!("{CHECK_MARK} Found images x{}", paths.len());
+        Ok(Self {
+            paths,
+            ..Default::default()
         })
     }
 
+    /// Images at `paths`, in this order
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Self {
+        Self {
+            paths: paths.iter().map(|x| x.as_ref().to_path_buf()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Decode batches on a background thread (images of a batch in parallel), keeping up to
+    /// `buffer_size` batches ready ahead of the consumer. Batches come in input order and a
+    /// batch with an unreadable image is an error instead of being skipped.
+    pub fn with_prefetch(self, buffer_size: usize) -> Prefetch {
+        let (sender, receiver) = sync_channel(buffer_size.max(1));
+        let (paths, batch) = (Vec::from(self.paths), self.batch.max(1));
+        let handle = std::thread::spawn(move || {
+            for chunk in paths.chunks(batch) {
+                let images = chunk
+                    .par_iter()
+                    .map(Self::try_read)
+                    .collect::<Result<Vec<_>>>()
+                    .map(|images| (images, chunk.to_vec()));
+                if sender.send(images).is_err() {
+                    break; // consumer dropped
+                }
+            }
+        });
+        Prefetch {
+            receiver: Some(receiver),
+            handle: Some(handle),
+        }
+    }
+
+    /// Decode an image, rotated or flipped upright as its EXIF orientation says
     pub fn try_read<P: AsRef<Path>>(path: P) -> Result<DynamicImage> {
-        image::io::Reader::open(&path)
+        let mut decoder = image::ImageReader::open(&path)
             .map_err(|_| anyhow!("Failed to open image at {:?}", path.as_ref()))?
-            .decode()
-            .map_err(|_| anyhow!("Failed to decode image at {:?}", path.as_ref()))
+            .with_guessed_format()
+            .map_err(|_| anyhow!("Failed to read image at {:?}", path.as_ref()))?
+            .into_decoder()
+            .map_err(|_| anyhow!("Failed to decode image at {:?}", path.as_ref()))?;
+        let orientation = decoder
+            .orientation()
+            .unwrap_or(image::metadata::Orientation::NoTransforms);
+        let mut x = DynamicImage::from_decoder(decoder)
+            .map_err(|_| anyhow!("Failed to decode image at {:?}", path.as_ref()))?;
+        x.apply_orientation(orientation);
+        Ok(x)
+    }
+
+    /// Read all images in parallel, returning the readable ones (in order) and the
+    /// failures, both with their paths, instead of stopping at the first bad file
+    pub fn try_read_many<P: AsRef<Path> + Sync>(
+        paths: &[P],
+    ) -> (Vec<(PathBuf, DynamicImage)>, Vec<ReadError>) {
+        let results: Vec<_> = paths
+            .par_iter()
+            .map(|p| (p.as_ref().to_path_buf(), Self::try_read(p)))
+            .collect();
+        let (mut images, mut errors) = (Vec::new(), Vec::new());
+        for (path, x) in results {
+            match x {
+                Ok(x) => images.push((path, x)),
+                Err(err) => errors.push((path, err)),
+            }
+        }
+        (images, errors)
     }
 
     pub fn with_batch(mut self, x: usize) -> Self {
@@ -103,6 +212,12 @@ impl DataLoader {
         self
     }
 
+    /// Show progress on stderr when run through `run_with_sink`
+    pub fn with_progress(mut self, x: bool) -> Self {
+        self.progress = x;
+        self
+    }
+
     pub fn with_recursive(mut self, x: bool) -> Self {
         self.recursive = x;
         self
@@ -112,6 +227,13 @@ impl DataLoader {
         &self.paths
     }
 
+    /// No `.ts`: it is TypeScript as often as MPEG-TS, pass those to `from_video` explicitly
+    fn is_video(path: &Path) -> bool {
+        path.extension().and_then(|x| x.to_str()).is_some_and(|x| {
+            ["mp4", "mkv", "avi", "mov", "webm", "flv", "m4v"].contains(&x.to_lowercase().as_str())
+        })
+    }
+
     fn _is_hidden(entry: &DirEntry) -> bool {
         entry
             .file_name()
@@ -120,3 +242,109 @@ impl DataLoader {
             .unwrap_or(false)
     }
 }
+
+type Batch = (Vec<DynamicImage>, Vec<PathBuf>);
+type ReadError = (PathBuf, anyhow::Error);
+
+/// Batches decoded ahead of time by [`DataLoader::with_prefetch`]
+#[derive(Debug)]
+pub struct Prefetch {
+    receiver: Option<Receiver<Result<Batch>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Iterator for Prefetch {
+    type Item = Result<Batch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.as_ref()?.recv().ok()
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        // unblock the decoding thread before waiting for it
+        drop(self.receiver.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_dataloader {
+    use super::DataLoader;
+
+    #[test]
+    fn read_many() {
+        let dir = std::env::temp_dir().join(format!("usls-read-many-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (valid, empty) = (dir.join("valid.png"), dir.join("empty.jpg"));
+        image::RgbImage::new(3, 2).save(&valid).unwrap();
+        std::fs::write(&empty, b"").unwrap();
+
+        let (images, errors) = DataLoader::try_read_many(&[&valid, &empty]);
+        assert_eq!(images.len(), 1);
+        assert_eq!((&images[0].0, images[0].1.width()), (&valid, 3));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, empty);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_videos() {
+        let dir = std::env::temp_dir().join(format!("usls-skip-videos-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(3, 2).save(dir.join("a.png")).unwrap();
+        std::fs::write(dir.join("clip.mp4"), b"").unwrap();
+
+        // videos are left to `from_video`
+        assert!(DataLoader::default().load(dir.join("clip.mp4")).is_err());
+        assert_eq!(DataLoader::default().load(&dir).unwrap().paths().len(), 1);
+        assert!(!DataLoader::is_video(&dir.join("index.ts")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prefetch() {
+        let dir = std::env::temp_dir().join(format!("usls-prefetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..5u32 {
+            let path = dir.join(format!("{}.png", 4 - i));
+            image::RgbImage::new(i + 1, 1).save(&path).unwrap();
+            paths.push(path);
+        }
+
+        let batches = DataLoader::from_paths(&paths)
+            .with_batch(2)
+            .with_prefetch(2)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(|x| x.0.len()).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        let widths: Vec<u32> = batches
+            .iter()
+            .flat_map(|x| x.0.iter().map(|x| x.width()))
+            .collect();
+        assert_eq!(widths, [1, 2, 3, 4, 5]);
+        assert_eq!(
+            batches.into_iter().flat_map(|x| x.1).collect::<Vec<_>>(),
+            paths
+        );
+
+        // an unreadable image fails its batch only
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not an image").unwrap();
+        let results: Vec<_> = DataLoader::from_paths(&[&paths[0], &broken, &paths[1]])
+            .with_prefetch(1)
+            .collect();
+        assert_eq!(
+            results.iter().map(|x| x.is_ok()).collect::<Vec<_>>(),
+            [true, false, true]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
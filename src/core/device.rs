@@ -1,3 +1,5 @@
+use ort::ExecutionProvider;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Device {
     Auto(usize), // TODO
@@ -5,6 +7,8 @@ pub enum Device {
     Cuda(usize),
     Trt(usize),
     CoreML(usize),
+    #[cfg(feature = "directml")]
+    DirectML(usize),
     // Cann(usize),
     // Acl(usize),
     // Rocm(usize),
@@ -12,3 +16,36 @@ pub enum Device {
     // Openvino(usize),
     // Onednn(usize),
 }
+
+impl Device {
+    /// Execution providers compiled into the loaded ONNX Runtime, e.g. `CUDAExecutionProvider`.
+    pub fn available_providers() -> Vec<String> {
+        #[allow(unused_mut)]
+        let mut eps: Vec<Box<dyn ExecutionProvider>> = vec![
+            Box::new(ort::CPUExecutionProvider::default()),
+            Box::new(ort::CUDAExecutionProvider::default()),
+            Box::new(ort::TensorRTExecutionProvider::default()),
+            Box::new(ort::CoreMLExecutionProvider::default()),
+            Box::new(ort::ROCmExecutionProvider::default()),
+            Box::new(ort::OpenVINOExecutionProvider::default()),
+        ];
+        #[cfg(feature = "directml")]
+        eps.push(Box::new(ort::DirectMLExecutionProvider::default()));
+        eps.iter()
+            .filter(|ep| ep.supported_by_platform() && ep.is_available().unwrap_or(false))
+            .map(|ep| ep.as_str().to_string())
+            .collect()
+    }
+
+    /// Check if an execution provider is available, matched case-insensitively
+    /// by full name (`CUDAExecutionProvider`) or short name (`cuda`, `trt`).
+    pub fn is_available(ep: &str) -> bool {
+        let ep = match ep.to_lowercase().as_str() {
+            "trt" => "tensorrt".to_string(),
+            x => x.trim_end_matches("executionprovider").to_string(),
+        };
+        Self::available_providers()
+            .iter()
+            .any(|x| x.to_lowercase().trim_end_matches("executionprovider") == ep)
+    }
+}
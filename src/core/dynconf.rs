@@ -1,3 +1,4 @@
+use anyhow::{bail, Result};
 use std::ops::Index;
 
 /// Dynamic Confidences
@@ -52,4 +53,48 @@ impl DynConf {
 
         Self { confs }
     }
+
+    /// Thresholds of `n = names.len()` classes from `defaults` (as in `new`), overridden
+    /// by `(class name, threshold)` pairs. Fails on names not in `names`.
+    pub fn from_named(named: &[(String, f32)], names: &[String], defaults: &[f32]) -> Result<Self> {
+        let mut x = Self::new(defaults, names.len());
+        for (name, conf) in named.iter() {
+            match names.iter().position(|x| x == name) {
+                Some(i) => x.confs[i] = *conf,
+                None => bail!(
+                    "Unknown class name {:?}, available names: {:?}",
+                    name,
+                    names
+                ),
+            }
+        }
+        Ok(x)
+    }
+
+    pub fn len(&self) -> usize {
+        self.confs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.confs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests_dynconf {
+    use super::DynConf;
+
+    #[test]
+    fn from_named() {
+        let names: Vec<String> = ["person", "bicycle", "car"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        let named = vec![("car".to_string(), 0.3), ("person".to_string(), 0.5)];
+        let confs = DynConf::from_named(&named, &names, &[0.25]).unwrap();
+        assert_eq!((confs[0], confs[1], confs[2]), (0.5, 0.25, 0.3));
+
+        let err = DynConf::from_named(&[("dog".to_string(), 0.1)], &names, &[0.25]).unwrap_err();
+        assert!(err.to_string().contains("bicycle"));
+    }
 }
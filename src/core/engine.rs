@@ -1,7 +1,7 @@
 use anyhow::Result;
 use half::f16;
 use human_bytes::human_bytes;
-use ndarray::{Array, IxDyn};
+use ndarray::{Array, Axis, IxDyn};
 use ort::{
     ExecutionProvider, Session, SessionBuilder, TensorElementType, TensorRTExecutionProvider,
     MINOR_VERSION,
@@ -10,7 +10,10 @@ use prost::Message;
 use std::collections::HashSet;
 
 use crate::{
-    home_dir, onnx, ops::make_divisible, Device, MinOptMax, Options, Ts, CHECK_MARK, CROSS_MARK,
+    core::ort_logger,
+    home_dir, onnx,
+    ops::{self, make_divisible},
+    Device, Hooks, MinOptMax, Options, Ts, CHECK_MARK, CROSS_MARK, SAFE_CROSS_MARK,
 };
 
 /// Ort Tensor Attrs: name, data_type, dims
@@ -21,6 +24,89 @@ pub struct OrtTensorAttr {
     pub dimss: Vec<Vec<isize>>,
 }
 
+/// Quantization parameters of one `QuantizeLinear`/`DequantizeLinear` node:
+/// `tensor` is the node's input, `axis` is set for per-channel quantization.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QuantInfo {
+    pub tensor: String,
+    pub op_type: String,
+    pub axis: Option<i64>,
+    pub scale: Vec<f32>,
+    pub zero_point: Vec<i64>,
+}
+
+/// Memory usage per stage in bytes: last `run` and peak over all runs.
+/// `vram_delta` is the device memory change around inference and is only
+/// available with CUDA/TensorRT when the CUDA runtime can be loaded.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MemReport {
+    pub inputs: usize,
+    pub outputs: usize,
+    pub vram_delta: Option<i64>,
+    pub peak_inputs: usize,
+    pub peak_outputs: usize,
+    pub peak_vram_delta: Option<i64>,
+}
+
+impl MemReport {
+    fn update(&mut self, inputs: usize, outputs: usize, vram_delta: Option<i64>) {
+        self.inputs = inputs;
+        self.outputs = outputs;
+        self.vram_delta = vram_delta;
+        self.peak_inputs = self.peak_inputs.max(inputs);
+        self.peak_outputs = self.peak_outputs.max(outputs);
+        self.peak_vram_delta = match (self.peak_vram_delta, vram_delta) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// Device memory query through `cudaMemGetInfo` of the dynamically loaded CUDA runtime.
+#[derive(Debug)]
+struct CudaMemInfo {
+    lib: libloading::Library,
+    device_id: i32,
+}
+
+impl CudaMemInfo {
+    fn new(device_id: usize) -> Option<Self> {
+        let names = [
+            "libcudart.so",
+            "libcudart.so.12",
+            "libcudart.so.11.0",
+            "cudart64_12.dll",
+            "cudart64_110.dll",
+        ];
+        names
+            .iter()
+            .find_map(|x| unsafe { libloading::Library::new(x) }.ok())
+            .map(|lib| Self {
+                lib,
+                device_id: device_id as i32,
+            })
+    }
+
+    /// Used device memory in bytes
+    fn used(&self) -> Option<usize> {
+        unsafe {
+            let set_device: libloading::Symbol<unsafe extern "C" fn(i32) -> i32> =
+                self.lib.get(b"cudaSetDevice").ok()?;
+            let mem_get_info: libloading::Symbol<
+                unsafe extern "C" fn(*mut usize, *mut usize) -> i32,
+            > = self.lib.get(b"cudaMemGetInfo").ok()?;
+            if set_device(self.device_id) != 0 {
+                return None;
+            }
+            let (mut free, mut total) = (0usize, 0usize);
+            match mem_get_info(&mut free, &mut total) {
+                0 => Some(total - free),
+                _ => None,
+            }
+        }
+    }
+}
+
 /// ONNXRuntime Backend
 #[derive(Debug)]
 pub struct OrtEngine {
@@ -30,17 +116,52 @@ pub struct OrtEngine {
     inputs_attrs: OrtTensorAttr,
     outputs_attrs: OrtTensorAttr,
     profile: bool,
+    profiling: bool, // ORT profiling running, not yet written
     num_dry_run: usize,
     model_proto: onnx::ModelProto,
     params: usize,
     wbmems: usize,
     pub ts: Ts,
+    mem: MemReport,
+    cuda_mem: Option<CudaMemInfo>,
+    adaptive_batch: bool,
+    sub_batch: Option<usize>,
+    save_preprocessed: Option<std::path::PathBuf>,
+    n_preprocessed: usize,
+    hooks: Hooks,
+    ort_log_level: Option<log::LevelFilter>,
 }
 
 impl OrtEngine {
     pub fn new(config: &Options) -> Result<Self> {
+        ort_logger::scope(config.ort_log_level, || Self::_new(config))
+    }
+
+    fn _new(config: &Options) -> Result<Self> {
         // onnx graph
-        let model_proto = Self::load_onnx(&config.onnx_path)?;
+        let mut onnx_path = config.onnx_path.to_owned();
+        let mut model_proto = Self::load_onnx(&onnx_path)?;
+        if let Some(mode) = config.quantization {
+            if !crate::core::quant::is_quantized(&model_proto) {
+                let cache_dir = crate::config_dir().join("quantized");
+                match crate::core::quant::quantized_model(&onnx_path, mode, &cache_dir)
+                    .and_then(|x| Ok((Self::load_onnx(&x)?, x)))
+                {
+                    Ok((x, path)) => {
+                        let (n, conv) = crate::core::quant::quantized_counts(&x);
+                        println!(
+                            "{CHECK_MARK} {mode:?} model: {}, {n} MatMul quantized, {conv} Conv left in FP32",
+                            path.display()
+                        );
+                        model_proto = x;
+                        onnx_path = path.to_string_lossy().into_owned();
+                    }
+                    Err(err) => {
+                        println!("{SAFE_CROSS_MARK} {mode:?} quantization failed, using the FP32 model: {err}")
+                    }
+                }
+            }
+        }
         let graph = match &model_proto.graph {
             Some(graph) => graph,
             None => anyhow::bail!("No graph found in this proto"),
@@ -136,15 +257,43 @@ impl OrtEngine {
                 device = Device::Cpu(0);
                 println!("{err}");
             }),
+            #[cfg(feature = "directml")]
+            Device::DirectML(device_id) => Self::build_directml(&builder, device_id)
+                .unwrap_or_else(|err| {
+                    device = Device::Cpu(0);
+                    println!("{err}");
+                }),
             Device::Cpu(_) => {
                 Self::build_cpu(&builder)?;
             }
             _ => todo!(),
         }
 
-        let session = builder
-            .with_optimization_level(ort::GraphOptimizationLevel::Level3)?
-            .commit_from_file(&config.onnx_path)?;
+        let builder = builder.with_optimization_level(ort::GraphOptimizationLevel::Level3)?;
+        let builder = if config.ort_profiling {
+            let dir = std::path::PathBuf::from("runs").join("ort-profiling");
+            std::fs::create_dir_all(&dir)?;
+            let stem = std::path::Path::new(&onnx_path)
+                .file_stem()
+                .map_or("model".into(), |x| x.to_string_lossy());
+            builder.with_profiling(dir.join(stem.as_ref()).to_string_lossy())?
+        } else {
+            builder
+        };
+        let (session, outputs_attrs) = match &config.output_names {
+            None => (builder.commit_from_file(&onnx_path)?, outputs_attrs),
+            Some(names) => {
+                let session =
+                    builder.commit_from_memory(&Self::with_graph_outputs(&model_proto, names)?)?;
+                let outputs_attrs = Self::o_from_session(&session)?;
+                (session, outputs_attrs)
+            }
+        };
+
+        let cuda_mem = match device {
+            Device::Cuda(device_id) | Device::Trt(device_id) => CudaMemInfo::new(device_id),
+            _ => None,
+        };
 
         // summary
         println!(
@@ -162,11 +311,20 @@ impl OrtEngine {
             inputs_attrs,
             outputs_attrs,
             profile: config.profile,
+            profiling: config.ort_profiling,
             num_dry_run: config.num_dry_run,
             model_proto,
             params,
             wbmems,
             ts: Ts::default(),
+            mem: MemReport::default(),
+            cuda_mem,
+            adaptive_batch: config.adaptive_batch,
+            sub_batch: None,
+            save_preprocessed: config.save_preprocessed.as_ref().map(|x| x.into()),
+            n_preprocessed: 0,
+            hooks: config.hooks.clone(),
+            ort_log_level: config.ort_log_level,
         })
     }
 
@@ -225,7 +383,10 @@ impl OrtEngine {
             println!("\n🐢 Initial model serialization with TensorRT may require a wait...\n");
             Ok(())
         } else {
-            anyhow::bail!("{CROSS_MARK} TensorRT initialization failed")
+            anyhow::bail!(
+                "{CROSS_MARK} TensorRT initialization failed. Available: {:?}",
+                Device::available_providers()
+            )
         }
     }
 
@@ -234,16 +395,36 @@ impl OrtEngine {
         if ep.is_available()? && ep.register(builder).is_ok() {
             Ok(())
         } else {
-            anyhow::bail!("{CROSS_MARK} CUDA initialization failed")
+            anyhow::bail!(
+                "{CROSS_MARK} CUDA initialization failed. Available: {:?}",
+                Device::available_providers()
+            )
         }
     }
 
     fn build_coreml(builder: &SessionBuilder) -> Result<()> {
+        // all compute units (ANE, GPU, CPU) are allowed by default, subgraphs included
         let ep = ort::CoreMLExecutionProvider::default().with_subgraphs(); //.with_ane_only();
         if ep.is_available()? && ep.register(builder).is_ok() {
             Ok(())
         } else {
-            anyhow::bail!("{CROSS_MARK} CoreML initialization failed")
+            anyhow::bail!(
+                "{CROSS_MARK} CoreML initialization failed. Available: {:?}",
+                Device::available_providers()
+            )
+        }
+    }
+
+    #[cfg(feature = "directml")]
+    fn build_directml(builder: &SessionBuilder, device_id: usize) -> Result<()> {
+        let ep = ort::DirectMLExecutionProvider::default().with_device_id(device_id as i32);
+        if ep.is_available()? && ep.register(builder).is_ok() {
+            Ok(())
+        } else {
+            anyhow::bail!(
+                "{CROSS_MARK} DirectML initialization failed. Available: {:?}",
+                Device::available_providers()
+            )
         }
     }
 
@@ -252,7 +433,10 @@ impl OrtEngine {
         if ep.is_available()? && ep.register(builder).is_ok() {
             Ok(())
         } else {
-            anyhow::bail!("{CROSS_MARK} CPU initialization failed")
+            anyhow::bail!(
+                "{CROSS_MARK} CPU initialization failed. Available: {:?}",
+                Device::available_providers()
+            )
         }
     }
 
@@ -267,20 +451,243 @@ impl OrtEngine {
                 let x: Array<f32, IxDyn> = Array::ones(x).into_dyn();
                 xs.push(x);
             }
+            let save_preprocessed = self.save_preprocessed.take();
+            let hooks = std::mem::take(&mut self.hooks);
             for _ in 0..self.num_dry_run {
                 self.run(xs.as_ref())?;
             }
+            self.save_preprocessed = save_preprocessed;
+            self.hooks = hooks;
             self.ts.clear();
+            self.mem = MemReport::default();
             println!("{CHECK_MARK} Dryrun x{}", self.num_dry_run);
         }
         Ok(())
     }
 
+    /// Stop ORT profiling and move its Chrome trace JSON to `path`. Runs after this are not
+    /// profiled anymore.
+    pub fn save_profiling<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<()> {
+        if !self.profiling {
+            anyhow::bail!("No profiling to save, enable it with `Options::with_profiling(true)`");
+        }
+        let trace = self.session.end_profiling()?;
+        self.profiling = false;
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        if std::fs::rename(&trace, path).is_err() {
+            std::fs::copy(&trace, path)?;
+            std::fs::remove_file(&trace)?;
+        }
+        println!("{CHECK_MARK} ORT profiling saved to: {:?}", path);
+        Ok(())
+    }
+
+    /// Inference. Batches smaller than the model's minimum batch are padded up to it with
+    /// copies of their last item and the padding is sliced off the outputs again.
     pub fn run(&mut self, xs: &[Array<f32, IxDyn>]) -> Result<Vec<Array<f32, IxDyn>>> {
-        // inputs dtype alignment
+        let n = xs.first().map_or(0, |x| x.shape()[0]);
+        let min_batch = self
+            .inputs_minoptmax
+            .first()
+            .and_then(|x| x.first())
+            .map_or(0, |x| x.min.max(0) as usize);
+        if n > 0 && n < min_batch {
+            let xs = Self::pad_batch(xs, n, min_batch);
+            let ys = self.run_batch(&xs)?;
+            return Ok(Self::truncate_batch(ys, n, min_batch));
+        }
+        self.run_batch(xs)
+    }
+
+    /// Repeat the last item of every input batched along axis 0 until it has `target` items
+    fn pad_batch(xs: &[Array<f32, IxDyn>], n: usize, target: usize) -> Vec<Array<f32, IxDyn>> {
+        xs.iter()
+            .map(|x| {
+                if x.ndim() == 0 || x.shape()[0] != n {
+                    return x.to_owned();
+                }
+                let last = x.slice_axis(Axis(0), (n - 1..n).into());
+                let mut views = vec![x.view()];
+                views.extend(std::iter::repeat(last).take(target - n));
+                ndarray::concatenate(Axis(0), &views).unwrap_or_else(|_| x.to_owned())
+            })
+            .collect()
+    }
+
+    /// Drop the padded items from outputs batched along axis 0
+    fn truncate_batch(
+        ys: Vec<Array<f32, IxDyn>>,
+        n: usize,
+        target: usize,
+    ) -> Vec<Array<f32, IxDyn>> {
+        ys.into_iter()
+            .map(|y| {
+                if y.ndim() > 0 && y.shape()[0] == target {
+                    y.slice_axis(Axis(0), (0..n).into()).to_owned()
+                } else {
+                    y
+                }
+            })
+            .collect()
+    }
+
+    fn run_batch(&mut self, xs: &[Array<f32, IxDyn>]) -> Result<Vec<Array<f32, IxDyn>>> {
+        if self.save_preprocessed.is_some() {
+            self.save_preprocessed(xs)?;
+        }
+        if !self.adaptive_batch {
+            return self._run(xs);
+        }
+
+        // adaptive batch: split along axis 0, halving the sub-batch on allocation failures
+        let mut sub_batch = self.sub_batch;
+        let ys = Self::run_adaptive(xs, &mut sub_batch, |xs| self._run(xs));
+        self.sub_batch = sub_batch;
+        ys
+    }
+
+    fn run_adaptive<F>(
+        xs: &[Array<f32, IxDyn>],
+        sub_batch: &mut Option<usize>,
+        mut run: F,
+    ) -> Result<Vec<Array<f32, IxDyn>>>
+    where
+        F: FnMut(&[Array<f32, IxDyn>]) -> Result<Vec<Array<f32, IxDyn>>>,
+    {
+        let n = xs.first().map_or(0, |x| x.shape()[0]);
+        loop {
+            let sub_batch_ = sub_batch.unwrap_or(n).clamp(1, n.max(1));
+            match Self::run_chunked(xs, n, sub_batch_, &mut run) {
+                Ok(ys) => return Ok(ys),
+                Err(err) if sub_batch_ > 1 && Self::is_oom(&err) => {
+                    let sub_batch_ = sub_batch_ / 2;
+                    *sub_batch = Some(sub_batch_);
+                    println!(
+                        "{SAFE_CROSS_MARK} Allocation failed, retrying with sub-batch: {sub_batch_}"
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Write every image-like input exactly as the model receives it (padding included)
+    fn save_preprocessed(&mut self, xs: &[Array<f32, IxDyn>]) -> Result<()> {
+        let dir = match &self.save_preprocessed {
+            None => return Ok(()),
+            Some(dir) => dir.to_owned(),
+        };
+        std::fs::create_dir_all(&dir)?;
+        for (i, x) in xs.iter().enumerate() {
+            for (b, img) in ops::tensor_to_images(x).iter().enumerate() {
+                let p = dir.join(format!("{:06}-input{}-{}.png", self.n_preprocessed, i, b));
+                img.save(&p)?;
+            }
+        }
+        self.n_preprocessed += 1;
+        Ok(())
+    }
+
+    /// Run `sub_batch` items at a time. Only inputs batched along axis 0 are split, the
+    /// others are passed to every chunk. Outputs batched in every chunk are concatenated,
+    /// the others are taken from the first chunk.
+    fn run_chunked<F>(
+        xs: &[Array<f32, IxDyn>],
+        n: usize,
+        sub_batch: usize,
+        run: &mut F,
+    ) -> Result<Vec<Array<f32, IxDyn>>>
+    where
+        F: FnMut(&[Array<f32, IxDyn>]) -> Result<Vec<Array<f32, IxDyn>>>,
+    {
+        if sub_batch >= n {
+            return run(xs);
+        }
+        let mut yss: Vec<Vec<Array<f32, IxDyn>>> = Vec::new();
+        let mut lens = Vec::new();
+        for i in (0..n).step_by(sub_batch) {
+            let range = i..n.min(i + sub_batch);
+            lens.push(range.len());
+            let xs_ = xs
+                .iter()
+                .map(|x| {
+                    if x.ndim() == 0 || x.shape()[0] != n {
+                        return x.to_owned();
+                    }
+                    x.slice_axis(Axis(0), range.clone().into()).to_owned()
+                })
+                .collect::<Vec<_>>();
+            yss.push(run(&xs_)?);
+        }
+        let mut ys = Vec::new();
+        for k in 0..yss[0].len() {
+            let batched = yss
+                .iter()
+                .zip(lens.iter())
+                .all(|(x, &len)| x[k].ndim() > 0 && x[k].shape()[0] == len);
+            if batched {
+                let views = yss.iter().map(|x| x[k].view()).collect::<Vec<_>>();
+                ys.push(ndarray::concatenate(Axis(0), &views)?);
+            } else {
+                ys.push(yss[0][k].to_owned());
+            }
+        }
+        Ok(ys)
+    }
+
+    /// Allocation failures reported by ORT's CPU/CUDA allocators
+    fn is_oom(err: &anyhow::Error) -> bool {
+        let err = err.to_string().to_lowercase();
+        [
+            "failed to allocate memory",
+            "out of memory",
+            "bad_alloc",
+            "cudaerrormemoryallocation",
+        ]
+        .iter()
+        .any(|x| err.contains(x))
+    }
+
+    /// Gray <-> RGB conversion when an image input's channels differ from the model's fixed ones
+    fn align_channels(&self, xs: &[Array<f32, IxDyn>]) -> Vec<Option<Array<f32, IxDyn>>> {
+        xs.iter()
+            .zip(self.inputs_minoptmax.iter())
+            .enumerate()
+            .map(|(i, (x, ixx))| {
+                let c = ixx.get(1)?;
+                if x.ndim() != 4 || c.min != c.max || !(c.opt == 1 || c.opt == 3) {
+                    return None;
+                }
+                let x_ = ops::convert_channels(x, c.opt as usize)?;
+                log::debug!(
+                    "Input `{}`: converted {} channel(s) to {}",
+                    self.inputs_attrs.names[i],
+                    x.shape()[1],
+                    c.opt
+                );
+                Some(x_)
+            })
+            .collect()
+    }
+
+    fn _run(&mut self, xs: &[Array<f32, IxDyn>]) -> Result<Vec<Array<f32, IxDyn>>> {
+        // inputs channels & dtype alignment
+        let xs_aligned = self.align_channels(xs);
+        let xs = xs
+            .iter()
+            .zip(xs_aligned.iter())
+            .map(|(x, x_)| x_.as_ref().unwrap_or(x))
+            .collect::<Vec<_>>();
         let mut xs_ = Vec::new();
+        let mut nbytes_inputs = 0;
         let t_pre = std::time::Instant::now();
         for (idtype, x) in self.inputs_attrs.dtypes.iter().zip(xs.iter()) {
+            nbytes_inputs += x.len() * Self::nbytes_from_onnx_dtype(idtype);
             let x_ = match &idtype {
                 TensorElementType::Float32 => ort::Value::from_array(x.view())?.into_dyn(),
                 TensorElementType::Float16 => {
@@ -298,15 +705,33 @@ impl OrtEngine {
         }
         let t_pre = t_pre.elapsed();
         self.ts.add_or_push(0, t_pre);
+        let ishapes = if self.hooks.is_empty() {
+            vec![]
+        } else {
+            xs.iter().map(|x| x.shape().to_vec()).collect::<Vec<_>>()
+        };
+        if let Some(f) = &self.hooks.on_preprocess {
+            f(t_pre, &ishapes);
+        }
 
         // inference
+        let vram_before = self.cuda_mem.as_ref().and_then(|x| x.used());
         let t_run = std::time::Instant::now();
-        let outputs = self.session.run(&xs_[..])?;
+        let session = &self.session;
+        let outputs = ort_logger::scope(self.ort_log_level, || session.run(&xs_[..]))?;
         let t_run = t_run.elapsed();
+        let vram_delta = match (vram_before, self.cuda_mem.as_ref().and_then(|x| x.used())) {
+            (Some(before), Some(after)) => Some(after as i64 - before as i64),
+            _ => None,
+        };
         self.ts.add_or_push(1, t_run);
+        if let Some(f) = &self.hooks.on_inference {
+            f(t_run, &ishapes);
+        }
 
         // oputput
         let mut ys = Vec::new();
+        let mut nbytes_outputs = 0;
         let t_post = std::time::Instant::now();
         for (dtype, name) in self
             .outputs_attrs
@@ -330,10 +755,18 @@ impl OrtEngine {
                     .into_owned(),
                 _ => todo!(),
             };
+            nbytes_outputs += y_.len() * Self::nbytes_from_onnx_dtype(dtype);
             ys.push(y_);
         }
         let t_post = t_post.elapsed();
         self.ts.add_or_push(2, t_post);
+        if let Some(f) = &self.hooks.on_postprocess {
+            f(
+                t_post,
+                &ys.iter().map(|x| x.shape().to_vec()).collect::<Vec<_>>(),
+            );
+        }
+        self.mem.update(nbytes_inputs, nbytes_outputs, vram_delta);
 
         if self.profile {
             let len = 10usize;
@@ -349,6 +782,12 @@ impl OrtEngine {
                 t_post,
                 self.ts.avgi(2),
             );
+            println!(
+                "[Memory] inputs: {} | outputs: {} | vram delta: {}",
+                human_bytes(nbytes_inputs as f64),
+                human_bytes(nbytes_outputs as f64),
+                vram_delta.map_or("N/A".to_string(), |x| format!("{x} B")),
+            );
         }
         Ok(ys)
     }
@@ -445,7 +884,39 @@ impl OrtEngine {
         })
     }
 
-    #[allow(dead_code)]
+    /// Re-export the graph with `names` as its outputs, so intermediate tensors can be fetched
+    fn with_graph_outputs(model_proto: &onnx::ModelProto, names: &[String]) -> Result<Vec<u8>> {
+        let mut model_proto = model_proto.clone();
+        let graph = match model_proto.graph.as_mut() {
+            Some(graph) => graph,
+            None => anyhow::bail!("No graph found in this proto"),
+        };
+        let produced: HashSet<&str> = graph
+            .node
+            .iter()
+            .flat_map(|x| x.output.iter().map(|x| x.as_str()))
+            .collect();
+        let mut outputs = Vec::new();
+        for name in names.iter() {
+            if !produced.contains(name.as_str()) {
+                anyhow::bail!("{CROSS_MARK} No tensor named {name:?} in this graph");
+            }
+            let output = graph
+                .output
+                .iter()
+                .chain(graph.value_info.iter())
+                .find(|x| &x.name == name)
+                .cloned()
+                .unwrap_or(onnx::ValueInfoProto {
+                    name: name.to_owned(),
+                    ..Default::default()
+                });
+            outputs.push(output);
+        }
+        graph.output = outputs;
+        Ok(model_proto.encode_to_vec())
+    }
+
     fn o_from_session(session: &ort::Session) -> Result<OrtTensorAttr> {
         let mut dimss = Vec::new();
         let mut dtypes = Vec::new();
@@ -630,4 +1101,162 @@ impl OrtEngine {
     pub fn memory_weights(&self) -> usize {
         self.wbmems
     }
+
+    pub fn mem_report(&self) -> &MemReport {
+        &self.mem
+    }
+
+    /// Scale and zero-point of every Q/DQ node the model declares, empty for float models
+    pub fn quantization_info(&self) -> Vec<QuantInfo> {
+        let graph = match &self.model_proto.graph {
+            Some(graph) => graph,
+            None => return vec![],
+        };
+        let mut constants: std::collections::HashMap<&str, &onnx::TensorProto> = graph
+            .initializer
+            .iter()
+            .map(|x| (x.name.as_str(), x))
+            .collect();
+        for node in graph.node.iter().filter(|x| x.op_type == "Constant") {
+            if let (Some(name), Some(t)) = (
+                node.output.first(),
+                node.attribute.iter().find_map(|x| x.t.as_ref()),
+            ) {
+                constants.insert(name.as_str(), t);
+            }
+        }
+
+        graph
+            .node
+            .iter()
+            .filter(|x| x.op_type == "QuantizeLinear" || x.op_type == "DequantizeLinear")
+            .map(|node| {
+                let input = |i: usize| node.input.get(i).and_then(|x| constants.get(x.as_str()));
+                QuantInfo {
+                    tensor: node.input.first().cloned().unwrap_or_default(),
+                    op_type: node.op_type.to_owned(),
+                    axis: node
+                        .attribute
+                        .iter()
+                        .find(|x| x.name == "axis")
+                        .map(|x| x.i),
+                    scale: input(1).map(|x| Self::tensor_to_f32(x)).unwrap_or_default(),
+                    zero_point: input(2).map(|x| Self::tensor_to_i64(x)).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    fn tensor_to_f32(x: &onnx::TensorProto) -> Vec<f32> {
+        if !x.float_data.is_empty() {
+            return x.float_data.to_owned();
+        }
+        match x.data_type {
+            1 => x
+                .raw_data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            10 => x
+                .raw_data
+                .chunks_exact(2)
+                .map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn tensor_to_i64(x: &onnx::TensorProto) -> Vec<i64> {
+        if !x.int32_data.is_empty() {
+            return x.int32_data.iter().map(|&v| v as i64).collect();
+        }
+        match x.data_type {
+            2 => x.raw_data.iter().map(|&v| v as i64).collect(),
+            3 => x.raw_data.iter().map(|&v| v as i8 as i64).collect(),
+            4 => x
+                .raw_data
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as i64)
+                .collect(),
+            5 => x
+                .raw_data
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as i64)
+                .collect(),
+            6 => x
+                .raw_data
+                .chunks_exact(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as i64)
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl Drop for OrtEngine {
+    fn drop(&mut self) {
+        if self.profiling {
+            match self.session.end_profiling() {
+                Ok(trace) => println!("{CHECK_MARK} ORT profiling saved to: {trace:?}"),
+                Err(err) => println!("{CROSS_MARK} Failed to save ORT profiling: {err}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_engine {
+    use super::OrtEngine;
+    use ndarray::{Array, IxDyn};
+
+    #[test]
+    fn pad_batch() {
+        // 3 images against a min batch of 4, plus an input without batch axis
+        let images = Array::from_shape_fn(IxDyn(&[3, 3, 2, 2]), |x| x[0] as f32);
+        let scale = Array::from_elem(IxDyn(&[2]), 1f32);
+        let xs = OrtEngine::pad_batch(&[images, scale.clone()], 3, 4);
+        assert_eq!(xs[0].shape(), [4, 3, 2, 2]);
+        assert_eq!(xs[0][[3, 0, 0, 0]], 2.);
+        assert_eq!(xs[1], scale);
+
+        // outputs: (batch, anchors, 6) and a batch independent one
+        let ys = vec![
+            Array::zeros(IxDyn(&[4, 100, 6])),
+            Array::zeros(IxDyn(&[1, 8])),
+        ];
+        let ys = OrtEngine::truncate_batch(ys, 3, 4);
+        assert_eq!(ys[0].shape(), [3, 100, 6]);
+        assert_eq!(ys[1].shape(), [1, 8]);
+    }
+
+    #[test]
+    fn run_adaptive() {
+        // 5 images plus an input without batch axis, OOM above 2 items per run
+        let images = Array::from_shape_fn(IxDyn(&[5, 3]), |x| x[0] as f32);
+        let scale = Array::from_elem(IxDyn(&[2]), 1f32);
+        let mut calls = Vec::new();
+        let mut sub_batch = None;
+        let ys = OrtEngine::run_adaptive(&[images, scale.clone()], &mut sub_batch, |xs| {
+            calls.push(xs[0].shape()[0]);
+            assert_eq!(xs[1], scale);
+            if xs[0].shape()[0] > 2 {
+                anyhow::bail!("Failed to allocate memory for requested buffer of size 1024");
+            }
+            Ok(vec![xs[0].to_owned(), Array::zeros(IxDyn(&[1, 8]))])
+        })
+        .unwrap();
+        assert_eq!(sub_batch, Some(2));
+        assert_eq!(calls, [5, 2, 2, 1]);
+        assert_eq!(ys[0], Array::from_shape_fn(IxDyn(&[5, 3]), |x| x[0] as f32));
+        assert_eq!(ys[1].shape(), [1, 8]);
+
+        // other errors are not retried
+        let mut sub_batch = None;
+        let xs = [Array::zeros(IxDyn(&[4, 3]))];
+        let err = OrtEngine::run_adaptive(&xs, &mut sub_batch, |_| {
+            anyhow::bail!("Invalid allocator configuration")
+        });
+        assert!(err.is_err());
+        assert_eq!(sub_batch, None);
+    }
 }
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Callback invoked with a stage's duration and the shapes of the tensors it handled
+pub type StageFn = Arc<dyn Fn(Duration, &[Vec<usize>]) + Send + Sync>;
+
+/// Optional per-stage callbacks of `OrtEngine::run`, e.g. for pushing metrics.
+///
+/// - `on_preprocess`: inputs alignment (channels & dtype), with input shapes
+/// - `on_inference`: session run, with input shapes
+/// - `on_postprocess`: outputs extraction to f32, with output shapes
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub on_preprocess: Option<StageFn>,
+    pub on_inference: Option<StageFn>,
+    pub on_postprocess: Option<StageFn>,
+}
+
+impl std::fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_preprocess", &self.on_preprocess.is_some())
+            .field("on_inference", &self.on_inference.is_some())
+            .field("on_postprocess", &self.on_postprocess.is_some())
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_preprocess.is_none() && self.on_inference.is_none() && self.on_postprocess.is_none()
+    }
+}
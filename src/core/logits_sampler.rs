@@ -1,11 +1,19 @@
 use anyhow::Result;
 use rand::distributions::{Distribution, WeightedIndex};
+use rand::SeedableRng;
+use std::cell::RefCell;
 
 /// Logits Sampler
+///
+/// Decoding applies temperature scaling, then top-k truncation, then top-p (nucleus)
+/// filtering, then draws from the renormalized distribution. A temperature of 0 or a
+/// top-k of 1 is exactly argmax.
 #[derive(Debug)]
 pub struct LogitsSampler {
     temperature: f32,
     p: f32,
+    top_k: Option<usize>,
+    rng: Option<RefCell<rand::rngs::StdRng>>, // falls back to the crate-wide RNG
 }
 
 impl Default for LogitsSampler {
@@ -13,6 +21,8 @@ impl Default for LogitsSampler {
         Self {
             temperature: 1.0,
             p: 0.0,
+            top_k: None,
+            rng: None,
         }
     }
 }
@@ -27,69 +37,133 @@ impl LogitsSampler {
         self
     }
 
+    /// Keep the smallest set of tokens whose probabilities add up to `p`
+    pub fn with_top_p(self, p: f32) -> Self {
+        self.with_topp(p)
+    }
+
+    /// Keep the `k` most likely tokens only
+    pub fn with_top_k(mut self, k: usize) -> Self {
+        self.top_k = Some(k.max(1));
+        self
+    }
+
+    /// 0 is greedy decoding
     pub fn with_temperature(mut self, temperature: f32) -> Self {
-        self.temperature = temperature.max(1e-7);
+        self.temperature = temperature.max(0.0);
         self
     }
 
+    /// Draw from an own seeded RNG, for deterministic decoding
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Some(RefCell::new(rand::rngs::StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Greedy unless top-p or top-k is set
     pub fn decode(&mut self, logits: &[f32]) -> Result<u32> {
-        if self.p == 0.0 {
+        if self.p == 0.0 && self.top_k.is_none() {
             self.search_by_argmax(logits)
         } else {
-            self.sample_by_topp(logits)
+            Ok(self.sample(logits) as u32)
         }
     }
 
-    fn search_by_argmax(&mut self, logits: &[f32]) -> Result<u32> {
-        // no need to do softmax
-        let (token_id, _) = logits
-            .iter()
-            .enumerate()
-            .reduce(|max, x| if x.1 > max.1 { x } else { max })
-            .unwrap();
-        Ok(token_id as u32)
-    }
+    /// Token id drawn after temperature, top-k and top-p
+    pub fn sample(&self, logits: &[f32]) -> usize {
+        if logits.is_empty() {
+            return 0;
+        }
+        if self.temperature == 0.0 || self.top_k == Some(1) {
+            return Self::argmax(logits);
+        }
 
-    fn sample_by_topp(&mut self, logits: &[f32]) -> Result<u32> {
-        let logits = self.softmax(logits);
-        let mut logits: Vec<(usize, f32)> = logits
+        // top-k on the scaled logits
+        let mut candidates: Vec<(usize, f32)> = logits
             .iter()
+            .map(|&x| x / self.temperature)
             .enumerate()
-            .map(|(i, &prob)| (i, prob))
             .collect();
-        logits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-
-        // candidates
-        let mut candidates: Vec<(usize, f32)> = Vec::new();
-        let mut acc_prob: f32 = 0.0;
-        for (idx, prob) in logits.iter() {
-            acc_prob += prob;
-            if acc_prob >= self.p {
-                if candidates.is_empty() {
-                    candidates.push((*idx, acc_prob));
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if let Some(k) = self.top_k {
+            candidates.truncate(k);
+        }
+
+        // top-p on the renormalized probabilities
+        let probs = self.softmax(&candidates.iter().map(|x| x.1).collect::<Vec<_>>());
+        let mut n = probs.len();
+        if self.p > 0.0 && self.p < 1.0 {
+            let mut acc_prob = 0.0;
+            for (i, prob) in probs.iter().enumerate() {
+                acc_prob += prob;
+                if acc_prob >= self.p {
+                    n = i + 1;
+                    break;
                 }
-                break;
             }
-            candidates.push((*idx, acc_prob));
         }
 
         // sample
-        let choices: Vec<usize> = candidates.iter().map(|&(idx, _)| idx).collect();
-        let probs: Vec<f32> = candidates.iter().map(|&(_, prob)| prob).collect();
-        let dist = WeightedIndex::new(probs)?;
-        let mut rng = rand::thread_rng();
-        let token_id = choices[dist.sample(&mut rng)];
-        Ok(token_id as u32)
+        match WeightedIndex::new(&probs[..n]) {
+            Ok(dist) => {
+                let i = match &self.rng {
+                    Some(rng) => dist.sample(&mut *rng.borrow_mut()),
+                    None => crate::with_rng(|rng| dist.sample(rng)),
+                };
+                candidates[i].0
+            }
+            Err(_) => candidates[0].0,
+        }
     }
 
-    fn softmax(&self, logits: &[f32]) -> Vec<f32> {
-        let logits_t = logits
+    fn search_by_argmax(&mut self, logits: &[f32]) -> Result<u32> {
+        Ok(Self::argmax(logits) as u32)
+    }
+
+    fn argmax(logits: &[f32]) -> usize {
+        // no need to do softmax
+        let (token_id, _) = logits
             .iter()
-            .map(|&x| x / self.temperature)
-            .collect::<Vec<f32>>();
-        let max_logit = logits_t.iter().fold(f32::MIN, |a, &b| a.max(b));
-        let exps: Vec<f32> = logits_t.iter().map(|&x| (x - max_logit).exp()).collect();
+            .enumerate()
+            .reduce(|max, x| if x.1 > max.1 { x } else { max })
+            .unwrap();
+        token_id
+    }
+
+    fn softmax(&self, logits: &[f32]) -> Vec<f32> {
+        let max_logit = logits.iter().fold(f32::MIN, |a, &b| a.max(b));
+        let exps: Vec<f32> = logits.iter().map(|&x| (x - max_logit).exp()).collect();
         let sum_exps: f32 = exps.iter().sum();
         exps.iter().map(|&exp| exp / sum_exps).collect()
     }
 }
+
+#[cfg(test)]
+mod tests_logits_sampler {
+    use super::LogitsSampler;
+
+    #[test]
+    fn peaked_low_temperature() {
+        let logits = [0.1, 5.0, 0.3, -1.0, 0.2];
+        let sampler = LogitsSampler::new()
+            .with_temperature(0.05)
+            .with_top_p(0.9)
+            .with_seed(0);
+        for _ in 0..100 {
+            assert_eq!(sampler.sample(&logits), 1);
+        }
+        assert_eq!(LogitsSampler::new().with_temperature(0.).sample(&logits), 1);
+        assert_eq!(LogitsSampler::new().with_top_k(1).sample(&logits), 1);
+    }
+
+    #[test]
+    fn seeded() {
+        let logits = [1.0, 1.1, 0.9, 1.05];
+        let draw = |seed| {
+            let sampler = LogitsSampler::new().with_top_k(3).with_seed(seed);
+            (0..20).map(|_| sampler.sample(&logits)).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(7), draw(7));
+        assert!(draw(7).iter().all(|&x| x != 2)); // outside the top 3
+    }
+}
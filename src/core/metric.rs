@@ -1,6 +1,354 @@
-#[derive(Debug)]
+use anyhow::{bail, Result};
+use ndarray::{Array, Ix2};
+
+use crate::{Bbox, Ys};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Metric {
     IP,
     Cos,
     L2,
+    L1,
+    Mahalanobis, // needs the inverse covariance matrix
+}
+
+impl Metric {
+    /// Inner product and cosine similarity for `IP` and `Cos` (higher is closer), distance
+    /// for the others (lower is closer). `inv_cov` is the `d` x `d` inverse covariance of
+    /// `Mahalanobis` and ignored otherwise.
+    pub fn score(&self, a: &[f32], b: &[f32], inv_cov: Option<&Array<f32, Ix2>>) -> Result<f32> {
+        if a.len() != b.len() {
+            bail!("Dimension mismatch: {} vs {}", a.len(), b.len());
+        }
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+        Ok(match self {
+            Self::IP => dot(a, b),
+            Self::Cos => {
+                let denom = dot(a, a).sqrt() * dot(b, b).sqrt();
+                if denom > 0. {
+                    dot(a, b) / denom
+                } else {
+                    0.
+                }
+            }
+            Self::L2 => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a - b).powi(2))
+                .sum::<f32>()
+                .sqrt(),
+            Self::L1 => a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum(),
+            Self::Mahalanobis => {
+                let Some(inv_cov) = inv_cov else {
+                    bail!("Mahalanobis distance needs an inverse covariance matrix");
+                };
+                if inv_cov.dim() != (a.len(), a.len()) {
+                    bail!(
+                        "Inverse covariance is {:?}, expected ({}, {})",
+                        inv_cov.dim(),
+                        a.len(),
+                        a.len()
+                    );
+                }
+                let d = Array::from_iter(a.iter().zip(b).map(|(a, b)| a - b));
+                d.dot(&inv_cov.dot(&d)).max(0.).sqrt()
+            }
+        })
+    }
+}
+
+/// COCO-style bbox evaluation of predicted `Ys` against ground truth `Ys` (same image order):
+/// AP over 101 recall points per class and IoU threshold, precision and recall at a
+/// confidence threshold, and a confusion matrix.
+///
+/// ```ignore
+/// let report = Evaluator::default().evaluate(&preds, &gts)?;
+/// println!("mAP50: {:.3}, mAP50-95: {:.3}", report.map50(), report.map());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    iou_thresholds: Vec<f32>,
+    conf_threshold: f32,
+    confusion_iou: f32,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            iou_thresholds: (0..10).map(|i| 0.5 + 0.05 * i as f32).collect(),
+            conf_threshold: 0.25,
+            confusion_iou: 0.45,
+        }
+    }
+}
+
+impl Evaluator {
+    /// IoU thresholds of the AP, the first one is used for precision, recall and PR curves.
+    /// 0.5:0.95:0.05 by default.
+    pub fn with_iou_thresholds(mut self, x: &[f32]) -> Self {
+        if !x.is_empty() {
+            self.iou_thresholds = x.to_vec();
+        }
+        self
+    }
+
+    /// Confidence threshold of precision, recall and the confusion matrix, 0.25 by default
+    pub fn with_conf_threshold(mut self, x: f32) -> Self {
+        self.conf_threshold = x;
+        self
+    }
+
+    /// IoU to pair boxes of any class in the confusion matrix, 0.45 by default
+    pub fn with_confusion_iou(mut self, x: f32) -> Self {
+        self.confusion_iou = x;
+        self
+    }
+
+    pub fn evaluate(&self, preds: &Ys, gts: &Ys) -> Result<EvalReport> {
+        if preds.len() != gts.len() {
+            bail!(
+                "Expect as many predictions as ground truths, got {} and {}",
+                preds.len(),
+                gts.len()
+            );
+        }
+        let bboxes = |y: &crate::Y| -> Vec<Bbox> {
+            y.bboxes().map_or(vec![], |x| {
+                x.iter().filter(|b| b.id() >= 0).cloned().collect()
+            })
+        };
+        let preds: Vec<Vec<Bbox>> = preds.iter().map(bboxes).collect();
+        let gts: Vec<Vec<Bbox>> = gts.iter().map(bboxes).collect();
+
+        // classes with ground truth, as COCO ignores the others
+        let mut classes: Vec<usize> = gts.iter().flatten().map(|x| x.id() as usize).collect();
+        classes.sort_unstable();
+        classes.dedup();
+
+        let mut ap = Vec::with_capacity(classes.len());
+        let (mut precision, mut recall, mut pr_curves) = (Vec::new(), Vec::new(), Vec::new());
+        for &class in classes.iter() {
+            let mut ap_class = Vec::with_capacity(self.iou_thresholds.len());
+            for (i, &iou) in self.iou_thresholds.iter().enumerate() {
+                let (matches, num_gts) = Self::match_class(&preds, &gts, class as isize, iou);
+                let curve = Self::pr_curve(&matches, num_gts);
+                ap_class.push(curve.iter().sum::<f32>() / curve.len() as f32);
+                if i == 0 {
+                    let kept = matches.iter().filter(|x| x.0 >= self.conf_threshold);
+                    let (n, tp) = kept.fold((0, 0), |(n, tp), x| (n + 1, tp + x.1 as usize));
+                    precision.push(if n == 0 { 0. } else { tp as f32 / n as f32 });
+                    recall.push(tp as f32 / num_gts as f32);
+                    pr_curves.push(curve);
+                }
+            }
+            ap.push(ap_class);
+        }
+
+        Ok(EvalReport {
+            classes,
+            iou_thresholds: self.iou_thresholds.to_owned(),
+            ap,
+            precision,
+            recall,
+            pr_curves,
+            confusion: self.confusion(&preds, &gts),
+        })
+    }
+
+    /// `(confidence, is true positive)` of the predictions of `class`, greedily matched per
+    /// image by descending confidence, and the number of ground truths
+    fn match_class(
+        preds: &[Vec<Bbox>],
+        gts: &[Vec<Bbox>],
+        class: isize,
+        iou_threshold: f32,
+    ) -> (Vec<(f32, bool)>, usize) {
+        let (mut matches, mut num_gts) = (Vec::new(), 0);
+        for (preds, gts) in preds.iter().zip(gts.iter()) {
+            let gts: Vec<&Bbox> = gts.iter().filter(|x| x.id() == class).collect();
+            num_gts += gts.len();
+            let mut preds: Vec<&Bbox> = preds.iter().filter(|x| x.id() == class).collect();
+            preds.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+            let mut used = vec![false; gts.len()];
+            for pred in preds {
+                let best = gts
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !used[*i])
+                    .map(|(i, x)| (i, pred.iou(x)))
+                    .filter(|(_, iou)| *iou >= iou_threshold)
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, _)) = best {
+                    used[i] = true;
+                }
+                matches.push((pred.confidence(), best.is_some()));
+            }
+        }
+        (matches, num_gts)
+    }
+
+    /// Interpolated precision at recall 0, 0.01, ..., 1
+    fn pr_curve(matches: &[(f32, bool)], num_gts: usize) -> Vec<f32> {
+        let mut matches = matches.to_vec();
+        matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let (mut tp, mut rs, mut ps) = (0, Vec::new(), Vec::new());
+        for (i, (_, m)) in matches.iter().enumerate() {
+            tp += *m as usize;
+            rs.push(tp as f32 / num_gts.max(1) as f32);
+            ps.push(tp as f32 / (i + 1) as f32);
+        }
+        for i in (0..ps.len().saturating_sub(1)).rev() {
+            ps[i] = ps[i].max(ps[i + 1]);
+        }
+        (0..=100)
+            .map(|r| {
+                let r = r as f32 / 100.;
+                rs.iter().position(|x| *x >= r).map_or(0., |i| ps[i])
+            })
+            .collect()
+    }
+
+    /// `(nc + 1) x (nc + 1)` counts indexed by `[predicted, true]` class, background last
+    fn confusion(&self, preds: &[Vec<Bbox>], gts: &[Vec<Bbox>]) -> Array<usize, Ix2> {
+        let nc = preds
+            .iter()
+            .chain(gts.iter())
+            .flatten()
+            .map(|x| x.id() as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut m = Array::zeros((nc + 1, nc + 1));
+        for (preds, gts) in preds.iter().zip(gts.iter()) {
+            let preds: Vec<&Bbox> = preds
+                .iter()
+                .filter(|x| x.confidence() >= self.conf_threshold)
+                .collect();
+            let mut pairs: Vec<(usize, usize, f32)> = preds
+                .iter()
+                .enumerate()
+                .flat_map(|(i, p)| gts.iter().enumerate().map(move |(j, g)| (i, j, p.iou(g))))
+                .filter(|x| x.2 > self.confusion_iou)
+                .collect();
+            pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+            let (mut used_p, mut used_g) = (vec![false; preds.len()], vec![false; gts.len()]);
+            for (i, j, _) in pairs {
+                if !used_p[i] && !used_g[j] {
+                    (used_p[i], used_g[j]) = (true, true);
+                    m[[preds[i].id() as usize, gts[j].id() as usize]] += 1;
+                }
+            }
+            for p in preds.iter().zip(used_p).filter(|x| !x.1).map(|x| x.0) {
+                m[[p.id() as usize, nc]] += 1;
+            }
+            for g in gts.iter().zip(used_g).filter(|x| !x.1).map(|x| x.0) {
+                m[[nc, g.id() as usize]] += 1;
+            }
+        }
+        m
+    }
+}
+
+/// Results of [`Evaluator::evaluate`], per class rows follow `classes`
+#[derive(Debug, Clone)]
+pub struct EvalReport {
+    pub classes: Vec<usize>,
+    pub iou_thresholds: Vec<f32>,
+    pub ap: Vec<Vec<f32>>,        // class x IoU threshold
+    pub precision: Vec<f32>,      // at the confidence threshold and the first IoU
+    pub recall: Vec<f32>,         // at the confidence threshold and the first IoU
+    pub pr_curves: Vec<Vec<f32>>, // precision at 101 recall points, first IoU
+    pub confusion: Array<usize, Ix2>,
+}
+
+impl EvalReport {
+    /// mAP at the first IoU threshold (0.5 by default)
+    pub fn map50(&self) -> f32 {
+        Self::mean(self.ap.iter().map(|x| x[0]))
+    }
+
+    /// mAP over all IoU thresholds (0.5:0.95 by default)
+    pub fn map(&self) -> f32 {
+        Self::mean(self.ap.iter().map(|x| Self::mean(x.iter().copied())))
+    }
+
+    /// AP of `class` averaged over IoU thresholds
+    pub fn ap_class(&self, class: usize) -> Option<f32> {
+        let i = self.classes.iter().position(|x| *x == class)?;
+        Some(Self::mean(self.ap[i].iter().copied()))
+    }
+
+    fn mean(xs: impl Iterator<Item = f32>) -> f32 {
+        let (n, sum) = xs.fold((0, 0.), |(n, sum), x| (n + 1, sum + x));
+        if n == 0 {
+            0.
+        } else {
+            sum / n as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_metric {
+    use super::{Evaluator, Metric};
+    use crate::{Bbox, Ys, Y};
+    use ndarray::Array;
+
+    #[test]
+    fn distances() {
+        let (a, b) = ([1., -2., 3.], [4., 2., 3.5]);
+        assert_eq!(Metric::L1.score(&a, &b, None).unwrap(), 7.5);
+        assert_eq!(Metric::L1.score(&a, &a, None).unwrap(), 0.);
+
+        // identity / 4 covariance inverse: half the Euclidean distance
+        let l2 = Metric::L2.score(&a, &b, None).unwrap();
+        let inv_cov = Array::eye(3) / 4.;
+        let d = Metric::Mahalanobis.score(&a, &b, Some(&inv_cov)).unwrap();
+        assert!((d - l2 / 2.).abs() < 1e-6);
+
+        assert!(Metric::Mahalanobis.score(&a, &b, None).is_err());
+        assert!(Metric::Mahalanobis
+            .score(&a, &b, Some(&Array::eye(2)))
+            .is_err());
+        assert!(Metric::L1.score(&a, &b[..2], None).is_err());
+    }
+
+    #[test]
+    fn evaluate() {
+        let gts = Ys::from(vec![
+            Y::default().with_bboxes(&[
+                Bbox::from((0., 0., 10., 10., 0, 1.)),
+                Bbox::from((20., 20., 10., 10., 0, 1.)),
+            ]),
+            Y::default().with_bboxes(&[Bbox::from((0., 0., 10., 10., 1, 1.))]),
+        ]);
+        let preds = Ys::from(vec![
+            Y::default().with_bboxes(&[
+                Bbox::from((0., 0., 10., 10., 0, 0.9)),
+                Bbox::from((50., 50., 10., 10., 0, 0.8)), // fp
+                Bbox::from((20., 20., 10., 10., 0, 0.7)),
+            ]),
+            // IoU 0.83, a tp up to the 0.8 threshold
+            Y::default().with_bboxes(&[Bbox::from((0., 0., 10., 12., 1, 0.6))]),
+        ]);
+        let report = Evaluator::default().evaluate(&preds, &gts).unwrap();
+        assert_eq!(report.classes, [0, 1]);
+
+        // recall 0.5 at precision 1, then 1 at 2/3
+        let ap0 = (51. + 50. * 2. / 3.) / 101.;
+        assert!((report.ap_class(0).unwrap() - ap0).abs() < 1e-5);
+        assert!((report.ap_class(1).unwrap() - 0.7).abs() < 1e-5);
+        assert!((report.map50() - (ap0 + 1.) / 2.).abs() < 1e-5);
+        assert!((report.map() - (ap0 + 0.7) / 2.).abs() < 1e-5);
+        assert!((report.precision[0] - 2. / 3.).abs() < 1e-6);
+        assert_eq!(report.recall, [1., 1.]);
+        assert_eq!(report.pr_curves[0][50], 1.);
+
+        assert_eq!(
+            report.confusion,
+            Array::from_shape_vec((3, 3), vec![2, 0, 1, 0, 1, 0, 0, 0, 0]).unwrap()
+        );
+        assert!(Evaluator::default()
+            .evaluate(&preds, &Ys::default())
+            .is_err());
+    }
 }
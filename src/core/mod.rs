@@ -3,23 +3,52 @@ mod dataloader;
 mod device;
 mod dynconf;
 mod engine;
+mod hooks;
 mod logits_sampler;
 mod metric;
 mod min_opt_max;
+mod motion_gate;
 pub mod onnx;
 pub mod ops;
 mod options;
+mod ort_logger;
+mod pipeline;
+mod pool;
+mod processor;
+mod quant;
+mod sink;
+mod sliced;
+#[cfg(feature = "async")]
+mod stream;
 mod tokenizer_stream;
+mod tracker;
 mod ts;
+#[cfg(feature = "video")]
+mod video;
+mod zone_counter;
 
 pub use annotator::Annotator;
-pub use dataloader::DataLoader;
+pub use dataloader::{DataLoader, Prefetch};
 pub use device::Device;
 pub use dynconf::DynConf;
-pub use engine::OrtEngine;
+pub use engine::{MemReport, OrtEngine, QuantInfo};
+pub use hooks::{Hooks, StageFn};
 pub use logits_sampler::LogitsSampler;
-pub use metric::Metric;
+pub use metric::{EvalReport, Evaluator, Metric};
 pub use min_opt_max::MinOptMax;
+pub use motion_gate::MotionGate;
 pub use options::Options;
+pub use pipeline::quick_run;
+pub use pool::{EnginePool, Pending};
+pub use processor::{ChannelOrder, Processor, ResizeInfo, ResizeMode};
+pub use quant::QuantMode;
+pub use sink::{run_with_sink, CollectSink, FileSink, JsonlSink, OutputSink, RunStats};
+pub use sliced::SlicedRunner;
+#[cfg(feature = "async")]
+pub use stream::{BatchStream, Model, NextBatch, YsStream};
 pub use tokenizer_stream::TokenizerStream;
-pub use ts::Ts;
+pub use tracker::{ByteTrack, Track, TrackState};
+pub use ts::{TimeUnit, Ts, TS_MAX_SAMPLES};
+#[cfg(feature = "video")]
+pub use video::{is_stream, VideoInfo, VideoLoader, VideoWriter};
+pub use zone_counter::{Anchor, ZoneCounter, ZoneEvent, ZoneEventKind};
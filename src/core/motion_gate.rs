@@ -0,0 +1,143 @@
+use image::{DynamicImage, GrayImage};
+
+use crate::{ops, Y};
+
+/// Skip inference on static frames: consecutive frames are downscaled, converted to gray
+/// and diffed, and the model only needs to run when enough pixels changed.
+#[derive(Debug, Clone)]
+pub struct MotionGate {
+    size: u32,
+    pixel_threshold: u8,
+    threshold: f32,
+    last_frame: Option<GrayImage>,
+    last_y: Option<Y>,
+}
+
+impl Default for MotionGate {
+    fn default() -> Self {
+        Self {
+            size: 64,
+            pixel_threshold: 25,
+            threshold: 0.01,
+            last_frame: None,
+            last_y: None,
+        }
+    }
+}
+
+impl MotionGate {
+    /// Side of the square thumbnail frames are compared at
+    pub fn with_size(mut self, x: u32) -> Self {
+        self.size = x.max(1);
+        self
+    }
+
+    /// Minimum gray-level change for a pixel to count as moving
+    pub fn with_pixel_threshold(mut self, x: u8) -> Self {
+        self.pixel_threshold = x;
+        self
+    }
+
+    /// Minimum ratio of moving pixels to run the model
+    pub fn with_threshold(mut self, x: f32) -> Self {
+        self.threshold = x;
+        self
+    }
+
+    /// Check `frame` against the last frame the model ran on, so slow motion adds up
+    /// across skipped frames. Always true for the first frame or when no result has
+    /// been cached yet.
+    pub fn should_run(&mut self, frame: &DynamicImage) -> bool {
+        let frame = ops::gray_thumbnail(frame, self.size);
+        let run = self.last_y.is_none()
+            || match &self.last_frame {
+                None => true,
+                Some(last) => {
+                    ops::motion_ratio(last, &frame, self.pixel_threshold) >= self.threshold
+                }
+            };
+        if run {
+            self.last_frame = Some(frame);
+        }
+        run
+    }
+
+    /// Cache the result to reuse on static frames
+    pub fn update(&mut self, y: Y) {
+        self.last_y = Some(y);
+    }
+
+    pub fn last(&self) -> Option<&Y> {
+        self.last_y.as_ref()
+    }
+
+    /// Run `f` only if `frame` moved, otherwise return the cached result
+    pub fn run_or_reuse<F>(&mut self, frame: &DynamicImage, f: F) -> anyhow::Result<Y>
+    where
+        F: FnOnce() -> anyhow::Result<Y>,
+    {
+        if self.should_run(frame) {
+            let y = f()?;
+            self.update(y.clone());
+            Ok(y)
+        } else {
+            Ok(self.last_y.clone().unwrap_or_default())
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last_frame = None;
+        self.last_y = None;
+    }
+}
+
+#[cfg(test)]
+mod tests_motion_gate {
+    use super::MotionGate;
+    use crate::Y;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    fn frame(level: u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_pixel(64, 64, Luma([level])))
+    }
+
+    #[test]
+    fn static_frames() {
+        let mut gate = MotionGate::default();
+        assert!(gate.should_run(&frame(100)));
+        assert!(gate.should_run(&frame(100))); // nothing cached yet
+        gate.update(Y::default());
+        assert!(!gate.should_run(&frame(100)));
+        assert!(!gate.should_run(&frame(100)));
+    }
+
+    #[test]
+    fn sudden_motion() {
+        let mut gate = MotionGate::default();
+        let mut runs = 0;
+        for level in [100, 100, 200, 200] {
+            gate.run_or_reuse(&frame(level), || {
+                runs += 1;
+                Ok(Y::default())
+            })
+            .unwrap();
+        }
+        assert_eq!(runs, 2);
+    }
+
+    #[test]
+    fn cumulative_slow_motion() {
+        // 10 gray levels per frame stays under the 25 pixel threshold frame to frame
+        let mut gate = MotionGate::default();
+        let runs = (0..7)
+            .filter(|i| {
+                let run = gate.should_run(&frame(100 + 10 * i));
+                if run {
+                    gate.update(Y::default());
+                }
+                run
+            })
+            .count();
+        assert_eq!(runs, 3); // 100, 130, 160
+    }
+}
@@ -1,7 +1,11 @@
 use anyhow::Result;
 use fast_image_resize as fr;
-use image::{DynamicImage, GenericImageView, ImageBuffer};
-use ndarray::{s, Array, Axis, IxDyn};
+use image::{
+    DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, RgbImage, Rgba, RgbaImage,
+};
+use ndarray::{s, Array, ArrayViewMut3, Axis, IxDyn};
+
+use crate::{Bbox, DynConf, Keypoint, NmsMethod, Polygon, Y};
 
 pub fn standardize(xs: Array<f32, IxDyn>, mean: &[f32], std: &[f32]) -> Array<f32, IxDyn> {
     let mean = Array::from_shape_vec((1, mean.len(), 1, 1), mean.to_vec()).unwrap();
@@ -45,6 +49,27 @@ pub fn resize(
     height: u32,
     width: u32,
     filter: &str,
+) -> Result<Array<f32, IxDyn>> {
+    _resize(xs, height, width, filter, None)
+}
+
+/// Resize and subtract a per-channel integer mean, staying in `u8` until the tensor is filled.
+pub fn resize_u8_mean(
+    xs: &[DynamicImage],
+    height: u32,
+    width: u32,
+    filter: &str,
+    mean: [u8; 3],
+) -> Result<Array<f32, IxDyn>> {
+    _resize(xs, height, width, filter, Some(mean))
+}
+
+fn _resize(
+    xs: &[DynamicImage],
+    height: u32,
+    width: u32,
+    filter: &str,
+    mean: Option<[u8; 3]>,
 ) -> Result<Array<f32, IxDyn>> {
     let mut ys = Array::ones((xs.len(), 3, height as usize, width as usize)).into_dyn();
     let mut resizer = build_resizer(filter);
@@ -72,12 +97,17 @@ pub fn resize(
         let buffer = dst_image.into_vec();
 
         // to ndarray
-        let y_ = Array::from_shape_vec((height as usize, width as usize, 3), buffer)
-            .unwrap()
-            .mapv(|x| x as f32)
-            .permuted_axes([2, 0, 1]);
         let mut data = ys.slice_mut(s![idx, .., .., ..]);
-        data.assign(&y_);
+        match mean {
+            Some(mean) => assign_u8_mean(&mut data, &buffer, mean),
+            None => {
+                let y_ = Array::from_shape_vec((height as usize, width as usize, 3), buffer)
+                    .unwrap()
+                    .mapv(|x| x as f32)
+                    .permuted_axes([2, 0, 1]);
+                data.assign(&y_);
+            }
+        }
     }
     Ok(ys)
 }
@@ -88,6 +118,29 @@ pub fn letterbox(
     width: u32,
     filter: &str,
     bg: Option<u8>,
+) -> Result<Array<f32, IxDyn>> {
+    _letterbox(xs, height, width, filter, bg, None)
+}
+
+/// Letterbox and subtract a per-channel integer mean, staying in `u8` until the tensor is filled.
+pub fn letterbox_u8_mean(
+    xs: &[DynamicImage],
+    height: u32,
+    width: u32,
+    filter: &str,
+    bg: Option<u8>,
+    mean: [u8; 3],
+) -> Result<Array<f32, IxDyn>> {
+    _letterbox(xs, height, width, filter, bg, Some(mean))
+}
+
+fn _letterbox(
+    xs: &[DynamicImage],
+    height: u32,
+    width: u32,
+    filter: &str,
+    bg: Option<u8>,
+    mean: Option<[u8; 3]>,
 ) -> Result<Array<f32, IxDyn>> {
     let mut ys = Array::ones((xs.len(), 3, height as usize, width as usize)).into_dyn();
     let mut resizer = build_resizer(filter);
@@ -136,12 +189,17 @@ pub fn letterbox(
         let buffer = dst_image.into_vec();
 
         // to ndarray
-        let y_ = Array::from_shape_vec((height as usize, width as usize, 3), buffer)
-            .unwrap()
-            .mapv(|x| x as f32)
-            .permuted_axes([2, 0, 1]);
         let mut data = ys.slice_mut(s![idx, .., .., ..]);
-        data.assign(&y_);
+        match mean {
+            Some(mean) => assign_u8_mean(&mut data, &buffer, mean),
+            None => {
+                let y_ = Array::from_shape_vec((height as usize, width as usize, 3), buffer)
+                    .unwrap()
+                    .mapv(|x| x as f32)
+                    .permuted_axes([2, 0, 1]);
+                data.assign(&y_);
+            }
+        }
     }
     Ok(ys)
 }
@@ -211,6 +269,17 @@ pub fn resize_with_fixed_height(
     Ok(ys)
 }
 
+/// Fill a CHW tensor from an HWC `u8` buffer, subtracting `mean` in integer space in a single pass.
+fn assign_u8_mean(data: &mut ArrayViewMut3<f32>, buffer: &[u8], mean: [u8; 3]) {
+    let (_, h, w) = data.dim();
+    for (i, px) in buffer.chunks_exact(3).take(h * w).enumerate() {
+        let (y, x) = (i / w, i % w);
+        for c in 0..3 {
+            data[[c, y, x]] = (px[c] as i16 - mean[c] as i16) as f32;
+        }
+    }
+}
+
 pub fn build_dyn_image_from_raw(v: Vec<f32>, height: u32, width: u32) -> DynamicImage {
     let v: ImageBuffer<image::Luma<_>, Vec<f32>> =
         ImageBuffer::from_raw(width, height, v).expect("Faild to create image from ndarray");
@@ -228,3 +297,728 @@ pub fn descale_mask(mask: DynamicImage, w0: f32, h0: f32, w1: f32, h1: f32) -> D
 pub fn make_divisible(x: usize, divisor: usize) -> usize {
     (x + divisor - 1) / divisor * divisor
 }
+
+/// Convert a NCHW tensor between 1 (luma) and 3 (RGB) channels, None if no conversion applies
+pub fn convert_channels(xs: &Array<f32, IxDyn>, channels: usize) -> Option<Array<f32, IxDyn>> {
+    if xs.ndim() != 4 || xs.shape()[1] == channels {
+        return None;
+    }
+    match (xs.shape()[1], channels) {
+        (3, 1) => {
+            let luma = &xs.slice(s![.., 0..1, .., ..]) * 0.299
+                + &xs.slice(s![.., 1..2, .., ..]) * 0.587
+                + &xs.slice(s![.., 2..3, .., ..]) * 0.114;
+            Some(luma.into_dyn())
+        }
+        (1, 3) => {
+            let views = vec![xs.view(); 3];
+            ndarray::concatenate(Axis(1), &views).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Turn a NCHW tensor with 1 or 3 channels back into images, one per batch item.
+/// Values in [0, 1] are scaled by 255, values in [0, 255] are kept, anything else
+/// (e.g. standardized inputs) is min-max stretched.
+pub fn tensor_to_images(xs: &Array<f32, IxDyn>) -> Vec<DynamicImage> {
+    if xs.ndim() != 4 || !(xs.shape()[1] == 1 || xs.shape()[1] == 3) {
+        return vec![];
+    }
+    let (min_, max_) = xs
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(a, b), &x| (a.min(x), b.max(x)));
+    let (offset, scale) = if min_ >= 0. && max_ <= 1. {
+        (0., 255.)
+    } else if min_ >= 0. && max_ <= 255. {
+        (0., 1.)
+    } else {
+        (min_, 255. / (max_ - min_).max(f32::EPSILON))
+    };
+    let (c, h, w) = (xs.shape()[1], xs.shape()[2], xs.shape()[3]);
+    xs.axis_iter(Axis(0))
+        .map(|x| {
+            let x = x
+                .permuted_axes(vec![1, 2, 0])
+                .mapv(|v| ((v - offset) * scale).round().clamp(0., 255.) as u8);
+            let v = x.iter().copied().collect::<Vec<_>>();
+            if c == 1 {
+                DynamicImage::from(GrayImage::from_raw(w as u32, h as u32, v).unwrap())
+            } else {
+                DynamicImage::from(RgbImage::from_raw(w as u32, h as u32, v).unwrap())
+            }
+        })
+        .collect()
+}
+
+/// (width, height) of each image
+pub fn image_sizes(xs: &[DynamicImage]) -> Vec<(u32, u32)> {
+    xs.iter().map(|x| (x.width(), x.height())).collect()
+}
+
+/// One (width, height) per image of the batch `xs`, for `run_tensor`
+pub fn check_sizes(xs: &Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<()> {
+    let n = xs.shape().first().copied().unwrap_or(0);
+    if sizes.len() != n {
+        anyhow::bail!("Got {} image sizes for a batch of {n}", sizes.len());
+    }
+    Ok(())
+}
+
+/// Downscale to a `size` x `size` gray image, cheap enough for per-frame comparisons
+pub fn gray_thumbnail(x: &DynamicImage, size: u32) -> GrayImage {
+    x.resize_exact(size, size, image::imageops::FilterType::Triangle)
+        .to_luma8()
+}
+
+/// Ratio of pixels whose absolute difference exceeds `pixel_threshold`
+pub fn motion_ratio(a: &GrayImage, b: &GrayImage, pixel_threshold: u8) -> f32 {
+    if a.dimensions() != b.dimensions() || a.is_empty() {
+        return 1.;
+    }
+    let n = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(p, q)| p.0[0].abs_diff(q.0[0]) > pixel_threshold)
+        .count();
+    n as f32 / (a.width() * a.height()) as f32
+}
+
+/// Add `delta` (in [-255, 255]) to every RGB channel, alpha untouched.
+#[cfg(feature = "test-utils")]
+pub fn adjust_brightness(x: &DynamicImage, delta: f32) -> DynamicImage {
+    let mut img = x.to_rgba8();
+    for p in img.pixels_mut() {
+        for c in p.0.iter_mut().take(3) {
+            *c = (*c as f32 + delta).round().clamp(0., 255.) as u8;
+        }
+    }
+    DynamicImage::from(img)
+}
+
+/// Add zero-mean gaussian noise with standard deviation `std` (in pixel values), reproducible by `seed`.
+#[cfg(feature = "test-utils")]
+pub fn add_gaussian_noise(x: &DynamicImage, std: f32, seed: u64) -> DynamicImage {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut img = x.to_rgba8();
+    for p in img.pixels_mut() {
+        for c in p.0.iter_mut().take(3) {
+            // Box-Muller
+            let (u1, u2): (f32, f32) = (rng.gen_range(f32::EPSILON..1.), rng.gen());
+            let n = (-2. * u1.ln()).sqrt() * (2. * std::f32::consts::PI * u2).cos();
+            *c = (*c as f32 + n * std).round().clamp(0., 255.) as u8;
+        }
+    }
+    DynamicImage::from(img)
+}
+
+/// Rotate by `degrees` (clockwise) about the center, keeping the size; uncovered pixels are black.
+#[cfg(feature = "test-utils")]
+pub fn rotate(x: &DynamicImage, degrees: f32) -> DynamicImage {
+    DynamicImage::from(imageproc::geometric_transformations::rotate_about_center(
+        &x.to_rgba8(),
+        degrees.to_radians(),
+        imageproc::geometric_transformations::Interpolation::Bilinear,
+        Rgba([0, 0, 0, 255]),
+    ))
+}
+
+/// Deterministic perturbation for stability tests: brightness delta in `[-brightness, brightness]`,
+/// rotation in `[-degrees, degrees]` and gaussian noise of `noise_std`, all drawn from `seed`.
+#[cfg(feature = "test-utils")]
+pub fn perturb(
+    x: &DynamicImage,
+    seed: u64,
+    brightness: f32,
+    noise_std: f32,
+    degrees: f32,
+) -> DynamicImage {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let delta = rng.gen_range(-1f32..=1.) * brightness;
+    let angle = rng.gen_range(-1f32..=1.) * degrees;
+    let mut y = adjust_brightness(x, delta);
+    if angle != 0. {
+        y = rotate(&y, angle);
+    }
+    if noise_std > 0. {
+        y = add_gaussian_noise(&y, noise_std, rng.gen());
+    }
+    y
+}
+
+/// Privacy redaction applied to image regions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedactMode {
+    /// Gaussian blur with the given sigma
+    Blur(f32),
+    /// Mosaic with square blocks of the given size in pixels
+    Pixelate(u32),
+}
+
+/// Redact the region `[xmin, xmax) x [ymin, ymax)` of `img` in place, clamped to the image.
+pub fn redact(img: &mut RgbaImage, xmin: f32, ymin: f32, xmax: f32, ymax: f32, mode: RedactMode) {
+    let x0 = (xmin.max(0.).round() as u32).min(img.width());
+    let y0 = (ymin.max(0.).round() as u32).min(img.height());
+    let x1 = (xmax.max(0.).round() as u32).min(img.width());
+    let y1 = (ymax.max(0.).round() as u32).min(img.height());
+    if x1 <= x0 || y1 <= y0 {
+        return;
+    }
+    let (w, h) = (x1 - x0, y1 - y0);
+    match mode {
+        RedactMode::Blur(sigma) => {
+            let region = img.view(x0, y0, w, h).to_image();
+            let region = imageproc::filter::gaussian_blur_f32(&region, sigma.max(0.1));
+            img.copy_from(&region, x0, y0).unwrap();
+        }
+        RedactMode::Pixelate(block) => {
+            let block = block.max(1);
+            for by in (y0..y1).step_by(block as usize) {
+                for bx in (x0..x1).step_by(block as usize) {
+                    let (bw, bh) = (block.min(x1 - bx), block.min(y1 - by));
+                    let mut sum = [0u64; 4];
+                    for y in by..by + bh {
+                        for x in bx..bx + bw {
+                            for (s, v) in sum.iter_mut().zip(img.get_pixel(x, y).0) {
+                                *s += v as u64;
+                            }
+                        }
+                    }
+                    let n = (bw * bh) as u64;
+                    let mean = Rgba(sum.map(|s| (s / n) as u8));
+                    for y in by..by + bh {
+                        for x in bx..bx + bw {
+                            img.put_pixel(x, y, mean);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Suppress overlapping boxes with any [`NmsMethod`], best first, the NMS of every model.
+/// With `ious` boxes only suppress boxes of their own class, with that class's threshold;
+/// soft methods drop boxes decayed below their class's `confs`.
+pub fn nms(
+    bboxes: &[Bbox],
+    method: NmsMethod,
+    iou_threshold: f32,
+    ious: Option<&DynConf>,
+    sigma: f32,
+    confs: &DynConf,
+) -> Vec<Bbox> {
+    let mut bboxes = bboxes.to_vec();
+    Y::soft_nms_bboxes(&mut bboxes, method, iou_threshold, ious, sigma, confs);
+    bboxes
+}
+
+/// Merge bboxes collected from overlapping tiles (`tiles` as `(x, y, w, h)` in image pixels).
+///
+/// Besides plain IoU > `iou_threshold`, two boxes of the same class are merged when both lie
+/// within `margin` pixels of a tile seam, touch each other (gap <= `margin`), and their center
+/// distance is below `dist_ratio` times the diagonal of their union. This joins the two
+/// half-boxes an object cut by a seam leaves behind, which per-tile IoU alone misses.
+/// Merged boxes take the union extent and the highest confidence.
+pub fn merge_tiled_bboxes(
+    bboxes: &[Bbox],
+    tiles: &[(f32, f32, f32, f32)],
+    iou_threshold: f32,
+    margin: f32,
+    dist_ratio: f32,
+) -> Vec<Bbox> {
+    // seams: tile edges inside the covered area
+    let (x_min, y_min, x_max, y_max) = tiles.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(a, b, c, d), &(x, y, w, h)| (a.min(x), b.min(y), c.max(x + w), d.max(y + h)),
+    );
+    let mut seams_x: Vec<f32> = Vec::new();
+    let mut seams_y: Vec<f32> = Vec::new();
+    for &(x, y, w, h) in tiles.iter() {
+        for v in [x, x + w] {
+            if v > x_min && v < x_max {
+                seams_x.push(v);
+            }
+        }
+        for v in [y, y + h] {
+            if v > y_min && v < y_max {
+                seams_y.push(v);
+            }
+        }
+    }
+    let near_seam = |b: &Bbox| {
+        seams_x
+            .iter()
+            .any(|&v| (b.xmin() - v).abs() <= margin || (b.xmax() - v).abs() <= margin)
+            || seams_y
+                .iter()
+                .any(|&v| (b.ymin() - v).abs() <= margin || (b.ymax() - v).abs() <= margin)
+    };
+    let seam_pair = |a: &Bbox, b: &Bbox| {
+        let gap_x = (a.xmin().max(b.xmin()) - a.xmax().min(b.xmax())).max(0.);
+        let gap_y = (a.ymin().max(b.ymin()) - a.ymax().min(b.ymax())).max(0.);
+        if gap_x > margin || gap_y > margin || !near_seam(a) || !near_seam(b) {
+            return false;
+        }
+        let (w, h) = (
+            a.xmax().max(b.xmax()) - a.xmin().min(b.xmin()),
+            a.ymax().max(b.ymax()) - a.ymin().min(b.ymin()),
+        );
+        let d = (a.cx() - b.cx()).hypot(a.cy() - b.cy());
+        d <= dist_ratio * w.hypot(h)
+    };
+
+    let mut order: Vec<&Bbox> = bboxes.iter().collect();
+    order.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+    let mut kept: Vec<Bbox> = Vec::new();
+    for b in order {
+        match kept
+            .iter_mut()
+            .find(|k| k.id() == b.id() && (k.iou(b) > iou_threshold || seam_pair(k, b)))
+        {
+            Some(k) => {
+                *k = k.clone().with_xyxy(
+                    k.xmin().min(b.xmin()),
+                    k.ymin().min(b.ymin()),
+                    k.xmax().max(b.xmax()),
+                    k.ymax().max(b.ymax()),
+                );
+            }
+            None => kept.push(b.clone()),
+        }
+    }
+    kept
+}
+
+/// Weighted boxes fusion (Solovyev et al.) of detections from several models or TTA views.
+///
+/// Boxes below `skip_thr` are dropped and the rest scaled by their source's weight (1 if
+/// `weights` is shorter than `boxes`). Going by confidence, each box joins the first fused
+/// box of its class with IoU above `iou_thr`, and fused boxes are the confidence-weighted
+/// mean of their members. The fused confidence is the mean member confidence, scaled down
+/// when fewer sources than available agree on it.
+pub fn weighted_boxes_fusion(
+    boxes: &[Vec<Bbox>],
+    weights: &[f32],
+    iou_thr: f32,
+    skip_thr: f32,
+) -> Vec<Bbox> {
+    let weight = |i: usize| weights.get(i).copied().unwrap_or(1.);
+    let wsum: f32 = (0..boxes.len()).map(weight).sum::<f32>().max(f32::EPSILON);
+    let mut all: Vec<Bbox> = boxes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, xs)| {
+            xs.iter()
+                .filter(|b| b.confidence() >= skip_thr)
+                .map(move |b| b.clone().with_confidence(b.confidence() * weight(i)))
+        })
+        .collect();
+    all.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+
+    let mut clusters: Vec<(Bbox, Vec<Bbox>)> = Vec::new(); // (fused, members)
+    for b in all {
+        match clusters
+            .iter_mut()
+            .find(|(fused, _)| fused.id() == b.id() && fused.iou(&b) > iou_thr)
+        {
+            Some((fused, members)) => {
+                members.push(b);
+                let refs: Vec<&Bbox> = members.iter().collect();
+                *fused = fuse_bboxes(&refs, members.len());
+            }
+            None => clusters.push((b.clone(), vec![b])),
+        }
+    }
+    let mut fused: Vec<Bbox> = clusters
+        .into_iter()
+        .map(|(fused, members)| {
+            let k = members.len().min(boxes.len()) as f32 / wsum;
+            let confidence = fused.confidence() * k;
+            fused.with_confidence(confidence)
+        })
+        .collect();
+    fused.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+    fused
+}
+
+/// Group detections from several models or TTA views: same class and IoU above `iou_threshold`
+/// with the cluster's highest-confidence box. Returns indices into `bboxes`.
+fn cluster_bboxes(bboxes: &[Bbox], iou_threshold: f32) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..bboxes.len()).collect();
+    order.sort_by(|&a, &b| bboxes[b].confidence().total_cmp(&bboxes[a].confidence()));
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        match clusters.iter_mut().find(|c| {
+            let head = &bboxes[c[0]];
+            head.id() == bboxes[i].id() && head.iou(&bboxes[i]) > iou_threshold
+        }) {
+            Some(c) => c.push(i),
+            None => clusters.push(vec![i]),
+        }
+    }
+    clusters
+}
+
+/// Confidence-weighted mean of boxes; confidence is averaged over `num_views`.
+fn fuse_bboxes(bboxes: &[&Bbox], num_views: usize) -> Bbox {
+    let ws: Vec<f32> = bboxes.iter().map(|b| b.confidence()).collect();
+    let wsum = ws.iter().sum::<f32>().max(f32::EPSILON);
+    let avg = |f: fn(&Bbox) -> f32| -> f32 {
+        bboxes.iter().zip(&ws).map(|(b, w)| f(b) * w).sum::<f32>() / wsum
+    };
+    Bbox::default()
+        .with_xyxy(
+            avg(Bbox::xmin),
+            avg(Bbox::ymin),
+            avg(Bbox::xmax),
+            avg(Bbox::ymax),
+        )
+        .with_id(bboxes[0].id())
+        .with_name(bboxes[0].name().cloned())
+        .with_confidence(ws.iter().sum::<f32>() / num_views.max(1) as f32)
+}
+
+/// Fuse pose results of the same image from multiple models or TTA views.
+///
+/// Detections are matched by bbox IoU, then each keypoint is averaged weighted by its
+/// own confidence. Keypoints below the models' thresholds (confidence 0) are ignored.
+pub fn fuse_keypoints(ys: &[Y], iou_threshold: f32) -> Y {
+    let mut bboxes: Vec<Bbox> = Vec::new();
+    let mut kpts: Vec<&Vec<Keypoint>> = Vec::new();
+    for y in ys.iter() {
+        if let (Some(b), Some(k)) = (y.bboxes(), y.keypoints()) {
+            bboxes.extend(b.iter().take(k.len()).cloned());
+            kpts.extend(k.iter().take(b.len()));
+        }
+    }
+
+    let (mut y_bboxes, mut y_kpts) = (Vec::new(), Vec::new());
+    for cluster in cluster_bboxes(&bboxes, iou_threshold) {
+        let members: Vec<&Bbox> = cluster.iter().map(|&i| &bboxes[i]).collect();
+        y_bboxes.push(fuse_bboxes(&members, ys.len()));
+
+        let nk = cluster.iter().map(|&i| kpts[i].len()).max().unwrap_or(0);
+        let fused = (0..nk)
+            .map(|k| {
+                let visible: Vec<&Keypoint> = cluster
+                    .iter()
+                    .filter_map(|&i| kpts[i].get(k))
+                    .filter(|kpt| kpt.confidence() > 0.)
+                    .collect();
+                if visible.is_empty() {
+                    return Keypoint::default();
+                }
+                let wsum: f32 = visible.iter().map(|kpt| kpt.confidence()).sum();
+                let x = visible
+                    .iter()
+                    .map(|kpt| kpt.x() * kpt.confidence())
+                    .sum::<f32>()
+                    / wsum;
+                let y = visible
+                    .iter()
+                    .map(|kpt| kpt.y() * kpt.confidence())
+                    .sum::<f32>()
+                    / wsum;
+                let z = visible
+                    .iter()
+                    .map(|kpt| kpt.z().map(|z| z * kpt.confidence()))
+                    .sum::<Option<f32>>()
+                    .map(|z| z / wsum);
+                Keypoint::default()
+                    .with_xy(x, y)
+                    .with_z(z)
+                    .with_id(visible[0].id())
+                    .with_name(visible[0].name().cloned())
+                    .with_confidence(wsum / cluster.len() as f32)
+            })
+            .collect::<Vec<_>>();
+        y_kpts.push(fused);
+    }
+    Y::default().with_bboxes(&y_bboxes).with_keypoints(&y_kpts)
+}
+
+/// Fuse segmentation polygons of the same image from multiple models or TTA views.
+///
+/// Polygons are matched by the IoU of their bounding boxes, resampled to `num_vertices`
+/// evenly spaced points so differing vertex counts line up, aligned to the highest-confidence
+/// polygon by the best cyclic shift, and averaged weighted by confidence.
+pub fn fuse_polygons(ys: &[Y], iou_threshold: f32, num_vertices: usize) -> Y {
+    let mut bboxes: Vec<Bbox> = Vec::new();
+    let mut polygons: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut sources: Vec<&Polygon> = Vec::new();
+    for polygon in ys.iter().filter_map(|y| y.polygons()).flatten() {
+        let bbox = match polygon.bbox() {
+            None => continue,
+            Some(bbox) => bbox
+                .with_id(polygon.id())
+                .with_name(polygon.name().cloned())
+                .with_confidence(polygon.confidence()),
+        };
+        let resampled = polygon.clone().resample_to(num_vertices);
+        let points: Vec<(f64, f64)> = resampled
+            .polygon()
+            .exterior()
+            .coords()
+            .take(num_vertices)
+            .map(|c| (c.x, c.y))
+            .collect();
+        if points.len() != num_vertices {
+            continue;
+        }
+        bboxes.push(bbox);
+        polygons.push(points);
+        sources.push(polygon);
+    }
+
+    let mut y_polygons = Vec::new();
+    for cluster in cluster_bboxes(&bboxes, iou_threshold) {
+        let reference = &polygons[cluster[0]];
+        let ws: Vec<f64> = cluster
+            .iter()
+            .map(|&i| (sources[i].confidence() as f64).max(f64::EPSILON))
+            .collect();
+        let wsum: f64 = ws.iter().sum();
+        let mut fused = vec![(0f64, 0f64); num_vertices];
+        for (&i, w) in cluster.iter().zip(&ws) {
+            let points = &polygons[i];
+            let shift = (0..num_vertices)
+                .min_by(|&a, &b| {
+                    let cost = |s: usize| -> f64 {
+                        (0..num_vertices)
+                            .map(|j| {
+                                let (p, q) = (points[(j + s) % num_vertices], reference[j]);
+                                (p.0 - q.0).powi(2) + (p.1 - q.1).powi(2)
+                            })
+                            .sum()
+                    };
+                    cost(a).total_cmp(&cost(b))
+                })
+                .unwrap_or(0);
+            for (j, f) in fused.iter_mut().enumerate() {
+                let p = points[(j + shift) % num_vertices];
+                f.0 += p.0 * w / wsum;
+                f.1 += p.1 * w / wsum;
+            }
+        }
+        let head = sources[cluster[0]];
+        let confidence = cluster
+            .iter()
+            .map(|&i| sources[i].confidence())
+            .sum::<f32>()
+            / ys.len().max(1) as f32;
+        y_polygons.push(
+            Polygon::default()
+                .with_polygon(geo::Polygon::new(geo::LineString::from(fused), vec![]))
+                .with_id(head.id())
+                .with_name(head.name().cloned())
+                .with_confidence(confidence),
+        );
+    }
+    Y::default().with_polygons(&y_polygons)
+}
+
+#[cfg(test)]
+mod tests_ops {
+    use super::{
+        check_sizes, fuse_keypoints, fuse_polygons, nms, redact, weighted_boxes_fusion, RedactMode,
+    };
+    use crate::{Bbox, DynConf, Keypoint, NmsMethod, Polygon, Y};
+
+    #[test]
+    fn nms_methods() {
+        let bbox = |x: f32, y: f32, w: f32, id: isize, confidence: f32| {
+            Bbox::from((x, y, w, 10., id, confidence))
+        };
+        let bboxes = [
+            bbox(0., 0., 10., 0, 0.9),
+            bbox(1., 0., 10., 0, 0.8), // iou 0.82 with the first
+            bbox(1., 0., 10., 1, 0.7), // another class
+            bbox(0., 1., 30., 0, 0.6), // iou 0.29, centers apart
+        ];
+        let confs = |xs: Vec<Bbox>| xs.iter().map(|x| x.confidence()).collect::<Vec<_>>();
+        let (zero, class_aware) = (DynConf::new(&[0.], 1), DynConf::new(&[0.5], 1));
+
+        assert_eq!(
+            confs(nms(&bboxes, NmsMethod::Hard, 0.5, None, 0.5, &zero)),
+            [0.9, 0.6]
+        );
+        assert_eq!(
+            confs(nms(
+                &bboxes,
+                NmsMethod::Hard,
+                0.5,
+                Some(&class_aware),
+                0.5,
+                &zero
+            )),
+            [0.9, 0.7, 0.6]
+        );
+        assert_eq!(
+            confs(nms(&bboxes, NmsMethod::Hard, 0.25, None, 0.5, &zero)),
+            [0.9]
+        );
+        assert_eq!(
+            confs(nms(&bboxes, NmsMethod::Diou, 0.25, None, 0.5, &zero)),
+            [0.9, 0.6]
+        );
+        let soft = confs(nms(
+            &bboxes,
+            NmsMethod::SoftGaussian,
+            0.5,
+            None,
+            0.5,
+            &DynConf::new(&[0.3], 1),
+        ));
+        assert_eq!(soft.len(), 2); // the two near duplicates decay below 0.3
+        assert!(soft[1] > 0.5 && soft[1] < 0.6);
+    }
+
+    #[test]
+    fn wbf() {
+        let bbox = |x: f32, id: isize, confidence: f32| {
+            Bbox::default()
+                .with_xyxy(x, 10., x + 100., 110.)
+                .with_id(id)
+                .with_confidence(confidence)
+        };
+        let fused = weighted_boxes_fusion(
+            &[
+                vec![bbox(10., 0, 0.9), bbox(300., 1, 0.05)],
+                vec![bbox(20., 0, 0.6), bbox(15., 1, 0.8)],
+            ],
+            &[1., 2.],
+            0.55,
+            0.1,
+        );
+        // weighted confidences 0.9 and 1.2 (2 x 0.6), the other class stays apart
+        assert_eq!(fused.len(), 2);
+        let (a, b) = (&fused[0], &fused[1]);
+        assert_eq!((a.id(), b.id()), (0, 1));
+        assert!((a.xmin() - (10. * 0.9 + 20. * 1.2) / 2.1).abs() < 1e-4);
+        assert!((a.ymax() - 110.).abs() < 1e-4);
+        assert!((a.confidence() - 2.1 / 2. * 2. / 3.).abs() < 1e-5);
+        assert!((b.confidence() - 1.6 / 3.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sizes() {
+        let xs = ndarray::Array::zeros(ndarray::IxDyn(&[2, 3, 4, 4]));
+        assert!(check_sizes(&xs, &[(4, 4), (8, 8)]).is_ok());
+        assert!(check_sizes(&xs, &[(4, 4)]).is_err());
+    }
+
+    #[test]
+    fn fuse_poses() {
+        let kpt =
+            |x: f32, y: f32, id: isize, confidence: f32| Keypoint::from((x, y, id, confidence));
+        let ys = [
+            Y::default()
+                .with_bboxes(&[Bbox::from((0., 0., 10., 10., 0, 0.9))])
+                .with_keypoints(&[vec![kpt(2., 2., 0, 0.9), kpt(4., 4., 1, 0.)]]),
+            Y::default()
+                .with_bboxes(&[
+                    Bbox::from((1., 0., 10., 10., 0, 0.6)), // iou 0.82 with the first model's
+                    Bbox::from((50., 50., 10., 10., 0, 0.7)),
+                ])
+                .with_keypoints(&[
+                    vec![kpt(4., 2., 0, 0.3), kpt(6., 6., 1, 0.5)],
+                    vec![kpt(55., 55., 0, 0.8), kpt(56., 56., 1, 0.4)],
+                ]),
+        ];
+        let y = fuse_keypoints(&ys, 0.5);
+        let (bboxes, kpts) = (y.bboxes().unwrap(), y.keypoints().unwrap());
+        assert_eq!((bboxes.len(), kpts.len()), (2, 2));
+
+        // matched: boxes and visible keypoints weighted by confidence
+        assert!((bboxes[0].xmin() - 0.6 / 1.5).abs() < 1e-5);
+        assert!((bboxes[0].confidence() - 1.5 / 2.).abs() < 1e-5);
+        assert!((kpts[0][0].x() - (2. * 0.9 + 4. * 0.3) / 1.2).abs() < 1e-5);
+        assert!((kpts[0][0].confidence() - 1.2 / 2.).abs() < 1e-5);
+        assert_eq!((kpts[0][1].x(), kpts[0][1].y()), (6., 6.)); // the other is invisible
+
+        // unmatched: passed through
+        assert_eq!(bboxes[1].xmin(), 50.);
+        assert_eq!((kpts[1][0].x(), kpts[1][0].confidence()), (55., 0.8));
+    }
+
+    #[test]
+    fn fuse_segments() {
+        let polygon = |xys: &[(f64, f64)], confidence: f32| {
+            Polygon::default()
+                .with_polygon(geo::Polygon::new(
+                    geo::LineString::from(xys.to_vec()),
+                    vec![],
+                ))
+                .with_confidence(confidence)
+        };
+        let ys = [
+            Y::default()
+                .with_polygons(&[polygon(&[(0., 0.), (10., 0.), (10., 10.), (0., 10.)], 0.9)]),
+            Y::default().with_polygons(&[
+                // same square shifted by 1, with 8 vertices starting elsewhere
+                polygon(
+                    &[
+                        (11., 0.),
+                        (11., 5.),
+                        (11., 10.),
+                        (6., 10.),
+                        (1., 10.),
+                        (1., 5.),
+                        (1., 0.),
+                        (6., 0.),
+                    ],
+                    0.3,
+                ),
+                polygon(&[(50., 50.), (60., 50.), (60., 60.)], 0.5),
+            ]),
+        ];
+        let y = fuse_polygons(&ys, 0.5, 16);
+        let polygons = y.polygons().unwrap();
+        assert_eq!(polygons.len(), 2);
+
+        let fused = &polygons[0];
+        assert_eq!(fused.polygon().exterior().coords().count(), 17); // closed ring
+        let bbox = fused.bbox().unwrap();
+        assert!((bbox.xmin() - 0.3 / 1.2).abs() < 1e-3, "{}", bbox.xmin());
+        assert!((bbox.xmax() - (10. + 0.3 / 1.2)).abs() < 1e-3);
+        assert!((fused.confidence() - 1.2 / 2.).abs() < 1e-5);
+
+        // unmatched: passed through, resampled too
+        let unmatched = &polygons[1];
+        assert_eq!(unmatched.polygon().exterior().coords().count(), 17);
+        let bbox = unmatched.bbox().unwrap();
+        assert_eq!(bbox.xmin(), 50.);
+        assert!(bbox.ymax() > 59. && bbox.ymax() <= 60.);
+    }
+
+    #[test]
+    fn redact_modes() {
+        let checkerboard = image::RgbaImage::from_fn(20, 20, |x, y| {
+            let v = if (x + y) % 2 == 0 { 0 } else { 255 };
+            image::Rgba([v, v, v, 255])
+        });
+        for mode in [RedactMode::Blur(2.), RedactMode::Pixelate(2)] {
+            // the box is clipped at the top-left corner to [0, 10) x [0, 10)
+            let mut img = checkerboard.clone();
+            redact(&mut img, -5., -5., 10., 10., mode);
+            for (x, y, p) in img.enumerate_pixels() {
+                let before = checkerboard.get_pixel(x, y);
+                if x < 10 && y < 10 {
+                    assert_ne!(p, before, "{mode:?} ({x}, {y})");
+                } else {
+                    assert_eq!(p, before, "{mode:?} ({x}, {y})");
+                }
+            }
+        }
+
+        // fully outside
+        let mut img = checkerboard.clone();
+        redact(&mut img, 30., 30., 40., 40., RedactMode::Pixelate(2));
+        assert_eq!(img, checkerboard);
+    }
+}
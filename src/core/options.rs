@@ -2,8 +2,8 @@ use anyhow::Result;
 
 use crate::{
     auto_load,
-    models::{YOLOTask, YOLOVersion},
-    Device, MinOptMax,
+    models::{PoseScore, ThreshMethod, YOLOTask, YOLOVersion},
+    ChannelOrder, Device, Hooks, MinOptMax, NmsMethod, QuantMode, ResizeMode, Winding,
 };
 
 /// Options for building models
@@ -12,9 +12,16 @@ pub struct Options {
     pub onnx_path: String,
     pub device: Device,
     pub profile: bool,
+    pub ort_profiling: bool, // ORT per-operator trace, see `OrtEngine::save_profiling`
+    pub quantization: Option<QuantMode>, // quantize FP32 models, cached in `config_dir()`
     pub num_dry_run: usize,
-    pub i00: Option<MinOptMax>, // 1st input, axis 0, batch usually
-    pub i01: Option<MinOptMax>, // 1st input, axis 1
+    pub ort_log_level: Option<log::LevelFilter>, // route ORT logs into the `log` crate
+    pub output_names: Option<Vec<String>>, // fetch these (possibly intermediate) tensors as outputs
+    pub adaptive_batch: bool,              // halve the sub-batch and retry on allocation failures
+    pub save_preprocessed: Option<String>, // dump model inputs as images into this directory
+    pub hooks: Hooks,                      // per-stage callbacks of the engine
+    pub i00: Option<MinOptMax>,            // 1st input, axis 0, batch usually
+    pub i01: Option<MinOptMax>,            // 1st input, axis 1
     pub i02: Option<MinOptMax>,
     pub i03: Option<MinOptMax>,
     pub i04: Option<MinOptMax>,
@@ -48,22 +55,45 @@ pub struct Options {
     pub nk: Option<usize>,
     pub nm: Option<usize>,
     pub confs: Vec<f32>,
+    pub class_confs_named: Option<Vec<(String, f32)>>, // resolved against the class names
     pub kconfs: Vec<f32>,
     pub iou: f32,
+    pub iou_per_class: Option<Vec<f32>>, // class-aware NMS, the last value is broadcast
     pub apply_nms: bool,
     pub tokenizer: Option<String>,
     pub vocab: Option<String>,
-    pub names: Option<Vec<String>>,  // names
-    pub names2: Option<Vec<String>>, // names2
-    pub names3: Option<Vec<String>>, // names3
+    pub names: Option<Vec<String>>,        // names
+    pub names2: Option<Vec<String>>,       // names2
+    pub names3: Option<Vec<String>>,       // names3
+    pub text_prompts: Option<Vec<String>>, // open-vocabulary classes, used as names
     pub min_width: Option<f32>,
     pub min_height: Option<f32>,
-    pub unclip_ratio: f32, // DB
+    pub min_box_sizes: Option<Vec<(f32, f32)>>, // per-class (min_w, min_h), the last value is broadcast
+    pub unclip_ratio: f32,                      // DB
+    pub db_resample: usize, // DB, samples per polygon edge, 0 to disable resampling
+    pub db_simplify: Option<f64>, // DB, Douglas-Peucker epsilon in pixels
+    pub db_thresh: ThreshMethod, // DB, binarization of the probability map
     pub yolo_task: Option<YOLOTask>,
     pub yolo_version: Option<YOLOVersion>,
     pub anchors_first: bool, // yolo model output format like: [batch_size, anchors, xywh_clss_xxx]
     pub conf_independent: bool, // xywh_conf_clss
+    pub obj_conf: Option<f32>, // objectness threshold, applied before the class threshold
     pub apply_probs_softmax: bool,
+    pub polygon_winding: Option<Winding>,
+    pub instance_masks: bool, // YOLO segment, keep per-instance binary masks besides polygons
+    pub adaptive_simplify: Option<f64>, // base eps of confidence-aware polygon simplification
+    pub pose_score: PoseScore, // YOLO pose, score used to rank poses for nms
+    pub nms_method: NmsMethod, // YOLO and YOLOPv2, hard, soft or diou nms
+    pub nms_sigma: f32,       // gaussian soft-nms sigma
+    pub nms_class_aware: bool, // boxes only suppress boxes of their own class
+    pub detection_ids: bool,  // attach a UUID to every detected bbox
+    pub seed: Option<u64>,    // per-model RNG, see `with_seed`
+    pub uint8_mean: Option<[u8; 3]>, // YOLO uint8 fast path: x - mean, no [0, 1] scaling
+    pub mean: Option<[f32; 3]>, // YOLO `Processor` normalization, in [0, 1]
+    pub std: Option<[f32; 3]>, // YOLO `Processor` normalization, relative to the [0, 1] range
+    pub channel_order: ChannelOrder, // YOLO `Processor` input channel order
+    pub grayscale: bool,      // YOLO `Processor` single-channel input
+    pub resize_mode: Option<ResizeMode>, // YOLO detection tasks, overrides the default letterbox
 }
 
 impl Default for Options {
@@ -72,7 +102,14 @@ impl Default for Options {
             onnx_path: String::new(),
             device: Device::Cuda(0),
             profile: false,
+            ort_profiling: false,
+            quantization: None,
             num_dry_run: 5,
+            ort_log_level: None,
+            output_names: None,
+            adaptive_batch: false,
+            save_preprocessed: None,
+            hooks: Hooks::default(),
             i00: None,
             i01: None,
             i02: None,
@@ -104,22 +141,45 @@ impl Default for Options {
             nk: None,
             nm: None,
             confs: vec![0.4f32],
+            class_confs_named: None,
             kconfs: vec![0.5f32],
             iou: 0.45f32,
+            iou_per_class: None,
             apply_nms: true,
             tokenizer: None,
             vocab: None,
             names: None,
             names2: None,
             names3: None,
+            text_prompts: None,
             min_width: None,
             min_height: None,
+            min_box_sizes: None,
             unclip_ratio: 1.5,
+            db_resample: 50,
+            db_simplify: None,
+            db_thresh: ThreshMethod::default(),
             yolo_task: None,
             yolo_version: None,
             anchors_first: false,
             conf_independent: false,
+            obj_conf: None,
             apply_probs_softmax: false,
+            polygon_winding: None,
+            instance_masks: false,
+            adaptive_simplify: None,
+            pose_score: PoseScore::BoxOnly,
+            nms_method: NmsMethod::Hard,
+            nms_sigma: 0.5,
+            nms_class_aware: false,
+            detection_ids: false,
+            seed: None,
+            uint8_mean: None,
+            mean: None,
+            std: None,
+            channel_order: ChannelOrder::Rgb,
+            grayscale: false,
+            resize_mode: None,
         }
     }
 }
@@ -135,6 +195,12 @@ impl Options {
         self
     }
 
+    /// CUDA and CoreML fall back to CPU when their provider can not be registered
+    pub fn with_device(mut self, device: Device) -> Self {
+        self.device = device;
+        self
+    }
+
     pub fn with_cuda(mut self, id: usize) -> Self {
         self.device = Device::Cuda(id);
         self
@@ -175,16 +241,188 @@ impl Options {
         self
     }
 
+    pub fn with_pose_score(mut self, x: PoseScore) -> Self {
+        self.pose_score = x;
+        self
+    }
+
+    pub fn with_obj_conf(mut self, x: f32) -> Self {
+        self.obj_conf = Some(x);
+        self
+    }
+
     pub fn apply_probs_softmax(mut self, x: bool) -> Self {
         self.apply_probs_softmax = x;
         self
     }
 
+    pub fn with_adaptive_simplify(mut self, base_eps: f64) -> Self {
+        self.adaptive_simplify = Some(base_eps);
+        self
+    }
+
+    pub fn with_instance_masks(mut self, x: bool) -> Self {
+        self.instance_masks = x;
+        self
+    }
+
+    pub fn with_polygon_winding(mut self, x: Winding) -> Self {
+        self.polygon_winding = Some(x);
+        self
+    }
+
+    pub fn with_nms_method(mut self, x: NmsMethod) -> Self {
+        self.nms_method = x;
+        self
+    }
+
+    pub fn with_nms_sigma(mut self, x: f32) -> Self {
+        self.nms_sigma = x;
+        self
+    }
+
+    /// Class-aware NMS with the `iou` threshold, implied by `with_iou_per_class`
+    pub fn with_nms_class_aware(mut self, x: bool) -> Self {
+        self.nms_class_aware = x;
+        self
+    }
+
+    pub fn with_detection_ids(mut self, x: bool) -> Self {
+        self.detection_ids = x;
+        self
+    }
+
+    /// Draw this model's random values (detection UUIDs) from its own seeded RNG, so
+    /// models in one process are reproducible independently. Without it they share the
+    /// crate-wide RNG, see [`set_seed`](crate::set_seed).
+    pub fn with_seed(mut self, x: u64) -> Self {
+        self.seed = Some(x);
+        self
+    }
+
+    pub fn with_resize_mode(mut self, x: ResizeMode) -> Self {
+        self.resize_mode = Some(x);
+        self
+    }
+
+    /// Mean of `(x / 255 - mean) / std` in the `Processor`, which is then used even
+    /// without a `with_resize_mode`. YOLO only, like `with_std`, `with_channel_order`,
+    /// `with_grayscale` and `with_uint8_mean`; other models reject them
+    pub fn with_mean(mut self, x: [f32; 3]) -> Self {
+        self.mean = Some(x);
+        self
+    }
+
+    /// Std of `(x / 255 - mean) / std` in the `Processor`
+    pub fn with_std(mut self, x: [f32; 3]) -> Self {
+        self.std = Some(x);
+        self
+    }
+
+    pub fn with_channel_order(mut self, x: ChannelOrder) -> Self {
+        self.channel_order = x;
+        self
+    }
+
+    /// Feed the model a single luma channel
+    pub fn with_grayscale(mut self, x: bool) -> Self {
+        self.grayscale = x;
+        self
+    }
+
+    /// Can't be combined with `with_mean` or `with_std`
+    pub fn with_uint8_mean(mut self, x: [u8; 3]) -> Self {
+        self.uint8_mean = Some(x);
+        self
+    }
+
+    /// Errors on input options `model` would silently ignore: the `Processor` ones are only
+    /// read by YOLO, which can't use both `uint8_mean` and `mean`/`std`
+    pub(crate) fn check_input(&self, model: &str) -> Result<()> {
+        let processor = self.mean.is_some()
+            || self.std.is_some()
+            || self.channel_order != ChannelOrder::Rgb
+            || self.grayscale
+            || self.uint8_mean.is_some()
+            || self.resize_mode.is_some();
+        if model != "YOLO" && processor {
+            anyhow::bail!(
+                "{model} doesn't support `with_mean`, `with_std`, `with_channel_order`, \
+                 `with_grayscale`, `with_uint8_mean` or `with_resize_mode`, they are YOLO only"
+            );
+        }
+        if self.uint8_mean.is_some() && (self.mean.is_some() || self.std.is_some()) {
+            anyhow::bail!("`with_uint8_mean` can't be combined with `with_mean` or `with_std`");
+        }
+        Ok(())
+    }
+
+    /// Route ORT's logs into the `log` crate while this model builds and runs, without
+    /// touching the application's global `tracing` subscriber
+    pub fn with_ort_log_level(mut self, x: log::LevelFilter) -> Self {
+        self.ort_log_level = Some(x);
+        self
+    }
+
+    pub fn with_output_names(mut self, names: &[&str]) -> Self {
+        self.output_names = Some(names.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    pub fn with_adaptive_batch(mut self, x: bool) -> Self {
+        self.adaptive_batch = x;
+        self
+    }
+
+    pub fn with_save_preprocessed(mut self, dir: &str) -> Self {
+        self.save_preprocessed = Some(dir.to_string());
+        self
+    }
+
+    pub fn with_on_preprocess<F>(mut self, f: F) -> Self
+    where
+        F: Fn(std::time::Duration, &[Vec<usize>]) + Send + Sync + 'static,
+    {
+        self.hooks.on_preprocess = Some(std::sync::Arc::new(f));
+        self
+    }
+
+    pub fn with_on_inference<F>(mut self, f: F) -> Self
+    where
+        F: Fn(std::time::Duration, &[Vec<usize>]) + Send + Sync + 'static,
+    {
+        self.hooks.on_inference = Some(std::sync::Arc::new(f));
+        self
+    }
+
+    pub fn with_on_postprocess<F>(mut self, f: F) -> Self
+    where
+        F: Fn(std::time::Duration, &[Vec<usize>]) + Send + Sync + 'static,
+    {
+        self.hooks.on_postprocess = Some(std::sync::Arc::new(f));
+        self
+    }
+
     pub fn with_profile(mut self, profile: bool) -> Self {
         self.profile = profile;
         self
     }
 
+    /// Record ORT's per-operator timings as a Chrome trace (`chrome://tracing`), written to
+    /// `runs/ort-profiling` when the engine is dropped or by `OrtEngine::save_profiling`
+    pub fn with_profiling(mut self, x: bool) -> Self {
+        self.ort_profiling = x;
+        self
+    }
+
+    /// Quantize an FP32 model before creating the session, e.g. `QuantMode::DynamicInt8`
+    /// for CPU deployment. The result is cached, and the FP32 model is used if it fails or
+    /// there is nothing to quantize; only `MatMul` is, see [`QuantMode`].
+    pub fn with_quantization(mut self, x: QuantMode) -> Self {
+        self.quantization = Some(x);
+        self
+    }
+
     pub fn with_names(mut self, names: &[&str]) -> Self {
         self.names = Some(names.iter().map(|x| x.to_string()).collect::<Vec<String>>());
         self
@@ -200,6 +438,12 @@ impl Options {
         self
     }
 
+    /// Free-text classes of open-vocabulary detectors (YOLO-World), e.g. `["red car", "dog"]`
+    pub fn with_text_prompts(mut self, prompts: &[&str]) -> Self {
+        self.text_prompts = Some(prompts.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
     pub fn with_vocab(mut self, vocab: &str) -> Result<Self> {
         self.vocab = Some(auto_load(vocab, Some("models"))?);
         Ok(self)
@@ -215,6 +459,22 @@ impl Options {
         self
     }
 
+    pub fn with_db_resample(mut self, n: usize) -> Self {
+        self.db_resample = n;
+        self
+    }
+
+    /// Simplify DB polygons, larger `eps` keeps fewer vertices
+    pub fn with_db_simplify(mut self, eps: f64) -> Self {
+        self.db_simplify = Some(eps);
+        self
+    }
+
+    pub fn with_db_thresh(mut self, x: ThreshMethod) -> Self {
+        self.db_thresh = x;
+        self
+    }
+
     pub fn with_min_width(mut self, x: f32) -> Self {
         self.min_width = Some(x);
         self
@@ -225,6 +485,11 @@ impl Options {
         self
     }
 
+    pub fn with_min_box_size_per_class(mut self, x: &[(f32, f32)]) -> Self {
+        self.min_box_sizes = Some(x.to_vec());
+        self
+    }
+
     pub fn with_anchors_first(mut self, x: bool) -> Self {
         self.anchors_first = x;
         self
@@ -250,11 +515,26 @@ impl Options {
         self
     }
 
+    /// Per-class IoU thresholds for class-aware NMS, the last one is broadcast
+    pub fn with_iou_per_class(mut self, x: &[f32]) -> Result<Self> {
+        if x.is_empty() {
+            anyhow::bail!("`with_iou_per_class` expects at least one threshold");
+        }
+        self.iou_per_class = Some(x.to_vec());
+        Ok(self)
+    }
+
     pub fn with_confs(mut self, confs: &[f32]) -> Self {
         self.confs = confs.to_vec();
         self
     }
 
+    /// Confidence thresholds by class name, on top of `confs`
+    pub fn with_class_confs_named(mut self, x: &[(&str, f32)]) -> Self {
+        self.class_confs_named = Some(x.iter().map(|(k, v)| (k.to_string(), *v)).collect());
+        self
+    }
+
     pub fn with_kconfs(mut self, kconfs: &[f32]) -> Self {
         self.kconfs = kconfs.to_vec();
         self
@@ -380,3 +660,18 @@ impl Options {
         self
     }
 }
+
+#[cfg(test)]
+mod tests_options {
+    use super::Options;
+
+    #[test]
+    fn check_input() {
+        let options = Options::default().with_mean([0.5; 3]);
+        assert!(options.check_input("YOLO").is_ok());
+        assert!(options.check_input("RTDETR").is_err());
+        assert!(options.with_uint8_mean([0; 3]).check_input("YOLO").is_err());
+        assert!(Options::default().check_input("RTDETR").is_ok());
+        assert!(Options::default().with_iou_per_class(&[]).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use tracing::{
+    field::Field, span, subscriber::Interest, Dispatch, Event, Level, Metadata, Subscriber,
+};
+
+/// Span id of ORT native log records
+const ORT_SPAN: u64 = 1;
+const OTHER_SPAN: u64 = 2;
+
+/// Run `f` with ORT's logs (native and `ort` crate) routed into the `log` crate, keeping records
+/// up to `level`. The subscriber is only set on this thread while `f` runs, the application's
+/// own `tracing` subscriber stays the global default.
+pub(crate) fn scope<T>(level: Option<log::LevelFilter>, f: impl FnOnce() -> T) -> T {
+    match level {
+        None => f(),
+        Some(level) => tracing::dispatcher::with_default(&Dispatch::new(OrtLogger { level }), f),
+    }
+}
+
+/// `ort` forwards native severities shifted one level down (warning -> INFO, ...), shift them back.
+fn to_log_level(level: &Level, native: bool) -> log::Level {
+    match (*level, native) {
+        (Level::ERROR, _) | (Level::WARN, true) => log::Level::Error,
+        (Level::WARN, false) | (Level::INFO, true) => log::Level::Warn,
+        (Level::INFO, false) | (Level::DEBUG, true) => log::Level::Info,
+        (Level::DEBUG, false) => log::Level::Debug,
+        (Level::TRACE, _) => log::Level::Trace,
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        } else {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+}
+
+struct OrtLogger {
+    level: log::LevelFilter,
+}
+
+impl Subscriber for OrtLogger {
+    // scoped subscriber: don't let the callsite cache disable events for other subscribers
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target().starts_with("ort") && self.level != log::LevelFilter::Off
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        match span.metadata().name() {
+            "ort" => span::Id::from_u64(ORT_SPAN),
+            _ => span::Id::from_u64(OTHER_SPAN),
+        }
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let native = event.parent().map(|x| x.into_u64()) == Some(ORT_SPAN);
+        let level = to_log_level(event.metadata().level(), native);
+        if level > self.level {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        log::log!(target: "ort", level, "{}", visitor.0);
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+#[cfg(test)]
+mod tests_ort_logger {
+    use super::{scope, OrtLogger};
+
+    #[test]
+    fn scoped() {
+        let is_ort = || tracing::dispatcher::get_default(|x| x.is::<OrtLogger>());
+        assert!(scope(Some(log::LevelFilter::Warn), is_ort));
+        assert!(!scope(None, is_ort));
+        assert!(!is_ort());
+    }
+}
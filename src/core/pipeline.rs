@@ -0,0 +1,170 @@
+use anyhow::{bail, Result};
+use image::DynamicImage;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::{
+    coco,
+    models::{YOLOTask, YOLOVersion, RTDETR, YOLO},
+    Annotator, DataLoader, Options, OrtEngine, Ys, Y,
+};
+
+type RunFn = Box<dyn FnMut(&[DynamicImage]) -> Result<Vec<Y>>>;
+
+/// Model families `quick_run` can tell apart from ONNX metadata
+#[derive(Debug, Clone)]
+enum Family {
+    Yolo(YOLOTask, YOLOVersion),
+    RtDetr,
+}
+
+/// Load a model, run it over images or folders, save annotated results to `runs/<saveout>`,
+/// and return all results. The model family and task are read from the ONNX metadata, so only
+/// Ultralytics exports (YOLOv5u/v8/v9/v10/11 of any task, and RT-DETR) are supported; build
+/// other models explicitly.
+pub fn quick_run<P: AsRef<Path>>(model: &str, sources: &[P], saveout: &str) -> Result<Ys> {
+    let options = Options::default().with_model(model)?;
+    let metadata: HashMap<String, String> = OrtEngine::load_onnx(&options.onnx_path)?
+        .metadata_props
+        .into_iter()
+        .map(|x| (x.key, x.value))
+        .collect();
+    let family = infer_family(&metadata)?;
+
+    // dynamic shapes from the export settings
+    let batch = metadata
+        .get("batch")
+        .and_then(|x| x.parse::<isize>().ok())
+        .unwrap_or(1);
+    let (h, w) = imgsz(&metadata).unwrap_or((640, 640));
+    let options = options
+        .with_i00((1, batch, batch.max(4)).into())
+        .with_i02((h, h, h).into())
+        .with_i03((w, w, w).into());
+
+    let mut annotator = Annotator::default().with_saveout(saveout);
+    let mut model: RunFn = match family {
+        Family::Yolo(task, version) => {
+            if matches!(task, YOLOTask::Pose)
+                && metadata
+                    .get("kpt_shape")
+                    .is_some_and(|x| x.starts_with("[17"))
+            {
+                annotator = annotator.with_skeletons(&coco::SKELETONS_16);
+            }
+            let mut model = YOLO::new(options.with_yolo_task(task).with_yolo_version(version))?;
+            Box::new(move |xs| model.run(xs))
+        }
+        Family::RtDetr => {
+            let mut model = RTDETR::new(options)?;
+            Box::new(move |xs| model.run(xs))
+        }
+    };
+
+    let mut dl = DataLoader::default();
+    for source in sources.iter() {
+        dl = dl.load(source)?;
+    }
+
+    let mut ys = Vec::new();
+    for (xs, _paths) in dl {
+        let ys_ = model(&xs)?;
+        annotator.annotate(&xs, &ys_);
+        ys.extend(ys_);
+    }
+
+    Ok(Ys::from(ys))
+}
+
+fn infer_family(metadata: &HashMap<String, String>) -> Result<Family> {
+    let description = metadata
+        .get("description")
+        .map_or(String::new(), |x| x.to_lowercase());
+    let ultralytics = metadata
+        .get("author")
+        .is_some_and(|x| x.to_lowercase().contains("ultralytics"));
+    if !ultralytics && !metadata.contains_key("task") {
+        bail!("Can't infer the model family from ONNX metadata, only Ultralytics exports are supported by `quick_run`");
+    }
+    if description.contains("rt-detr") {
+        return Ok(Family::RtDetr);
+    }
+    let task = match metadata.get("task").map(|x| x.as_str()) {
+        None | Some("detect") => YOLOTask::Detect,
+        Some("classify") => YOLOTask::Classify,
+        Some("pose") => YOLOTask::Pose,
+        Some("segment") => YOLOTask::Segment,
+        Some("obb") => YOLOTask::Obb,
+        Some(x) => bail!("YOLO task {x:?} is not supported"),
+    };
+    let version = if description.contains("yolov10") {
+        YOLOVersion::V10
+    } else {
+        YOLOVersion::V8 // v5u, v8, v9 and 11 share the output layout
+    };
+    Ok(Family::Yolo(task, version))
+}
+
+/// `(height, width)` of an `imgsz` entry such as `[640, 640]`
+fn imgsz(metadata: &HashMap<String, String>) -> Option<(isize, isize)> {
+    let xs: Vec<isize> = metadata
+        .get("imgsz")?
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .map(|x| x.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match xs[..] {
+        [x] => Some((x, x)),
+        [h, w] => Some((h, w)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests_pipeline {
+    use super::{imgsz, infer_family, Family};
+    use crate::models::{YOLOTask, YOLOVersion};
+    use std::collections::HashMap;
+
+    fn metadata(xs: &[(&str, &str)]) -> HashMap<String, String> {
+        xs.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn families() {
+        let yolo = |description: &str, task: &str| {
+            infer_family(&metadata(&[
+                ("author", "Ultralytics"),
+                ("description", description),
+                ("task", task),
+            ]))
+            .unwrap()
+        };
+        assert!(matches!(
+            yolo("Ultralytics YOLOv8m-pose model", "pose"),
+            Family::Yolo(YOLOTask::Pose, YOLOVersion::V8)
+        ));
+        assert!(matches!(
+            yolo("Ultralytics YOLOv10n model", "detect"),
+            Family::Yolo(YOLOTask::Detect, YOLOVersion::V10)
+        ));
+        assert!(matches!(
+            yolo("Ultralytics RT-DETR-l model", "detect"),
+            Family::RtDetr
+        ));
+        assert!(infer_family(&metadata(&[("producer", "pytorch")])).is_err());
+    }
+
+    #[test]
+    fn image_sizes() {
+        assert_eq!(
+            imgsz(&metadata(&[("imgsz", "[480, 640]")])),
+            Some((480, 640))
+        );
+        assert_eq!(imgsz(&metadata(&[("imgsz", "640")])), Some((640, 640)));
+        assert_eq!(imgsz(&metadata(&[("imgsz", "[a, b]")])), None);
+        assert_eq!(imgsz(&HashMap::new()), None);
+    }
+}
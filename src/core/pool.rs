@@ -0,0 +1,255 @@
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+type Job<M> = Box<dyn FnOnce(&mut M) + Send>;
+
+/// A fixed number of model instances (each with its own ORT session), every one owned by
+/// a worker thread. Jobs queue up and at most `size` of them run at once, so concurrent
+/// callers never oversubscribe a session. Results come back as a [`Pending`], which can
+/// be awaited from any async runtime or waited on from a plain thread.
+///
+/// ```ignore
+/// let pool = EnginePool::new(2, || YOLO::new(options.clone()))?;
+/// let ys = pool.run(move |model| model.run(&images)).await?;
+/// ```
+pub struct EnginePool<M> {
+    sender: Option<Sender<Job<M>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<M> std::fmt::Debug for EnginePool<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnginePool")
+            .field("size", &self.workers.len())
+            .finish()
+    }
+}
+
+impl<M: Send + 'static> EnginePool<M> {
+    /// Build `size` models with `build` and start one worker per model
+    pub fn new<F: FnMut() -> Result<M>>(size: usize, mut build: F) -> Result<Self> {
+        let (sender, receiver) = channel::<Job<M>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut workers = Vec::new();
+        for i in 0..size.max(1) {
+            let mut model = build()?;
+            let receiver: Arc<Mutex<Receiver<Job<M>>>> = Arc::clone(&receiver);
+            let worker = std::thread::Builder::new()
+                .name(format!("usls-pool-{i}"))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    match job {
+                        // a panicking job fails its own `Pending` only
+                        Ok(job) => {
+                            let job = AssertUnwindSafe(|| job(&mut model));
+                            let _ = std::panic::catch_unwind(job);
+                        }
+                        Err(_) => break, // pool dropped
+                    }
+                })?;
+            workers.push(worker);
+        }
+        Ok(Self {
+            sender: Some(sender),
+            workers,
+        })
+    }
+
+    /// Queue `f` to run on the next free model
+    pub fn run<T, F>(&self, f: F) -> Pending<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut M) -> T + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let guard = Guard(Some(Arc::clone(&shared)));
+        let job: Job<M> = Box::new(move |model| {
+            let mut guard = guard;
+            let x = f(model);
+            if let Some(shared) = guard.0.take() {
+                shared.set(Ok(x));
+            }
+        });
+        let sent = self.sender.as_ref().is_some_and(|x| x.send(job).is_ok());
+        if !sent {
+            shared.set(Err(anyhow!("Engine pool workers are gone")));
+        }
+        Pending { shared }
+    }
+
+    /// Number of model instances
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl<M> Drop for EnginePool<M> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<(Option<Result<T>>, Option<Waker>)>,
+    ready: Condvar,
+}
+
+impl<T> Default for Shared<T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new((None, None)),
+            ready: Condvar::new(),
+        }
+    }
+}
+
+impl<T> Shared<T> {
+    fn set(&self, x: Result<T>) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = Some(x);
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+        self.ready.notify_all();
+    }
+}
+
+/// Fails the `Pending` of a job that panicked or was dropped without running, waking
+/// whoever awaits it
+struct Guard<T>(Option<Arc<Shared<T>>>);
+
+impl<T> Drop for Guard<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0.take() {
+            shared.set(Err(anyhow!("Engine pool job panicked or was dropped")));
+        }
+    }
+}
+
+/// Result of a job queued on an [`EnginePool`]. Fails if the job panicked.
+pub struct Pending<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> std::fmt::Debug for Pending<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending").finish()
+    }
+}
+
+impl<T> Pending<T> {
+    /// Block the current thread until the job is done
+    pub fn wait(self) -> Result<T> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if let Some(x) = state.0.take() {
+                return x;
+            }
+            state = self.shared.ready.wait(state).unwrap();
+        }
+    }
+}
+
+impl<T> Future for Pending<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.0.take() {
+            Some(x) => Poll::Ready(x),
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_pool {
+    use super::EnginePool;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut n = 0;
+        let pool = EnginePool::new(2, || {
+            n += 1;
+            Ok(n * 100)
+        })
+        .unwrap();
+        assert_eq!(pool.size(), 2);
+
+        let pendings: Vec<_> = (0..8)
+            .map(|i| {
+                let (running, peak) = (Arc::clone(&running), Arc::clone(&peak));
+                pool.run(move |model: &mut usize| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    *model + i
+                })
+            })
+            .collect();
+        for (i, pending) in pendings.into_iter().enumerate() {
+            let x = pending.wait().unwrap();
+            assert!(x == 100 + i || x == 200 + i);
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+
+        let pending = pool.run(|_: &mut usize| -> usize { panic!("boom") });
+        assert!(pending.wait().is_err());
+        assert_eq!(
+            pool.run(|model: &mut usize| *model % 100).wait().unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn panic_wakes() {
+        let pool = EnginePool::new(1, || Ok(())).unwrap();
+        let pending = pool.run(|_: &mut ()| -> usize {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            panic!("boom")
+        });
+
+        // polled (and parked) before the job panics
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut pending = std::pin::pin!(pending);
+        let t = std::time::Instant::now();
+        let x = loop {
+            match pending.as_mut().poll(&mut cx) {
+                Poll::Ready(x) => break x,
+                Poll::Pending => std::thread::park_timeout(std::time::Duration::from_secs(5)),
+            }
+        };
+        assert!(x.is_err());
+        assert!(t.elapsed().as_secs() < 5, "not woken by the panic");
+    }
+}
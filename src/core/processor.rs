@@ -0,0 +1,286 @@
+use anyhow::Result;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgb, RgbImage};
+use ndarray::{s, Array, IxDyn};
+
+/// How images are fitted into the model input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Keep the aspect ratio, fit inside and pad the right and bottom with `pad_value`
+    Letterbox { pad_value: u8 },
+    /// Resize both sides independently, distorting the aspect ratio
+    Stretch,
+    /// Keep the aspect ratio, cover the whole input and crop the overflow around the center
+    FitCenter,
+}
+
+impl Default for ResizeMode {
+    fn default() -> Self {
+        Self::Letterbox { pad_value: 114 }
+    }
+}
+
+/// Channel order of the model input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelOrder {
+    #[default]
+    Rgb,
+    Bgr,
+}
+
+/// Geometry of one resized image: a point `(x, y)` of the original image lands on
+/// `(x * scale_x + pad_x, y * scale_y + pad_y)` in the model input. Pads are negative
+/// when the image is cropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResizeInfo {
+    pub width0: u32,
+    pub height0: u32,
+    pub scale_x: f32,
+    pub scale_y: f32,
+    pub pad_x: f32,
+    pub pad_y: f32,
+}
+
+impl ResizeInfo {
+    pub fn new(mode: ResizeMode, width0: u32, height0: u32, width: u32, height: u32) -> Self {
+        let (rx, ry) = (
+            width as f32 / width0.max(1) as f32,
+            height as f32 / height0.max(1) as f32,
+        );
+        let (scale_x, scale_y) = match mode {
+            ResizeMode::Letterbox { .. } => (rx.min(ry), rx.min(ry)),
+            ResizeMode::Stretch => (rx, ry),
+            ResizeMode::FitCenter => (rx.max(ry), rx.max(ry)),
+        };
+        let (pad_x, pad_y) = match mode {
+            ResizeMode::FitCenter => (
+                ((width as f32 - (width0 as f32 * scale_x).round()) / 2.).round(),
+                ((height as f32 - (height0 as f32 * scale_y).round()) / 2.).round(),
+            ),
+            _ => (0., 0.),
+        };
+        Self {
+            width0,
+            height0,
+            scale_x,
+            scale_y,
+            pad_x,
+            pad_y,
+        }
+    }
+
+    /// Model input coordinates back to the original image
+    pub fn to_original(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (x - self.pad_x) / self.scale_x,
+            (y - self.pad_y) / self.scale_y,
+        )
+    }
+
+    /// Original image coordinates to the model input
+    pub fn to_model(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale_x + self.pad_x, y * self.scale_y + self.pad_y)
+    }
+}
+
+/// Resize images into an NCHW tensor according to a `ResizeMode`, keeping the geometry
+/// of every image to map results back. Values are in [0, 255], or `(x / 255 - mean) / std`
+/// once a mean or std is set.
+#[derive(Debug, Clone)]
+pub struct Processor {
+    mode: ResizeMode,
+    width: u32,
+    height: u32,
+    infos: Vec<ResizeInfo>,
+    mean: Option<[f32; 3]>,
+    std: Option<[f32; 3]>,
+    channel_order: ChannelOrder,
+    grayscale: bool, // a single luma channel
+}
+
+impl Processor {
+    pub fn new(mode: ResizeMode, width: u32, height: u32) -> Self {
+        Self {
+            mode,
+            width,
+            height,
+            infos: Vec::new(),
+            mean: None,
+            std: None,
+            channel_order: ChannelOrder::Rgb,
+            grayscale: false,
+        }
+    }
+
+    /// Per-channel mean in [0, 1], in the channel order of the model
+    pub fn with_mean(mut self, x: [f32; 3]) -> Self {
+        self.mean = Some(x);
+        self
+    }
+
+    /// Per-channel std in [0, 1], in the channel order of the model
+    pub fn with_std(mut self, x: [f32; 3]) -> Self {
+        self.std = Some(x);
+        self
+    }
+
+    pub fn with_channel_order(mut self, x: ChannelOrder) -> Self {
+        self.channel_order = x;
+        self
+    }
+
+    /// One luma channel instead of three, normalized with the first mean and std
+    pub fn with_grayscale(mut self, x: bool) -> Self {
+        self.grayscale = x;
+        self
+    }
+
+    /// Whether `process` already scales and normalizes values
+    pub fn is_normalized(&self) -> bool {
+        self.mean.is_some() || self.std.is_some()
+    }
+
+    pub fn process(&mut self, xs: &[DynamicImage]) -> Result<Array<f32, IxDyn>> {
+        let (w, h) = (self.width, self.height);
+        let pad_value = match self.mode {
+            ResizeMode::Letterbox { pad_value } => pad_value,
+            _ => 0,
+        };
+        let nc = if self.grayscale { 1 } else { 3 };
+        let mut ys = Array::zeros((xs.len(), nc, h as usize, w as usize)).into_dyn();
+        self.infos.clear();
+        for (idx, x) in xs.iter().enumerate() {
+            let (w0, h0) = x.dimensions();
+            let info = ResizeInfo::new(self.mode, w0, h0, w, h);
+            let resized = image::imageops::resize(
+                &x.to_rgb8(),
+                ((w0 as f32 * info.scale_x).round() as u32).max(1),
+                ((h0 as f32 * info.scale_y).round() as u32).max(1),
+                FilterType::CatmullRom,
+            );
+            let mut canvas = RgbImage::from_pixel(w, h, Rgb([pad_value; 3]));
+            image::imageops::replace(&mut canvas, &resized, info.pad_x as i64, info.pad_y as i64);
+            let raw = if self.grayscale {
+                DynamicImage::ImageRgb8(canvas).to_luma8().into_raw()
+            } else {
+                canvas.into_raw()
+            };
+            let mut y = Array::from_shape_vec((h as usize, w as usize, nc), raw)?
+                .mapv(|x| x as f32)
+                .permuted_axes([2, 0, 1]);
+            if self.channel_order == ChannelOrder::Bgr && nc == 3 {
+                y.invert_axis(ndarray::Axis(0));
+            }
+            if self.is_normalized() {
+                let (mean, std) = (self.mean.unwrap_or([0.; 3]), self.std.unwrap_or([1.; 3]));
+                for (c, mut x) in y.outer_iter_mut().enumerate() {
+                    x.mapv_inplace(|x| (x / 255. - mean[c]) / std[c]);
+                }
+            }
+            ys.slice_mut(s![idx, .., .., ..]).assign(&y);
+            self.infos.push(info);
+        }
+        Ok(ys)
+    }
+
+    /// Geometry of the images of the last `process` call
+    pub fn infos(&self) -> &[ResizeInfo] {
+        &self.infos
+    }
+
+    /// (scale_y, scale_x) of the images of the last `process` call
+    pub fn scale_factors_hw(&self) -> Vec<(f32, f32)> {
+        self.infos.iter().map(|x| (x.scale_y, x.scale_x)).collect()
+    }
+
+    pub fn mode(&self) -> ResizeMode {
+        self.mode
+    }
+}
+
+#[cfg(test)]
+mod tests_processor {
+    use super::{ChannelOrder, Processor, ResizeMode};
+    use crate::{Bbox, Y};
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn map_back() {
+        // white box at x 100..140, y 20..60 of a 200 x 100 image
+        let mut image = RgbImage::new(200, 100);
+        for y in 20..60 {
+            for x in 100..140 {
+                image.put_pixel(x, y, Rgb([255; 3]));
+            }
+        }
+        let image = DynamicImage::ImageRgb8(image);
+
+        for mode in [
+            ResizeMode::Letterbox { pad_value: 0 },
+            ResizeMode::Stretch,
+            ResizeMode::FitCenter,
+        ] {
+            let mut processor = Processor::new(mode, 64, 64);
+            let xs = processor.process(std::slice::from_ref(&image)).unwrap();
+
+            // bright region in the model input
+            let (mut x1, mut y1, mut x2, mut y2) = (64f32, 64f32, 0f32, 0f32);
+            for y in 0..64 {
+                for x in 0..64 {
+                    if xs[[0, 0, y, x]] > 127. {
+                        x1 = x1.min(x as f32);
+                        y1 = y1.min(y as f32);
+                        x2 = x2.max(x as f32 + 1.);
+                        y2 = y2.max(y as f32 + 1.);
+                    }
+                }
+            }
+            let y = Y::default()
+                .with_bboxes(&[Bbox::default().with_xyxy(x1, y1, x2, y2)])
+                .unresize(&processor.infos()[0]);
+            let bbox = &y.bboxes().unwrap()[0];
+            let tol = 2.
+                / processor.infos()[0]
+                    .scale_x
+                    .min(processor.infos()[0].scale_y);
+            for (a, b) in [
+                (bbox.xmin(), 100.),
+                (bbox.ymin(), 20.),
+                (bbox.xmax(), 140.),
+                (bbox.ymax(), 60.),
+            ] {
+                assert!((a - b).abs() <= tol, "{mode:?}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([200, 100, 50])));
+        let (mean, std) = ([0.485, 0.456, 0.406], [0.229, 0.224, 0.225]);
+        let mut processor = Processor::new(ResizeMode::Stretch, 4, 4)
+            .with_mean(mean)
+            .with_std(std);
+        let xs = processor.process(std::slice::from_ref(&image)).unwrap();
+        assert_eq!(xs.shape(), [1, 3, 4, 4]);
+        for (c, v) in [200., 100., 50.].iter().enumerate() {
+            let expected = (v / 255. - mean[c]) / std[c];
+            assert!((xs[[0, c, 1, 2]] - expected).abs() < 1e-5);
+        }
+
+        let mut processor =
+            Processor::new(ResizeMode::Stretch, 4, 4).with_channel_order(ChannelOrder::Bgr);
+        let xs = processor.process(std::slice::from_ref(&image)).unwrap();
+        assert_eq!([xs[[0, 0, 0, 0]], xs[[0, 2, 0, 0]]], [50., 200.]);
+
+        let mut processor = Processor::new(ResizeMode::Stretch, 4, 4)
+            .with_grayscale(true)
+            .with_mean([0.5; 3])
+            .with_std([0.5; 3]);
+        let xs = processor.process(&[image]).unwrap();
+        assert_eq!(xs.shape(), [1, 1, 4, 4]);
+        let luma = image::RgbImage::from_pixel(1, 1, Rgb([200, 100, 50]));
+        let luma = DynamicImage::ImageRgb8(luma).to_luma8().get_pixel(0, 0).0[0] as f32;
+        assert!((luma - (0.2126 * 200. + 0.7152 * 100. + 0.0722 * 50.)).abs() <= 1.);
+        assert!((xs[[0, 0, 3, 3]] - (luma / 255. - 0.5) / 0.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{bail, Result};
+use prost::Message;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::onnx::{self, tensor_proto::DataType};
+
+/// Quantization applied to FP32 models before the session is created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuantMode {
+    /// INT8 weights and dynamically quantized UINT8 activations for every `MatMul` with a
+    /// constant 2D weight, like `onnxruntime.quantization.quantize_dynamic`. `Conv` stays
+    /// FP32, so convolutional models gain little
+    DynamicInt8,
+}
+
+impl QuantMode {
+    fn tag(&self) -> &'static str {
+        match self {
+            Self::DynamicInt8 => "dynamic-int8",
+        }
+    }
+}
+
+/// Path of the quantized variant of `onnx_path` under `cache_dir`, named after the hash
+/// of the source file. The model is only quantized if it is not cached yet.
+pub(crate) fn quantized_model(
+    onnx_path: &str,
+    mode: QuantMode,
+    cache_dir: &Path,
+) -> Result<PathBuf> {
+    let bytes = std::fs::read(onnx_path)?;
+    let dst = cache_dir.join(format!("{:016x}-{}.onnx", fnv1a(&bytes), mode.tag()));
+    if dst.exists() {
+        return Ok(dst);
+    }
+    let model_proto = onnx::ModelProto::decode(bytes.as_slice())?;
+    let model_proto = match mode {
+        QuantMode::DynamicInt8 => quantize_dynamic(&model_proto)?,
+    };
+    std::fs::create_dir_all(cache_dir)?;
+    let tmp = dst.with_extension("tmp");
+    std::fs::write(&tmp, model_proto.encode_to_vec())?;
+    std::fs::rename(&tmp, &dst)?;
+    Ok(dst)
+}
+
+/// Whether the graph already carries quantized operators
+pub(crate) fn is_quantized(model_proto: &onnx::ModelProto) -> bool {
+    model_proto.graph.as_ref().is_some_and(|graph| {
+        graph.node.iter().any(|x| {
+            matches!(
+                x.op_type.as_str(),
+                "QuantizeLinear"
+                    | "DequantizeLinear"
+                    | "DynamicQuantizeLinear"
+                    | "MatMulInteger"
+                    | "ConvInteger"
+                    | "QLinearConv"
+                    | "QLinearMatMul"
+            )
+        })
+    })
+}
+
+/// Operators `quantize_dynamic` rewrote and the `Conv` ones it left in FP32, as
+/// `(MatMulInteger, Conv)` counts
+pub(crate) fn quantized_counts(model_proto: &onnx::ModelProto) -> (usize, usize) {
+    let count = |op: &str| {
+        model_proto
+            .graph
+            .as_ref()
+            .map_or(0, |x| x.node.iter().filter(|x| x.op_type == op).count())
+    };
+    (count("MatMulInteger"), count("Conv"))
+}
+
+/// Rewrite `Y = MatMul(A, W)` with a constant FP32 `W` into
+/// `DynamicQuantizeLinear(A) -> MatMulInteger(A_q, W_q) -> Cast -> Mul(scale_a * scale_w)`,
+/// with `W` quantized symmetrically (per tensor) to INT8.
+pub(crate) fn quantize_dynamic(model_proto: &onnx::ModelProto) -> Result<onnx::ModelProto> {
+    let opset = model_proto
+        .opset_import
+        .iter()
+        .find(|x| x.domain.is_empty() || x.domain == "ai.onnx")
+        .map_or(0, |x| x.version);
+    if opset < 11 {
+        bail!("Dynamic quantization needs opset 11 or later, got {opset}");
+    }
+    let mut model_proto = model_proto.clone();
+    let Some(graph) = model_proto.graph.as_mut() else {
+        bail!("No graph found in this proto");
+    };
+
+    let weights: HashMap<String, onnx::TensorProto> = graph
+        .initializer
+        .iter()
+        .filter(|x| x.data_type == DataType::Float as i32 && x.dims.len() == 2)
+        .filter(|x| x.external_data.is_empty())
+        .map(|x| (x.name.to_owned(), x.clone()))
+        .collect();
+    let mut quantized: HashMap<String, (String, String, String)> = HashMap::new(); // w_q, scale, zp
+    let mut nodes = Vec::with_capacity(graph.node.len());
+    for node in std::mem::take(&mut graph.node) {
+        let w = match node.input.get(1) {
+            Some(w) if node.op_type == "MatMul" && weights.contains_key(w) => w.to_owned(),
+            _ => {
+                nodes.push(node);
+                continue;
+            }
+        };
+        if !quantized.contains_key(&w) {
+            let (q, scale) = quantize_symmetric(&tensor_f32(&weights[&w]));
+            let names = (
+                format!("{w}_quantized"),
+                format!("{w}_scale"),
+                format!("{w}_zero_point"),
+            );
+            let dims = weights[&w].dims.to_owned();
+            graph.initializer.push(onnx::TensorProto {
+                name: names.0.to_owned(),
+                dims,
+                data_type: DataType::Int8 as i32,
+                raw_data: q.iter().map(|&x| x as u8).collect(),
+                ..Default::default()
+            });
+            graph.initializer.push(onnx::TensorProto {
+                name: names.1.to_owned(),
+                data_type: DataType::Float as i32,
+                float_data: vec![scale],
+                ..Default::default()
+            });
+            graph.initializer.push(onnx::TensorProto {
+                name: names.2.to_owned(),
+                data_type: DataType::Int8 as i32,
+                raw_data: vec![0],
+                ..Default::default()
+            });
+            quantized.insert(w.to_owned(), names);
+        }
+
+        let (w_q, w_scale, w_zp) = &quantized[&w];
+        let (a, y) = (&node.input[0], &node.output[0]);
+        let t = |s: &str| format!("{y}_{s}");
+        nodes.push(make_node(
+            "DynamicQuantizeLinear",
+            &[a],
+            &[&t("a_quantized"), &t("a_scale"), &t("a_zero_point")],
+        ));
+        nodes.push(make_node(
+            "MatMulInteger",
+            &[&t("a_quantized"), w_q, &t("a_zero_point"), w_zp],
+            &[&t("int32")],
+        ));
+        let mut cast = make_node("Cast", &[&t("int32")], &[&t("float")]);
+        cast.attribute.push(onnx::AttributeProto {
+            name: "to".into(),
+            i: DataType::Float as i64,
+            r#type: onnx::attribute_proto::AttributeType::Int as i32,
+            ..Default::default()
+        });
+        nodes.push(cast);
+        nodes.push(make_node("Mul", &[&t("a_scale"), w_scale], &[&t("scale")]));
+        nodes.push(make_node("Mul", &[&t("float"), &t("scale")], &[y]));
+    }
+    if quantized.is_empty() {
+        bail!("No MatMul with constant weights to quantize");
+    }
+
+    // drop FP32 weights nothing reads anymore
+    let used: std::collections::HashSet<&str> = nodes
+        .iter()
+        .flat_map(|x| x.input.iter().map(|x| x.as_str()))
+        .chain(graph.output.iter().map(|x| x.name.as_str()))
+        .collect();
+    let unused: Vec<String> = quantized
+        .keys()
+        .filter(|x| !used.contains(x.as_str()))
+        .cloned()
+        .collect();
+    graph.initializer.retain(|x| !unused.contains(&x.name));
+    graph.input.retain(|x| !unused.contains(&x.name));
+    graph.node = nodes;
+    Ok(model_proto)
+}
+
+fn make_node(op_type: &str, inputs: &[&str], outputs: &[&str]) -> onnx::NodeProto {
+    onnx::NodeProto {
+        name: format!("{}_{op_type}", outputs[0]),
+        op_type: op_type.into(),
+        input: inputs.iter().map(|x| x.to_string()).collect(),
+        output: outputs.iter().map(|x| x.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn tensor_f32(x: &onnx::TensorProto) -> Vec<f32> {
+    if !x.float_data.is_empty() {
+        return x.float_data.to_owned();
+    }
+    x.raw_data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// INT8 values in [-127, 127] and their scale
+fn quantize_symmetric(xs: &[f32]) -> (Vec<i8>, f32) {
+    let max = xs.iter().fold(0f32, |a, x| a.max(x.abs()));
+    let scale = if max > 0. { max / 127. } else { 1. };
+    let q = xs
+        .iter()
+        .map(|x| (x / scale).round().clamp(-127., 127.) as i8)
+        .collect();
+    (q, scale)
+}
+
+/// 64-bit FNV-1a, stable across platforms and Rust versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests_quant {
+    use super::{quantize_symmetric, quantized_counts, quantized_model, QuantMode};
+    use crate::onnx;
+    use prost::Message;
+
+    #[test]
+    fn dynamic_int8() {
+        let w: Vec<f32> = (0..12).map(|x| x as f32 / 11. - 0.5).collect();
+        let model_proto = onnx::ModelProto {
+            opset_import: vec![onnx::OperatorSetIdProto {
+                domain: String::new(),
+                version: 13,
+            }],
+            graph: Some(onnx::GraphProto {
+                node: vec![onnx::NodeProto {
+                    op_type: "MatMul".into(),
+                    input: vec!["x".into(), "w".into()],
+                    output: vec!["y".into()],
+                    ..Default::default()
+                }],
+                initializer: vec![onnx::TensorProto {
+                    name: "w".into(),
+                    dims: vec![4, 3],
+                    data_type: 1,
+                    float_data: w.clone(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let dir = std::env::temp_dir().join(format!("usls-quant-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("model.onnx");
+        std::fs::write(&src, model_proto.encode_to_vec()).unwrap();
+
+        let cache = dir.join("cache");
+        let dst = quantized_model(src.to_str().unwrap(), QuantMode::DynamicInt8, &cache).unwrap();
+        assert!(dst.exists());
+        let modified = std::fs::metadata(&dst).unwrap().modified().unwrap();
+        assert_eq!(
+            quantized_model(src.to_str().unwrap(), QuantMode::DynamicInt8, &cache).unwrap(),
+            dst
+        );
+        assert_eq!(
+            std::fs::metadata(&dst).unwrap().modified().unwrap(),
+            modified
+        );
+
+        let quantized = onnx::ModelProto::decode(std::fs::read(&dst).unwrap().as_slice()).unwrap();
+        let graph = quantized.graph.unwrap();
+        let ops: Vec<&str> = graph.node.iter().map(|x| x.op_type.as_str()).collect();
+        assert_eq!(
+            ops,
+            [
+                "DynamicQuantizeLinear",
+                "MatMulInteger",
+                "Cast",
+                "Mul",
+                "Mul"
+            ]
+        );
+        assert_eq!(graph.node.last().unwrap().output, ["y"]);
+        assert!(graph.initializer.iter().all(|x| x.name != "w"));
+        assert_eq!(
+            quantized_counts(&onnx::ModelProto {
+                graph: Some(graph.clone()),
+                ..Default::default()
+            }),
+            (1, 0)
+        );
+
+        let (q, scale) = quantize_symmetric(&w);
+        assert_eq!(q.iter().map(|x| x.abs()).max(), Some(127));
+        for (q, w) in q.iter().zip(w.iter()) {
+            assert!((*q as f32 * scale - w).abs() <= scale / 2. + 1e-6);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use anyhow::Result;
+use image::DynamicImage;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::{Annotator, DataLoader, TimeUnit, Ts, Y};
+
+/// Destination of results, driven by `run_with_sink` one image at a time.
+///
+/// Implement it to send results anywhere (a database, a queue, a socket...).
+pub trait OutputSink {
+    /// `index` counts images over the whole run, starting from 0
+    fn consume(&mut self, index: usize, y: &Y, image: &DynamicImage) -> Result<()>;
+
+    /// Called once after the last image
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Annotate and save every image with an `Annotator` (which should have a saveout)
+pub struct FileSink {
+    annotator: Annotator,
+}
+
+impl FileSink {
+    pub fn new(annotator: Annotator) -> Self {
+        Self { annotator }
+    }
+}
+
+impl OutputSink for FileSink {
+    fn consume(&mut self, _index: usize, y: &Y, image: &DynamicImage) -> Result<()> {
+        self.annotator
+            .annotate(std::slice::from_ref(image), std::slice::from_ref(y));
+        Ok(())
+    }
+}
+
+/// One JSON line per image: `{"index": .., "result": Y::to_json()}`
+pub struct JsonlSink<W: Write> {
+    writer: W,
+}
+
+impl JsonlSink<std::io::BufWriter<std::fs::File>> {
+    pub fn create<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(std::io::BufWriter::new(std::fs::File::create(
+            path,
+        )?)))
+    }
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> OutputSink for JsonlSink<W> {
+    fn consume(&mut self, index: usize, y: &Y, _image: &DynamicImage) -> Result<()> {
+        let line = serde_json::json!({ "index": index, "result": y.to_json() });
+        writeln!(self.writer, "{}", line)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Keep results in memory
+#[derive(Debug, Default)]
+pub struct CollectSink {
+    pub ys: Vec<Y>,
+}
+
+impl OutputSink for CollectSink {
+    fn consume(&mut self, _index: usize, y: &Y, _image: &DynamicImage) -> Result<()> {
+        self.ys.push(y.clone());
+        Ok(())
+    }
+}
+
+/// Aggregate numbers of a `run_with_sink` run. Stage times are kept in a [`Ts`]:
+/// `[0]` loading and decoding, `[1]` the model (its pre- and post-processing included),
+/// `[2]` the sink, one sample per batch. The model's own split into pre-processing,
+/// inference and post-processing is added with [`RunStats::with_model_ts`].
+#[derive(Debug, Default)]
+pub struct RunStats {
+    images: usize,
+    batches: usize,
+    elapsed: Duration,
+    ts: Ts,
+    model_ts: Option<Ts>,
+}
+
+impl RunStats {
+    pub fn images(&self) -> usize {
+        self.images
+    }
+
+    pub fn batches(&self) -> usize {
+        self.batches
+    }
+
+    /// Wall time of the whole run
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn images_per_sec(&self) -> f64 {
+        self.images as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn ts(&self) -> &Ts {
+        &self.ts
+    }
+
+    /// Stage times of the model's engine, e.g. `model.ts()` once the run is done
+    pub fn with_model_ts(mut self, x: &Ts) -> Self {
+        self.model_ts = Some(x.clone());
+        self
+    }
+
+    pub fn model_ts(&self) -> Option<&Ts> {
+        self.model_ts.as_ref()
+    }
+
+    /// Totals followed by `Ts::summary` of the load, model and sink stages, and of the
+    /// model's pre-processing, inference and post-processing if given
+    pub fn summary(&self, unit: TimeUnit, decimal_places: usize) -> String {
+        let mut x = format!(
+            "images: {} | batches: {} | elapsed: {:.2?} | {:.2} images/s\n{}",
+            self.images,
+            self.batches,
+            self.elapsed,
+            self.images_per_sec(),
+            self.ts.summary(unit, decimal_places)
+        );
+        if let Some(ts) = &self.model_ts {
+            x.push_str("\nmodel:\n");
+            x.push_str(&ts.summary(unit, decimal_places));
+        }
+        x
+    }
+}
+
+/// Run `f` (typically a model's `run`) over every batch of `dl` and hand each result to `sink`.
+///
+/// ```ignore
+/// let mut sink = JsonlSink::create("results.jsonl")?;
+/// let stats = run_with_sink(dl.with_progress(true), |xs| model.run(xs), &mut sink)?
+///     .with_model_ts(model.ts());
+/// println!("{}", stats.summary(TimeUnit::Ms, 2));
+/// ```
+pub fn run_with_sink<F>(mut dl: DataLoader, mut f: F, sink: &mut dyn OutputSink) -> Result<RunStats>
+where
+    F: FnMut(&[DynamicImage]) -> Result<Vec<Y>>,
+{
+    let (total, progress) = (dl.paths.len(), dl.progress);
+    let mut stats = RunStats::default();
+    let t0 = Instant::now();
+    loop {
+        let t = Instant::now();
+        let Some((xs, _paths)) = dl.next() else {
+            break;
+        };
+        stats.ts.add_or_push(0, t.elapsed());
+
+        let t = Instant::now();
+        let ys = f(&xs)?;
+        stats.ts.add_or_push(1, t.elapsed());
+
+        let t = Instant::now();
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            sink.consume(stats.images, y, x)?;
+            stats.images += 1;
+        }
+        stats.ts.add_or_push(2, t.elapsed());
+        stats.batches += 1;
+
+        if progress {
+            let done = total - dl.paths.len();
+            let secs = t0.elapsed().as_secs_f64();
+            let eta = secs / done.max(1) as f64 * dl.paths.len() as f64;
+            eprint!(
+                "\r{done}/{total} images | {:.1} images/s | ETA {eta:.0}s ",
+                stats.images as f64 / secs.max(f64::EPSILON)
+            );
+        }
+    }
+    if progress {
+        eprintln!();
+    }
+    sink.finish()?;
+    stats.elapsed = t0.elapsed();
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests_sink {
+    use super::{run_with_sink, CollectSink};
+    use crate::{DataLoader, Ts, Y};
+
+    #[test]
+    fn stats() {
+        let dir = std::env::temp_dir().join(format!("usls-sink-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = (0..5)
+            .map(|i| {
+                let p = dir.join(format!("{i}.png"));
+                image::RgbImage::new(8, 8).save(&p).unwrap();
+                p
+            })
+            .collect();
+
+        let dl = DataLoader::from_paths(&paths).with_batch(2);
+        let mut sink = CollectSink::default();
+        let stats = run_with_sink(dl, |xs| Ok(vec![Y::default(); xs.len()]), &mut sink).unwrap();
+        assert_eq!(stats.images(), 5);
+        assert_eq!(stats.batches(), 3);
+        assert_eq!(sink.ys.len(), 5);
+        assert_eq!(stats.ts().samples(1).len(), 3);
+        assert!(stats
+            .summary(crate::TimeUnit::Ms, 2)
+            .starts_with("images: 5"));
+
+        let mut ts = Ts::default();
+        for i in 0..3 {
+            ts.add_or_push(i, std::time::Duration::from_millis(1));
+        }
+        let stats = stats.with_model_ts(&ts);
+        assert_eq!(stats.model_ts().unwrap().samples(2).len(), 1);
+        let summary = stats.summary(crate::TimeUnit::Ms, 2);
+        assert!(
+            summary.contains("\nmodel:\n[0] n: 1 | avg: 1.00ms\n[1]"),
+            "{summary}"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView};
+
+use crate::{ops, Bbox, Keypoint, Y};
+
+/// Sliced inference for small objects in large images: the image is cut into overlapping
+/// tiles, the model runs on every tile (and optionally the full frame), results are moved
+/// back to image coordinates and merged with [`ops::merge_tiled_bboxes`], which also joins
+/// the halves of objects cut by a tile seam.
+///
+/// ```ignore
+/// let runner = SlicedRunner::new(640).with_overlap(0.2).with_full_frame(true);
+/// let y = runner.run(&image, |xs| model.run(xs))?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct SlicedRunner {
+    tile_size: u32,
+    overlap: f32,
+    full_frame: bool,
+    iou_threshold: f32,
+    seam_margin: f32,
+    seam_dist_ratio: f32,
+}
+
+impl SlicedRunner {
+    pub fn new(tile_size: u32) -> Self {
+        Self {
+            tile_size: tile_size.max(1),
+            overlap: 0.2,
+            full_frame: false,
+            iou_threshold: 0.5,
+            seam_margin: 4.,
+            seam_dist_ratio: 0.5,
+        }
+    }
+
+    /// Fraction of the tile size shared by neighbouring tiles, in [0, 0.9]
+    pub fn with_overlap(mut self, x: f32) -> Self {
+        self.overlap = x.clamp(0., 0.9);
+        self
+    }
+
+    /// Also run on the whole (downscaled) image, for objects larger than a tile
+    pub fn with_full_frame(mut self, x: bool) -> Self {
+        self.full_frame = x;
+        self
+    }
+
+    /// IoU above which boxes of the same class from different tiles are merged
+    pub fn with_iou(mut self, x: f32) -> Self {
+        self.iou_threshold = x;
+        self
+    }
+
+    /// `margin` and `dist_ratio` of [`ops::merge_tiled_bboxes`]
+    pub fn with_seam(mut self, margin: f32, dist_ratio: f32) -> Self {
+        self.seam_margin = margin;
+        self.seam_dist_ratio = dist_ratio;
+        self
+    }
+
+    /// Tiles of a `width` x `height` image as `(x, y, w, h)`, the last row and column
+    /// shifted back to end at the image border
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+        let step = ((self.tile_size as f32 * (1. - self.overlap)).round() as u32).max(1);
+        let starts = |n: u32| {
+            let size = self.tile_size.min(n);
+            let mut xs: Vec<u32> = (0..n.saturating_sub(size)).step_by(step as usize).collect();
+            xs.push(n - size);
+            xs.dedup();
+            (xs, size)
+        };
+        let ((xs, w), (ys, h)) = (starts(width), starts(height));
+        ys.iter()
+            .flat_map(|&y| xs.iter().map(move |&x| (x, y, w, h)))
+            .collect()
+    }
+
+    /// Whether `x` reaches, within the seam margin, a border of `tile` inside the image
+    fn touches_inner_border(
+        &self,
+        x: &Bbox,
+        (tx, ty, tw, th): (u32, u32, u32, u32),
+        width: u32,
+        height: u32,
+    ) -> bool {
+        let m = self.seam_margin;
+        let (x1, y1, x2, y2) = (tx as f32, ty as f32, (tx + tw) as f32, (ty + th) as f32);
+        (tx > 0 && x.xmin() <= x1 + m)
+            || (ty > 0 && x.ymin() <= y1 + m)
+            || (tx + tw < width && x.xmax() >= x2 - m)
+            || (ty + th < height && x.ymax() >= y2 - m)
+    }
+
+    /// Run `f` on every tile of `x`, one tile per call, and merge the results
+    pub fn run<F>(&self, x: &DynamicImage, mut f: F) -> Result<Y>
+    where
+        F: FnMut(&[DynamicImage]) -> Result<Vec<Y>>,
+    {
+        let (width, height) = x.dimensions();
+        let mut tiles = self.tiles(width, height);
+        if self.full_frame && tiles.len() > 1 {
+            tiles.push((0, 0, width, height));
+        }
+
+        let mut bboxes: Vec<Bbox> = Vec::new();
+        let mut kpts: Vec<Option<Vec<Keypoint>>> = Vec::new();
+        let mut tile_of: Vec<usize> = Vec::new();
+        for (t, &(tx, ty, tw, th)) in tiles.iter().enumerate() {
+            let tile = x.crop_imm(tx, ty, tw, th);
+            for y in f(std::slice::from_ref(&tile))? {
+                let y = y.translate(tx as f32, ty as f32);
+                let n = y.bboxes().map_or(0, |x| x.len());
+                bboxes.extend(y.bboxes().into_iter().flatten().cloned());
+                tile_of.extend(std::iter::repeat(t).take(n));
+                match y.keypoints() {
+                    Some(x) => kpts.extend((0..n).map(|i| x.get(i).cloned())),
+                    None => kpts.extend((0..n).map(|_| None)),
+                }
+            }
+        }
+
+        // a tile that only sees part of an object gives a box cut by the tile border and mostly
+        // inside the full one from another tile
+        let contained: Vec<bool> = bboxes
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let cut = self.touches_inner_border(a, tiles[tile_of[i]], width, height);
+                cut && bboxes.iter().enumerate().any(|(j, b)| {
+                    tile_of[i] != tile_of[j]
+                        && a.id() == b.id()
+                        && (b.area() > a.area() || (b.area() == a.area() && j < i))
+                        && a.intersect(b) >= 0.9 * a.area()
+                })
+            })
+            .collect();
+        let (bboxes, kpts): (Vec<Bbox>, Vec<_>) = bboxes
+            .into_iter()
+            .zip(kpts)
+            .zip(contained)
+            .filter_map(|(x, contained)| (!contained).then_some(x))
+            .unzip();
+
+        let tiles: Vec<_> = tiles
+            .iter()
+            .map(|&(x, y, w, h)| (x as f32, y as f32, w as f32, h as f32))
+            .collect();
+        let merged = ops::merge_tiled_bboxes(
+            &bboxes,
+            &tiles,
+            self.iou_threshold,
+            self.seam_margin,
+            self.seam_dist_ratio,
+        );
+
+        // keypoints of the box each merged one grew from
+        let mut y = Y::default();
+        if kpts.iter().any(|x| x.is_some()) {
+            let y_kpts: Vec<Vec<Keypoint>> = merged
+                .iter()
+                .map(|m| {
+                    bboxes
+                        .iter()
+                        .position(|b| {
+                            b.id() == m.id()
+                                && b.confidence() == m.confidence()
+                                && b.xmin() >= m.xmin()
+                                && b.ymin() >= m.ymin()
+                                && b.xmax() <= m.xmax()
+                                && b.ymax() <= m.ymax()
+                        })
+                        .and_then(|i| kpts[i].clone())
+                        .unwrap_or_default()
+                })
+                .collect();
+            y = y.with_keypoints(&y_kpts);
+        }
+        if !merged.is_empty() {
+            y = y.with_bboxes(&merged);
+        }
+        Ok(y)
+    }
+}
+
+#[cfg(test)]
+mod tests_sliced {
+    use super::SlicedRunner;
+    use crate::{Bbox, Keypoint, Y};
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    /// Box around the white pixels of a tile, with a keypoint at its top-left corner
+    fn detect(xs: &[DynamicImage]) -> anyhow::Result<Vec<Y>> {
+        Ok(xs
+            .iter()
+            .map(|x| {
+                let (mut x1, mut y1, mut x2, mut y2) = (u32::MAX, u32::MAX, 0, 0);
+                for (x, y, p) in x.pixels() {
+                    if p.0[0] > 127 {
+                        (x1, y1, x2, y2) = (x1.min(x), y1.min(y), x2.max(x + 1), y2.max(y + 1));
+                    }
+                }
+                if x2 == 0 {
+                    return Y::default();
+                }
+                let (x1, y1, x2, y2) = (x1 as f32, y1 as f32, x2 as f32, y2 as f32);
+                Y::default()
+                    .with_bboxes(&[Bbox::default()
+                        .with_xyxy(x1, y1, x2, y2)
+                        .with_id(0)
+                        .with_confidence(0.9)])
+                    .with_keypoints(&[vec![Keypoint::from((x1, y1)).with_confidence(0.9)]])
+            })
+            .collect())
+    }
+
+    #[test]
+    fn translate() {
+        let runner = SlicedRunner::new(64).with_overlap(0.25);
+        assert_eq!(
+            runner.tiles(200, 100),
+            [
+                (0, 0, 64, 64),
+                (48, 0, 64, 64),
+                (96, 0, 64, 64),
+                (136, 0, 64, 64),
+                (0, 36, 64, 64),
+                (48, 36, 64, 64),
+                (96, 36, 64, 64),
+                (136, 36, 64, 64),
+            ]
+        );
+        assert_eq!(runner.tiles(30, 20), [(0, 0, 30, 20)]);
+
+        // inside the last tile only, then across the seam at x = 64
+        for (x1, x2) in [(170, 180), (56, 72)] {
+            let mut image = RgbImage::new(200, 100);
+            for y in 10..20 {
+                for x in x1..x2 {
+                    image.put_pixel(x, y, Rgb([255; 3]));
+                }
+            }
+            let y = runner.run(&DynamicImage::ImageRgb8(image), detect).unwrap();
+            let bboxes = y.bboxes().unwrap();
+            assert_eq!(bboxes.len(), 1, "{bboxes:?}");
+            assert_eq!(
+                [
+                    bboxes[0].xmin(),
+                    bboxes[0].ymin(),
+                    bboxes[0].xmax(),
+                    bboxes[0].ymax()
+                ],
+                [x1 as f32, 10., x2 as f32, 20.]
+            );
+            let kpt = &y.keypoints().unwrap()[0][0];
+            assert_eq!((kpt.y(), kpt.confidence()), (10., 0.9));
+        }
+    }
+    #[test]
+    fn nested() {
+        // a small box inside a large one in the same tile, away from its borders, is kept
+        let runner = SlicedRunner::new(64).with_overlap(0.25);
+        let mut calls = 0;
+        let y = runner
+            .run(&DynamicImage::new_rgb8(200, 100), |xs| {
+                calls += 1;
+                let y = match calls {
+                    1 => Y::default().with_bboxes(&[
+                        Bbox::from((5., 5., 35., 35., 0, 0.9)),
+                        Bbox::from((10., 10., 10., 10., 0, 0.8)),
+                    ]),
+                    _ => Y::default(),
+                };
+                Ok(vec![y; xs.len()])
+            })
+            .unwrap();
+        assert_eq!(y.bboxes().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::models::{
+    DepthAnything, FeatureExtractor, MODNet, YOLOPv2, DB, OCR, RTDETR, RTMO, SVTR, YOLO,
+};
+use crate::{DataLoader, EnginePool, Pending, Y};
+
+type Batch = (Vec<DynamicImage>, Vec<PathBuf>);
+
+struct Queue {
+    batches: VecDeque<Result<Batch>>,
+    done: bool,   // no more batches from the decoder
+    closed: bool, // consumer dropped
+    waker: Option<Waker>,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    space: Condvar,
+    capacity: usize,
+}
+
+/// Batches decoded on a background thread and awaited from any async runtime, see
+/// [`DataLoader::into_stream`]. Up to `buffer_size` batches are kept ready ahead.
+///
+/// ```ignore
+/// let mut stream = DataLoader::new("./images")?.with_batch(4).into_stream(2);
+/// while let Some(batch) = stream.next_batch().await {
+///     let (xs, paths) = batch?;
+/// }
+/// ```
+pub struct BatchStream {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for BatchStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchStream")
+            .field("capacity", &self.shared.capacity)
+            .finish()
+    }
+}
+
+impl DataLoader {
+    /// Like [`DataLoader::with_prefetch`], but batches are awaited instead of blocking
+    pub fn into_stream(self, buffer_size: usize) -> BatchStream {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                batches: VecDeque::new(),
+                done: false,
+                closed: false,
+                waker: None,
+            }),
+            space: Condvar::new(),
+            capacity: buffer_size.max(1),
+        });
+        let (paths, batch) = (Vec::from(self.paths), self.batch.max(1));
+        let shared_ = Arc::clone(&shared);
+        std::thread::spawn(move || {
+            for chunk in paths.chunks(batch) {
+                let images = chunk
+                    .par_iter()
+                    .map(Self::try_read)
+                    .collect::<Result<Vec<_>>>()
+                    .map(|images| (images, chunk.to_vec()));
+                let mut queue = shared_.queue.lock().unwrap();
+                while queue.batches.len() >= shared_.capacity && !queue.closed {
+                    queue = shared_.space.wait(queue).unwrap();
+                }
+                if queue.closed {
+                    return;
+                }
+                queue.batches.push_back(images);
+                if let Some(waker) = queue.waker.take() {
+                    waker.wake();
+                }
+            }
+            let mut queue = shared_.queue.lock().unwrap();
+            queue.done = true;
+            if let Some(waker) = queue.waker.take() {
+                waker.wake();
+            }
+        });
+        BatchStream { shared }
+    }
+}
+
+impl BatchStream {
+    /// Next batch, `None` once all of them were taken
+    pub fn next_batch(&mut self) -> NextBatch<'_> {
+        NextBatch { stream: self }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Batch>>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match queue.batches.pop_front() {
+            Some(x) => {
+                self.shared.space.notify_one();
+                Poll::Ready(Some(x))
+            }
+            None if queue.done => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for BatchStream {
+    fn drop(&mut self) {
+        // the decoding thread stops after its current batch, it is not joined so that
+        // dropping the stream never blocks the executor
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.space.notify_all();
+    }
+}
+
+/// Future of [`BatchStream::next_batch`]
+#[derive(Debug)]
+pub struct NextBatch<'a> {
+    stream: &'a mut BatchStream,
+}
+
+impl Future for NextBatch<'_> {
+    type Output = Option<Result<Batch>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.stream.poll_next(cx)
+    }
+}
+
+type Job<M> = dyn Fn(&mut M, &[DynamicImage]) -> Result<Vec<Y>> + Send + Sync;
+
+/// A batch being inferred, or one that failed to decode and waits for its turn
+enum InFlight {
+    Job(Pending<Result<(Vec<DynamicImage>, Vec<Y>)>>, Vec<PathBuf>),
+    Failed(anyhow::Error),
+}
+
+/// Results of [`EnginePool::map_stream`], in input order
+pub struct YsStream<'a, M> {
+    pool: &'a EnginePool<M>,
+    batches: BatchStream,
+    f: Arc<Job<M>>,
+    in_flight: VecDeque<InFlight>,
+    exhausted: bool,
+}
+
+impl<M> std::fmt::Debug for YsStream<'_, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("YsStream")
+            .field("in_flight", &self.in_flight.len())
+            .finish()
+    }
+}
+
+impl<M: Send + 'static> EnginePool<M> {
+    /// Run `f` on every batch of `batches` with up to `size()` batches inferred at once while
+    /// the next ones are decoded, so decoding, pre-processing and inference overlap.
+    ///
+    /// ```ignore
+    /// let pool = EnginePool::new(2, || YOLO::new(options.clone()))?;
+    /// let mut ys = pool.map_stream(dl.into_stream(4), |model, xs| model.run(xs));
+    /// while let Some(x) = ys.next().await {
+    ///     let (xs, ys, paths) = x?;
+    /// }
+    /// ```
+    pub fn map_stream<F>(&self, batches: BatchStream, f: F) -> YsStream<'_, M>
+    where
+        F: Fn(&mut M, &[DynamicImage]) -> Result<Vec<Y>> + Send + Sync + 'static,
+    {
+        YsStream {
+            pool: self,
+            batches,
+            f: Arc::new(f),
+            in_flight: VecDeque::new(),
+            exhausted: false,
+        }
+    }
+}
+
+impl<M: Send + 'static> YsStream<'_, M> {
+    /// Images, results and paths of the next batch, `None` at the end
+    pub async fn next(&mut self) -> Option<Result<(Vec<DynamicImage>, Vec<Y>, Vec<PathBuf>)>> {
+        while !self.exhausted && self.in_flight.len() < self.pool.size() {
+            match self.batches.next_batch().await {
+                Some(Ok((xs, paths))) => {
+                    let f = Arc::clone(&self.f);
+                    let pending = self.pool.run(move |model| f(model, &xs).map(|ys| (xs, ys)));
+                    self.in_flight.push_back(InFlight::Job(pending, paths));
+                }
+                Some(Err(err)) => self.in_flight.push_back(InFlight::Failed(err)),
+                None => self.exhausted = true,
+            }
+        }
+        Some(match self.in_flight.pop_front()? {
+            InFlight::Failed(err) => Err(err),
+            InFlight::Job(pending, paths) => match pending.await.and_then(|x| x) {
+                Ok((xs, ys)) => Ok((xs, ys, paths)),
+                Err(err) => Err(anyhow!("Batch starting at {:?}: {err}", paths.first())),
+            },
+        })
+    }
+}
+
+/// Models turning a batch of images into one `Y` per image, awaited on an [`EnginePool`]
+/// with [`EnginePool::run_async`]
+pub trait Model: Send + 'static {
+    fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>>;
+}
+
+macro_rules! impl_model {
+    ($($model:ty),*) => {
+        $(
+            impl Model for $model {
+                fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>> {
+                    <$model>::run(self, xs)
+                }
+            }
+        )*
+    };
+}
+
+impl_model!(
+    YOLO,
+    RTDETR,
+    RTMO,
+    YOLOPv2,
+    DB,
+    SVTR,
+    OCR,
+    DepthAnything,
+    MODNet,
+    FeatureExtractor
+);
+
+impl<M: Model> EnginePool<M> {
+    /// Run `xs` on the next free model without blocking the executor
+    ///
+    /// ```ignore
+    /// let pool = EnginePool::new(2, || YOLO::new(options.clone()))?;
+    /// let ys = pool.run_async(xs).await?;
+    /// ```
+    pub async fn run_async(&self, xs: Vec<DynamicImage>) -> Result<Vec<Y>> {
+        self.run(move |model| Model::run(model, &xs)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests_stream {
+    use super::Model;
+    use crate::{Bbox, DataLoader, EnginePool, Y};
+    use image::DynamicImage;
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut f = std::pin::pin!(f);
+        loop {
+            match f.as_mut().poll(&mut cx) {
+                Poll::Ready(x) => return x,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn map_stream() {
+        let dir = std::env::temp_dir().join(format!("usls-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = (0..7)
+            .map(|i| {
+                let p = dir.join(format!("{i}.png"));
+                image::RgbImage::new(8 + i, 8).save(&p).unwrap();
+                p
+            })
+            .collect();
+        std::fs::write(&paths[4], b"not an image").unwrap(); // fails the second batch
+
+        let pool = EnginePool::new(2, || Ok(())).unwrap();
+        let stream = DataLoader::from_paths(&paths).with_batch(3).into_stream(1);
+        let mut ys = pool.map_stream(stream, |_, xs| {
+            Ok(xs
+                .iter()
+                .map(|x| {
+                    Y::default().with_bboxes(&[Bbox::from((0., 0., x.width() as f32, 1., 0, 1.))])
+                })
+                .collect())
+        });
+        let widths = block_on(async {
+            let mut widths = Vec::new();
+            while let Some(x) = ys.next().await {
+                match x {
+                    Ok((xs, ys, batch)) => {
+                        assert_eq!((xs.len(), ys.len()), (batch.len(), batch.len()));
+                        widths.push(
+                            ys.iter()
+                                .map(|y| y.bboxes().unwrap()[0].width() as u32)
+                                .collect(),
+                        );
+                    }
+                    Err(_) => widths.push(vec![]),
+                }
+            }
+            widths
+        });
+        // the decoding error keeps its place
+        assert_eq!(widths, [vec![8, 9, 10], vec![], vec![14]]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct Count;
+
+    impl Model for Count {
+        fn run(&mut self, xs: &[DynamicImage]) -> anyhow::Result<Vec<Y>> {
+            Ok(vec![Y::default().with_texts(&[xs.len().to_string()])])
+        }
+    }
+
+    #[test]
+    fn run_async() {
+        let pool = EnginePool::new(1, || Ok(Count)).unwrap();
+        let ys = block_on(pool.run_async(vec![DynamicImage::new_rgb8(2, 2); 3])).unwrap();
+        assert_eq!(ys[0].texts().unwrap()[0], "3");
+    }
+}
@@ -68,6 +68,37 @@ impl TokenizerStream {
         }
     }
 
+    /// Add a token and return the text it completes, if any. Text is held back while it
+    /// ends in an incomplete UTF-8 sequence (e.g. the first byte-level piece of a multi-byte
+    /// character), so only whole characters are ever returned.
+    pub fn push(&mut self, token: u32) -> Result<Option<String>> {
+        let prev_text = self.decode(&self.tokens[self.prev_index..self.current_index])?;
+        self.tokens.push(token);
+        let text = self.decode(&self.tokens[self.prev_index..])?;
+        if text.ends_with(char::REPLACEMENT_CHARACTER) {
+            return Ok(None);
+        }
+        match text.strip_prefix(prev_text.as_str()) {
+            Some(x) if !x.is_empty() => {
+                let x = x.to_string();
+                self.prev_index = self.current_index;
+                self.current_index = self.tokens.len();
+                Ok(Some(x))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Text still held back by `push` at the end of the stream, clears the stream
+    pub fn flush(&mut self) -> Result<Option<String>> {
+        let rest = match self.decode_rest()? {
+            Some(x) if !x.is_empty() => Some(x),
+            _ => None,
+        };
+        self.clear();
+        Ok(rest)
+    }
+
     pub fn decode_all(&self) -> Result<String> {
         self.decode(&self.tokens)
     }
@@ -86,3 +117,46 @@ impl TokenizerStream {
         self.current_index = 0;
     }
 }
+
+#[cfg(test)]
+mod tests_tokenizer_stream {
+    use super::TokenizerStream;
+    use std::collections::HashMap;
+    use tokenizers::{decoders::byte_level::ByteLevel, models::bpe::BPE, Tokenizer};
+
+    #[test]
+    fn push() {
+        // byte-level pieces: "é" is 0xC3 0xA9, i.e. "Ã" + "©", "ß" is 0xC3 0x9F, i.e. "Ã" + "Ł"
+        let vocab: HashMap<String, u32> = [("h", 0), ("Ã", 1), ("©", 2), ("Ł", 3), ("Ġ", 4)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        let bpe = BPE::builder()
+            .vocab_and_merges(vocab, vec![])
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(bpe);
+        tokenizer.with_decoder(ByteLevel::default());
+        let mut stream = TokenizerStream::new(tokenizer);
+
+        let mut out = Vec::new();
+        for token in [0, 1, 2, 4, 1, 3, 1] {
+            out.push(stream.push(token).unwrap());
+        }
+        assert_eq!(
+            out,
+            [
+                Some("h".to_string()),
+                None,
+                Some("é".to_string()),
+                Some(" ".to_string()),
+                None,
+                Some("ß".to_string()),
+                None,
+            ]
+        );
+        // a dangling lead byte is all that is left
+        assert_eq!(stream.flush().unwrap(), Some("\u{FFFD}".to_string()));
+        assert_eq!(stream.flush().unwrap(), None);
+    }
+}
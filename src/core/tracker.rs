@@ -0,0 +1,612 @@
+use crate::{Bbox, Y};
+
+const STD_WEIGHT_POSITION: f32 = 1. / 20.;
+const STD_WEIGHT_VELOCITY: f32 = 1. / 160.;
+
+/// Lifecycle of a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackState {
+    Tracked,
+    Lost,
+    Removed,
+}
+
+/// A tracked object as returned by `ByteTrack::update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub id: u64,
+    pub bbox: Bbox, // Kalman-filtered box, with the class and confidence of the last detection
+    pub age: usize, // frames since the track was born
+    pub hits: usize, // frames with a matched detection
+}
+
+/// Constant velocity Kalman filter over (cx, cy, aspect ratio, height).
+///
+/// Process and measurement noises are diagonal, so each coordinate and its
+/// velocity form an independent 2x2 filter.
+#[derive(Debug, Clone)]
+struct KalmanFilter {
+    mean: [[f32; 2]; 4],       // (value, velocity) per coordinate
+    covariance: [[f32; 4]; 4], // (p00, p01, p10, p11) per coordinate
+}
+
+impl KalmanFilter {
+    fn new(z: [f32; 4]) -> Self {
+        let h = z[3];
+        let std_pos = [
+            2. * STD_WEIGHT_POSITION * h,
+            2. * STD_WEIGHT_POSITION * h,
+            1e-2,
+            2. * STD_WEIGHT_POSITION * h,
+        ];
+        let std_vel = [
+            10. * STD_WEIGHT_VELOCITY * h,
+            10. * STD_WEIGHT_VELOCITY * h,
+            1e-5,
+            10. * STD_WEIGHT_VELOCITY * h,
+        ];
+        let mut mean = [[0.; 2]; 4];
+        let mut covariance = [[0.; 4]; 4];
+        for i in 0..4 {
+            mean[i] = [z[i], 0.];
+            covariance[i] = [std_pos[i].powi(2), 0., 0., std_vel[i].powi(2)];
+        }
+        Self { mean, covariance }
+    }
+
+    fn predict(&mut self) {
+        let h = self.mean[3][0];
+        let q_pos = [
+            STD_WEIGHT_POSITION * h,
+            STD_WEIGHT_POSITION * h,
+            1e-2,
+            STD_WEIGHT_POSITION * h,
+        ];
+        let q_vel = [
+            STD_WEIGHT_VELOCITY * h,
+            STD_WEIGHT_VELOCITY * h,
+            1e-5,
+            STD_WEIGHT_VELOCITY * h,
+        ];
+        for i in 0..4 {
+            let [x, v] = self.mean[i];
+            self.mean[i] = [x + v, v];
+
+            // P = F P F^T + Q, with F = [[1, 1], [0, 1]]
+            let [p00, p01, p10, p11] = self.covariance[i];
+            self.covariance[i] = [
+                p00 + p01 + p10 + p11 + q_pos[i].powi(2),
+                p01 + p11,
+                p10 + p11,
+                p11 + q_vel[i].powi(2),
+            ];
+        }
+    }
+
+    fn update(&mut self, z: [f32; 4]) {
+        let h = self.mean[3][0];
+        let r = [
+            STD_WEIGHT_POSITION * h,
+            STD_WEIGHT_POSITION * h,
+            1e-1,
+            STD_WEIGHT_POSITION * h,
+        ];
+        for i in 0..4 {
+            let [p00, p01, p10, p11] = self.covariance[i];
+            let s = p00 + r[i].powi(2);
+            let (k0, k1) = (p00 / s, p10 / s);
+            let innovation = z[i] - self.mean[i][0];
+            self.mean[i][0] += k0 * innovation;
+            self.mean[i][1] += k1 * innovation;
+            self.covariance[i] = [
+                p00 - k0 * p00,
+                p01 - k0 * p01,
+                p10 - k1 * p00,
+                p11 - k1 * p01,
+            ];
+        }
+    }
+
+    fn bbox(&self) -> Bbox {
+        let (cx, cy, a, h) = (
+            self.mean[0][0],
+            self.mean[1][0],
+            self.mean[2][0],
+            self.mean[3][0],
+        );
+        let w = a * h;
+        Bbox::default().with_xywh(cx - w / 2., cy - h / 2., w, h)
+    }
+
+    fn measurement(bbox: &Bbox) -> [f32; 4] {
+        [
+            bbox.cx(),
+            bbox.cy(),
+            bbox.width() / bbox.height().max(f32::EPSILON),
+            bbox.height(),
+        ]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct STrack {
+    id: u64,
+    kf: KalmanFilter,
+    detection: Bbox,
+    state: TrackState,
+    activated: bool,
+    start_frame: usize,
+    end_frame: usize,
+    hits: usize,
+}
+
+impl STrack {
+    fn new(detection: Bbox, id: u64, frame: usize, activated: bool) -> Self {
+        Self {
+            id,
+            kf: KalmanFilter::new(KalmanFilter::measurement(&detection)),
+            detection,
+            state: TrackState::Tracked,
+            activated,
+            start_frame: frame,
+            end_frame: frame,
+            hits: 1,
+        }
+    }
+
+    fn update(&mut self, detection: &Bbox, frame: usize) {
+        self.kf.update(KalmanFilter::measurement(detection));
+        self.detection = detection.clone();
+        self.state = TrackState::Tracked;
+        self.activated = true;
+        self.end_frame = frame;
+        self.hits += 1;
+    }
+
+    fn bbox(&self) -> Bbox {
+        let b = self.kf.bbox();
+        self.detection
+            .clone()
+            .with_xywh(b.xmin(), b.ymin(), b.width(), b.height())
+    }
+
+    fn to_track(&self, frame: usize) -> Track {
+        Track {
+            id: self.id,
+            bbox: self.bbox(),
+            age: frame - self.start_frame,
+            hits: self.hits,
+        }
+    }
+}
+
+/// ByteTrack multi-object tracker: Kalman-predicted boxes associated to detections by IoU
+/// with the Hungarian algorithm, high confidence detections first, then low confidence
+/// ones to keep tracks alive through occlusions.
+///
+/// ```ignore
+/// let mut tracker = ByteTrack::default();
+/// for (xs, _) in dl {
+///     for y in model.run(&xs)? {
+///         for track in tracker.update(&y) {
+///             println!("{} {:?}", track.id, track.bbox);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ByteTrack {
+    high_thresh: f32,
+    low_thresh: f32,
+    new_track_thresh: f32,
+    match_thresh: f32,
+    max_lost: usize,
+    tracks: Vec<STrack>,
+    frame: usize,
+    next_id: u64,
+}
+
+impl Default for ByteTrack {
+    fn default() -> Self {
+        Self {
+            high_thresh: 0.5,
+            low_thresh: 0.1,
+            new_track_thresh: 0.6,
+            match_thresh: 0.8,
+            max_lost: 30,
+            tracks: Vec::new(),
+            frame: 0,
+            next_id: 1,
+        }
+    }
+}
+
+impl ByteTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detections at or above this confidence go to the first association
+    pub fn with_high_thresh(mut self, x: f32) -> Self {
+        self.high_thresh = x;
+        self
+    }
+
+    /// Detections below this confidence are ignored
+    pub fn with_low_thresh(mut self, x: f32) -> Self {
+        self.low_thresh = x;
+        self
+    }
+
+    /// Minimum confidence of an unmatched detection to start a new track
+    pub fn with_new_track_thresh(mut self, x: f32) -> Self {
+        self.new_track_thresh = x;
+        self
+    }
+
+    /// Maximum `1 - iou` cost of a match in the first association
+    pub fn with_match_thresh(mut self, x: f32) -> Self {
+        self.match_thresh = x;
+        self
+    }
+
+    /// Frames a lost track is kept for re-association before it is removed
+    pub fn with_max_lost(mut self, x: usize) -> Self {
+        self.max_lost = x;
+        self
+    }
+
+    /// Feed the detections of the next frame, returns the active tracks
+    pub fn update(&mut self, y: &Y) -> Vec<Track> {
+        self.step(y);
+        self.tracks
+            .iter()
+            .filter(|t| t.state == TrackState::Tracked && t.activated)
+            .map(|t| t.to_track(self.frame))
+            .collect()
+    }
+
+    /// Feed consecutive frames and return them with the `track_id` of every tracked bbox
+    /// set. Bboxes keep their order (so keypoints and masks stay aligned), the ones no
+    /// confirmed track matched this frame get `None`.
+    pub fn track(&mut self, ys: &[Y]) -> Vec<Y> {
+        ys.iter()
+            .map(|y| {
+                let matched = self.step(y);
+                let Some(bboxes) = y.bboxes() else {
+                    return y.clone();
+                };
+                let mut bboxes: Vec<Bbox> = bboxes
+                    .iter()
+                    .map(|x| x.clone().with_track_id(None))
+                    .collect();
+                for (id, d) in matched {
+                    bboxes[d] = std::mem::take(&mut bboxes[d]).with_track_id(Some(id));
+                }
+                y.clone().with_bboxes(&bboxes)
+            })
+            .collect()
+    }
+
+    /// One frame of tracking, returns (track id, index of its bbox in `y`) of every
+    /// confirmed track that got a detection this frame
+    fn step(&mut self, y: &Y) -> Vec<(u64, usize)> {
+        self.frame += 1;
+        let frame = self.frame;
+        let detections = y.bboxes().cloned().unwrap_or_default();
+        let (high, low): (Vec<usize>, Vec<usize>) = (0..detections.len())
+            .filter(|&d| detections[d].confidence() >= self.low_thresh)
+            .partition(|&d| detections[d].confidence() >= self.high_thresh);
+        let pick =
+            |xs: &[usize]| -> Vec<Bbox> { xs.iter().map(|&d| detections[d].clone()).collect() };
+        let mut matched: Vec<(usize, usize)> = Vec::new(); // (track, detection)
+
+        // predict, confirmed and lost tracks form the pool of the first association
+        let mut pool = Vec::new();
+        let mut unconfirmed = Vec::new();
+        for (i, t) in self.tracks.iter_mut().enumerate() {
+            if t.state != TrackState::Removed {
+                t.kf.predict();
+            }
+            match (t.state, t.activated) {
+                (TrackState::Removed, _) => {}
+                (TrackState::Tracked, false) => unconfirmed.push(i),
+                _ => pool.push(i),
+            }
+        }
+
+        // first association, high confidence detections
+        let (matches, pool_left, high_left) =
+            self.associate(&pool, &pick(&high), self.match_thresh);
+        for (t, d) in matches {
+            self.tracks[t].update(&detections[high[d]], frame);
+            matched.push((t, high[d]));
+        }
+
+        // second association, low confidence detections with the still tracked ones
+        let tracked_left: Vec<usize> = pool_left
+            .iter()
+            .copied()
+            .filter(|&t| self.tracks[t].state == TrackState::Tracked)
+            .collect();
+        let (matches, tracked_left, _) = self.associate(&tracked_left, &pick(&low), 0.5);
+        for (t, d) in matches {
+            self.tracks[t].update(&detections[low[d]], frame);
+            matched.push((t, low[d]));
+        }
+        for t in tracked_left {
+            self.tracks[t].state = TrackState::Lost;
+        }
+
+        // unconfirmed tracks only get one more chance, with the remaining high detections
+        let high_left: Vec<usize> = high_left.into_iter().map(|d| high[d]).collect();
+        let (matches, unconfirmed_left, high_left_idx) =
+            self.associate(&unconfirmed, &pick(&high_left), 0.7);
+        for (t, d) in matches {
+            self.tracks[t].update(&detections[high_left[d]], frame);
+            matched.push((t, high_left[d]));
+        }
+        for t in unconfirmed_left {
+            self.tracks[t].state = TrackState::Removed;
+        }
+
+        // births, only confirmed right away on the first frame
+        for d in high_left_idx.into_iter().map(|d| high_left[d]) {
+            if detections[d].confidence() >= self.new_track_thresh {
+                matched.push((self.tracks.len(), d));
+                self.tracks.push(STrack::new(
+                    detections[d].clone(),
+                    self.next_id,
+                    frame,
+                    frame == 1,
+                ));
+                self.next_id += 1;
+            }
+        }
+
+        // confirmed matches, taken before removals shift the indices
+        let matched = matched
+            .into_iter()
+            .filter(|&(t, _)| self.tracks[t].activated)
+            .map(|(t, d)| (self.tracks[t].id, d))
+            .collect();
+
+        // removals
+        let max_lost = self.max_lost;
+        for t in self.tracks.iter_mut() {
+            if t.state == TrackState::Lost && frame - t.end_frame > max_lost {
+                t.state = TrackState::Removed;
+            }
+        }
+        self.tracks.retain(|t| t.state != TrackState::Removed);
+        matched
+    }
+
+    /// Tracks currently lost, kept for re-association
+    pub fn lost(&self) -> Vec<Track> {
+        self.tracks
+            .iter()
+            .filter(|t| t.state == TrackState::Lost)
+            .map(|t| t.to_track(self.frame))
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+        self.frame = 0;
+        self.next_id = 1;
+    }
+
+    /// IoU matching of `tracks` (indices into `self.tracks`) against `detections`,
+    /// returns (matches, unmatched tracks, unmatched detections)
+    fn associate(
+        &self,
+        tracks: &[usize],
+        detections: &[Bbox],
+        thresh: f32,
+    ) -> (Vec<(usize, usize)>, Vec<usize>, Vec<usize>) {
+        let cost: Vec<Vec<f32>> = tracks
+            .iter()
+            .map(|&t| {
+                let bbox = self.tracks[t].kf.bbox();
+                detections
+                    .iter()
+                    .map(|d| {
+                        let iou = bbox.iou(d);
+                        if iou.is_nan() {
+                            1.
+                        } else {
+                            1. - iou
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut matches = Vec::new();
+        let mut track_matched = vec![false; tracks.len()];
+        let mut det_matched = vec![false; detections.len()];
+        for (i, j) in linear_assignment(&cost) {
+            if cost[i][j] <= thresh {
+                matches.push((tracks[i], j));
+                track_matched[i] = true;
+                det_matched[j] = true;
+            }
+        }
+        let tracks_left = tracks
+            .iter()
+            .zip(track_matched)
+            .filter(|(_, m)| !m)
+            .map(|(&t, _)| t)
+            .collect();
+        let dets_left = (0..detections.len()).filter(|&j| !det_matched[j]).collect();
+        (matches, tracks_left, dets_left)
+    }
+}
+
+/// Minimum cost assignment of a rectangular cost matrix (Hungarian algorithm with
+/// potentials), returns (row, col) pairs.
+fn linear_assignment(cost: &[Vec<f32>]) -> Vec<(usize, usize)> {
+    let n = cost.len();
+    let m = cost.first().map_or(0, |x| x.len());
+    if n == 0 || m == 0 {
+        return Vec::new();
+    }
+    if n > m {
+        let transposed: Vec<Vec<f32>> = (0..m)
+            .map(|j| (0..n).map(|i| cost[i][j]).collect())
+            .collect();
+        return linear_assignment(&transposed)
+            .into_iter()
+            .map(|(j, i)| (i, j))
+            .collect();
+    }
+
+    // 1-based, rows n <= columns m
+    let (mut u, mut v) = (vec![0f64; n + 1], vec![0f64; m + 1]);
+    let mut p = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+    for i in 1..=n {
+        p[0] = i;
+        let mut j0 = 0;
+        let mut minv = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = p[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if !used[j] {
+                    let cur = cost[i0 - 1][j - 1] as f64 - u[i0] - v[j];
+                    if cur < minv[j] {
+                        minv[j] = cur;
+                        way[j] = j0;
+                    }
+                    if minv[j] < delta {
+                        delta = minv[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[p[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    minv[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if p[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            p[j0] = p[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+    (1..=m)
+        .filter(|&j| p[j] != 0)
+        .map(|j| (p[j] - 1, j - 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests_tracker {
+    use super::{ByteTrack, KalmanFilter};
+    use crate::{Bbox, Y};
+
+    #[test]
+    fn track() {
+        // two boxes moving right, plus a weak detection that never starts a track
+        let ys: Vec<Y> = (0..6)
+            .map(|i| {
+                let dx = 5. * i as f32;
+                Y::default().with_bboxes(&[
+                    Bbox::from((10. + dx, 10., 40., 80., 0, 0.9)),
+                    Bbox::from((300., 10., 20., 20., 1, 0.3)),
+                    Bbox::from((200. + dx, 50., 40., 80., 0, 0.8)),
+                ])
+            })
+            .collect();
+        let mut tracker = ByteTrack::new();
+        let ys = tracker.track(&ys);
+        for y in ys.iter() {
+            let ids: Vec<_> = y.bboxes().unwrap().iter().map(|x| x.track_id()).collect();
+            assert_eq!(ids, [Some(1), None, Some(2)]);
+        }
+        let bbox = &ys[0].bboxes().unwrap()[0];
+        assert_eq!(bbox.label(true, false, 2), "#1 0");
+        assert_eq!(bbox.xmin(), 10.);
+        assert_eq!(ys[0].to_json()["bboxes"][2]["track_id"], 2);
+
+        // the first box leaves, the second keeps its id
+        let y = Y::default().with_bboxes(&[Bbox::from((230., 50., 40., 80., 0, 0.8))]);
+        let y = &tracker.track(&[y])[0];
+        assert_eq!(y.bboxes().unwrap()[0].track_id(), Some(2));
+        assert_eq!(tracker.lost().len(), 1);
+    }
+
+    #[test]
+    fn kalman() {
+        // constant velocity: the filter learns it and predicts ahead of the last measurement
+        let bbox = |i: usize| Bbox::from((10. + 4. * i as f32, 20., 20., 40., 0, 0.9));
+        let mut kf = KalmanFilter::new(KalmanFilter::measurement(&bbox(0)));
+        for i in 1..20 {
+            kf.predict();
+            kf.update(KalmanFilter::measurement(&bbox(i)));
+        }
+        assert!((kf.mean[0][1] - 4.).abs() < 0.1, "{:?}", kf.mean);
+        kf.predict();
+        let predicted = kf.bbox();
+        assert!((predicted.xmin() - bbox(20).xmin()).abs() < 0.5);
+        assert!((predicted.height() - 40.).abs() < 0.01);
+
+        // a measurement pulls the state towards it, never past it
+        let before = kf.mean[1][0];
+        kf.update(KalmanFilter::measurement(
+            &bbox(20).with_xywh(90., 30., 20., 40.),
+        ));
+        assert!(kf.mean[1][0] > before && kf.mean[1][0] < 50.);
+    }
+
+    #[test]
+    fn low_confidence_association() {
+        let mut tracker = ByteTrack::new();
+        let y = |x: f32, confidence: f32| {
+            Y::default().with_bboxes(&[Bbox::from((x, 10., 40., 80., 0, confidence))])
+        };
+        for i in 0..3 {
+            let tracks = tracker.update(&y(10. + 5. * i as f32, 0.9));
+            assert_eq!(tracks.len(), 1);
+        }
+
+        // below the high threshold: only the second association keeps the track
+        let ys = tracker.track(&[y(25., 0.3), y(30., 0.3)]);
+        for y in ys.iter() {
+            assert_eq!(y.bboxes().unwrap()[0].track_id(), Some(1));
+        }
+        assert!(tracker.lost().is_empty());
+
+        // alone, a low confidence detection never starts a track
+        let mut tracker = ByteTrack::new();
+        assert!(tracker.update(&y(10., 0.3)).is_empty());
+        assert!(tracker.update(&y(10., 0.3)).is_empty());
+
+        // far from the track: it's lost, then found again by the first association
+        let mut tracker = ByteTrack::new().with_max_lost(5);
+        tracker.update(&y(10., 0.9));
+        tracker.update(&y(500., 0.3));
+        assert_eq!(tracker.lost().len(), 1);
+        let tracks = tracker.update(&y(10., 0.9));
+        assert_eq!(
+            (tracks.len(), tracks[0].id, tracker.lost().len()),
+            (1, 1, 0)
+        );
+    }
+}
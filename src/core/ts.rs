@@ -1,9 +1,44 @@
+use std::collections::VecDeque;
 use std::time::Duration;
 
-#[derive(Debug, Default)]
+/// Samples kept per stage for percentiles, older ones are dropped
+pub const TS_MAX_SAMPLES: usize = 4096;
+
+/// Unit of the durations printed by `Ts::summary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeUnit {
+    S,
+    #[default]
+    Ms,
+    Us,
+}
+
+impl TimeUnit {
+    fn convert(&self, x: Duration) -> f64 {
+        match self {
+            Self::S => x.as_secs_f64(),
+            Self::Ms => x.as_secs_f64() * 1e3,
+            Self::Us => x.as_secs_f64() * 1e6,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::S => "s",
+            Self::Ms => "ms",
+            Self::Us => "us",
+        }
+    }
+}
+
+/// Accumulated durations per stage; the last `TS_MAX_SAMPLES` calls of every stage are also
+/// kept as samples for percentiles
+#[derive(Debug, Default, Clone)]
 pub struct Ts {
     n: usize,
     ts: Vec<Duration>,
+    counts: Vec<usize>,
+    samples: Vec<VecDeque<Duration>>,
 }
 
 impl Ts {
@@ -40,11 +75,87 @@ impl Ts {
                 }
             }
         }
+        match self.samples.get_mut(i) {
+            Some(samples) => {
+                if samples.len() == TS_MAX_SAMPLES {
+                    samples.pop_front();
+                }
+                samples.push_back(x);
+                self.counts[i] += 1;
+            }
+            None => {
+                self.samples.push(VecDeque::from([x]));
+                self.counts.push(1);
+            }
+        }
         self.n += 1;
     }
 
+    /// The last `TS_MAX_SAMPLES` samples of stage `i` in call order
+    pub fn samples(&self, i: usize) -> Vec<Duration> {
+        self.samples
+            .get(i)
+            .map_or(Vec::new(), |x| x.iter().copied().collect())
+    }
+
+    /// Nearest-rank percentile `p` in [0, 100] of stage `i`
+    pub fn percentile(&self, i: usize, p: f32) -> Option<Duration> {
+        let mut xs = self.samples(i);
+        if xs.is_empty() {
+            return None;
+        }
+        xs.sort();
+        let rank = ((p.clamp(0., 100.) / 100. * xs.len() as f32).ceil() as usize).max(1);
+        Some(xs[rank - 1])
+    }
+
+    /// One line per stage: calls, average, and p50/p90/p99 of the kept samples once more than
+    /// one call is recorded
+    pub fn summary(&self, unit: TimeUnit, decimal_places: usize) -> String {
+        let fmt = |x: Duration| format!("{:.decimal_places$}{}", unit.convert(x), unit.as_str());
+        let mut lines = Vec::new();
+        for i in 0..self.samples.len() {
+            let n = self.counts[i];
+            let mut line = format!(
+                "[{i}] n: {n} | avg: {}",
+                fmt(self.ts.get(i).copied().unwrap_or_default() / n.max(1) as u32)
+            );
+            if n > 1 {
+                for p in [50., 90., 99.] {
+                    if let Some(x) = self.percentile(i, p) {
+                        line.push_str(&format!(" | p{}: {}", p, fmt(x)));
+                    }
+                }
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
     pub fn clear(&mut self) {
         self.n = Default::default();
         self.ts = Default::default();
+        self.counts = Default::default();
+        self.samples = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests_ts {
+    use super::{TimeUnit, Ts, TS_MAX_SAMPLES};
+    use std::time::Duration;
+
+    #[test]
+    fn bounded_samples() {
+        let mut ts = Ts::default();
+        for i in 0..TS_MAX_SAMPLES + 10 {
+            ts.add_or_push(0, Duration::from_millis(i as u64));
+        }
+        let xs = ts.samples(0);
+        assert_eq!(xs.len(), TS_MAX_SAMPLES);
+        assert_eq!(xs[0], Duration::from_millis(10));
+        assert!(ts
+            .summary(TimeUnit::Ms, 0)
+            .starts_with(&format!("[0] n: {} |", TS_MAX_SAMPLES + 10)));
     }
 }
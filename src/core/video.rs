@@ -0,0 +1,555 @@
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, RgbImage};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use crate::{Annotator, DataLoader, Y};
+
+/// Properties of a video stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    pub frames: Option<usize>, // not every container records it
+}
+
+/// Whether `src` is a live source rather than a file: a network stream
+/// (`rtsp://`, `rtmp://`, `http(s)://`, `udp://`, `tcp://`) or a V4L2 camera (`/dev/video0`)
+pub fn is_stream(src: &str) -> bool {
+    [
+        "rtsp://",
+        "rtmp://",
+        "http://",
+        "https://",
+        "udp://",
+        "tcp://",
+        "/dev/video",
+    ]
+    .iter()
+    .any(|x| src.starts_with(x))
+}
+
+/// ffmpeg/ffprobe options placed before `-i src`
+fn input_args(src: &str) -> &'static [&'static str] {
+    if src.starts_with("/dev/video") {
+        &["-f", "v4l2"]
+    } else if src.starts_with("rtsp://") {
+        &["-rtsp_transport", "tcp"]
+    } else {
+        &[]
+    }
+}
+
+impl VideoInfo {
+    /// Probe the first video stream of `path` (a file, stream URL or camera) with `ffprobe`
+    pub fn probe<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let output = Command::new("ffprobe")
+            .args(input_args(&path.to_string_lossy()))
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "v:0",
+                "-show_entries",
+                "stream=width,height,r_frame_rate,nb_frames",
+                "-of",
+                "default=noprint_wrappers=1",
+            ])
+            .arg(path)
+            .output()
+            .map_err(|err| anyhow!("Failed to run ffprobe, is ffmpeg installed? {err}"))?;
+        if !output.status.success() {
+            bail!(
+                "Failed to probe video at {:?}: {}",
+                path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let (mut width, mut height, mut fps, mut frames) = (None, None, None, None);
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            match line.split_once('=') {
+                Some(("width", x)) => width = x.parse().ok(),
+                Some(("height", x)) => height = x.parse().ok(),
+                Some(("nb_frames", x)) => frames = x.parse().ok(),
+                Some(("r_frame_rate", x)) => {
+                    fps = match x.split_once('/') {
+                        Some((n, d)) => match (n.parse::<f32>(), d.parse::<f32>()) {
+                            (Ok(n), Ok(d)) if d > 0. => Some(n / d),
+                            _ => None,
+                        },
+                        None => x.parse().ok(),
+                    }
+                }
+                _ => {}
+            }
+        }
+        match (width, height) {
+            (Some(width), Some(height)) => Ok(Self {
+                width,
+                height,
+                fps: fps.unwrap_or(0.),
+                frames,
+            }),
+            _ => bail!("No video stream found in {:?}", path),
+        }
+    }
+}
+
+/// Frames of a video file, network stream or camera decoded lazily by an `ffmpeg` child
+/// process, in batches of `(images, frame indices)`. Only every `stride`-th frame is kept.
+/// Live sources end only when the stream does, decode failures end it early: see `status`.
+#[derive(Debug)]
+pub struct VideoLoader {
+    src: String,
+    info: VideoInfo,
+    stride: usize,
+    batch: usize,
+    index: usize,
+    child: Child,
+    stdout: ChildStdout,
+    stderr: Option<std::thread::JoinHandle<String>>, // drained aside so ffmpeg never blocks
+    error: Option<String>,
+}
+
+impl VideoLoader {
+    pub fn new<P: AsRef<Path>>(path: P, stride: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let src = path.to_string_lossy();
+        if !path.is_file() && !is_stream(&src) {
+            bail!("{path:?} Not Exists");
+        }
+        let info = VideoInfo::probe(path)?;
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-v", "error"])
+            .args(input_args(&src))
+            .arg("-i")
+            .arg(path)
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"]);
+        Self::spawn(command, &src, info, stride)
+    }
+
+    /// Read raw RGB frames of `info`'s size from the stdout of `command`
+    fn spawn(mut command: Command, src: &str, info: VideoInfo, stride: usize) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("Failed to run ffmpeg, is it installed? {err}"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or(anyhow!("Failed to read ffmpeg output"))?;
+        let stderr = child.stderr.take().map(|mut x| {
+            std::thread::spawn(move || {
+                let mut s = String::new();
+                let _ = x.read_to_string(&mut s);
+                s
+            })
+        });
+        Ok(Self {
+            src: src.to_string(),
+            info,
+            stride: stride.max(1),
+            batch: 1,
+            index: 0,
+            child,
+            stdout,
+            stderr,
+            error: None,
+        })
+    }
+
+    pub fn with_batch(mut self, x: usize) -> Self {
+        self.batch = x.max(1);
+        self
+    }
+
+    pub fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.info.fps
+    }
+
+    /// Frames in the source video, if known
+    pub fn frames(&self) -> Option<usize> {
+        self.info.frames
+    }
+
+    /// Frame rate of the frames actually yielded
+    pub fn output_fps(&self) -> f32 {
+        self.info.fps / self.stride as f32
+    }
+
+    /// Presentation time of source frame `index`, if the frame rate is known
+    pub fn timestamp(&self, index: usize) -> Option<std::time::Duration> {
+        (self.info.fps > 0.)
+            .then(|| std::time::Duration::from_secs_f64(index as f64 / self.info.fps as f64))
+    }
+
+    /// Ok while decoding and after a clean end of stream, ffmpeg's error once it failed
+    pub fn status(&self) -> Result<()> {
+        match &self.error {
+            None => Ok(()),
+            Some(err) => bail!("{err}"),
+        }
+    }
+
+    fn read_frame(&mut self) -> Option<RgbImage> {
+        let mut buf = vec![0u8; (self.info.width * self.info.height * 3) as usize];
+        if self.stdout.read_exact(&mut buf).is_err() {
+            self.end();
+            return None;
+        }
+        RgbImage::from_raw(self.info.width, self.info.height, buf)
+    }
+
+    /// Collect the exit status and stderr of ffmpeg once its output ended
+    fn end(&mut self) {
+        let Some(stderr) = self.stderr.take() else {
+            return;
+        };
+        let status = self.child.wait();
+        let stderr = stderr.join().unwrap_or_default();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                let err = format!(
+                    "Failed to decode {:?} after {} frames ({status}): {}",
+                    self.src,
+                    self.index,
+                    stderr.trim()
+                );
+                println!("{} {err}", crate::CROSS_MARK);
+                self.error = Some(err);
+            }
+            Err(err) => self.error = Some(format!("Failed to wait for ffmpeg: {err}")),
+        }
+    }
+}
+
+impl Iterator for VideoLoader {
+    type Item = (Vec<DynamicImage>, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut images = Vec::new();
+        let mut indices = Vec::new();
+        while images.len() < self.batch {
+            let Some(frame) = self.read_frame() else {
+                break;
+            };
+            if self.index % self.stride == 0 {
+                images.push(DynamicImage::ImageRgb8(frame));
+                indices.push(self.index);
+            }
+            self.index += 1;
+        }
+        if images.is_empty() {
+            None
+        } else {
+            Some((images, indices))
+        }
+    }
+}
+
+impl Drop for VideoLoader {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Encode frames into a video file through an `ffmpeg` child process, the container
+/// follows the extension of `path` (`.mp4`, `.webm`, `.mkv`...). The frame size is taken
+/// from the first frame and every later frame must match it.
+#[derive(Debug)]
+pub struct VideoWriter {
+    path: PathBuf,
+    fps: f32,
+    codec: Option<String>, // ffmpeg encoder, picked from the container if unset
+    crf: Option<u8>,       // constant rate factor, lower is better quality
+    size: Option<(u32, u32)>,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    n: usize,
+}
+
+impl VideoWriter {
+    pub fn new<P: AsRef<Path>>(path: P, fps: f32) -> Result<Self> {
+        if fps.is_nan() || fps <= 0. {
+            bail!("Invalid fps: {fps}");
+        }
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(Self {
+            path,
+            fps,
+            codec: None,
+            crf: None,
+            size: None,
+            child: None,
+            stdin: None,
+            n: 0,
+        })
+    }
+
+    /// ffmpeg encoder name, e.g. `libx264`, `libx265` or `libvpx-vp9`
+    pub fn with_codec(mut self, x: &str) -> Self {
+        self.codec = Some(x.to_string());
+        self
+    }
+
+    /// Constant rate factor of the encoder (e.g. 23 for x264, 31 for VP9)
+    pub fn with_crf(mut self, x: u8) -> Self {
+        self.crf = Some(x);
+        self
+    }
+
+    /// Encoder options passed to ffmpeg after the input
+    fn encoder_args(&self) -> Vec<String> {
+        let ext = self
+            .path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase());
+        let codec = match (&self.codec, ext.as_deref()) {
+            (Some(codec), _) => Some(codec.as_str()),
+            (None, Some("mp4" | "mkv" | "mov")) => Some("libx264"),
+            (None, Some("webm")) => Some("libvpx-vp9"),
+            _ => None,
+        };
+        let mut args = Vec::new();
+        if let Some(codec) = codec {
+            args.extend(["-c:v".to_string(), codec.to_string()]);
+            if codec == "libvpx-vp9" && self.crf.is_some() {
+                args.extend(["-b:v".to_string(), "0".to_string()]); // constant quality mode
+            }
+        }
+        if let Some(crf) = self.crf {
+            args.extend(["-crf".to_string(), crf.to_string()]);
+        }
+        args
+    }
+
+    /// Append a frame
+    pub fn write(&mut self, frame: &RgbImage) -> Result<()> {
+        let size = frame.dimensions();
+        match self.size {
+            None => self.start(size)?,
+            Some(expected) if expected != size => bail!(
+                "Frame {} is {}x{}, expected {}x{} like the first frame",
+                self.n,
+                size.0,
+                size.1,
+                expected.0,
+                expected.1
+            ),
+            _ => {}
+        }
+        self.stdin
+            .as_mut()
+            .ok_or(anyhow!("Video writer already finished"))?
+            .write_all(frame.as_raw())
+            .map_err(|err| anyhow!("Failed to send frame {} to ffmpeg: {err}", self.n))?;
+        self.n += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Flush and close the file, called on drop as well
+    pub fn finish(&mut self) -> Result<()> {
+        drop(self.stdin.take());
+        if let Some(child) = self.child.take() {
+            let output = child.wait_with_output()?;
+            if !output.status.success() {
+                bail!(
+                    "Failed to encode video at {:?}: {}",
+                    self.path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            println!(
+                "{} Video saved to: {:?} ({} frames)",
+                crate::CHECK_MARK,
+                self.path,
+                self.n
+            );
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, (w, h): (u32, u32)) -> Result<()> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-v", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{w}x{h}"), "-r", &self.fps.to_string()])
+            .args(["-i", "-"])
+            // yuv420p needs even dimensions
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .args(self.encoder_args())
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("Failed to run ffmpeg, is it installed? {err}"))?;
+        self.stdin = child.stdin.take();
+        self.child = Some(child);
+        self.size = Some((w, h));
+        Ok(())
+    }
+}
+
+impl Drop for VideoWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            println!("{} {err}", crate::CROSS_MARK);
+        }
+    }
+}
+
+impl Annotator {
+    /// Annotate images and append them, in order, to `writer` instead of saving PNGs
+    pub fn annotate_to_video(
+        &self,
+        writer: &mut VideoWriter,
+        imgs: &[DynamicImage],
+        ys: &[Y],
+    ) -> Result<()> {
+        for (img, y) in imgs.iter().zip(ys.iter()) {
+            let frame = DynamicImage::ImageRgba8(self.plot(img, y)).into_rgb8();
+            writer.write(&frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl DataLoader {
+    /// Decode the frames of a video file, keeping one frame out of `stride`.
+    /// Needs `ffmpeg` and `ffprobe` on the PATH.
+    pub fn from_video<P: AsRef<Path>>(path: P, stride: usize) -> Result<VideoLoader> {
+        VideoLoader::new(path, stride)
+    }
+
+    /// Decode a live source frame by frame: an RTSP/RTMP/HTTP stream URL or a V4L2
+    /// camera such as `/dev/video0`. Needs `ffmpeg` and `ffprobe` on the PATH.
+    pub fn from_stream(src: &str) -> Result<VideoLoader> {
+        if !is_stream(src) {
+            bail!("{src:?} is not a stream URL or camera device");
+        }
+        VideoLoader::new(src, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests_video {
+    use super::{input_args, is_stream, VideoInfo, VideoLoader, VideoWriter};
+
+    #[test]
+    fn encoder() {
+        let dir = std::env::temp_dir().join(format!("usls-video-{}", std::process::id()));
+        let args = |name: &str| {
+            VideoWriter::new(dir.join(name), 30.)
+                .unwrap()
+                .encoder_args()
+        };
+        assert_eq!(args("a.mp4"), ["-c:v", "libx264"]);
+        assert_eq!(args("a.webm"), ["-c:v", "libvpx-vp9"]);
+        assert!(args("a.avi").is_empty());
+        let writer = VideoWriter::new(dir.join("a.webm"), 30.)
+            .unwrap()
+            .with_crf(31);
+        assert_eq!(
+            writer.encoder_args(),
+            ["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "31"]
+        );
+        let writer = VideoWriter::new(dir.join("a.mp4"), 30.)
+            .unwrap()
+            .with_codec("libx265");
+        assert_eq!(writer.encoder_args(), ["-c:v", "libx265"]);
+        assert!(VideoWriter::new(dir.join("a.mp4"), 0.).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn streams() {
+        for src in [
+            "rtsp://10.0.0.2:554/live",
+            "/dev/video0",
+            "https://x.org/a.m3u8",
+        ] {
+            assert!(is_stream(src), "{src}");
+        }
+        assert!(!is_stream("./assets/video.mp4"));
+        assert_eq!(input_args("/dev/video0"), ["-f", "v4l2"]);
+        assert_eq!(
+            input_args("rtsp://10.0.0.2/live"),
+            ["-rtsp_transport", "tcp"]
+        );
+        assert!(input_args("clip.mp4").is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn decode_error() {
+        // one 1x1 frame, then a failing decoder
+        let info = VideoInfo {
+            width: 1,
+            height: 1,
+            fps: 30.,
+            frames: None,
+        };
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "printf abc; echo 'Invalid data found' >&2; exit 1"]);
+        let mut dl = VideoLoader::spawn(command, "clip.mp4", info, 1).unwrap();
+        assert_eq!(dl.next().unwrap().1, [0]);
+        assert!(dl.status().is_ok());
+        assert!(dl.next().is_none());
+        let err = dl.status().unwrap_err().to_string();
+        assert!(
+            err.contains("after 1 frames") && err.contains("Invalid data found"),
+            "{err}"
+        );
+
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "printf abc"]);
+        let mut dl = VideoLoader::spawn(command, "clip.mp4", info, 1).unwrap();
+        assert_eq!(dl.by_ref().count(), 1);
+        assert!(dl.status().is_ok());
+    }
+
+    #[test]
+    fn frame_size_mismatch() {
+        let dir = std::env::temp_dir().join(format!("usls-video-size-{}", std::process::id()));
+        let mut writer = VideoWriter::new(dir.join("a.mp4"), 30.).unwrap();
+        writer.size = Some((4, 2)); // as if started by a first 4x2 frame
+        let err = writer.write(&image::RgbImage::new(2, 4)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Frame 0 is 2x4, expected 4x2 like the first frame"
+        );
+        assert!(writer.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
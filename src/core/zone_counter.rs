@@ -0,0 +1,224 @@
+use geo::{Contains, Point};
+use std::collections::{HashMap, HashSet};
+
+use crate::Bbox;
+
+/// Which point of a bbox decides whether it is inside a zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    Center,
+    #[default]
+    BottomCenter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneEventKind {
+    Enter,
+    Exit,
+}
+
+/// A track entering or leaving a zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZoneEvent {
+    pub zone: String,
+    pub track_id: u64,
+    pub kind: ZoneEventKind,
+    pub frame: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Zone {
+    name: String,
+    polygon: geo::Polygon<f32>,
+    inside: HashSet<u64>,
+}
+
+/// Count tracked detections inside named polygon regions, with enter/exit events per track
+#[derive(Debug, Clone)]
+pub struct ZoneCounter {
+    zones: Vec<Zone>,
+    anchor: Anchor,
+    max_missing: usize,
+    last_seen: HashMap<u64, usize>,
+    frame: usize,
+}
+
+impl Default for ZoneCounter {
+    fn default() -> Self {
+        Self {
+            zones: Vec::new(),
+            anchor: Anchor::default(),
+            max_missing: 30,
+            last_seen: HashMap::new(),
+            frame: 0,
+        }
+    }
+}
+
+impl ZoneCounter {
+    /// Add a zone from its vertices in image coordinates
+    pub fn with_zone(mut self, name: &str, points: &[(f32, f32)]) -> Self {
+        self.zones.push(Zone {
+            name: name.to_string(),
+            polygon: geo::Polygon::new(points.to_vec().into(), vec![]),
+            inside: HashSet::new(),
+        });
+        self
+    }
+
+    pub fn with_anchor(mut self, x: Anchor) -> Self {
+        self.anchor = x;
+        self
+    }
+
+    /// Frames a track can go unseen before it is considered to have left its zones
+    pub fn with_max_missing(mut self, x: usize) -> Self {
+        self.max_missing = x;
+        self
+    }
+
+    /// Feed one frame of `(track id, bbox)` pairs, returns the events it caused
+    pub fn update(&mut self, tracks: &[(u64, Bbox)]) -> Vec<ZoneEvent> {
+        let mut events = Vec::new();
+        let frame = self.frame;
+        for (track_id, bbox) in tracks.iter() {
+            self.last_seen.insert(*track_id, frame);
+            let point = match self.anchor {
+                Anchor::Center => Point::new(bbox.cx(), bbox.cy()),
+                Anchor::BottomCenter => Point::new(bbox.cx(), bbox.ymax()),
+            };
+            for zone in self.zones.iter_mut() {
+                let inside = zone.polygon.contains(&point);
+                let kind = match (inside, zone.inside.contains(track_id)) {
+                    (true, false) => {
+                        zone.inside.insert(*track_id);
+                        ZoneEventKind::Enter
+                    }
+                    (false, true) => {
+                        zone.inside.remove(track_id);
+                        ZoneEventKind::Exit
+                    }
+                    _ => continue,
+                };
+                events.push(ZoneEvent {
+                    zone: zone.name.clone(),
+                    track_id: *track_id,
+                    kind,
+                    frame,
+                });
+            }
+        }
+
+        // lost tracks leave every zone
+        let max_missing = self.max_missing;
+        let mut lost: Vec<u64> = self
+            .last_seen
+            .iter()
+            .filter(|(_, &seen)| frame - seen > max_missing)
+            .map(|(&id, _)| id)
+            .collect();
+        lost.sort_unstable();
+        for track_id in lost {
+            self.last_seen.remove(&track_id);
+            for zone in self.zones.iter_mut() {
+                if zone.inside.remove(&track_id) {
+                    events.push(ZoneEvent {
+                        zone: zone.name.clone(),
+                        track_id,
+                        kind: ZoneEventKind::Exit,
+                        frame,
+                    });
+                }
+            }
+        }
+
+        self.frame += 1;
+        events
+    }
+
+    /// Number of tracks currently inside the zone `name`
+    pub fn occupancy(&self, name: &str) -> Option<usize> {
+        self.zones
+            .iter()
+            .find(|x| x.name == name)
+            .map(|x| x.inside.len())
+    }
+
+    /// Current occupancy of every zone, in insertion order
+    pub fn occupancies(&self) -> Vec<(&str, usize)> {
+        self.zones
+            .iter()
+            .map(|x| (x.name.as_str(), x.inside.len()))
+            .collect()
+    }
+
+    pub fn reset(&mut self) {
+        self.zones.iter_mut().for_each(|x| x.inside.clear());
+        self.last_seen.clear();
+        self.frame = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests_zone_counter {
+    use super::{Anchor, ZoneCounter, ZoneEvent, ZoneEventKind};
+    use crate::Bbox;
+
+    fn event(track_id: u64, kind: ZoneEventKind, frame: usize) -> ZoneEvent {
+        ZoneEvent {
+            zone: "door".to_string(),
+            track_id,
+            kind,
+            frame,
+        }
+    }
+
+    #[test]
+    fn enter_exit() {
+        let zone = [(100., 100.), (200., 100.), (200., 200.), (100., 200.)];
+        // 40 px tall box moving down by 30 px per frame, bottom at 70, 100, 130, ...
+        let bbox = |i: usize| Bbox::from((130., 30. + 30. * i as f32, 40., 40., 0, 0.9));
+
+        let mut counter = ZoneCounter::default().with_zone("door", &zone);
+        let events: Vec<_> = (0..8)
+            .flat_map(|i| counter.update(&[(7, bbox(i))]))
+            .collect();
+        // the bottom center is inside from y = 130 to 190
+        assert_eq!(
+            events,
+            [
+                event(7, ZoneEventKind::Enter, 2),
+                event(7, ZoneEventKind::Exit, 5)
+            ]
+        );
+        assert_eq!(counter.occupancy("door"), Some(0));
+
+        // a 100 px tall box: the bottom center enters right away, the center later
+        let tall = |i: usize| Bbox::from((130., 30. + 30. * i as f32, 40., 100., 0, 0.9));
+        for (anchor, frames) in [(Anchor::BottomCenter, (0, 3)), (Anchor::Center, (1, 4))] {
+            let mut counter = ZoneCounter::default()
+                .with_zone("door", &zone)
+                .with_anchor(anchor);
+            let events: Vec<_> = (0..6)
+                .flat_map(|i| counter.update(&[(7, tall(i))]))
+                .collect();
+            assert_eq!(
+                events,
+                [
+                    event(7, ZoneEventKind::Enter, frames.0),
+                    event(7, ZoneEventKind::Exit, frames.1)
+                ]
+            );
+        }
+
+        // a track unseen for longer than max_missing leaves
+        let mut counter = ZoneCounter::default()
+            .with_zone("door", &zone)
+            .with_max_missing(2);
+        counter.update(&[(7, bbox(3))]);
+        assert_eq!(counter.occupancy("door"), Some(1));
+        let events: Vec<_> = (0..3).flat_map(|_| counter.update(&[])).collect();
+        assert_eq!(events, [event(7, ZoneEventKind::Exit, 3)]);
+        assert_eq!(counter.occupancies(), [("door", 0)]);
+    }
+}
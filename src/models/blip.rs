@@ -19,7 +19,9 @@ pub struct Blip {
 
 impl Blip {
     pub fn new(options_visual: Options, options_textual: Options) -> Result<Self> {
+        options_visual.check_input("Blip")?;
         let mut visual = OrtEngine::new(&options_visual)?;
+        options_textual.check_input("Blip")?;
         let mut textual = OrtEngine::new(&options_textual)?;
         let (batch_visual, batch_textual, height, width) = (
             visual.batch().to_owned(),
@@ -119,7 +121,7 @@ impl Blip {
             }
 
             // streaming generation
-            if let Some(t) = self.tokenizer.next_token(token_id as u32)? {
+            if let Some(t) = self.tokenizer.push(token_id as u32)? {
                 y_text.push_str(&t);
                 if show {
                     print!("{t}");
@@ -128,10 +130,15 @@ impl Blip {
                 std::io::stdout().flush()?;
             }
         }
+        if let Some(t) = self.tokenizer.flush()? {
+            y_text.push_str(&t);
+            if show {
+                print!("{t}");
+            }
+        }
         if show {
             println!();
         }
-        self.tokenizer.clear();
         ys.push(Y::default().with_texts(&[y_text]));
         Ok(ys)
     }
@@ -1,4 +1,4 @@
-use crate::{ops, Embedding, MinOptMax, Options, OrtEngine, Y};
+use crate::{ops, Embedding, MinOptMax, Options, OrtEngine, Prob, Y};
 use anyhow::Result;
 use image::DynamicImage;
 use ndarray::{Array, Array2, IxDyn};
@@ -14,12 +14,15 @@ pub struct Clip {
     pub batch_textual: MinOptMax,
     tokenizer: Tokenizer,
     context_length: usize,
+    logit_scale: f32,
 }
 
 impl Clip {
     pub fn new(options_visual: Options, options_textual: Options) -> Result<Self> {
         let context_length = 77;
+        options_visual.check_input("Clip")?;
         let mut visual = OrtEngine::new(&options_visual)?;
+        options_textual.check_input("Clip")?;
         let mut textual = OrtEngine::new(&options_textual)?;
         let (batch_visual, batch_textual, height, width) = (
             visual.inputs_minoptmax()[0][0].to_owned(),
@@ -49,6 +52,7 @@ impl Clip {
             width,
             tokenizer,
             context_length,
+            logit_scale: 100.,
         })
     }
 
@@ -83,6 +87,43 @@ impl Clip {
         Ok(Y::default().with_embedding(Embedding::new(ys[0].to_owned())))
     }
 
+    /// Zero-shot classification of every image against `labels` (e.g. "a photo of a dog"),
+    /// as softmax probabilities over the scaled cosine similarities
+    pub fn classify(&mut self, xs: &[DynamicImage], labels: &[String]) -> Result<Vec<Y>> {
+        let feats_text = self.encode_texts(labels)?;
+        let feats_image = self.encode_images(xs)?;
+        let (Some(feats_image), Some(feats_text)) =
+            (feats_image.embedding(), feats_text.embedding())
+        else {
+            anyhow::bail!("No embeddings from CLIP");
+        };
+        let ys = feats_image
+            .cosine(feats_text)?
+            .into_iter()
+            .map(|row| {
+                let max = row.iter().fold(f32::MIN, |a, &x| a.max(x));
+                let exps: Vec<f32> = row
+                    .iter()
+                    .map(|x| ((x - max) * self.logit_scale).exp())
+                    .collect();
+                let sum: f32 = exps.iter().sum();
+                let probs: Vec<f32> = exps.iter().map(|x| x / sum).collect();
+                Y::default().with_probs(
+                    Prob::default()
+                        .with_probs(&probs)
+                        .with_names(Some(labels.to_vec())),
+                )
+            })
+            .collect();
+        Ok(ys)
+    }
+
+    /// Temperature of `classify`, 100 as in the released CLIP models
+    pub fn with_logit_scale(mut self, x: f32) -> Self {
+        self.logit_scale = x;
+        self
+    }
+
     pub fn batch_visual(&self) -> usize {
         self.batch_visual.opt as usize
     }
@@ -1,23 +1,128 @@
-use crate::{ops, DynConf, Mbr, MinOptMax, Options, OrtEngine, Polygon, Y};
+use crate::{
+    models::DetectionPost, ops, DynConf, Mbr, MinOptMax, Options, OrtEngine, Polygon, Winding, Y,
+};
 use anyhow::Result;
 use image::DynamicImage;
 use ndarray::{Array, Axis, IxDyn};
 
+/// How the DB probability map is binarized before contours are traced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreshMethod {
+    /// One threshold for every pixel
+    Fixed(f32),
+    /// Per image threshold from the histogram of the map
+    Otsu,
+    /// Keep pixels above the mean of their `block` x `block` neighborhood plus `c`
+    AdaptiveMean { block: u32, c: f32 },
+}
+
+impl Default for ThreshMethod {
+    fn default() -> Self {
+        Self::Fixed(0.2)
+    }
+}
+
+impl ThreshMethod {
+    /// Zero every value of the `w` x `h` map `xs` at or below its threshold
+    pub fn apply(&self, xs: &[f32], w: usize, h: usize) -> Vec<f32> {
+        match *self {
+            Self::Fixed(t) => xs.iter().map(|&x| if x <= t { 0. } else { x }).collect(),
+            Self::Otsu => {
+                let t = Self::otsu(xs);
+                xs.iter().map(|&x| if x <= t { 0. } else { x }).collect()
+            }
+            Self::AdaptiveMean { block, c } => {
+                // summed-area table, one extra row and column of zeros
+                let mut sat = vec![0f64; (w + 1) * (h + 1)];
+                for y in 0..h {
+                    let mut row = 0f64;
+                    for x in 0..w {
+                        row += xs[y * w + x] as f64;
+                        sat[(y + 1) * (w + 1) + x + 1] = sat[y * (w + 1) + x + 1] + row;
+                    }
+                }
+                let r = (block.max(1) / 2) as usize;
+                let mut ys = vec![0f32; xs.len()];
+                for y in 0..h {
+                    let (y1, y2) = (y.saturating_sub(r), (y + r + 1).min(h));
+                    for x in 0..w {
+                        let (x1, x2) = (x.saturating_sub(r), (x + r + 1).min(w));
+                        let sum = sat[y2 * (w + 1) + x2]
+                            - sat[y1 * (w + 1) + x2]
+                            - sat[y2 * (w + 1) + x1]
+                            + sat[y1 * (w + 1) + x1];
+                        let mean = sum / ((y2 - y1) * (x2 - x1)) as f64;
+                        let v = xs[y * w + x];
+                        if v > mean as f32 + c {
+                            ys[y * w + x] = v;
+                        }
+                    }
+                }
+                ys
+            }
+        }
+    }
+
+    /// Otsu's threshold of values in [0, 1], over 256 bins
+    fn otsu(xs: &[f32]) -> f32 {
+        let mut hist = [0usize; 256];
+        for &x in xs.iter() {
+            hist[(x.clamp(0., 1.) * 255.).round() as usize] += 1;
+        }
+        let total = xs.len() as f64;
+        let sum: f64 = hist
+            .iter()
+            .enumerate()
+            .map(|(i, &n)| i as f64 * n as f64)
+            .sum();
+        let (mut sum_b, mut w_b) = (0f64, 0f64);
+        // empty bins between the modes tie, take the middle of them
+        let (mut best, mut t1, mut t2) = (0f64, 0usize, 0usize);
+        for (i, &n) in hist.iter().enumerate() {
+            w_b += n as f64;
+            if w_b == 0. {
+                continue;
+            }
+            let w_f = total - w_b;
+            if w_f == 0. {
+                break;
+            }
+            sum_b += i as f64 * n as f64;
+            let (m_b, m_f) = (sum_b / w_b, (sum - sum_b) / w_f);
+            let between = w_b * w_f * (m_b - m_f).powi(2);
+            if between > best {
+                (best, t1, t2) = (between, i, i);
+            } else if between == best {
+                t2 = i;
+            }
+        }
+        (t1 + t2) as f32 / 2. / 255.
+    }
+}
+
 #[derive(Debug)]
 pub struct DB {
     engine: OrtEngine,
+    post: DetectionPost,
     height: MinOptMax,
     width: MinOptMax,
     batch: MinOptMax,
     confs: DynConf,
     unclip_ratio: f32,
-    binary_thresh: f32,
+    resample: usize,
+    simplify: Option<f64>,
+    thresh: ThreshMethod,
     min_width: f32,
     min_height: f32,
+    polygon_winding: Option<Winding>,
+    adaptive_simplify: Option<f64>,
 }
 
+impl_ts!(DB);
+
 impl DB {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("DB")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -26,13 +131,13 @@ impl DB {
         );
         let confs = DynConf::new(&options.confs, 1);
         let unclip_ratio = options.unclip_ratio;
-        let binary_thresh = 0.2;
         let min_width = options.min_width.unwrap_or(0.);
         let min_height = options.min_height.unwrap_or(0.);
         engine.dry_run()?;
 
         Ok(Self {
             engine,
+            post: DetectionPost::new(&options),
             confs,
             height,
             width,
@@ -40,7 +145,11 @@ impl DB {
             min_width,
             min_height,
             unclip_ratio,
-            binary_thresh,
+            resample: options.db_resample,
+            simplify: options.db_simplify,
+            thresh: options.db_thresh,
+            polygon_winding: options.polygon_winding,
+            adaptive_simplify: options.adaptive_simplify,
         })
     }
 
@@ -58,7 +167,22 @@ impl DB {
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.engine.run(&[xs])?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys = Vec::new();
         for (idx, luma) in xs[0].axis_iter(Axis(0)).enumerate() {
             let mut y_bbox = Vec::new();
@@ -71,17 +195,13 @@ impl DB {
             let luma = luma.into_shape((h, w, 1))?.into_owned();
 
             // build image from ndarray
-            let v = luma
-                .into_raw_vec()
-                .iter()
-                .map(|x| if x <= &self.binary_thresh { 0.0 } else { *x })
-                .collect::<Vec<_>>();
+            let v = self.thresh.apply(&luma.into_raw_vec(), w, h);
             let mut mask_im =
                 ops::build_dyn_image_from_raw(v, self.height() as u32, self.width() as u32);
 
             // input image
-            let image_width = xs0[idx].width() as f32;
-            let image_height = xs0[idx].height() as f32;
+            let image_width = sizes[idx].0 as f32;
+            let image_height = sizes[idx].1 as f32;
 
             // rescale mask image
             let (ratio, w_mask, h_mask) =
@@ -100,19 +220,17 @@ impl DB {
 
             // loop
             for contour in contours.iter() {
-                if contour.border_type == imageproc::contours::BorderType::Hole
-                    && contour.points.len() <= 2
-                {
-                    continue;
-                }
-                let mask = Polygon::default().with_points_imageproc(&contour.points);
-                let delta = mask.area() * ratio.round() as f64 * self.unclip_ratio as f64
-                    / mask.perimeter();
-                let mask = mask
-                    .unclip(delta, image_width as f64, image_height as f64)
-                    .resample(50)
-                    // .simplify(6e-4)
-                    .convex_hull();
+                let mask = match Self::contour_polygon(
+                    contour,
+                    ratio.round() as f64 * self.unclip_ratio as f64,
+                    self.resample,
+                    self.simplify,
+                    image_width as f64,
+                    image_height as f64,
+                ) {
+                    None => continue,
+                    Some(mask) => mask,
+                };
                 if let Some(bbox) = mask.bbox() {
                     if bbox.height() < self.min_height || bbox.width() < self.min_width {
                         continue;
@@ -126,19 +244,58 @@ impl DB {
                     if let Some(mbr) = mask.mbr() {
                         y_mbrs.push(mbr.with_confidence(confidence).with_id(0));
                     }
-                    y_polygons.push(mask.with_id(0));
+                    y_polygons.push(mask.with_id(0).with_confidence(confidence));
                 } else {
                     continue;
                 }
             }
-            ys.push(
-                Y::default()
-                    .with_bboxes(&y_bbox)
-                    .with_polygons(&y_polygons)
-                    .with_mbrs(&y_mbrs),
-            );
+            let mut y = Y::default()
+                .with_bboxes(&y_bbox)
+                .with_polygons(&y_polygons)
+                .with_mbrs(&y_mbrs)
+                .clip(image_width, image_height);
+            if let Some(base_eps) = self.adaptive_simplify {
+                y = y.apply_polygons_simplify_adaptive(base_eps);
+            }
+            if let Some(winding) = self.polygon_winding {
+                y = y.apply_polygons_winding(winding);
+            }
+            ys.push(y);
         }
-        Ok(ys)
+        Ok(self.post.ids(ys))
+    }
+
+    /// Unclipped convex hull of a contour, `None` for degenerate ones (a single pixel,
+    /// a line, no area) and for those without a positive-area bbox
+    fn contour_polygon(
+        contour: &imageproc::contours::Contour<i32>,
+        unclip_ratio: f64,
+        resample: usize,
+        simplify: Option<f64>,
+        width: f64,
+        height: f64,
+    ) -> Option<Polygon> {
+        if contour.border_type == imageproc::contours::BorderType::Hole && contour.points.len() <= 2
+        {
+            return None;
+        }
+        let mask = Polygon::default().with_points_imageproc(&contour.points);
+        if mask.is_degenerate() {
+            return None;
+        }
+        let delta = mask.area() * unclip_ratio / mask.perimeter();
+        let mut mask = mask.unclip(delta, width, height);
+        if resample > 0 {
+            mask = mask.resample(resample);
+        }
+        if let Some(eps) = simplify {
+            mask = mask.simplify(eps);
+        }
+        let mask = mask.convex_hull();
+        if mask.is_degenerate() || mask.bbox().map_or(true, |x| x.area() <= 0.) {
+            return None;
+        }
+        Some(mask)
     }
 
     pub fn batch(&self) -> isize {
@@ -153,3 +310,70 @@ impl DB {
         self.height.opt
     }
 }
+
+#[cfg(test)]
+mod tests_db {
+    use super::{ThreshMethod, DB};
+
+    #[test]
+    fn otsu() {
+        // background around 0.1, text around 0.8
+        let xs: Vec<f32> = (0..1000)
+            .map(|i| match i % 10 {
+                0..=6 => 0.05 + (i % 7) as f32 * 0.02,
+                _ => 0.75 + (i % 3) as f32 * 0.05,
+            })
+            .collect();
+        let t = ThreshMethod::otsu(&xs);
+        assert!(t > 0.2 && t < 0.75, "{t}");
+        let ys = ThreshMethod::Otsu.apply(&xs, 1000, 1);
+        assert_eq!(ys.iter().filter(|&&x| x > 0.).count(), 300);
+        assert_eq!(ThreshMethod::default().apply(&xs, 1000, 1), ys);
+
+        // a brighter stroke on a lighter right half
+        let xs: Vec<f32> = (0..20)
+            .map(|x| match x {
+                5 => 0.4,
+                15 => 0.9,
+                x if x >= 10 => 0.5,
+                _ => 0.,
+            })
+            .collect();
+        let ys = ThreshMethod::AdaptiveMean { block: 3, c: 0.05 }.apply(&xs, 20, 1);
+        assert_eq!(ys[5], 0.4);
+        assert_eq!(ys[15], 0.9);
+        assert_eq!(ys[12], 0.);
+    }
+
+    #[test]
+    fn degenerate_contours() {
+        let polygons = |pixels: &[(u32, u32)]| {
+            let mut mask = image::GrayImage::new(16, 16);
+            for &(x, y) in pixels {
+                mask.put_pixel(x, y, image::Luma([255]));
+            }
+            imageproc::contours::find_contours_with_threshold::<i32>(&mask, 1)
+                .iter()
+                .filter_map(|x| DB::contour_polygon(x, 1.5, 0, None, 16., 16.))
+                .collect::<Vec<_>>()
+        };
+        assert!(polygons(&[(8, 8)]).is_empty());
+        assert!(polygons(&[(4, 8), (5, 8), (6, 8), (7, 8)]).is_empty());
+        assert!(polygons(&[(4, 4), (5, 5), (6, 6)]).is_empty());
+
+        let ps = polygons(&[
+            (4, 4),
+            (5, 4),
+            (6, 4),
+            (4, 5),
+            (5, 5),
+            (6, 5),
+            (4, 6),
+            (5, 6),
+            (6, 6),
+        ]);
+        assert_eq!(ps.len(), 1);
+        assert!(ps[0].area() > 0.);
+        assert!(ps[0].bbox().unwrap().area() > 0.);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{ops, Mask, MinOptMax, Options, OrtEngine, Y};
 use anyhow::Result;
 use image::{DynamicImage, ImageBuffer};
-use ndarray::{Array, Axis, IxDyn};
+use ndarray::{Array, Axis, Ix2, IxDyn};
 
 #[derive(Debug)]
 pub struct DepthAnything {
@@ -11,8 +11,11 @@ pub struct DepthAnything {
     batch: MinOptMax,
 }
 
+impl_ts!(DepthAnything);
+
 impl DepthAnything {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("DepthAnything")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -42,13 +45,30 @@ impl DepthAnything {
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.engine.run(&[xs])?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys: Vec<Y> = Vec::new();
         for (idx, luma) in xs[0].axis_iter(Axis(0)).enumerate() {
             let luma = luma
                 .into_shape((self.height() as usize, self.width() as usize, 1))?
                 .into_owned();
             let v = luma.into_raw_vec();
+            let depth =
+                Self::resize_depth(&v, self.width() as u32, self.height() as u32, sizes[idx])?;
             let max_ = v.iter().max_by(|x, y| x.total_cmp(y)).unwrap();
             let min_ = v.iter().min_by(|x, y| x.total_cmp(y)).unwrap();
             let v = v
@@ -60,15 +80,43 @@ impl DepthAnything {
                     .expect("Faild to create image from ndarray");
             let luma = image::DynamicImage::from(luma);
             let luma = luma.resize_exact(
-                xs0[idx].width(),
-                xs0[idx].height(),
+                sizes[idx].0,
+                sizes[idx].1,
                 image::imageops::FilterType::CatmullRom,
             );
-            ys.push(Y::default().with_masks(&[Mask::default().with_mask(luma)]));
+            ys.push(
+                Y::default()
+                    .with_masks(&[Mask::default().with_mask(luma)])
+                    .with_depth(depth),
+            );
         }
         Ok(ys)
     }
 
+    /// Raw depth of `(width, height)` resized to the image size `(w, h)`
+    fn resize_depth(
+        v: &[f32],
+        width: u32,
+        height: u32,
+        (w, h): (u32, u32),
+    ) -> Result<Array<f32, Ix2>> {
+        // the resize of `image` clamps floats to [0, 1]
+        let (min, max) = v
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(a, b), &x| (a.min(x), b.max(x)));
+        let range = (max - min).max(f32::EPSILON);
+        let v = v.iter().map(|x| (x - min) / range).collect();
+        let Some(map) = ImageBuffer::<image::Luma<f32>, Vec<f32>>::from_raw(width, height, v)
+        else {
+            anyhow::bail!("Depth is not {width}x{height}");
+        };
+        let map = image::imageops::resize(&map, w, h, image::imageops::FilterType::Triangle);
+        Ok(
+            Array::from_shape_vec((h as usize, w as usize), map.into_raw())?
+                .mapv(|x| min + x * range),
+        )
+    }
+
     pub fn batch(&self) -> isize {
         self.batch.opt
     }
@@ -81,3 +129,17 @@ impl DepthAnything {
         self.height.opt
     }
 }
+
+#[cfg(test)]
+mod tests_depth_anything {
+    use super::DepthAnything;
+
+    #[test]
+    fn resize_depth() {
+        let depth = DepthAnything::resize_depth(&[1., 2., 3., 4., 5., 6.], 3, 2, (6, 4)).unwrap();
+        assert_eq!(depth.dim(), (4, 6));
+        assert!((depth[[0, 0]] - 1.).abs() < 1e-5);
+        assert!((depth[[3, 5]] - 6.).abs() < 1e-5);
+        assert!(DepthAnything::resize_depth(&[1., 2.], 3, 2, (6, 4)).is_err());
+    }
+}
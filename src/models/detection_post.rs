@@ -0,0 +1,68 @@
+use crate::{ModelRng, Options, Y};
+
+/// Post-processing shared by detection models: per-class minimum box sizes and detection ids
+#[derive(Debug, Default)]
+pub(crate) struct DetectionPost {
+    min_box_sizes: Option<Vec<(f32, f32)>>,
+    detection_ids: bool,
+    rng: ModelRng,
+}
+
+impl DetectionPost {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            min_box_sizes: options.min_box_sizes.to_owned(),
+            detection_ids: options.detection_ids,
+            rng: ModelRng::new(options.seed),
+        }
+    }
+
+    /// Drop boxes smaller than their class's minimum size
+    pub(crate) fn filter(&self, y: Y) -> Y {
+        match &self.min_box_sizes {
+            Some(sizes) => y.apply_min_box_size(sizes),
+            None => y,
+        }
+    }
+
+    /// Give every box a UUID if enabled
+    pub(crate) fn ids(&self, ys: Vec<Y>) -> Vec<Y> {
+        if !self.detection_ids {
+            return ys;
+        }
+        ys.into_iter()
+            .map(|y| y.apply_detection_ids_with(&self.rng))
+            .collect()
+    }
+
+    /// `filter` then `ids`
+    pub(crate) fn apply(&self, ys: Vec<Y>) -> Vec<Y> {
+        self.ids(ys.into_iter().map(|y| self.filter(y)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests_detection_post {
+    use super::DetectionPost;
+    use crate::{Bbox, Options, Y};
+
+    #[test]
+    fn apply() {
+        let options = Options::default()
+            .with_min_box_size_per_class(&[(5., 5.)])
+            .with_detection_ids(true)
+            .with_seed(0);
+        let y = Y::default().with_bboxes(&[
+            Bbox::from((0., 0., 10., 10., 0, 0.9)),
+            Bbox::from((0., 0., 2., 10., 1, 0.8)), // narrower than 5
+        ]);
+        let ys = DetectionPost::new(&options).apply(vec![y.clone()]);
+        let bboxes = ys[0].bboxes().unwrap();
+        assert_eq!(bboxes.len(), 1);
+        assert!(bboxes[0].uuid().is_some());
+
+        let ys = DetectionPost::default().apply(vec![y]);
+        assert_eq!(ys[0].bboxes().unwrap().len(), 2);
+        assert!(ys[0].bboxes().unwrap()[0].uuid().is_none());
+    }
+}
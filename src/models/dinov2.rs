@@ -22,6 +22,7 @@ pub struct Dinov2 {
 
 impl Dinov2 {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("Dinov2")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.inputs_minoptmax()[0][0].to_owned(),
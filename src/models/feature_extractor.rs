@@ -0,0 +1,60 @@
+use crate::{ops, Embedding, MinOptMax, Options, OrtEngine, Y};
+use anyhow::Result;
+use image::DynamicImage;
+use ndarray::{Array, IxDyn};
+
+/// Generic backbone returning raw (possibly intermediate) tensors, see `Options::with_output_names`
+#[derive(Debug)]
+pub struct FeatureExtractor {
+    engine: OrtEngine,
+    pub height: MinOptMax,
+    pub width: MinOptMax,
+    pub batch: MinOptMax,
+}
+
+impl_ts!(FeatureExtractor);
+
+impl FeatureExtractor {
+    pub fn new(options: Options) -> Result<Self> {
+        options.check_input("FeatureExtractor")?;
+        let mut engine = OrtEngine::new(&options)?;
+        let (batch, height, width) = (
+            engine.inputs_minoptmax()[0][0].to_owned(),
+            engine.inputs_minoptmax()[0][2].to_owned(),
+            engine.inputs_minoptmax()[0][3].to_owned(),
+        );
+        engine.dry_run()?;
+
+        Ok(Self {
+            engine,
+            height,
+            width,
+            batch,
+        })
+    }
+
+    /// One `Y` per output, in the order of `outputs()`, each holding the whole batch
+    pub fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>> {
+        let xs_ = ops::resize(
+            xs,
+            self.height.opt as u32,
+            self.width.opt as u32,
+            "bilinear",
+        )?;
+        let xs_ = ops::normalize(xs_, 0., 255.);
+        let xs_ = ops::standardize(xs_, &[0.485, 0.456, 0.406], &[0.229, 0.224, 0.225]);
+        let ys: Vec<Array<f32, IxDyn>> = self.engine.run(&[xs_])?;
+        Ok(ys
+            .into_iter()
+            .map(|y| Y::default().with_embedding(Embedding::new(y)))
+            .collect())
+    }
+
+    pub fn outputs(&self) -> &Vec<String> {
+        self.engine.onames()
+    }
+
+    pub fn batch(&self) -> isize {
+        self.batch.opt
+    }
+}
@@ -1,23 +1,46 @@
+//! Models. `run_tensor` skips preprocessing and runs on an already prepared tensor,
+//! `sizes` being the original images' (width, height) used to map results back.
+
+/// Per-call engine times of a model holding an `engine`
+macro_rules! impl_ts {
+    ($model:ty) => {
+        impl $model {
+            /// Per-call engine times: `[0]` pre-processing, `[1]` inference, `[2]` post-processing
+            pub fn ts(&self) -> &crate::Ts {
+                &self.engine.ts
+            }
+        }
+    };
+}
+
 mod blip;
 mod clip;
 mod db;
 mod depth_anything;
+mod detection_post;
 mod dinov2;
+mod feature_extractor;
 mod modnet;
+mod ocr;
 mod rtdetr;
 mod rtmo;
+mod sam;
 mod svtr;
 mod yolo;
 mod yolop;
 
 pub use blip::Blip;
 pub use clip::Clip;
-pub use db::DB;
+pub use db::{ThreshMethod, DB};
 pub use depth_anything::DepthAnything;
+pub(crate) use detection_post::DetectionPost;
 pub use dinov2::Dinov2;
+pub use feature_extractor::FeatureExtractor;
 pub use modnet::MODNet;
+pub use ocr::OCR;
 pub use rtdetr::RTDETR;
 pub use rtmo::RTMO;
+pub use sam::{SamPrompt, SAM};
 pub use svtr::SVTR;
-pub use yolo::{YOLOTask, YOLOVersion, YOLO};
+pub use yolo::{PoseScore, YOLOTask, YOLOVersion, YOLO};
 pub use yolop::YOLOPv2;
@@ -12,8 +12,11 @@ pub struct MODNet {
     batch: MinOptMax,
 }
 
+impl_ts!(MODNet);
+
 impl MODNet {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("MODNet")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -42,7 +45,22 @@ impl MODNet {
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.engine.run(&[xs])?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys: Vec<Y> = Vec::new();
         for (idx, luma) in xs[0].axis_iter(Axis(0)).enumerate() {
             let luma = luma
@@ -58,8 +76,8 @@ impl MODNet {
                     .expect("Faild to create image from ndarray");
             let luma = image::DynamicImage::from(luma);
             let luma = luma.resize_exact(
-                xs0[idx].width(),
-                xs0[idx].height(),
+                sizes[idx].0,
+                sizes[idx].1,
                 image::imageops::FilterType::CatmullRom,
             );
             ys.push(Y::default().with_masks(&[Mask::default().with_mask(luma)]));
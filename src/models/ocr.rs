@@ -0,0 +1,150 @@
+use anyhow::Result;
+use image::DynamicImage;
+
+use crate::{models::DB, models::SVTR, Bbox, Options, Polygon, Y};
+
+/// End-to-end OCR: text regions from `DB`, cropped upright along their rotated rectangles
+/// and read by `SVTR`. Regions come back in reading order, each polygon named after its
+/// text (so the Annotator draws it), and `texts` of every `Y` follow the polygons.
+///
+/// ```ignore
+/// let mut ocr = OCR::new(options_det, options_rec)?;
+/// let ys = ocr.run(&xs)?;
+/// println!("{:?}", ys[0].texts());
+/// ```
+#[derive(Debug)]
+pub struct OCR {
+    pub det: DB,
+    pub rec: SVTR,
+    line_tolerance: f32,
+}
+
+impl OCR {
+    pub fn new(options_det: Options, options_rec: Options) -> Result<Self> {
+        Ok(Self {
+            det: DB::new(options_det)?,
+            rec: SVTR::new(options_rec)?,
+            line_tolerance: 0.5,
+        })
+    }
+
+    /// Regions whose vertical centers differ by less than `x` times their mean height are
+    /// read as one line, left to right. 0.5 by default.
+    pub fn with_line_tolerance(mut self, x: f32) -> Self {
+        self.line_tolerance = x;
+        self
+    }
+
+    pub fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>> {
+        let ys = self.det.run(xs)?;
+        let ys: Vec<Y> = ys
+            .iter()
+            .map(|y| Self::sort(y, self.line_tolerance))
+            .collect();
+        let ys = self.rec.recognize(xs, &ys)?;
+        Ok(ys.into_iter().map(Self::name_polygons).collect())
+    }
+
+    /// Polygons (and their bboxes and mbrs) in reading order
+    fn sort(y: &Y, line_tolerance: f32) -> Y {
+        let Some(polygons) = y.polygons() else {
+            return y.clone();
+        };
+        let bboxes: Vec<Bbox> = polygons
+            .iter()
+            .map(|x| x.bbox().unwrap_or_default())
+            .collect();
+
+        // greedy lines from top to bottom, each read left to right
+        let mut order: Vec<usize> = (0..polygons.len()).collect();
+        order.sort_by(|&a, &b| bboxes[a].cy().total_cmp(&bboxes[b].cy()));
+        let mut lines: Vec<Vec<usize>> = Vec::new();
+        for i in order {
+            let b = &bboxes[i];
+            match lines.last_mut() {
+                Some(line)
+                    if {
+                        let last = &bboxes[*line.last().unwrap()];
+                        (b.cy() - last.cy()).abs()
+                            < line_tolerance * (b.height() + last.height()) / 2.
+                    } =>
+                {
+                    line.push(i)
+                }
+                _ => lines.push(vec![i]),
+            }
+        }
+        let order: Vec<usize> = lines
+            .into_iter()
+            .flat_map(|mut line| {
+                line.sort_by(|&a, &b| bboxes[a].xmin().total_cmp(&bboxes[b].xmin()));
+                line
+            })
+            .collect();
+
+        let polygons: Vec<Polygon> = order.iter().map(|&i| polygons[i].clone()).collect();
+        let mut y = y.clone().with_polygons(&polygons);
+        if let Some(x) = y.bboxes().filter(|x| x.len() == bboxes.len()) {
+            let x: Vec<Bbox> = order.iter().map(|&i| x[i].clone()).collect();
+            y = y.with_bboxes(&x);
+        }
+        let mbrs: Vec<_> = polygons
+            .iter()
+            .filter_map(|x| {
+                x.mbr()
+                    .map(|m| m.with_id(x.id()).with_confidence(x.confidence()))
+            })
+            .collect();
+        y.with_mbrs(&mbrs)
+    }
+
+    fn name_polygons(y: Y) -> Y {
+        let (Some(polygons), Some(texts)) = (y.polygons(), y.texts()) else {
+            return y;
+        };
+        let polygons: Vec<Polygon> = polygons
+            .iter()
+            .zip(texts.iter())
+            .map(|(x, text)| x.clone().with_name(Some(text.to_owned())))
+            .collect();
+        y.with_polygons(&polygons)
+    }
+}
+
+#[cfg(test)]
+mod tests_ocr {
+    use super::OCR;
+    use crate::{Polygon, Y};
+
+    #[test]
+    fn reading_order() {
+        let polygon = |x1: f64, y1: f64, x2: f64, y2: f64| {
+            Polygon::default().with_polygon(geo::Polygon::new(
+                vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)].into(),
+                vec![],
+            ))
+        };
+        // second line, then the right and left words of a slightly tilted first line
+        let polygons = [
+            polygon(10., 50., 90., 70.),
+            polygon(60., 14., 90., 34.),
+            polygon(10., 10., 50., 30.),
+        ];
+        let bboxes: Vec<_> = polygons.iter().map(|x| x.bbox().unwrap()).collect();
+        let y = Y::default().with_polygons(&polygons).with_bboxes(&bboxes);
+
+        let y = OCR::sort(&y, 0.5);
+        let xs: Vec<_> = y
+            .bboxes()
+            .unwrap()
+            .iter()
+            .map(|x| (x.xmin(), x.ymin()))
+            .collect();
+        assert_eq!(xs, [(10., 10.), (60., 14.), (10., 50.)]);
+        assert_eq!(y.polygons().unwrap()[1].bbox().unwrap().xmin(), 60.);
+        assert_eq!(y.mbrs().unwrap().len(), 3);
+
+        let y = OCR::name_polygons(y.with_texts(&["a".into(), "b".into(), "c".into()]));
+        assert_eq!(y.polygons().unwrap()[2].name().unwrap(), "c");
+    }
+}
@@ -1,13 +1,50 @@
 use anyhow::Result;
 use image::DynamicImage;
-use ndarray::{s, Array, Axis, IxDyn};
+use ndarray::{s, Array, ArrayView2, Axis, IxDyn};
 use regex::Regex;
 
-use crate::{ops, Bbox, DynConf, MinOptMax, Options, OrtEngine, Y};
+use crate::{models::DetectionPost, ops, Bbox, DynConf, MinOptMax, Options, OrtEngine, Y};
 
+/// Output layout of the exported model
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    /// `[bs, num_query, 4 + nc]`, normalized cxcywh of the letterboxed input (Ultralytics)
+    Ultralytics,
+    /// `labels`, `boxes` (xyxy in image pixels) and `scores` of `[bs, num_query]`, with an
+    /// `orig_target_sizes` input (RT-DETRv2, D-FINE)
+    Official {
+        labels: usize,
+        boxes: usize,
+        scores: usize,
+    },
+    /// `logits` of `[bs, num_query, nc]` and normalized cxcywh `pred_boxes` (transformers)
+    Logits { logits: usize, boxes: usize },
+}
+
+impl Layout {
+    fn from_output_names(names: &[String]) -> Self {
+        let find = |x: &str| names.iter().position(|name| name == x);
+        match (find("labels"), find("boxes"), find("scores")) {
+            (Some(labels), Some(boxes), Some(scores)) => Self::Official {
+                labels,
+                boxes,
+                scores,
+            },
+            _ => match (find("logits"), find("pred_boxes")) {
+                (Some(logits), Some(boxes)) => Self::Logits { logits, boxes },
+                _ => Self::Ultralytics,
+            },
+        }
+    }
+}
+
+/// RT-DETR family: RT-DETR from Ultralytics, RT-DETRv2 and D-FINE official exports, and
+/// RT-DETR from transformers. Queries are decoded one box each, no NMS needed.
 #[derive(Debug)]
 pub struct RTDETR {
     engine: OrtEngine,
+    post: DetectionPost,
+    layout: Layout,
     height: MinOptMax,
     width: MinOptMax,
     batch: MinOptMax,
@@ -16,9 +53,13 @@ pub struct RTDETR {
     names: Option<Vec<String>>,
 }
 
+impl_ts!(RTDETR);
+
 impl RTDETR {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("RTDETR")?;
         let mut engine = OrtEngine::new(&options)?;
+        let post = DetectionPost::new(&options);
         let (batch, height, width) = (
             engine.inputs_minoptmax()[0][0].to_owned(),
             engine.inputs_minoptmax()[0][2].to_owned(),
@@ -41,11 +82,18 @@ impl RTDETR {
                 .expect("Failed to get num_classes, make it explicit with `--nc`")
                 .len(),
         );
-        let confs = DynConf::new(&options.confs, nc);
+        let confs = match (&options.class_confs_named, &names) {
+            (Some(named), Some(names)) => DynConf::from_named(named, names, &options.confs)?,
+            (Some(_), None) => anyhow::bail!("Class names are needed for `class_confs_named`"),
+            _ => DynConf::new(&options.confs, nc),
+        };
+        let layout = Layout::from_output_names(engine.onames());
         engine.dry_run()?;
 
         Ok(Self {
             engine,
+            post,
+            layout,
             confs,
             nc,
             height,
@@ -56,69 +104,142 @@ impl RTDETR {
     }
 
     pub fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>> {
-        let xs_ = ops::letterbox(
-            xs,
-            self.height() as u32,
-            self.width() as u32,
-            "catmullRom",
-            Some(114),
-        )?;
+        let (h, w) = (self.height() as u32, self.width() as u32);
+        let xs_ = match self.layout {
+            Layout::Ultralytics => ops::letterbox(xs, h, w, "catmullRom", Some(114))?,
+            _ => ops::resize(xs, h, w, "bilinear")?,
+        };
         let xs_ = ops::normalize(xs_, 0.0, 255.0);
-        let ys = self.engine.run(&[xs_])?;
+        let ys = self.inference(xs_, &ops::image_sizes(xs))?;
         self.postprocess(ys, xs)
     }
 
+    /// Official exports also take the original images' sizes as `orig_target_sizes`
+    fn inference(
+        &mut self,
+        xs: Array<f32, IxDyn>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Array<f32, IxDyn>>> {
+        match self.layout {
+            Layout::Official { .. } => {
+                let sizes_: Vec<f32> = sizes
+                    .iter()
+                    .flat_map(|&(w, h)| [w as f32, h as f32])
+                    .collect();
+                let sizes_ = Array::from_shape_vec((sizes.len(), 2), sizes_)?.into_dyn();
+                self.engine.run(&[xs, sizes_])
+            }
+            _ => self.engine.run(&[xs]),
+        }
+    }
+
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.inference(xs, sizes)?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
-        const CXYWH_OFFSET: usize = 4; // cxcywh
-        let preds = &xs[0];
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
 
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys = Vec::new();
-        for (idx, anchor) in preds.axis_iter(Axis(0)).enumerate() {
-            // [bs, num_query, 4 + nc]
-            let width_original = xs0[idx].width() as f32;
-            let height_original = xs0[idx].height() as f32;
-            let ratio =
-                (self.width() as f32 / width_original).min(self.height() as f32 / height_original);
-
-            // save each result
-            let mut y_bboxes = Vec::new();
-            for pred in anchor.axis_iter(Axis(0)) {
-                let bbox = pred.slice(s![0..CXYWH_OFFSET]);
-                let clss = pred.slice(s![CXYWH_OFFSET..CXYWH_OFFSET + self.nc]);
+        for (idx, &(width_original, height_original)) in sizes.iter().enumerate() {
+            let (width_original, height_original) = (width_original as f32, height_original as f32);
 
-                // confidence & id
-                let (id, &confidence) = clss
-                    .into_iter()
-                    .enumerate()
-                    .reduce(|max, x| if x.1 > max.1 { x } else { max })
-                    .unwrap();
+            // (id, confidence, xyxy in image pixels) of every query
+            let dets = match self.layout {
+                Layout::Ultralytics => {
+                    let ratio = (self.width() as f32 / width_original)
+                        .min(self.height() as f32 / height_original);
+                    let (sx, sy) = (self.width() as f32 / ratio, self.height() as f32 / ratio);
+                    Self::decode_scores(xs[0].slice(s![idx, .., 4..4 + self.nc]), false)
+                        .into_iter()
+                        .zip(xs[0].slice(s![idx, .., ..4]).axis_iter(Axis(0)))
+                        .map(|((id, conf), b)| {
+                            (id, conf, Self::cxcywh_to_xyxy(&b.to_vec(), sx, sy))
+                        })
+                        .collect::<Vec<_>>()
+                }
+                Layout::Logits { logits, boxes } => {
+                    Self::decode_scores(xs[logits].slice(s![idx, .., ..]), true)
+                        .into_iter()
+                        .zip(xs[boxes].slice(s![idx, .., ..]).axis_iter(Axis(0)))
+                        .map(|((id, conf), b)| {
+                            let b: Vec<f32> = b.to_vec();
+                            (
+                                id,
+                                conf,
+                                Self::cxcywh_to_xyxy(&b, width_original, height_original),
+                            )
+                        })
+                        .collect()
+                }
+                Layout::Official {
+                    labels,
+                    boxes,
+                    scores,
+                } => xs[labels]
+                    .slice(s![idx, ..])
+                    .iter()
+                    .zip(xs[scores].slice(s![idx, ..]).iter())
+                    .zip(xs[boxes].slice(s![idx, .., ..]).axis_iter(Axis(0)))
+                    .map(|((&id, &conf), b)| (id.max(0.) as usize, conf, [b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            };
 
-                // confs filter
-                if confidence < self.confs[id] {
+            let mut y_bboxes = Vec::new();
+            for (id, confidence, [x1, y1, x2, y2]) in dets {
+                if id >= self.nc || confidence < self.confs[id] {
                     continue;
                 }
-
-                // bbox -> input size scale -> rescale
-                let x = (bbox[0] - bbox[2] / 2.) * self.width() as f32 / ratio;
-                let y = (bbox[1] - bbox[3] / 2.) * self.height() as f32 / ratio;
-                let w = bbox[2] * self.width() as f32 / ratio;
-                let h = bbox[3] * self.height() as f32 / ratio;
+                let (x1, y1) = (x1.clamp(0., width_original), y1.clamp(0., height_original));
                 y_bboxes.push(
                     Bbox::default()
-                        .with_xywh(
-                            x.max(0.0f32).min(width_original),
-                            y.max(0.0f32).min(height_original),
-                            w,
-                            h,
-                        )
+                        .with_xyxy(x1, y1, x2.max(x1), y2.max(y1))
                         .with_confidence(confidence)
                         .with_id(id as isize)
-                        .with_name(self.names.as_ref().map(|names| names[id].to_owned())),
+                        .with_name(self.names.as_ref().and_then(|names| names.get(id).cloned())),
                 )
             }
             ys.push(Y::default().with_bboxes(&y_bboxes));
         }
-        Ok(ys)
+        Ok(self.post.apply(ys))
+    }
+
+    /// Best class and its score of every `[num_query, nc]` row, through a sigmoid for logits
+    fn decode_scores(x: ArrayView2<f32>, sigmoid: bool) -> Vec<(usize, f32)> {
+        x.axis_iter(Axis(0))
+            .map(|row| {
+                let (id, &score) = row
+                    .iter()
+                    .enumerate()
+                    .reduce(|max, x| if x.1 > max.1 { x } else { max })
+                    .unwrap_or((0, &f32::MIN));
+                let score = if sigmoid {
+                    1. / (1. + (-score).exp())
+                } else {
+                    score
+                };
+                (id, score)
+            })
+            .collect()
+    }
+
+    /// Normalized cxcywh scaled by `(sx, sy)` to xyxy
+    fn cxcywh_to_xyxy(b: &[f32], sx: f32, sy: f32) -> [f32; 4] {
+        [
+            (b[0] - b[2] / 2.) * sx,
+            (b[1] - b[3] / 2.) * sy,
+            (b[0] + b[2] / 2.) * sx,
+            (b[1] + b[3] / 2.) * sy,
+        ]
     }
 
     pub fn batch(&self) -> isize {
@@ -133,3 +254,44 @@ impl RTDETR {
         self.height.opt
     }
 }
+
+#[cfg(test)]
+mod tests_rtdetr {
+    use super::{Layout, RTDETR};
+    use ndarray::array;
+
+    #[test]
+    fn layout() {
+        let names = |xs: &[&str]| xs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            Layout::from_output_names(&names(&["labels", "boxes", "scores"])),
+            Layout::Official {
+                labels: 0,
+                boxes: 1,
+                scores: 2
+            }
+        );
+        assert_eq!(
+            Layout::from_output_names(&names(&["logits", "pred_boxes"])),
+            Layout::Logits {
+                logits: 0,
+                boxes: 1
+            }
+        );
+        assert_eq!(
+            Layout::from_output_names(&names(&["output0"])),
+            Layout::Ultralytics
+        );
+
+        let scores = array![[0.1, 0.7, 0.2], [0., 0., 0.]];
+        assert_eq!(
+            RTDETR::decode_scores(scores.view(), false),
+            [(1, 0.7), (0, 0.)]
+        );
+        assert_eq!(RTDETR::decode_scores(scores.view(), true)[1], (0, 0.5));
+        assert_eq!(
+            RTDETR::cxcywh_to_xyxy(&[0.5, 0.5, 0.25, 0.5], 100., 50.),
+            [37.5, 12.5, 62.5, 37.5]
+        );
+    }
+}
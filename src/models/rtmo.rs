@@ -2,11 +2,14 @@ use anyhow::Result;
 use image::DynamicImage;
 use ndarray::{Array, Axis, IxDyn};
 
-use crate::{ops, Bbox, DynConf, Keypoint, MinOptMax, Options, OrtEngine, Y};
+use crate::{
+    models::DetectionPost, ops, Bbox, DynConf, Keypoint, MinOptMax, Options, OrtEngine, Y,
+};
 
 #[derive(Debug)]
 pub struct RTMO {
     engine: OrtEngine,
+    post: DetectionPost,
     height: MinOptMax,
     width: MinOptMax,
     batch: MinOptMax,
@@ -14,8 +17,11 @@ pub struct RTMO {
     kconfs: DynConf,
 }
 
+impl_ts!(RTMO);
+
 impl RTMO {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("RTMO")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -30,6 +36,7 @@ impl RTMO {
 
         Ok(Self {
             engine,
+            post: DetectionPost::new(&options),
             confs,
             kconfs,
             height,
@@ -50,7 +57,22 @@ impl RTMO {
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.engine.run(&[xs])?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys: Vec<Y> = Vec::new();
         let (preds_bboxes, preds_kpts) = if xs[0].ndim() == 3 {
             (&xs[0], &xs[1])
@@ -63,8 +85,8 @@ impl RTMO {
             .zip(preds_kpts.axis_iter(Axis(0)))
             .enumerate()
         {
-            let width_original = xs0[idx].width() as f32;
-            let height_original = xs0[idx].height() as f32;
+            let width_original = sizes[idx].0 as f32;
+            let height_original = sizes[idx].1 as f32;
             let ratio =
                 (self.width() as f32 / width_original).min(self.height() as f32 / height_original);
 
@@ -120,7 +142,7 @@ impl RTMO {
             }
             ys.push(Y::default().with_bboxes(&y_bboxes).with_keypoints(&y_kpts));
         }
-        Ok(ys)
+        Ok(self.post.apply(ys))
     }
 
     pub fn batch(&self) -> isize {
@@ -0,0 +1,267 @@
+use anyhow::{bail, Result};
+use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+use ndarray::{s, Array, Axis, IxDyn};
+
+use crate::{ops, Mask, MinOptMax, Options, OrtEngine, Polygon, Y};
+
+/// Points and box prompting SAM, in pixels of the original image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SamPrompt {
+    coords: Vec<[f32; 2]>,
+    labels: Vec<f32>, // 1: foreground, 0: background, 2/3: box corners
+}
+
+impl SamPrompt {
+    /// Point on the object
+    pub fn with_positive_point(mut self, x: f32, y: f32) -> Self {
+        self.coords.push([x, y]);
+        self.labels.push(1.);
+        self
+    }
+
+    /// Point outside the object
+    pub fn with_negative_point(mut self, x: f32, y: f32) -> Self {
+        self.coords.push([x, y]);
+        self.labels.push(0.);
+        self
+    }
+
+    /// Box around the object, at most one per prompt: replaces any previous box
+    pub fn with_xyxy(mut self, x1: f32, y1: f32, x2: f32, y2: f32) -> Self {
+        let (coords, labels) = self
+            .coords
+            .iter()
+            .zip(self.labels.iter())
+            .filter(|(_, &label)| label != 2. && label != 3.)
+            .unzip();
+        (self.coords, self.labels) = (coords, labels);
+        self.coords.extend([[x1, y1], [x2, y2]]);
+        self.labels.extend([2., 3.]);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    /// `point_coords` and `point_labels` of the decoder, scaled by `ratio`. Without a box,
+    /// a padding point labeled -1 is appended as the official export expects.
+    fn to_tensors(&self, ratio: f32) -> (Array<f32, IxDyn>, Array<f32, IxDyn>) {
+        let mut coords: Vec<f32> = self
+            .coords
+            .iter()
+            .flat_map(|[x, y]| [x * ratio, y * ratio])
+            .collect();
+        let mut labels = self.labels.to_owned();
+        if !labels.contains(&2.) {
+            coords.extend([0., 0.]);
+            labels.push(-1.);
+        }
+        let n = labels.len();
+        (
+            Array::from_shape_vec((1, n, 2), coords).unwrap().into_dyn(),
+            Array::from_shape_vec((1, n), labels).unwrap().into_dyn(),
+        )
+    }
+}
+
+/// Segment Anything (SAM, MobileSAM...) with separate image encoder and prompt decoder
+/// engines. The image embedding is computed once per image and every prompt only runs
+/// the light decoder.
+#[derive(Debug)]
+pub struct SAM {
+    pub encoder: OrtEngine,
+    pub decoder: OrtEngine,
+    height: MinOptMax,
+    width: MinOptMax,
+    batch: MinOptMax,
+}
+
+impl SAM {
+    pub fn new(options_encoder: Options, options_decoder: Options) -> Result<Self> {
+        options_encoder.check_input("SAM")?;
+        let mut encoder = OrtEngine::new(&options_encoder)?;
+        options_decoder.check_input("SAM")?;
+        let mut decoder = OrtEngine::new(&options_decoder)?;
+        let (batch, height, width) = (
+            encoder.batch().to_owned(),
+            encoder.height().to_owned(),
+            encoder.width().to_owned(),
+        );
+        encoder.dry_run()?;
+        decoder.dry_run()?;
+
+        Ok(Self {
+            encoder,
+            decoder,
+            height,
+            width,
+            batch,
+        })
+    }
+
+    /// One prompt per image, one `Y` per image with the best mask and its outline
+    pub fn run(&mut self, xs: &[DynamicImage], prompts: &[SamPrompt]) -> Result<Vec<Y>> {
+        if xs.len() != prompts.len() {
+            bail!(
+                "SAM needs one prompt per image, got {} images and {} prompts",
+                xs.len(),
+                prompts.len()
+            );
+        }
+        let embeddings = self.encode_images(xs)?;
+        let mut ys = Vec::new();
+        for (idx, (x, prompt)) in xs.iter().zip(prompts.iter()).enumerate() {
+            let embedding = embeddings.slice_axis(Axis(0), (idx..idx + 1).into());
+            ys.push(self.decode(&embedding.to_owned(), prompt, x.dimensions())?);
+        }
+        Ok(ys)
+    }
+
+    /// Image embeddings, images are resized on their longest side and padded bottom-right.
+    /// They are encoded in chunks of the encoder's max batch, 1 for static-batch exports.
+    pub fn encode_images(&mut self, xs: &[DynamicImage]) -> Result<Array<f32, IxDyn>> {
+        let (height, width) = (self.height() as u32, self.width() as u32);
+        let mut embeddings = Vec::new();
+        for xs in xs.chunks(self.batch.max.max(1) as usize) {
+            let xs_ = ops::letterbox(xs, height, width, "bilinear", Some(0))?;
+            let mut xs_ =
+                ops::standardize(xs_, &[123.675, 116.28, 103.53], &[58.395, 57.12, 57.375]);
+
+            // padding stays at 0 after normalization
+            for (idx, x) in xs.iter().enumerate() {
+                let (w0, h0) = x.dimensions();
+                let (_, w, h) = ops::scale_wh(w0 as f32, h0 as f32, width as f32, height as f32);
+                let (w, h) = (w as usize, h as usize);
+                xs_.slice_mut(s![idx, .., h.., ..]).fill(0.);
+                xs_.slice_mut(s![idx, .., .., w..]).fill(0.);
+            }
+            let ys = self.encoder.run(&[xs_])?;
+            embeddings.push(ys[0].to_owned());
+        }
+        let views: Vec<_> = embeddings.iter().map(|x| x.view()).collect();
+        Ok(ndarray::concatenate(Axis(0), &views)?)
+    }
+
+    /// Decode one prompt on the embedding of an image of `(width, height)`
+    pub fn decode(
+        &mut self,
+        embedding: &Array<f32, IxDyn>,
+        prompt: &SamPrompt,
+        (width, height): (u32, u32),
+    ) -> Result<Y> {
+        if prompt.is_empty() {
+            bail!("Empty SAM prompt");
+        }
+        let (ratio, _, _) = ops::scale_wh(
+            width as f32,
+            height as f32,
+            self.width() as f32,
+            self.height() as f32,
+        );
+        let (coords, labels) = prompt.to_tensors(ratio);
+        let ys = self.decoder.run(&[
+            embedding.to_owned(),
+            coords,
+            labels,
+            Array::zeros((1, 1, 256, 256)).into_dyn(), // mask_input
+            Array::zeros(1).into_dyn(),                // has_mask_input
+            Array::from_vec(vec![height as f32, width as f32]).into_dyn(), // orig_im_size
+        ])?;
+
+        // masks: [1, M, height, width] logits, iou_predictions: [1, M]
+        let (masks, ious) = (&ys[0], &ys[1]);
+        let Some((best, iou)) = ious
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+        else {
+            return Ok(Y::default());
+        };
+        let mask = Self::binarize(&masks.slice(s![0, best, .., ..]).to_owned().into_dyn())?;
+        let mut y = Y::default().with_masks(&[Mask::default()
+            .with_mask(DynamicImage::from(mask.to_owned()))
+            .with_id(0)
+            .with_confidence(iou)]);
+        let contours: Vec<imageproc::contours::Contour<i32>> =
+            imageproc::contours::find_contours_with_threshold(&mask, 0);
+        if let Some(polygon) = contours
+            .iter()
+            .map(|x| {
+                Polygon::default()
+                    .with_id(0)
+                    .with_confidence(iou)
+                    .with_points_imageproc(&x.points)
+            })
+            .max_by(|x, y| x.area().total_cmp(&y.area()))
+        {
+            y = y.with_polygons(&[polygon]);
+        }
+        Ok(y)
+    }
+
+    /// Foreground at 255 where the `[height, width]` logits are positive
+    fn binarize(logits: &Array<f32, IxDyn>) -> Result<GrayImage> {
+        let &[height, width] = logits.shape() else {
+            bail!("Expected 2D mask logits, got {:?}", logits.shape());
+        };
+        Ok(GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([if logits[[y as usize, x as usize]] > 0. {
+                255
+            } else {
+                0
+            }])
+        }))
+    }
+
+    pub fn batch(&self) -> isize {
+        self.batch.opt
+    }
+
+    pub fn width(&self) -> isize {
+        self.width.opt
+    }
+
+    pub fn height(&self) -> isize {
+        self.height.opt
+    }
+}
+
+#[cfg(test)]
+mod tests_sam {
+    use super::{SamPrompt, SAM};
+    use ndarray::Array;
+
+    #[test]
+    fn prompt() {
+        let prompt = SamPrompt::default()
+            .with_positive_point(100., 50.)
+            .with_negative_point(10., 20.);
+        let (coords, labels) = prompt.to_tensors(0.5);
+        assert_eq!(coords.shape(), [1, 3, 2]);
+        assert_eq!(
+            coords.iter().copied().collect::<Vec<_>>(),
+            [50., 25., 5., 10., 0., 0.]
+        );
+        assert_eq!(labels.iter().copied().collect::<Vec<_>>(), [1., 0., -1.]);
+
+        let prompt = SamPrompt::default()
+            .with_xyxy(0., 0., 1., 1.)
+            .with_xyxy(10., 20., 30., 40.);
+        let (coords, labels) = prompt.to_tensors(2.);
+        assert_eq!(
+            coords.iter().copied().collect::<Vec<_>>(),
+            [20., 40., 60., 80.]
+        );
+        assert_eq!(labels.iter().copied().collect::<Vec<_>>(), [2., 3.]);
+        assert!(SamPrompt::default().is_empty());
+
+        let logits = Array::from_shape_vec((2, 3), vec![-1., 2., 0., 0.5, -3., 1.])
+            .unwrap()
+            .into_dyn();
+        let mask = SAM::binarize(&logits).unwrap();
+        assert_eq!(mask.dimensions(), (3, 2));
+        assert_eq!(mask.into_raw(), [0, 255, 0, 255, 0, 255]);
+    }
+}
@@ -14,8 +14,11 @@ pub struct SVTR {
     vocab: Vec<String>,
 }
 
+impl_ts!(SVTR);
+
 impl SVTR {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("SVTR")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -56,6 +59,49 @@ impl SVTR {
         self.postprocess(&ys)
     }
 
+    /// End-to-end OCR on text detections (e.g. from `DB`): every polygon (or bbox when there
+    /// are none) of `ys[i]` is cropped upright from `xs[i]` and recognized, and `texts` of
+    /// the returned `Y` follow the order of the polygons
+    pub fn recognize(&mut self, xs: &[DynamicImage], ys: &[Y]) -> Result<Vec<Y>> {
+        let mut ys_ = Vec::new();
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            let crops = Self::text_crops(x, y)?;
+            let mut texts = Vec::new();
+            for chunk in crops.chunks(self.batch.opt.max(1) as usize) {
+                for y in self.run(chunk)? {
+                    texts.extend(y.texts().cloned().unwrap_or_default());
+                }
+            }
+            ys_.push(y.clone().with_texts(&texts));
+        }
+        Ok(ys_)
+    }
+
+    /// Upright crops of the text regions, vertical ones turned to read left to right
+    fn text_crops(x: &DynamicImage, y: &Y) -> Result<Vec<DynamicImage>> {
+        let crops = match y.polygons() {
+            Some(polygons) => polygons
+                .iter()
+                .map(|polygon| match (polygon.mbr(), polygon.bbox()) {
+                    (Some(mbr), _) => mbr.crop(x),
+                    (None, Some(bbox)) => bbox.crop(x),
+                    (None, None) => anyhow::bail!("Empty text region: {polygon:?}"),
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => y.crops(x)?,
+        };
+        Ok(crops
+            .into_iter()
+            .map(|x| {
+                if x.height() as f32 >= 1.5 * x.width() as f32 {
+                    x.rotate270()
+                } else {
+                    x
+                }
+            })
+            .collect())
+    }
+
     pub fn postprocess(&self, output: &Array<f32, IxDyn>) -> Result<Vec<Y>> {
         let mut ys: Vec<Y> = Vec::new();
         for batch in output.axis_iter(Axis(0)) {
@@ -95,3 +141,32 @@ impl SVTR {
         Ok(ys)
     }
 }
+
+#[cfg(test)]
+mod tests_svtr {
+    use super::SVTR;
+    use crate::{Bbox, Polygon, Y};
+    use image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn text_crops() {
+        let x = DynamicImage::ImageRgb8(RgbImage::new(100, 100));
+        let polygon = |x1: f64, y1: f64, x2: f64, y2: f64| {
+            Polygon::default().with_polygon(geo::Polygon::new(
+                vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)].into(),
+                vec![],
+            ))
+        };
+        let y =
+            Y::default().with_polygons(&[polygon(10., 10., 50., 20.), polygon(60., 10., 70., 60.)]);
+        let sizes: Vec<_> = SVTR::text_crops(&x, &y)
+            .unwrap()
+            .iter()
+            .map(|x| (x.width(), x.height()))
+            .collect();
+        assert_eq!(sizes, [(40, 10), (50, 10)]);
+
+        let y = Y::default().with_bboxes(&[Bbox::default().with_xyxy(0., 0., 30., 10.)]);
+        assert_eq!(SVTR::text_crops(&x, &y).unwrap()[0].width(), 30);
+    }
+}
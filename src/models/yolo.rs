@@ -4,7 +4,10 @@ use image::{DynamicImage, ImageBuffer};
 use ndarray::{s, Array, Axis, IxDyn};
 use regex::Regex;
 
-use crate::{ops, Bbox, DynConf, Keypoint, Mbr, MinOptMax, Options, OrtEngine, Polygon, Prob, Y};
+use crate::{
+    models::Clip, models::DetectionPost, ops, Bbox, ChannelOrder, DynConf, Embedding, Keypoint,
+    Mask, Mbr, MinOptMax, NmsMethod, Options, OrtEngine, Polygon, Prob, Processor, Winding, Y,
+};
 
 const CXYWH_OFFSET: usize = 4;
 const KPT_STEP: usize = 3;
@@ -27,9 +30,23 @@ pub enum YOLOVersion {
     Customized,
 }
 
+/// Score used to rank poses for NMS and output ordering.
+/// Reported bbox confidences are always the detection ones.
+#[derive(Debug, Copy, Clone, Default, PartialEq, ValueEnum)]
+pub enum PoseScore {
+    /// Detection confidence only (default)
+    #[default]
+    BoxOnly,
+    /// Mean confidence of all keypoints
+    MeanKptConf,
+    /// Detection confidence x mean keypoint confidence
+    Product,
+}
+
 #[derive(Debug)]
 pub struct YOLO {
     engine: OrtEngine,
+    post: DetectionPost,
     nc: usize,
     nk: usize,
     nm: usize,
@@ -41,17 +58,32 @@ pub struct YOLO {
     confs: DynConf,
     kconfs: DynConf,
     iou: f32,
+    ious: Option<DynConf>,
     names: Option<Vec<String>>,
     names_kpt: Option<Vec<String>>,
     apply_nms: bool,
     anchors_first: bool,
     conf_independent: bool,
     apply_probs_softmax: bool,
+    uint8_mean: Option<[u8; 3]>,
+    processor: Option<Processor>,
+    polygon_winding: Option<Winding>,
+    instance_masks: bool,
+    adaptive_simplify: Option<f64>,
+    obj_conf: Option<f32>,
+    pose_score: PoseScore,
+    nms_method: NmsMethod,
+    nms_sigma: f32,
+    text_feats: Option<Array<f32, IxDyn>>,
 }
 
+impl_ts!(YOLO);
+
 impl YOLO {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("YOLO")?;
         let mut engine = OrtEngine::new(&options)?;
+        let post = DetectionPost::new(&options);
         let (batch, height, width) = (
             engine.batch().to_owned(),
             engine.height().to_owned(),
@@ -95,8 +127,17 @@ impl YOLO {
             ),
         };
 
-        // try from custom class names, and then model metadata
-        let mut names = options.names.or(Self::fetch_names(&engine));
+        // try from text prompts, custom class names, and then model metadata
+        if engine.inames().len() < 2 {
+            if let Some(prompts) = &options.text_prompts {
+                Self::check_prompts(prompts, Self::fetch_names(&engine).as_deref())?;
+            }
+        }
+        let mut names = options
+            .text_prompts
+            .or(options.names)
+            .or(Self::fetch_names(&engine));
+        let has_names = names.is_some(); // not the placeholder names made from `nc`
         let nc = match options.nc {
             Some(nc) => {
                 match &names {
@@ -135,15 +176,47 @@ impl YOLO {
         } else {
             0_usize
         };
-        let confs = DynConf::new(&options.confs, nc);
+        let confs = match (&options.class_confs_named, &names) {
+            (Some(_), _) if !has_names => {
+                anyhow::bail!("Class names are needed for `class_confs_named`")
+            }
+            (Some(named), Some(names)) => DynConf::from_named(named, names, &options.confs)?,
+            _ => DynConf::new(&options.confs, nc),
+        };
         let kconfs = DynConf::new(&options.kconfs, nk);
+        let custom_input = options.mean.is_some()
+            || options.std.is_some()
+            || options.channel_order != ChannelOrder::Rgb
+            || options.grayscale;
+        let processor = match (options.resize_mode, custom_input) {
+            (None, false) => None,
+            (mode, _) => {
+                let (w, h) = (width.opt as u32, height.opt as u32);
+                let mut processor = Processor::new(mode.unwrap_or_default(), w, h)
+                    .with_channel_order(options.channel_order)
+                    .with_grayscale(options.grayscale);
+                if let Some(mean) = options.mean {
+                    processor = processor.with_mean(mean);
+                }
+                if let Some(std) = options.std {
+                    processor = processor.with_std(std);
+                }
+                Some(processor)
+            }
+        };
         engine.dry_run()?;
 
         Ok(Self {
             engine,
+            post,
             confs,
             kconfs,
             iou: options.iou,
+            ious: match (&options.iou_per_class, options.nms_class_aware) {
+                (Some(x), _) => Some(DynConf::new(x, nc)),
+                (None, true) => Some(DynConf::new(&[options.iou], nc)),
+                (None, false) => None,
+            },
             nc,
             nk,
             nm,
@@ -158,33 +231,143 @@ impl YOLO {
             conf_independent,
             apply_nms,
             apply_probs_softmax,
+            uint8_mean: options.uint8_mean,
+            processor,
+            polygon_winding: options.polygon_winding,
+            instance_masks: options.instance_masks,
+            adaptive_simplify: options.adaptive_simplify,
+            obj_conf: options.obj_conf,
+            pose_score: options.pose_score,
+            nms_method: options.nms_method,
+            nms_sigma: options.nms_sigma,
+            text_feats: None,
         })
     }
 
+    /// Embed the class names (the text prompts) with the textual encoder of `clip`, for
+    /// YOLO-World models exported with a text embeddings input
+    pub fn encode_prompts(&mut self, clip: &mut Clip) -> Result<()> {
+        let Some(names) = self.names.to_owned() else {
+            anyhow::bail!("No text prompts to encode, set them with `Options::with_text_prompts`");
+        };
+        let y = clip.encode_texts(&names)?;
+        let Some(embedding) = y.embedding() else {
+            anyhow::bail!("No text embeddings from CLIP");
+        };
+        self.text_feats = Some(Self::text_feats(embedding)?);
+        Ok(())
+    }
+
+    /// Models without a text embeddings input have their vocabulary baked in, the prompts
+    /// can only name the same classes
+    fn check_prompts(prompts: &[String], baked: Option<&[String]>) -> Result<()> {
+        match baked {
+            Some(baked) if baked != prompts => anyhow::bail!(
+                "This model has its classes baked in, text prompts {prompts:?} don't match {baked:?}"
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// `[1, nc, ndim]` L2-normalized text embeddings
+    fn text_feats(x: &Embedding) -> Result<Array<f32, IxDyn>> {
+        let x = x.data().to_owned().into_dimensionality::<ndarray::Ix2>()?;
+        let norms = x
+            .mapv(|x| x * x)
+            .sum_axis(Axis(1))
+            .mapv(|x| x.sqrt().max(f32::EPSILON));
+        Ok((x / norms.insert_axis(Axis(1)))
+            .insert_axis(Axis(0))
+            .into_dyn())
+    }
+
+    /// Engine inputs, the text embeddings repeated for every image when the model takes them
+    fn inputs(&self, xs: Array<f32, IxDyn>) -> Result<Vec<Array<f32, IxDyn>>> {
+        if self.engine.inames().len() < 2 {
+            return Ok(vec![xs]);
+        }
+        let Some(text_feats) = &self.text_feats else {
+            anyhow::bail!("This model takes text embeddings, call `YOLO::encode_prompts` first");
+        };
+        let n = xs.shape()[0];
+        let views = vec![text_feats.view(); n];
+        let text_feats = ndarray::concatenate(Axis(0), &views)?;
+        Ok(vec![xs, text_feats])
+    }
+
     pub fn run(&mut self, xs: &[DynamicImage]) -> Result<Vec<Y>> {
-        let xs_ = match self.task {
-            YOLOTask::Classify => {
-                ops::resize(xs, self.height() as u32, self.width() as u32, "bilinear")?
+        let (h, w) = (self.height() as u32, self.width() as u32);
+        if let (Some(processor), false) =
+            (&mut self.processor, matches!(self.task, YOLOTask::Classify))
+        {
+            let xs_ = processor.process(xs)?;
+            let xs_ = match self.uint8_mean {
+                _ if processor.is_normalized() => xs_,
+                None => ops::normalize(xs_, 0., 255.),
+                Some(mean) => ops::standardize(
+                    xs_,
+                    &[mean[0] as f32, mean[1] as f32, mean[2] as f32],
+                    &[1., 1., 1.],
+                ),
+            };
+            let infos = processor.infos().to_vec();
+            let xs_ = self.inputs(xs_)?;
+            let ys = self.engine.run(&xs_)?;
+            let ys = self.postprocess_with_sizes(ys, &vec![(w, h); infos.len()])?;
+            return Ok(ys
+                .into_iter()
+                .zip(infos.iter())
+                .map(|(y, info)| {
+                    y.unresize(info)
+                        .clip(info.width0 as f32, info.height0 as f32)
+                })
+                .collect());
+        }
+        let xs_ = match (&self.task, self.uint8_mean) {
+            (YOLOTask::Classify, None) => {
+                ops::normalize(ops::resize(xs, h, w, "bilinear")?, 0., 255.)
             }
-            _ => ops::letterbox(
-                xs,
-                self.height() as u32,
-                self.width() as u32,
-                "catmullRom",
-                Some(114),
-            )?,
+            (YOLOTask::Classify, Some(mean)) => ops::resize_u8_mean(xs, h, w, "bilinear", mean)?,
+            (_, None) => {
+                ops::normalize(ops::letterbox(xs, h, w, "catmullRom", Some(114))?, 0., 255.)
+            }
+            (_, Some(mean)) => ops::letterbox_u8_mean(xs, h, w, "catmullRom", Some(114), mean)?,
         };
-        let xs_ = ops::normalize(xs_, 0., 255.);
-        let ys = self.engine.run(&[xs_])?;
+        let xs_ = self.inputs(xs_)?;
+        let ys = self.engine.run(&xs_)?;
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let xs = self.inputs(xs)?;
+        let ys = self.engine.run(&xs)?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
+    /// Box of a `[cx, cy, w, h]` prediction scaled back by `ratio`, clipped to the image
+    fn cxcywh_to_bbox(cxcywh: [f32; 4], ratio: f32, image_width: f32, image_height: f32) -> Bbox {
+        let [cx, cy, w, h] = cxcywh.map(|x| x / ratio);
+        Bbox::default()
+            .with_xywh(cx - w / 2., cy - h / 2., w, h)
+            .clip(image_width, image_height)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys = Vec::new();
         let protos = if xs.len() == 2 { Some(&xs[1]) } else { None };
         for (idx, preds) in xs[0].axis_iter(Axis(0)).enumerate() {
-            let image_width = xs0[idx].width() as f32;
-            let image_height = xs0[idx].height() as f32;
+            let image_width = sizes[idx].0 as f32;
+            let image_height = sizes[idx].1 as f32;
 
             // decode
             match self.task {
@@ -232,7 +415,8 @@ impl YOLO {
                         } else {
                             (h, w, radians + std::f32::consts::PI / 2.)
                         };
-                        let radians = radians % std::f32::consts::PI;
+                        // wrap into [0, pi), a rotated box is the same every half turn
+                        let radians = radians.rem_euclid(std::f32::consts::PI);
                         y_mbrs.push(
                             Mbr::from_cxcywhr(
                                 cx as f64,
@@ -246,7 +430,11 @@ impl YOLO {
                             .with_name(self.names.as_ref().map(|names| names[id].to_owned())),
                         );
                     }
-                    ys.push(Y::default().with_mbrs(&y_mbrs).apply_mbrs_nms(self.iou));
+                    ys.push(
+                        Y::default()
+                            .with_mbrs(&y_mbrs)
+                            .apply_mbrs_nms_probiou(self.iou),
+                    );
                 }
                 _ => {
                     let mut y_bboxes: Vec<Bbox> = Vec::new();
@@ -274,6 +462,7 @@ impl YOLO {
                                 let h = y2 - y;
                                 let y_bbox = Bbox::default()
                                     .with_xywh(x, y, w, h)
+                                    .clip(image_width, image_height)
                                     .with_confidence(confidence)
                                     .with_id(class_id as isize)
                                     .with_id_born(i as isize)
@@ -293,41 +482,99 @@ impl YOLO {
                                 } else {
                                     (1.0, pred.slice(s![CXYWH_OFFSET..CXYWH_OFFSET + self.nc]))
                                 };
+                                if let Some(obj_conf) = self.obj_conf {
+                                    if self.conf_independent && conf_ < obj_conf {
+                                        continue;
+                                    }
+                                }
                                 let (id, &confidence) = clss
                                     .into_iter()
                                     .enumerate()
                                     .max_by(|a, b| a.1.total_cmp(b.1))
                                     .unwrap();
+
+                                // with `obj_conf`, objectness and class are thresholded independently
+                                let thresh = match self.obj_conf {
+                                    Some(_) => confidence,
+                                    None => confidence * conf_,
+                                };
                                 let confidence = confidence * conf_;
-                                if confidence < self.confs[id] {
+                                if thresh < self.confs[id] {
                                     continue;
                                 }
-                                let bbox = pred.slice(s![0..CXYWH_OFFSET]);
-                                let cx = bbox[0] / ratio;
-                                let cy = bbox[1] / ratio;
-                                let w = bbox[2] / ratio;
-                                let h = bbox[3] / ratio;
-                                let x = cx - w / 2.;
-                                let y = cy - h / 2.;
-                                let x = x.max(0.0).min(image_width);
-                                let y = y.max(0.0).min(image_height);
-                                let y_bbox = Bbox::default()
-                                    .with_xywh(x, y, w, h)
-                                    .with_confidence(confidence)
-                                    .with_id(id as isize)
-                                    .with_id_born(i as isize)
-                                    .with_name(
-                                        self.names.as_ref().map(|names| names[id].to_owned()),
-                                    );
+                                let y_bbox = Self::cxcywh_to_bbox(
+                                    [pred[0], pred[1], pred[2], pred[3]],
+                                    ratio,
+                                    image_width,
+                                    image_height,
+                                )
+                                .with_confidence(confidence)
+                                .with_id(id as isize)
+                                .with_id_born(i as isize)
+                                .with_name(self.names.as_ref().map(|names| names[id].to_owned()));
                                 y_bboxes.push(y_bbox);
                             }
                         }
                     }
 
+                    // pose ranking score, detection confidences are put back after nms
+                    let mut confs_det: Vec<f32> = Vec::new();
+                    if let (YOLOTask::Pose, PoseScore::MeanKptConf | PoseScore::Product) =
+                        (&self.task, self.pose_score)
+                    {
+                        for bbox in y_bboxes.iter_mut() {
+                            let kconf_mean = (0..self.nk)
+                                .map(|i| {
+                                    let k = preds.shape()[if self.anchors_first { 1 } else { 0 }]
+                                        - KPT_STEP * (self.nk - i)
+                                        + 2;
+                                    if self.anchors_first {
+                                        preds[[bbox.id_born() as usize, k]]
+                                    } else {
+                                        preds[[k, bbox.id_born() as usize]]
+                                    }
+                                })
+                                .sum::<f32>()
+                                / self.nk.max(1) as f32;
+                            let score = match self.pose_score {
+                                PoseScore::Product => bbox.confidence() * kconf_mean,
+                                _ => kconf_mean,
+                            };
+                            confs_det.push(bbox.confidence());
+                            *bbox = std::mem::take(bbox).with_confidence(score);
+                        }
+                    }
+
                     // nms
                     let mut y = Y::default().with_bboxes(&y_bboxes);
                     if self.apply_nms {
-                        y = y.apply_bboxes_nms(self.iou);
+                        y = y.with_bboxes(&ops::nms(
+                            &y_bboxes,
+                            self.nms_method,
+                            self.iou,
+                            self.ious.as_ref(),
+                            self.nms_sigma,
+                            &self.confs,
+                        ));
+                    }
+                    y = self.post.filter(y);
+                    if !confs_det.is_empty() {
+                        let mut bboxes = y.bboxes().cloned().unwrap_or_default();
+                        if !self.apply_nms {
+                            bboxes.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+                        }
+                        for bbox in bboxes.iter_mut() {
+                            let i = y_bboxes
+                                .iter()
+                                .position(|x| x.id_born() == bbox.id_born())
+                                .unwrap();
+                            // carry over any soft-nms decay of the ranking score
+                            let decay =
+                                bbox.confidence() / y_bboxes[i].confidence().max(f32::EPSILON);
+                            let conf = confs_det[i] * decay;
+                            *bbox = std::mem::take(bbox).with_confidence(conf);
+                        }
+                        y = y.with_bboxes(&bboxes);
                     }
 
                     // keypoints
@@ -381,6 +628,7 @@ impl YOLO {
                     if let YOLOTask::Segment = self.task {
                         if let Some(bboxes) = y.bboxes() {
                             let mut y_polygons: Vec<Polygon> = Vec::new();
+                            let mut y_masks: Vec<Mask> = Vec::new();
                             for bbox in bboxes.iter() {
                                 let coefs = if self.anchors_first {
                                     preds
@@ -452,6 +700,7 @@ impl YOLO {
                                             .with_id(bbox.id())
                                             .with_points_imageproc(&x.points)
                                             .with_name(bbox.name().cloned())
+                                            .with_confidence(bbox.confidence())
                                     })
                                     .max_by(|x, y| x.area().total_cmp(&y.area()))
                                 {
@@ -459,15 +708,51 @@ impl YOLO {
                                     Some(x) => x,
                                 };
                                 y_polygons.push(polygon);
+                                if self.instance_masks {
+                                    let binary = image::GrayImage::from_fn(
+                                        mask_original.width(),
+                                        mask_original.height(),
+                                        |x, y| {
+                                            image::Luma([
+                                                if mask_original.get_pixel(x, y).0[0] > 0 {
+                                                    255
+                                                } else {
+                                                    0
+                                                },
+                                            ])
+                                        },
+                                    );
+                                    y_masks.push(
+                                        Mask::default()
+                                            .with_mask(DynamicImage::ImageLuma8(binary))
+                                            .with_id(bbox.id())
+                                            .with_name(bbox.name().cloned())
+                                            .with_confidence(bbox.confidence()),
+                                    );
+                                }
                             }
                             y = y.with_polygons(&y_polygons);
+                            if self.instance_masks {
+                                y = y.with_masks(&y_masks);
+                            }
+                            if let Some(base_eps) = self.adaptive_simplify {
+                                y = y.apply_polygons_simplify_adaptive(base_eps);
+                            }
+                            if let Some(winding) = self.polygon_winding {
+                                y = y.apply_polygons_winding(winding);
+                            }
                         }
                     }
                     ys.push(y);
                 }
             }
         }
-        Ok(ys)
+        Ok(self.post.ids(ys))
+    }
+
+    /// See [`OrtEngine::save_profiling`]
+    pub fn save_profiling<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<()> {
+        self.engine.save_profiling(path)
     }
 
     pub fn batch(&self) -> isize {
@@ -495,3 +780,42 @@ impl YOLO {
         })
     }
 }
+
+#[cfg(test)]
+mod tests_yolo {
+    use super::YOLO;
+    use crate::Embedding;
+    use ndarray::Array;
+
+    #[test]
+    fn text_feats() {
+        let x = Array::from_shape_vec((2, 2), vec![3., 4., 0., 2.]).unwrap();
+        let x = YOLO::text_feats(&Embedding::new(x.into_dyn())).unwrap();
+        assert_eq!(x.shape(), [1, 2, 2]);
+        assert_eq!(x.iter().copied().collect::<Vec<_>>(), [0.6, 0.8, 0., 1.]);
+        let x = Array::from_vec(vec![1., 2.]).into_dyn();
+        assert!(YOLO::text_feats(&Embedding::new(x)).is_err());
+    }
+
+    #[test]
+    fn check_prompts() {
+        let baked = ["dog".to_string(), "cat".to_string()];
+        assert!(YOLO::check_prompts(&baked, Some(&baked)).is_ok());
+        assert!(YOLO::check_prompts(&baked, None).is_ok());
+        assert!(YOLO::check_prompts(&baked[..1], Some(&baked)).is_err());
+    }
+
+    #[test]
+    fn cxcywh_to_bbox() {
+        let bbox = YOLO::cxcywh_to_bbox([10., 20., 40., 20.], 0.5, 100., 60.);
+        assert_eq!(
+            (bbox.xmin(), bbox.ymin(), bbox.xmax(), bbox.ymax()),
+            (0., 20., 60., 60.)
+        );
+        let bbox = YOLO::cxcywh_to_bbox([95., 5., 20., 20.], 1., 100., 60.);
+        assert_eq!(
+            (bbox.xmin(), bbox.ymin(), bbox.xmax(), bbox.ymax()),
+            (85., 0., 100., 15.)
+        );
+    }
+}
@@ -2,20 +2,31 @@ use anyhow::Result;
 use image::DynamicImage;
 use ndarray::{s, Array, Axis, IxDyn};
 
-use crate::{ops, Bbox, DynConf, MinOptMax, Options, OrtEngine, Polygon, Y};
+use crate::{
+    models::DetectionPost, ops, Bbox, DynConf, MinOptMax, NmsMethod, Options, OrtEngine, Polygon,
+    Winding, Y,
+};
 
 #[derive(Debug)]
 pub struct YOLOPv2 {
     engine: OrtEngine,
+    post: DetectionPost,
     height: MinOptMax,
     width: MinOptMax,
     batch: MinOptMax,
     confs: DynConf,
     iou: f32,
+    ious: Option<DynConf>,
+    nms_method: NmsMethod,
+    nms_sigma: f32,
+    polygon_winding: Option<Winding>,
 }
 
+impl_ts!(YOLOPv2);
+
 impl YOLOPv2 {
     pub fn new(options: Options) -> Result<Self> {
+        options.check_input("YOLOPv2")?;
         let mut engine = OrtEngine::new(&options)?;
         let (batch, height, width) = (
             engine.batch().to_owned(),
@@ -28,11 +39,20 @@ impl YOLOPv2 {
 
         Ok(Self {
             engine,
+            post: DetectionPost::new(&options),
             confs,
             height,
             width,
             batch,
             iou: options.iou,
+            ious: match (&options.iou_per_class, options.nms_class_aware) {
+                (Some(x), _) => Some(DynConf::new(x, nc)),
+                (None, true) => Some(DynConf::new(&[options.iou], nc)),
+                (None, false) => None,
+            },
+            nms_method: options.nms_method,
+            nms_sigma: options.nms_sigma,
+            polygon_winding: options.polygon_winding,
         })
     }
 
@@ -49,7 +69,22 @@ impl YOLOPv2 {
         self.postprocess(ys, xs)
     }
 
+    /// Run on an already prepared tensor, see [`models`](crate::models)
+    pub fn run_tensor(&mut self, xs: Array<f32, IxDyn>, sizes: &[(u32, u32)]) -> Result<Vec<Y>> {
+        ops::check_sizes(&xs, sizes)?;
+        let ys = self.engine.run(&[xs])?;
+        self.postprocess_with_sizes(ys, sizes)
+    }
+
     pub fn postprocess(&self, xs: Vec<Array<f32, IxDyn>>, xs0: &[DynamicImage]) -> Result<Vec<Y>> {
+        self.postprocess_with_sizes(xs, &ops::image_sizes(xs0))
+    }
+
+    pub fn postprocess_with_sizes(
+        &self,
+        xs: Vec<Array<f32, IxDyn>>,
+        sizes: &[(u32, u32)],
+    ) -> Result<Vec<Y>> {
         let mut ys: Vec<Y> = Vec::new();
         let (xs_da, xs_ll, xs_det) = (&xs[0], &xs[1], &xs[2]);
         for (idx, ((x_det, x_ll), x_da)) in xs_det
@@ -58,8 +93,8 @@ impl YOLOPv2 {
             .zip(xs_da.axis_iter(Axis(0)))
             .enumerate()
         {
-            let image_width = xs0[idx].width() as f32;
-            let image_height = xs0[idx].height() as f32;
+            let image_width = sizes[idx].0 as f32;
+            let image_height = sizes[idx].1 as f32;
             let (ratio, _, _) = ops::scale_wh(
                 image_width,
                 image_height,
@@ -171,14 +206,23 @@ impl YOLOPv2 {
             };
 
             // save
-            ys.push(
-                Y::default()
-                    .with_bboxes(&y_bboxes)
-                    .with_polygons(&y_polygons)
-                    .apply_bboxes_nms(self.iou),
+            let y_bboxes = ops::nms(
+                &y_bboxes,
+                self.nms_method,
+                self.iou,
+                self.ious.as_ref(),
+                self.nms_sigma,
+                &self.confs,
             );
+            let mut y = Y::default()
+                .with_bboxes(&y_bboxes)
+                .with_polygons(&y_polygons);
+            if let Some(winding) = self.polygon_winding {
+                y = y.apply_polygons_winding(winding);
+            }
+            ys.push(y);
         }
-        Ok(ys)
+        Ok(self.post.apply(ys))
     }
 
     pub fn batch(&self) -> isize {
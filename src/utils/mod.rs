@@ -1,7 +1,8 @@
-use anyhow::{anyhow, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::io::{Read, Write};
+use anyhow::Result;
+use rand::{RngCore, SeedableRng};
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 pub mod coco;
 pub mod colormap256;
@@ -13,6 +14,8 @@ pub const CHECK_MARK: &str = "✅";
 pub const CROSS_MARK: &str = "❌";
 pub const SAFE_CROSS_MARK: &str = "❎";
 
+/// Resolve `src` to a local file: as given, or cached under `~/.usls/{sub}`.
+/// With the `download` feature, files missing from the cache are fetched from `GITHUB_ASSETS`.
 pub fn auto_load<P: AsRef<Path>>(src: P, sub: Option<&str>) -> Result<String> {
     let src = src.as_ref();
     let p = if src.is_file() {
@@ -22,34 +25,87 @@ pub fn auto_load<P: AsRef<Path>>(src: P, sub: Option<&str>) -> Result<String> {
         let mut p = home_dir(sub);
         p.push(sth);
         if !p.is_file() {
+            #[cfg(feature = "download")]
             download(
                 &format!("{}/{}", GITHUB_ASSETS, sth),
                 &p,
                 Some(sth.to_string().as_str()),
             )?;
+            #[cfg(not(feature = "download"))]
+            anyhow::bail!(
+                "{} not found locally nor in {:?}, and the `download` feature is disabled",
+                src.display(),
+                p
+            );
         }
         p
     };
     Ok(p.to_str().unwrap().to_string())
 }
 
+/// Download the listed model assets into the cache without building any engine,
+/// e.g. `usls::prefetch_models(&["yolov8m-dyn.onnx", "ppocr-v4-db-dyn.onnx"])`.
+///
+/// Files already cached are checked against their `<file>.sha256` sidecar if there is one
+/// and downloaded again on mismatch; without a sidecar they are reported as unverified.
+#[cfg(feature = "download")]
+pub fn prefetch_models(names: &[&str]) -> Result<()> {
+    let mut failed = Vec::new();
+    for name in names.iter() {
+        match auto_load(name, Some("models")).and_then(|p| verify_cached(name, &p).map(|x| (p, x)))
+        {
+            Ok((p, true)) => println!("{CHECK_MARK} {}", p),
+            Ok((p, false)) => println!("{CHECK_MARK} {} (no checksum to verify)", p),
+            Err(err) => {
+                println!("{CROSS_MARK} {}: {:?}", name, err);
+                failed.push(name.to_string());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to prefetch: {:?}", failed);
+    }
+    Ok(())
+}
+
+/// Check a cached `path` against its `.sha256` sidecar, fetching `name` again on mismatch.
+/// `false` if there is nothing to check against.
+#[cfg(feature = "download")]
+fn verify_cached(name: &str, path: &str) -> Result<bool> {
+    let path = Path::new(path);
+    let Some(expected) = std::fs::read_to_string(sidecar(path, "sha256"))
+        .ok()
+        .and_then(|x| x.split_whitespace().next().map(|x| x.to_lowercase()))
+    else {
+        return Ok(false);
+    };
+    if sha256_hex(path)? != expected {
+        println!(
+            "{SAFE_CROSS_MARK} Checksum mismatch for {:?}, downloading again",
+            path
+        );
+        std::fs::remove_file(path)?;
+        auto_load(name, Some("models"))?;
+        let actual = sha256_hex(path)?;
+        if actual != expected {
+            anyhow::bail!(
+                "Checksum mismatch for {:?}: expected {expected}, got {actual}",
+                path
+            );
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(feature = "download")]
 pub fn download<P: AsRef<Path> + std::fmt::Debug>(
     src: &str,
     dst: P,
     prompt: Option<&str>,
 ) -> Result<()> {
-    let resp = ureq::AgentBuilder::new()
-        .try_proxy_from_env(true)
-        .build()
-        .get(src)
-        .timeout(std::time::Duration::from_secs(2000))
-        .call()
-        .map_err(|err| anyhow!("Failed to download. {err:?}"))?;
-    let ntotal = resp
-        .header("Content-Length")
-        .and_then(|s| s.parse::<u64>().ok())
-        .expect("Content-Length header should be present on archive response");
-    let pb = ProgressBar::new(ntotal);
+    use indicatif::{ProgressBar, ProgressStyle};
+
+    let pb = ProgressBar::new(0);
     pb.set_style(
             ProgressStyle::with_template(
                 "{prefix:.bold} {msg:.dim} [{bar:.blue.bright/white.dim}] {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec}, {percent_precise}%, {elapsed})"
@@ -58,25 +114,257 @@ pub fn download<P: AsRef<Path> + std::fmt::Debug>(
             .progress_chars("#>-"));
     pb.set_prefix(String::from("\n🐢 Downloading"));
     pb.set_message(prompt.unwrap_or_default().to_string());
+    download_with(src, dst, None, |n, total| {
+        if let Some(total) = total {
+            pb.set_length(total);
+        }
+        pb.set_position(n);
+    })?;
+    pb.finish();
+    println!();
+    Ok(())
+}
+
+/// Download `src` to `dst` through `<dst>.part`, which is only renamed to `dst` once
+/// complete and verified:
+///
+/// - An existing `.part` from an interrupted download is resumed with an HTTP range request,
+///   guarded by `If-Range` with the `ETag` or `Last-Modified` of the first response and by
+///   the `Content-Range` of the answer; the download restarts from zero if either differs.
+/// - The SHA-256 (hex) is checked against `sha256`, or the content of a `<dst>.sha256`
+///   sidecar file if `None`; on mismatch the file is downloaded again from scratch once.
+/// - `progress` gets the bytes on disk so far and the total size, if known.
+#[cfg(feature = "download")]
+pub fn download_with<P: AsRef<Path>, F: FnMut(u64, Option<u64>)>(
+    src: &str,
+    dst: P,
+    sha256: Option<&str>,
+    mut progress: F,
+) -> Result<()> {
+    let dst = dst.as_ref();
+    let tmp = sidecar(dst, "part");
+    let expected = match sha256 {
+        Some(x) => Some(x.trim().to_lowercase()),
+        None => std::fs::read_to_string(sidecar(dst, "sha256"))
+            .ok()
+            .and_then(|x| x.split_whitespace().next().map(|x| x.to_lowercase())),
+    };
+    for attempt in 0..2 {
+        fetch_part(src, &tmp, &mut progress)?;
+        let Some(expected) = &expected else {
+            break;
+        };
+        let actual = sha256_hex(&tmp)?;
+        if &actual == expected {
+            break;
+        }
+        std::fs::remove_file(&tmp)?;
+        let _ = std::fs::remove_file(sidecar(&tmp, "validator"));
+        if attempt == 1 {
+            anyhow::bail!("Checksum mismatch for {src}: expected {expected}, got {actual}");
+        }
+        println!("{SAFE_CROSS_MARK} Checksum mismatch for {src}, downloading again");
+    }
+    std::fs::rename(&tmp, dst)?;
+    let _ = std::fs::remove_file(sidecar(&tmp, "validator"));
+    Ok(())
+}
+
+/// `<path>.<ext>`, keeping the original extension
+#[cfg(feature = "download")]
+fn sidecar(path: &Path, ext: &str) -> PathBuf {
+    let mut x = path.as_os_str().to_owned();
+    x.push(".");
+    x.push(ext);
+    x.into()
+}
+
+/// Fetch `src` into `tmp`, resuming from its current length when `<tmp>.validator` holds
+/// the `ETag` or `Last-Modified` it was started with
+#[cfg(feature = "download")]
+fn fetch_part<F: FnMut(u64, Option<u64>)>(src: &str, tmp: &Path, progress: &mut F) -> Result<()> {
+    use std::io::{Read, Write};
+
+    let validator_path = sidecar(tmp, "validator");
+    let validator = std::fs::read_to_string(&validator_path).ok();
+    let offset = match validator {
+        Some(_) => std::fs::metadata(tmp).map_or(0, |x| x.len()),
+        None => 0,
+    };
+    let mut req = ureq::AgentBuilder::new()
+        .try_proxy_from_env(true)
+        .build()
+        .get(src)
+        .timeout(std::time::Duration::from_secs(2000));
+    if let (true, Some(validator)) = (offset > 0, &validator) {
+        req = req
+            .set("Range", &format!("bytes={offset}-"))
+            .set("If-Range", validator);
+    }
+    let resp = match req.call() {
+        // the partial file already is the whole file
+        Err(ureq::Error::Status(416, _)) if offset > 0 => return Ok(()),
+        x => x.map_err(|err| anyhow::anyhow!("Failed to download. {err:?}"))?,
+    };
+    let resumed = offset > 0 && resp.status() == 206;
+    if resumed && content_range_start(resp.header("Content-Range")) != Some(offset) {
+        // not the bytes that were asked for, start over
+        std::fs::remove_file(tmp)?;
+        let _ = std::fs::remove_file(&validator_path);
+        return fetch_part(src, tmp, progress);
+    }
+    if !resumed {
+        // weak ETags can't be used with If-Range
+        match resp
+            .header("ETag")
+            .filter(|x| !x.starts_with("W/"))
+            .or(resp.header("Last-Modified"))
+        {
+            Some(x) => std::fs::write(&validator_path, x)?,
+            None => {
+                let _ = std::fs::remove_file(&validator_path);
+            }
+        }
+    }
+    let offset = if resumed { offset } else { 0 };
+    let total = resp
+        .header("Content-Length")
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(|x| x + offset);
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(tmp)?;
     let mut reader = resp.into_reader();
-    let mut buffer = [0; 256];
-    let mut downloaded_bytes = 0usize;
-    let mut f = std::fs::File::create(&dst).expect("Failed to create file");
+    let mut buffer = [0; 8192];
+    let mut n = offset;
+    progress(n, total);
     loop {
-        let bytes_read = reader.read(&mut buffer)?;
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(x) => x,
+            Err(err) => {
+                anyhow::bail!("Interrupted download of {src} at {n} bytes, retry to resume: {err}")
+            }
+        };
         if bytes_read == 0 {
             break;
         }
-        pb.inc(bytes_read as u64);
         f.write_all(&buffer[..bytes_read])?;
-        downloaded_bytes += bytes_read;
+        n += bytes_read as u64;
+        progress(n, total);
+    }
+    f.flush()?;
+    if let Some(total) = total {
+        if n != total {
+            anyhow::bail!(
+                "Incomplete download of {}: {} of {} bytes, retry to resume",
+                src,
+                n,
+                total
+            );
+        }
     }
-    assert_eq!(downloaded_bytes as u64, ntotal);
-    pb.finish();
-    println!();
     Ok(())
 }
 
+/// First byte of a `Content-Range: bytes <start>-<end>/<total>` header
+#[cfg(feature = "download")]
+fn content_range_start(x: Option<&str>) -> Option<u64> {
+    x?.trim()
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// SHA-256 of a file as lowercase hex
+#[cfg(feature = "download")]
+pub fn sha256_hex<P: AsRef<Path>>(path: P) -> Result<String> {
+    use std::io::Read;
+
+    let mut f = std::fs::File::open(path)?;
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = [0; 8192];
+    loop {
+        let n = f.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        ctx.update(&buffer[..n]);
+    }
+    Ok(ctx
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|x| format!("{x:02x}"))
+        .collect())
+}
+
+static SEEDED_RNG: Mutex<Option<rand::rngs::StdRng>> = Mutex::new(None);
+
+/// Seed every later random draw of the crate for reproducible runs: token sampling
+/// (`LogitsSampler` top-p without its own `with_seed`) and detection UUIDs. Colors come from
+/// a fixed palette and batch/NMS ordering is already deterministic (stable sorts,
+/// order-preserving rayon), so nothing else depends on randomness.
+///
+/// The RNG is shared by the whole process: draws of concurrent models interleave. Give
+/// models their own stream with `Options::with_seed` instead.
+pub fn set_seed(seed: u64) {
+    *SEEDED_RNG.lock().unwrap() = Some(rand::rngs::StdRng::seed_from_u64(seed));
+}
+
+/// Run `f` with the seeded RNG if `set_seed` was called, the thread RNG otherwise
+pub(crate) fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    match SEEDED_RNG.lock().unwrap().as_mut() {
+        Some(rng) => f(rng),
+        None => f(&mut rand::thread_rng()),
+    }
+}
+
+/// Per-model RNG seeded by `Options::with_seed`, the crate-wide one (see `set_seed`) otherwise
+#[derive(Debug, Default)]
+pub(crate) struct ModelRng(Option<RefCell<rand::rngs::StdRng>>);
+
+impl ModelRng {
+    pub(crate) fn new(seed: Option<u64>) -> Self {
+        Self(seed.map(|x| RefCell::new(rand::rngs::StdRng::seed_from_u64(x))))
+    }
+
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &self.0 {
+            Some(rng) => f(&mut *rng.borrow_mut()),
+            None => with_rng(f),
+        }
+    }
+}
+
+/// Random (version 4) UUID in its hyphenated form
+pub fn uuid_v4() -> String {
+    uuid_v4_with(&ModelRng::default())
+}
+
+pub(crate) fn uuid_v4_with(rng: &ModelRng) -> String {
+    let x = rng.with(|rng| {
+        let mut b = [0u8; 16];
+        rng.fill_bytes(&mut b);
+        u128::from_le_bytes(b)
+    });
+    let x = (x & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    let s = format!("{:032x}", x);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &s[0..8],
+        &s[8..12],
+        &s[12..16],
+        &s[16..20],
+        &s[20..32]
+    )
+}
+
 pub fn string_now(delimiter: &str) -> String {
     let t_now = chrono::Local::now();
     let fmt = format!(
@@ -114,3 +402,128 @@ pub fn home_dir(sub: Option<&str>) -> PathBuf {
         None => panic!("Unsupported operating system. Now support Linux, MacOS, Windows."),
     }
 }
+
+#[cfg(all(test, feature = "download"))]
+mod tests_download {
+    use super::{download_with, sha256_hex, sidecar, verify_cached};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serve `data` tagged `etag` on `n` connections, cutting the first response in half and
+    /// ignoring ranges whose `If-Range` doesn't match
+    fn serve(data: Vec<u8>, n: usize, etag: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for (i, stream) in listener.incoming().take(n).enumerate() {
+                let mut stream = stream.unwrap();
+                let (mut offset, mut if_range) = (0, None);
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let line = line.to_lowercase();
+                    if let Some(x) = line.strip_prefix("range: bytes=") {
+                        offset = x.trim().trim_end_matches('-').parse().unwrap();
+                    }
+                    if let Some(x) = line.strip_prefix("if-range: ") {
+                        if_range = Some(x.trim().to_string());
+                    }
+                }
+                if if_range.as_deref() != Some(etag) {
+                    offset = 0;
+                }
+                let body = &data[offset..];
+                let (status, body) = match (i, offset) {
+                    (0, _) => ("200 OK", &body[..body.len() / 2]),
+                    (_, 0) => ("200 OK", body),
+                    _ => ("206 Partial Content", body),
+                };
+                let range = match offset {
+                    0 => String::new(),
+                    _ => format!(
+                        "Content-Range: bytes {offset}-{}/{}\r\n",
+                        data.len() - 1,
+                        data.len()
+                    ),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nETag: {etag}\r\n{range}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len() - offset
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        format!("http://{addr}/model.onnx")
+    }
+
+    #[test]
+    fn resume() {
+        let dir = std::env::temp_dir().join(format!("usls-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("abc"), b"abc").unwrap();
+        assert_eq!(
+            sha256_hex(dir.join("abc")).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let data: Vec<u8> = (0..100_000u32).map(|x| (x % 251) as u8).collect();
+        std::fs::write(dir.join("reference"), &data).unwrap();
+        let sha = sha256_hex(dir.join("reference")).unwrap();
+
+        // truncated first attempt keeps the partial file only
+        let src = serve(data.clone(), 2, "\"v1\"");
+        let dst = dir.join("model.v8.onnx");
+        let part = dir.join("model.v8.onnx.part");
+        assert!(download_with(&src, &dst, Some(&sha), |_, _| {}).is_err());
+        assert!(!dst.exists());
+        let partial = std::fs::metadata(&part).unwrap().len();
+        assert!(partial > 0 && partial < data.len() as u64);
+
+        // the second one resumes from there
+        let mut last = (0, None);
+        download_with(&src, &dst, Some(&sha), |n, total| {
+            if last.0 == 0 {
+                assert_eq!(n, partial);
+            }
+            last = (n, total)
+        })
+        .unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+        assert_eq!(last, (data.len() as u64, Some(data.len() as u64)));
+        assert!(!part.exists());
+
+        // the file changed on the server in between, restart from zero
+        std::fs::remove_file(&dst).unwrap();
+        let src = serve(data.clone(), 1, "\"v1\"");
+        assert!(download_with(&src, &dst, Some(&sha), |_, _| {}).is_err());
+        let src = serve(data.clone(), 2, "\"v2\"");
+        let mut first = None;
+        let _ = download_with(&src, &dst, Some(&sha), |n, _| {
+            first.get_or_insert(n);
+        });
+        assert_eq!(first, Some(0));
+        download_with(&src, &dst, Some(&sha), |_, _| {}).unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), data);
+
+        // a wrong checksum never produces the file
+        let src = serve(data.clone(), 3, "\"v1\"");
+        let dst = dir.join("bad.onnx");
+        let _ = download_with(&src, &dst, Some("00"), |_, _| {});
+        assert!(download_with(&src, &dst, Some("00"), |_, _| {}).is_err());
+        assert!(!dst.exists());
+
+        // cached files are verified against their sidecar when there is one
+        let reference = dir.join("reference");
+        let name = reference.to_str().unwrap();
+        assert!(!verify_cached(name, name).unwrap());
+        std::fs::write(sidecar(&reference, "sha256"), format!("{sha}  reference\n")).unwrap();
+        assert!(verify_cached(name, name).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
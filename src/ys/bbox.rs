@@ -1,5 +1,9 @@
+use anyhow::{bail, Result};
+use image::{DynamicImage, GenericImageView};
+
 /// Bounding Box 2D
 #[derive(Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bbox {
     x: f32,
     y: f32,
@@ -9,6 +13,8 @@ pub struct Bbox {
     confidence: f32,
     name: Option<String>,
     id_born: isize,
+    uuid: Option<String>,
+    track_id: Option<u64>,
 }
 
 impl Default for Bbox {
@@ -22,19 +28,24 @@ impl Default for Bbox {
             confidence: 0.,
             name: None,
             id_born: -1,
+            uuid: None,
+            track_id: None,
         }
     }
 }
 
 impl std::fmt::Debug for Bbox {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Bbox")
-            .field("xyxy", &[self.x, self.y, self.xmax(), self.ymax()])
+        let mut f = f.debug_struct("Bbox");
+        f.field("xyxy", &[self.x, self.y, self.xmax(), self.ymax()])
             .field("id", &self.id)
             // .field("id_born", &self.id_born)
             .field("name", &self.name)
-            .field("confidence", &self.confidence)
-            .finish()
+            .field("confidence", &self.confidence);
+        if let Some(x) = self.track_id {
+            f.field("track_id", &x);
+        }
+        f.finish()
     }
 }
 
@@ -103,6 +114,17 @@ impl Bbox {
         self
     }
 
+    pub fn with_uuid(mut self, x: Option<String>) -> Self {
+        self.uuid = x;
+        self
+    }
+
+    /// Persistent id given by a tracker, see `ByteTrack::track`
+    pub fn with_track_id(mut self, x: Option<u64>) -> Self {
+        self.track_id = x;
+        self
+    }
+
     pub fn with_confidence(mut self, x: f32) -> Self {
         self.confidence = x;
         self
@@ -153,10 +175,18 @@ impl Bbox {
         self.id_born
     }
 
+    pub fn uuid(&self) -> Option<&String> {
+        self.uuid.as_ref()
+    }
+
     pub fn name(&self) -> Option<&String> {
         self.name.as_ref()
     }
 
+    pub fn track_id(&self) -> Option<u64> {
+        self.track_id
+    }
+
     pub fn confidence(&self) -> f32 {
         self.confidence
     }
@@ -179,13 +209,25 @@ impl Bbox {
                 label.push_str(&format!("{:.decimal_places$}", self.confidence));
             }
         }
-        label
+        match self.track_id {
+            Some(x) if label.is_empty() => format!("#{x}"),
+            Some(x) => format!("#{x} {label}"),
+            None => label,
+        }
     }
 
     pub fn area(&self) -> f32 {
         self.h * self.w
     }
 
+    /// Box clamped to `[0, width] x [0, height]`. A box entirely outside the image comes
+    /// back with zero width or height, check `area() == 0.` to drop it.
+    pub fn clip(&self, width: f32, height: f32) -> Bbox {
+        let (x1, x2) = (self.xmin().clamp(0., width), self.xmax().clamp(0., width));
+        let (y1, y2) = (self.ymin().clamp(0., height), self.ymax().clamp(0., height));
+        self.clone().with_xyxy(x1, y1, x2, y2)
+    }
+
     pub fn perimeter(&self) -> f32 {
         (self.h + self.w) * 2.0
     }
@@ -210,17 +252,117 @@ impl Bbox {
         self.intersect(other) / self.union(other)
     }
 
+    /// Generalized IoU, penalized by the empty part of the smallest enclosing box, in [-1, 1]
+    pub fn giou(&self, other: &Bbox) -> f32 {
+        let iou = self.iou_or_zero(other);
+        let (w, h) = self.enclosing_wh(other);
+        let c = w * h;
+        if c <= 0. {
+            return iou;
+        }
+        iou - (c - self.union(other)) / c
+    }
+
+    /// Distance IoU, penalized by the squared center distance over the squared enclosing diagonal
+    pub fn diou(&self, other: &Bbox) -> f32 {
+        let iou = self.iou_or_zero(other);
+        let (w, h) = self.enclosing_wh(other);
+        let c2 = w * w + h * h;
+        if c2 <= 0. {
+            return iou;
+        }
+        let d2 = (self.cx() - other.cx()).powi(2) + (self.cy() - other.cy()).powi(2);
+        iou - d2 / c2
+    }
+
+    /// Complete IoU, DIoU with an extra aspect ratio consistency term
+    pub fn ciou(&self, other: &Bbox) -> f32 {
+        let iou = self.iou_or_zero(other);
+        let v = 4. / std::f32::consts::PI.powi(2)
+            * (other.width().atan2(other.height()) - self.width().atan2(self.height())).powi(2);
+        let alpha = if v > 0. { v / (1. - iou + v) } else { 0. };
+        self.diou(other) - alpha * v
+    }
+
+    fn iou_or_zero(&self, other: &Bbox) -> f32 {
+        let union = self.union(other);
+        if union <= 0. {
+            0.
+        } else {
+            self.intersect(other) / union
+        }
+    }
+
+    fn enclosing_wh(&self, other: &Bbox) -> (f32, f32) {
+        (
+            self.xmax().max(other.xmax()) - self.xmin().min(other.xmin()),
+            self.ymax().max(other.ymax()) - self.ymin().min(other.ymin()),
+        )
+    }
+
     pub fn contains(&self, other: &Bbox) -> bool {
         self.xmin() <= other.xmin()
             && self.xmax() >= other.xmax()
             && self.ymin() <= other.ymin()
             && self.ymax() >= other.ymax()
     }
+
+    /// Image patch under the box, clipped to the image (partial pixels included).
+    /// Fails if nothing of the box is left inside the image.
+    pub fn crop(&self, img: &DynamicImage) -> Result<DynamicImage> {
+        let (w, h) = img.dimensions();
+        let bbox = self.clip(w as f32, h as f32);
+        let (x1, y1) = (bbox.xmin().floor() as u32, bbox.ymin().floor() as u32);
+        let (x2, y2) = (bbox.xmax().ceil() as u32, bbox.ymax().ceil() as u32);
+        if x2 <= x1 || y2 <= y1 || bbox.area() == 0. {
+            bail!("Nothing of {self:?} is inside the {w}x{h} image");
+        }
+        Ok(img.crop_imm(x1, y1, x2 - x1, y2 - y1))
+    }
 }
 
 #[cfg(test)]
 mod tests_bbox {
     use super::Bbox;
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn crop() {
+        let image = RgbImage::from_fn(64, 48, |x, y| Rgb([x as u8, y as u8, 0]));
+        let image = DynamicImage::ImageRgb8(image);
+        let patch = Bbox::default()
+            .with_xyxy(10., 20., 30., 25.)
+            .crop(&image)
+            .unwrap();
+        assert_eq!(patch.dimensions(), (20, 5));
+        assert_eq!(patch.get_pixel(3, 2).0[..2], [13, 22]);
+
+        let patch = Bbox::default()
+            .with_xyxy(50., -10., 80., 10.)
+            .crop(&image)
+            .unwrap();
+        assert_eq!(patch.dimensions(), (14, 10));
+        assert!(Bbox::default()
+            .with_xyxy(70., 10., 80., 20.)
+            .crop(&image)
+            .is_err());
+    }
+
+    #[test]
+    fn clip() {
+        let bbox = Bbox::default()
+            .with_xyxy(600., 100., 700., 200.)
+            .clip(640., 480.);
+        assert_eq!(
+            (bbox.xmin(), bbox.width(), bbox.height()),
+            (600., 40., 100.)
+        );
+
+        let outside = Bbox::default()
+            .with_xyxy(650., 100., 700., 200.)
+            .clip(640., 480.);
+        assert_eq!(outside.area(), 0.);
+    }
 
     #[test]
     fn new() {
@@ -263,4 +405,34 @@ mod tests_bbox {
         assert!(bbox1.contains(&bbox3));
         assert!(bbox2.contains(&bbox3));
     }
+
+    #[test]
+    fn iou_variants() {
+        let eq = |a: f32, b: f32| (a - b).abs() < 1e-6;
+
+        // overlapping squares
+        let bbox1 = Bbox::default().with_xyxy(0., 0., 5., 5.);
+        let bbox2 = Bbox::default().with_xyxy(1., 1., 6., 6.);
+        let iou = 16. / 34.;
+        assert!(eq(bbox1.giou(&bbox2), iou - 2. / 36.));
+        assert!(eq(bbox1.diou(&bbox2), iou - 2. / 72.));
+        assert!(eq(bbox1.ciou(&bbox2), bbox1.diou(&bbox2)));
+
+        // disjoint boxes of different aspect ratios
+        let bbox1 = Bbox::default().with_xyxy(0., 0., 2., 2.);
+        let bbox2 = Bbox::default().with_xyxy(3., 0., 5., 4.);
+        assert!(eq(bbox1.giou(&bbox2), -0.4));
+        assert!(eq(bbox1.diou(&bbox2), -10. / 41.));
+        let v = 4. / std::f32::consts::PI.powi(2) * (0.5f32.atan() - 1f32.atan()).powi(2);
+        assert!(eq(bbox1.ciou(&bbox2), -10. / 41. - v / (1. + v) * v));
+
+        // contained and degenerate boxes
+        let bbox3 = Bbox::default().with_xyxy(1., 1., 2., 2.);
+        let outer = Bbox::default().with_xyxy(0., 0., 4., 4.);
+        assert!(eq(outer.giou(&bbox3), outer.iou(&bbox3)));
+        let point = Bbox::default().with_xyxy(1., 1., 1., 1.);
+        for x in [point.giou(&point), point.diou(&point), point.ciou(&point)] {
+            assert_eq!(x, 0.);
+        }
+    }
 }
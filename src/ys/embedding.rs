@@ -1,8 +1,12 @@
 use anyhow::Result;
 use ndarray::{Array, Axis, Ix2, IxDyn};
+use rayon::prelude::*;
+
+use crate::Metric;
 
 /// Embedding
 #[derive(Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Embedding(Array<f32, IxDyn>);
 
 impl std::fmt::Debug for Embedding {
@@ -42,4 +46,90 @@ impl Embedding {
         let matrix: Vec<Vec<f32>> = matrix.axis_iter(Axis(0)).map(|row| row.to_vec()).collect();
         Ok(matrix)
     }
+
+    /// Cosine similarities between the rows of two `(m, ndim)` and `(n, ndim)` embeddings
+    /// as an `(m, n)` matrix, a 1D embedding counts as a single row
+    pub fn cosine(&self, other: &Embedding) -> Result<Vec<Vec<f32>>> {
+        let rows = |x: &Array<f32, IxDyn>| -> Result<Array<f32, Ix2>> {
+            let x = match x.ndim() {
+                1 => x.to_shape((1, x.len()))?.to_owned(),
+                _ => x.to_owned().into_dimensionality::<Ix2>()?,
+            };
+            let norms = x
+                .mapv(|x| x * x)
+                .sum_axis(Axis(1))
+                .mapv(|x| x.sqrt().max(f32::EPSILON));
+            Ok(x / norms.insert_axis(Axis(1)))
+        };
+        let matrix = rows(&self.0)?.dot(&rows(&other.0)?.t());
+        Ok(matrix.axis_iter(Axis(0)).map(|row| row.to_vec()).collect())
+    }
+
+    /// Score of this (flattened) embedding against every one of `gallery`, see
+    /// [`Metric::score`]. `Mahalanobis` needs a covariance and scores NaN here.
+    pub fn similarity_matrix(&self, gallery: &[Embedding], metric: Metric) -> Vec<f32> {
+        let query = self.0.iter().copied().collect::<Vec<_>>();
+        gallery
+            .par_iter()
+            .map(|x| {
+                let x = x.0.iter().copied().collect::<Vec<_>>();
+                metric.score(&query, &x, None).unwrap_or(f32::NAN)
+            })
+            .collect()
+    }
+
+    /// Best `k` matches in `gallery` as `(index, score)`, best first (the largest score for
+    /// `IP` and `Cos`, the smallest distance otherwise)
+    pub fn top_k(&self, gallery: &[Embedding], k: usize, metric: Metric) -> Vec<(usize, f32)> {
+        let mut scores = self
+            .similarity_matrix(gallery, metric)
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>();
+        match metric {
+            Metric::IP | Metric::Cos => scores.sort_by(|a, b| b.1.total_cmp(&a.1)),
+            _ => scores.sort_by(|a, b| a.1.total_cmp(&b.1)),
+        }
+        scores.truncate(k);
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests_embedding {
+    use super::Embedding;
+    use crate::Metric;
+    use ndarray::Array;
+
+    #[test]
+    fn top_k() {
+        let embedding = |x: [f32; 3]| Embedding::new(Array::from_vec(x.to_vec()).into_dyn());
+        let gallery = [
+            embedding([1., 0., 0.]),
+            embedding([0., 1., 0.]),
+            embedding([0., 0., 1.]),
+            embedding([0., 2., 0.]),
+        ];
+        let query = embedding([0.1, 1., 0.]);
+
+        let top = query.top_k(&gallery, 2, Metric::Cos);
+        assert_eq!(top.len(), 2);
+        assert!(top.iter().all(|x| x.0 == 1 || x.0 == 3));
+        assert!((top[0].1 - top[1].1).abs() < 1e-6);
+
+        assert_eq!(query.top_k(&gallery, 1, Metric::IP)[0].0, 3);
+        assert_eq!(query.top_k(&gallery, 1, Metric::L2)[0].0, 1);
+        assert_eq!(query.top_k(&gallery, 4, Metric::L2)[3].0, 2);
+
+        let texts = Embedding::new(
+            Array::from_shape_vec((2, 3), vec![0., 3., 0., 2., 0., 0.])
+                .unwrap()
+                .into_dyn(),
+        );
+        let matrix = embedding([1., 1., 0.]).cosine(&texts).unwrap();
+        assert_eq!(matrix.len(), 1);
+        assert!(matrix[0].iter().all(|x| (x - 0.5f32.sqrt()).abs() < 1e-6));
+        assert_eq!(texts.cosine(&texts).unwrap(), [[1., 0.], [0., 1.]]);
+        assert!(embedding([1., 0., 0.]).cosine(&gallery[0]).unwrap()[0][0] == 1.);
+    }
 }
@@ -1,10 +1,12 @@
 use std::ops::{Add, Div, Mul, Sub};
 
-/// Keypoint 2D
+/// Keypoint, 2D with an optional depth `z`
 #[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Keypoint {
     x: f32,
     y: f32,
+    z: Option<f32>, // depth, for 2.5D / 3D pose models
     id: isize,
     confidence: f32,
     name: Option<String>,
@@ -15,6 +17,7 @@ impl Default for Keypoint {
         Self {
             x: 0.,
             y: 0.,
+            z: None,
             confidence: 0.,
             id: -1,
             name: None,
@@ -24,9 +27,12 @@ impl Default for Keypoint {
 
 impl std::fmt::Debug for Keypoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Keypoint")
-            .field("xy", &[self.x, self.y])
-            .field("id", &self.id())
+        let mut f = f.debug_struct("Keypoint");
+        match self.z {
+            Some(z) => f.field("xyz", &[self.x, self.y, z]),
+            None => f.field("xy", &[self.x, self.y]),
+        };
+        f.field("id", &self.id())
             .field("name", &self.name())
             .field("confidence", &self.confidence())
             .finish()
@@ -149,6 +155,28 @@ impl From<[f32; 2]> for Keypoint {
     }
 }
 
+impl From<(f32, f32, f32)> for Keypoint {
+    fn from((x, y, z): (f32, f32, f32)) -> Self {
+        Self {
+            x,
+            y,
+            z: Some(z),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<[f32; 3]> for Keypoint {
+    fn from([x, y, z]: [f32; 3]) -> Self {
+        Self {
+            x,
+            y,
+            z: Some(z),
+            ..Default::default()
+        }
+    }
+}
+
 impl From<(f32, f32, isize, f32)> for Keypoint {
     fn from((x, y, id, confidence): (f32, f32, isize, f32)) -> Self {
         Self {
@@ -180,6 +208,18 @@ impl Keypoint {
         self
     }
 
+    pub fn with_xyz(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.x = x;
+        self.y = y;
+        self.z = Some(z);
+        self
+    }
+
+    pub fn with_z(mut self, x: Option<f32>) -> Self {
+        self.z = x;
+        self
+    }
+
     pub fn with_confidence(mut self, x: f32) -> Self {
         self.confidence = x;
         self
@@ -195,6 +235,17 @@ impl Keypoint {
         self
     }
 
+    /// Keypoint clamped to `[0, width] x [0, height]`, with its confidence zeroed (i.e.
+    /// invisible) if it was outside and `zero_outside` is set
+    pub fn clip(&self, width: f32, height: f32, zero_outside: bool) -> Keypoint {
+        let (x, y) = (self.x.clamp(0., width), self.y.clamp(0., height));
+        let mut kpt = self.clone().with_xy(x, y);
+        if zero_outside && (x != self.x || y != self.y) {
+            kpt.confidence = 0.;
+        }
+        kpt
+    }
+
     pub fn x(&self) -> f32 {
         self.x
     }
@@ -203,6 +254,11 @@ impl Keypoint {
         self.y
     }
 
+    /// Depth, if the model predicts one
+    pub fn z(&self) -> Option<f32> {
+        self.z
+    }
+
     pub fn confidence(&self) -> f32 {
         self.confidence
     }
@@ -240,12 +296,22 @@ impl Keypoint {
         self.x == 0.0_f32 && self.y == 0.0_f32
     }
 
+    /// Euclidean distance, in 3D if both keypoints have a depth
     pub fn distance_from(&self, other: &Keypoint) -> f32 {
+        let dz = match (self.z, other.z) {
+            (Some(a), Some(b)) => a - b,
+            _ => 0.,
+        };
+        ((self.x - other.x).powf(2.0) + (self.y - other.y).powf(2.0) + dz.powf(2.0)).sqrt()
+    }
+
+    /// Euclidean distance in the image plane, ignoring depth
+    pub fn distance_from_2d(&self, other: &Keypoint) -> f32 {
         ((self.x - other.x).powf(2.0) + (self.y - other.y).powf(2.0)).sqrt()
     }
 
     pub fn distance_from_origin(&self) -> f32 {
-        (self.x.powf(2.0) + self.y.powf(2.0)).sqrt()
+        (self.x.powf(2.0) + self.y.powf(2.0) + self.z.unwrap_or(0.).powf(2.0)).sqrt()
     }
 
     pub fn sum(&self) -> f32 {
@@ -285,6 +351,20 @@ mod tests_keypoint {
         assert_eq!(kpt6, kpt5);
     }
 
+    #[test]
+    fn clip() {
+        let kpt = Keypoint::default().with_xy(-3., 5.).with_confidence(0.9);
+        assert_eq!(kpt.clip(10., 10., false), kpt.clone().with_xy(0., 5.));
+        assert_eq!(kpt.clip(10., 10., true).confidence(), 0.);
+        assert_eq!(
+            kpt.clone()
+                .with_xy(3., 5.)
+                .clip(10., 10., true)
+                .confidence(),
+            0.9
+        );
+    }
+
     #[test]
     fn into_tuple() {
         let kpt = Keypoint::from((1., 2.));
@@ -343,4 +423,22 @@ mod tests_keypoint {
         let kpt2 = Keypoint::from((5., 0.));
         assert_eq!(kpt1.distance_from(&kpt2), 5.);
     }
+
+    #[test]
+    fn depth() {
+        let kpt1 = Keypoint::from((1., 2., 3.));
+        let kpt2 = Keypoint::default().with_xyz(3., 5., 9.);
+        assert_eq!(kpt1.z(), Some(3.));
+        assert_eq!(kpt1.distance_from(&kpt2), 7.);
+        assert_eq!(kpt1.distance_from_2d(&kpt2), 13f32.sqrt());
+        assert_eq!(Keypoint::from([2., 3., 6.]).distance_from_origin(), 7.);
+
+        // 2D as soon as one side has no depth
+        let kpt3 = Keypoint::from((3., 5.));
+        assert_eq!(kpt3.z(), None);
+        assert_eq!(kpt1.distance_from(&kpt3), 13f32.sqrt());
+
+        // geometry updates keep the depth
+        assert_eq!(kpt1.clip(2., 2., false).z(), Some(3.));
+    }
 }
@@ -1,12 +1,28 @@
 use image::DynamicImage;
 
+use crate::Rle;
+
+/// How overlapping instances are resolved when masks are flattened into one label map
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaskPriority {
+    /// Higher confidence wins
+    Confidence,
+    /// Classes listed first win, unlisted classes come last, ties go to higher confidence
+    Classes(Vec<isize>),
+}
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "MaskRepr", from = "MaskRepr")
+)]
 pub struct Mask {
     mask: DynamicImage,
     mask_vec: Vec<u8>,
     id: isize,
     name: Option<String>,
-    confidence: f32, // placeholder
+    confidence: f32,
 }
 
 impl Default for Mask {
@@ -21,6 +37,50 @@ impl Default for Mask {
     }
 }
 
+/// Serialized form of `Mask`, the image stored as 8-bit luma
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MaskRepr {
+    width: u32,
+    height: u32,
+    luma: Vec<u8>,
+    vec: Vec<u8>,
+    id: isize,
+    name: Option<String>,
+    confidence: f32,
+}
+
+#[cfg(feature = "serde")]
+impl From<Mask> for MaskRepr {
+    fn from(x: Mask) -> Self {
+        Self {
+            width: x.mask.width(),
+            height: x.mask.height(),
+            luma: x.mask.to_luma8().into_raw(),
+            vec: x.mask_vec,
+            id: x.id,
+            name: x.name,
+            confidence: x.confidence,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<MaskRepr> for Mask {
+    fn from(x: MaskRepr) -> Self {
+        let mask = image::GrayImage::from_raw(x.width, x.height, x.luma)
+            .map(DynamicImage::ImageLuma8)
+            .unwrap_or_default();
+        Self {
+            mask,
+            mask_vec: x.vec,
+            id: x.id,
+            name: x.name,
+            confidence: x.confidence,
+        }
+    }
+}
+
 impl std::fmt::Debug for Mask {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Mask")
@@ -48,6 +108,11 @@ impl Mask {
         self
     }
 
+    pub fn with_confidence(mut self, x: f32) -> Self {
+        self.confidence = x;
+        self
+    }
+
     pub fn with_name(mut self, x: Option<String>) -> Self {
         self.name = x;
         self
@@ -73,4 +138,14 @@ impl Mask {
     pub fn confidence(&self) -> f32 {
         self.confidence
     }
+
+    /// COCO run-length encoding of the mask, non-zero pixels are foreground
+    pub fn to_rle(&self) -> Rle {
+        Rle::encode(&self.mask.to_luma8())
+    }
+
+    /// Binary mask decoded from `rle`
+    pub fn from_rle(rle: &Rle) -> Self {
+        Self::default().with_mask(DynamicImage::ImageLuma8(rle.decode()))
+    }
 }
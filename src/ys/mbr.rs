@@ -1,7 +1,10 @@
-use geo::{coord, line_string, Area, BooleanOps, Coord, EuclideanDistance, LineString, Polygon};
+use anyhow::{bail, Result};
+use geo::{coord, line_string, Area, Coord, EuclideanDistance, LineString, Polygon};
+use image::{DynamicImage, Rgba, RgbaImage};
 
 /// Minimum Bounding Rectangle
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mbr {
     ls: LineString,
     id: isize,
@@ -184,20 +187,167 @@ impl Mbr {
         }
     }
 
+    /// Intersection area, the two quads clipped with Sutherland-Hodgman
     pub fn intersect(&self, other: &Mbr) -> f32 {
-        let p1 = Polygon::new(self.ls.clone(), vec![]);
-        let p2 = Polygon::new(other.ls.clone(), vec![]);
-        p1.intersection(&p2).unsigned_area() as f32
+        let clip = Self::ccw(&other.ls.0);
+        let mut points = Self::ccw(&self.ls.0);
+        for i in 0..clip.len() {
+            if points.is_empty() {
+                break;
+            }
+            let (a, b) = (clip[i], clip[(i + 1) % clip.len()]);
+            let side = |p: &Coord| (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x);
+            let input = std::mem::take(&mut points);
+            for j in 0..input.len() {
+                let (p, q) = (input[j], input[(j + 1) % input.len()]);
+                let (sp, sq) = (side(&p), side(&q));
+                if sp >= 0. {
+                    points.push(p);
+                }
+                if (sp >= 0.) != (sq >= 0.) {
+                    let t = sp / (sp - sq);
+                    points.push(p + (q - p) * t);
+                }
+            }
+        }
+        Self::shoelace(&points).abs() as f32
     }
 
     pub fn union(&self, other: &Mbr) -> f32 {
-        let p1 = Polygon::new(self.ls.clone(), vec![]);
-        let p2 = Polygon::new(other.ls.clone(), vec![]);
-        p1.union(&p2).unsigned_area() as f32
+        self.area() + other.area() - self.intersect(other)
     }
 
+    /// IoU from the exact intersection of the two quads, 0 when both are empty
     pub fn iou(&self, other: &Mbr) -> f32 {
-        self.intersect(other) / self.union(other)
+        let union = self.union(other);
+        if union <= 0. {
+            return 0.;
+        }
+        (self.intersect(other) / union).clamp(0., 1.)
+    }
+
+    pub fn area(&self) -> f32 {
+        Polygon::new(self.ls.clone(), vec![]).unsigned_area() as f32
+    }
+
+    /// Rotated region warped to an upright patch (bilinear), e.g. for text recognition.
+    /// The side closer to horizontal becomes the patch width, read left to right.
+    pub fn crop(&self, img: &DynamicImage) -> Result<DynamicImage> {
+        let mut vs = self.vertices();
+        if vs.len() > 1 && vs.first() == vs.last() {
+            vs.pop();
+        }
+        if vs.len() != 4 {
+            bail!("Expected 4 vertices, got {}", vs.len());
+        }
+        let (a, b) = (vs[1] - vs[0], vs[2] - vs[1]);
+        let (mut u, mut v) = if a.x.abs() >= b.x.abs() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        if u.x < 0. {
+            u = coord! { x: -u.x, y: -u.y };
+        }
+        if v.y < 0. {
+            v = coord! { x: -v.x, y: -v.y };
+        }
+        let (pw, ph) = (
+            (u.x.hypot(u.y).round() as u32),
+            (v.x.hypot(v.y).round() as u32),
+        );
+        if pw == 0 || ph == 0 {
+            bail!("Empty rotated region: {self:?}");
+        }
+        let center = vs.iter().fold(coord! { x: 0., y: 0. }, |acc, &c| acc + c) / 4.;
+        let origin = center - u / 2. - v / 2.;
+
+        let src = img.to_rgba8();
+        let (w, h) = (src.width() as f64, src.height() as f64);
+        let sample = |x: f64, y: f64| -> Rgba<u8> {
+            let (x, y) = ((x - 0.5).clamp(0., w - 1.), (y - 0.5).clamp(0., h - 1.));
+            let (x0, y0) = (x.floor(), y.floor());
+            let (x1, y1) = ((x0 + 1.).min(w - 1.), (y0 + 1.).min(h - 1.));
+            let (fx, fy) = (x - x0, y - y0);
+            let p = |x: f64, y: f64| src.get_pixel(x as u32, y as u32).0;
+            let (p00, p10, p01, p11) = (p(x0, y0), p(x1, y0), p(x0, y1), p(x1, y1));
+            Rgba(std::array::from_fn(|c| {
+                let top = p00[c] as f64 * (1. - fx) + p10[c] as f64 * fx;
+                let bottom = p01[c] as f64 * (1. - fx) + p11[c] as f64 * fx;
+                (top * (1. - fy) + bottom * fy).round() as u8
+            }))
+        };
+        let patch = RgbaImage::from_fn(pw, ph, |i, j| {
+            let (s, t) = ((i as f64 + 0.5) / pw as f64, (j as f64 + 0.5) / ph as f64);
+            let p = origin + u * s + v * t;
+            sample(p.x, p.y)
+        });
+        Ok(match img.color().has_alpha() {
+            true => DynamicImage::ImageRgba8(patch),
+            false => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(patch).to_rgb8()),
+        })
+    }
+
+    /// Vertices without the closing one, counter-clockwise in the usual y-up sense
+    fn ccw(vs: &[Coord]) -> Vec<Coord> {
+        let mut vs = vs.to_vec();
+        if vs.len() > 1 && vs.first() == vs.last() {
+            vs.pop();
+        }
+        if Self::shoelace(&vs) < 0. {
+            vs.reverse();
+        }
+        vs
+    }
+
+    fn shoelace(vs: &[Coord]) -> f64 {
+        (0..vs.len())
+            .map(|i| {
+                let (p, q) = (vs[i], vs[(i + 1) % vs.len()]);
+                p.x * q.y - q.x * p.y
+            })
+            .sum::<f64>()
+            / 2.
+    }
+
+    /// Probabilistic IoU (1 - Hellinger distance between the boxes seen as 2D Gaussians),
+    /// smooth for thin boxes and independent of how the angle is represented.
+    pub fn probiou(&self, other: &Mbr) -> f32 {
+        const EPS: f64 = 1e-7;
+        let (x1, y1, a1, b1, c1) = self.gaussian();
+        let (x2, y2, a2, b2, c2) = other.gaussian();
+        let (a, b, c) = (a1 + a2, b1 + b2, c1 + c2);
+        let det = a * b - c * c;
+        let t1 = (a * (y1 - y2).powi(2) + b * (x1 - x2).powi(2)) / (det + EPS) * 0.25;
+        let t2 = (c * (x2 - x1) * (y1 - y2)) / (det + EPS) * 0.5;
+        let t3 = (det
+            / (4. * ((a1 * b1 - c1 * c1).max(0.) * (a2 * b2 - c2 * c2).max(0.)).sqrt() + EPS)
+            + EPS)
+            .ln()
+            * 0.5;
+        let bd = (t1 + t2 + t3).clamp(EPS, 100.);
+        let hd = (1. - (-bd).exp() + EPS).sqrt();
+        (1. - hd).clamp(0., 1.) as f32
+    }
+
+    /// Center and covariance (xx, yy, xy) of a uniform distribution over the rectangle,
+    /// built from its edge vectors: (e1 e1^T + e2 e2^T) / 12
+    fn gaussian(&self) -> (f64, f64, f64, f64, f64) {
+        let vs = &self.ls.0;
+        if vs.len() < 4 {
+            return (0., 0., 0., 0., 0.);
+        }
+        let cx = vs[..4].iter().map(|v| v.x).sum::<f64>() / 4.;
+        let cy = vs[..4].iter().map(|v| v.y).sum::<f64>() / 4.;
+        let e1 = vs[1] - vs[0];
+        let e2 = vs[2] - vs[1];
+        (
+            cx,
+            cy,
+            (e1.x * e1.x + e2.x * e2.x) / 12.,
+            (e1.y * e1.y + e2.y * e2.y) / 12.,
+            (e1.x * e1.y + e2.x * e2.y) / 12.,
+        )
     }
 }
 
@@ -205,6 +355,28 @@ impl Mbr {
 mod tests_mbr {
     use super::Mbr;
     use geo::{coord, line_string};
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn crop() {
+        // a bright 30 x 8 bar rotated by 30 degrees comes out upright and horizontal
+        use geo::Contains;
+        let mbr = Mbr::from_cxcywhd(32., 32., 30., 8., 30.);
+        let region = geo::Polygon::new(mbr.vertices().into(), vec![]);
+        let image = RgbImage::from_fn(64, 64, |x, y| {
+            match region.contains(&geo::Point::new(x as f64 + 0.5, y as f64 + 0.5)) {
+                true => Rgb([255, 255, 255]),
+                false => Rgb([0, 0, 0]),
+            }
+        });
+        let patch = mbr.crop(&DynamicImage::ImageRgb8(image)).unwrap();
+        assert_eq!(patch.dimensions(), (30, 8));
+        for (x, y) in [(2, 2), (15, 4), (27, 5)] {
+            assert!(patch.get_pixel(x, y).0[0] > 200, "{x}, {y}");
+        }
+        let image = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+        assert!(Mbr::from_cxcywhd(4., 4., 0., 3., 0.).crop(&image).is_err());
+    }
 
     #[test]
     fn from_cxcywhd1() {
@@ -336,4 +508,48 @@ mod tests_mbr {
         ]);
         assert_eq!(mbr1.iou(&mbr2), 0.2);
     }
+
+    #[test]
+    fn probiou() {
+        let a = Mbr::from_cxcywhd(50., 50., 40., 10., 30.);
+        let b = Mbr::from_cxcywhd(55., 52., 30., 12., 45.);
+        assert_eq!(a.probiou(&b), b.probiou(&a));
+        assert!(a.probiou(&b) > 0. && a.probiou(&b) < 1.);
+
+        // angle representation does not matter
+        let a2 = Mbr::from_cxcywhd(50., 50., 40., 10., 210.);
+        let a3 = Mbr::from_cxcywhd(50., 50., 10., 40., 120.);
+        assert!(a.probiou(&a) > 0.99);
+        assert!((a.probiou(&a2) - a.probiou(&a)).abs() < 1e-5);
+        assert!((a.probiou(&a3) - a.probiou(&a)).abs() < 1e-5);
+
+        // far apart and very thin boxes
+        let far = Mbr::from_cxcywhd(500., 500., 40., 10., 30.);
+        assert!(a.probiou(&far) < 1e-3);
+        let thin = Mbr::from_cxcywhd(50., 50., 40., 0., 89.9);
+        assert!(thin.probiou(&a).is_finite() && thin.probiou(&thin).is_finite());
+    }
+
+    #[test]
+    fn iou_rotated() {
+        let a = Mbr::from_cxcywhd(10., 10., 8., 4., 37.);
+
+        // identical, whatever the angle representation
+        assert!((a.iou(&a) - 1.).abs() < 1e-5);
+        assert!((a.iou(&Mbr::from_cxcywhd(10., 10., 8., 4., 217.)) - 1.).abs() < 1e-5);
+        assert!((a.iou(&Mbr::from_cxcywhd(10., 10., 4., 8., 127.)) - 1.).abs() < 1e-5);
+
+        // disjoint
+        assert_eq!(a.iou(&Mbr::from_cxcywhd(30., 30., 8., 4., 37.)), 0.);
+
+        // a square and itself rotated by 45 degrees around the same center:
+        // the intersection is a regular octagon of area 2 (sqrt(2) - 1) s^2
+        let s = 2f64;
+        let b = Mbr::from_cxcywhd(0., 0., s, s, 15.);
+        let c = Mbr::from_cxcywhd(0., 0., s, s, 60.);
+        let inter = 2. * (2f64.sqrt() - 1.) * s * s;
+        assert!((b.intersect(&c) as f64 - inter).abs() < 1e-4);
+        assert!((b.iou(&c) as f64 - inter / (2. * s * s - inter)).abs() < 1e-4);
+        assert_eq!(b.iou(&c), c.iou(&b));
+    }
 }
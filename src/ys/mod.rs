@@ -5,13 +5,15 @@ mod mask;
 mod mbr;
 mod polygon;
 mod prob;
+mod rle;
 mod y;
 
 pub use bbox::Bbox;
 pub use embedding::Embedding;
 pub use keypoint::Keypoint;
-pub use mask::Mask;
+pub use mask::{Mask, MaskPriority};
 pub use mbr::Mbr;
-pub use polygon::Polygon;
+pub use polygon::{Polygon, Winding};
 pub use prob::Prob;
-pub use y::Y;
+pub use rle::Rle;
+pub use y::{NmsMethod, Ys, Y};
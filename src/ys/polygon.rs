@@ -1,11 +1,19 @@
 use geo::{
-    coord, point, polygon, Area, BoundingRect, Centroid, ConvexHull, EuclideanLength, LineString,
-    MinimumRotatedRect, Point, Simplify,
+    coord, orient::Direction, point, polygon, Area, BoundingRect, Centroid, ConvexHull,
+    EuclideanLength, LineString, MinimumRotatedRect, Orient, Point, Simplify,
 };
 
 use crate::{Bbox, Mbr};
 
+/// Vertex order as seen on the image, i.e. with the y axis pointing down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    CW,
+    CCW,
+}
+
 #[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Polygon {
     polygon: geo::Polygon,
     id: isize,
@@ -61,6 +69,11 @@ impl Polygon {
         self
     }
 
+    pub fn with_confidence(mut self, x: f32) -> Self {
+        self.confidence = x;
+        self
+    }
+
     pub fn id(&self) -> isize {
         self.id
     }
@@ -106,6 +119,16 @@ impl Polygon {
         self.polygon.exterior().points().len()
     }
 
+    /// Fewer than 3 distinct vertices or no area, e.g. from a single-pixel or one-pixel-wide contour
+    pub fn is_degenerate(&self) -> bool {
+        let mut coords: Vec<_> = self.polygon.exterior().coords().collect();
+        coords.dedup();
+        if coords.len() > 1 && coords.first() == coords.last() {
+            coords.pop();
+        }
+        coords.len() < 3 || self.area().is_nan() || self.area() <= f64::EPSILON
+    }
+
     pub fn perimeter(&self) -> f64 {
         self.polygon.exterior().euclidean_length()
     }
@@ -136,16 +159,123 @@ impl Polygon {
             .map(|x| Mbr::from_line_string(x.exterior().to_owned()))
     }
 
+    /// Whether `p` is inside the exterior by ray casting, points on an edge or a vertex count
+    /// as inside. Self-intersecting rings follow the even-odd rule.
+    pub fn contains_point(&self, p: &Point) -> bool {
+        let (x, y) = (p.x(), p.y());
+        let coords = &self.polygon.exterior().0;
+        let mut inside = false;
+        for w in coords.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            // on the edge
+            let cross = (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x);
+            if cross.abs() <= 1e-9 * (1. + (b.x - a.x).abs() + (b.y - a.y).abs())
+                && x >= a.x.min(b.x)
+                && x <= a.x.max(b.x)
+                && y >= a.y.min(b.y)
+                && y <= a.y.max(b.y)
+            {
+                return true;
+            }
+            // half-open in y, so a ray through a vertex is counted once
+            if (a.y > y) != (b.y > y) && x < a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Whether the whole of `b` is inside the exterior, touching the boundary is allowed
+    pub fn contains_bbox(&self, b: &Bbox) -> bool {
+        let (x1, y1, x2, y2) = (
+            b.xmin() as f64,
+            b.ymin() as f64,
+            b.xmax() as f64,
+            b.ymax() as f64,
+        );
+        if ![(x1, y1), (x2, y1), (x2, y2), (x1, y2)]
+            .iter()
+            .all(|&(x, y)| self.contains_point(&point! { x: x, y: y }))
+        {
+            return false;
+        }
+        // no edge may cut through the box, e.g. a concave notch
+        !self.polygon.exterior().0.windows(2).any(|w| {
+            let (a, b) = (w[0], w[1]);
+            let (dx, dy) = (b.x - a.x, b.y - a.y);
+            let (mut t0, mut t1) = (0f64, 1f64);
+            for (p, q) in [
+                (-dx, a.x - x1),
+                (dx, x2 - a.x),
+                (-dy, a.y - y1),
+                (dy, y2 - a.y),
+            ] {
+                if p == 0. {
+                    if q < 0. {
+                        return false;
+                    }
+                } else {
+                    let t = q / p;
+                    if p < 0. {
+                        t0 = t0.max(t);
+                    } else {
+                        t1 = t1.min(t);
+                    }
+                }
+            }
+            if t0 > t1 {
+                return false;
+            }
+            let t = (t0 + t1) / 2.;
+            let (x, y) = (a.x + t * dx, a.y + t * dy);
+            x > x1 && x < x2 && y > y1 && y < y2
+        })
+    }
+
     pub fn convex_hull(mut self) -> Self {
         self.polygon = self.polygon.convex_hull();
         self
     }
 
+    /// Ramer-Douglas-Peucker simplification of the exterior, dropping vertices closer than
+    /// `eps` to the simplified outline. The ring stays closed; if `eps` would leave fewer than
+    /// 3 vertices the coarsest simplification that still has them is kept, so a larger `eps`
+    /// never gives more vertices.
     pub fn simplify(mut self, eps: f64) -> Self {
-        self.polygon = self.polygon.simplify(&eps);
+        // the ring itself, `geo` falls back to the original polygon when it gets too small
+        let simplify = |eps: f64| {
+            let polygon = geo::Polygon::new(
+                self.polygon.exterior().simplify(&eps),
+                self.polygon.interiors().to_vec(),
+            );
+            (polygon.exterior().0.len() > 3 && polygon.unsigned_area() > 0.).then_some(polygon)
+        };
+        if let Some(polygon) = simplify(eps) {
+            self.polygon = polygon;
+            return self;
+        }
+        let (mut lo, mut hi, mut best) = (0., eps, None);
+        for _ in 0..32 {
+            let mid = (lo + hi) / 2.;
+            if let Some(polygon) = simplify(mid) {
+                (lo, best) = (mid, Some(polygon));
+            } else {
+                hi = mid;
+            }
+        }
+        if let Some(polygon) = best {
+            self.polygon = polygon;
+        }
         self
     }
 
+    /// `simplify` with `eps = base_eps / confidence` (confidence floored at 0.1), so confident
+    /// polygons keep more of their points.
+    pub fn simplify_adaptive(self, base_eps: f64) -> Self {
+        let eps = base_eps / (self.confidence as f64).max(0.1);
+        self.simplify(eps)
+    }
+
     pub fn resample(mut self, num_samples: usize) -> Self {
         let points = self.polygon.exterior().to_owned().into_points();
         let mut new_points = Vec::new();
@@ -166,6 +296,60 @@ impl Polygon {
         self
     }
 
+    /// Resample the exterior to exactly `n` vertices evenly spaced along the perimeter, counter-clockwise.
+    pub fn resample_to(mut self, n: usize) -> Self {
+        let polygon = self.polygon.orient(Direction::Default);
+        let coords: Vec<_> = polygon.exterior().coords().copied().collect();
+        let perimeter = self.perimeter();
+        if n == 0 || coords.len() < 2 || perimeter <= 0. {
+            return self;
+        }
+        let step = perimeter / n as f64;
+        let mut new_points = Vec::with_capacity(n);
+        let (mut walked, mut i) = (0., 0);
+        for k in 0..n {
+            let target = k as f64 * step;
+            loop {
+                let (a, b) = (coords[i], coords[(i + 1) % coords.len()]);
+                let seg = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+                if walked + seg >= target || i + 1 >= coords.len() {
+                    let t = if seg > 0. {
+                        (target - walked) / seg
+                    } else {
+                        0.
+                    };
+                    new_points.push(coord! { x: a.x + t * (b.x - a.x), y: a.y + t * (b.y - a.y) });
+                    break;
+                }
+                walked += seg;
+                i += 1;
+            }
+        }
+        self.polygon = geo::Polygon::new(LineString::from(new_points), vec![]);
+        self
+    }
+
+    /// Winding of the exterior, `None` if degenerate
+    pub fn winding(&self) -> Option<Winding> {
+        // shoelace sum is positive for clockwise rings when y points down
+        let area = self.polygon.signed_area();
+        if area > 0. {
+            Some(Winding::CW)
+        } else if area < 0. {
+            Some(Winding::CCW)
+        } else {
+            None
+        }
+    }
+
+    /// Reorder exterior vertices to follow `winding`
+    pub fn ensure_winding(mut self, winding: Winding) -> Self {
+        if matches!(self.winding(), Some(x) if x != winding) {
+            self.polygon.exterior_mut(|ls| ls.0.reverse());
+        }
+        self
+    }
+
     pub fn unclip(mut self, delta: f64, width: f64, height: f64) -> Self {
         let points = self.polygon.exterior().to_owned().into_points();
         let num_points = points.len();
@@ -207,3 +391,125 @@ impl Polygon {
         self
     }
 }
+
+#[cfg(test)]
+mod tests_polygon {
+    use super::{Polygon, Winding};
+    use crate::Bbox;
+    use geo::{polygon, Area};
+
+    #[test]
+    fn ensure_winding() {
+        // clockwise on the image: right along the top, then down
+        let polygon = Polygon::default()
+            .with_polygon(polygon![(x: 0., y: 0.), (x: 4., y: 0.), (x: 4., y: 2.), (x: 0., y: 2.)]);
+        assert_eq!(polygon.winding(), Some(Winding::CW));
+        assert_eq!(polygon.polygon().signed_area(), 8.);
+
+        let ccw = polygon.clone().ensure_winding(Winding::CCW);
+        assert_eq!(ccw.winding(), Some(Winding::CCW));
+        assert_eq!(ccw.polygon().signed_area(), -8.);
+        assert_eq!(ccw.area(), polygon.area());
+
+        let cw = ccw.ensure_winding(Winding::CW);
+        assert_eq!(cw.polygon().signed_area(), 8.);
+        assert_eq!(cw.clone().ensure_winding(Winding::CW), cw);
+    }
+
+    #[test]
+    fn simplify() {
+        // near-collinear runs along the top and right edges of a square
+        let polygon = Polygon::default().with_polygon(polygon![
+            (x: 0., y: 0.),
+            (x: 2., y: 0.05),
+            (x: 5., y: -0.05),
+            (x: 8., y: 0.02),
+            (x: 10., y: 0.),
+            (x: 10.03, y: 5.),
+            (x: 10., y: 10.),
+            (x: 0., y: 10.),
+        ]);
+        let simplified = polygon.clone().simplify(0.5);
+        assert!(simplified.is_closed());
+        assert_eq!(simplified.count(), 5);
+        assert_eq!(
+            simplified
+                .polygon()
+                .exterior()
+                .coords()
+                .take(2)
+                .collect::<Vec<_>>(),
+            [
+                &geo::coord! { x: 0., y: 0. },
+                &geo::coord! { x: 10., y: 0. }
+            ]
+        );
+
+        // never below a triangle
+        let triangle = polygon![(x: 0., y: 0.), (x: 10., y: 0.), (x: 5., y: 0.5)];
+        let triangle = Polygon::default().with_polygon(triangle);
+        assert_eq!(triangle.clone().simplify(100.), triangle);
+
+        // more eps, never more vertices
+        let counts: Vec<_> = [0.01, 0.1, 0.5, 2., 100.]
+            .iter()
+            .map(|&eps| polygon.clone().simplify(eps).count())
+            .collect();
+        assert!(counts.windows(2).all(|x| x[1] <= x[0]), "{counts:?}");
+        assert_eq!(counts[4], 5);
+    }
+
+    #[test]
+    fn contains() {
+        // U shape opening upwards, the notch spans x 4..6, y 0..6
+        let polygon = Polygon::default().with_polygon(polygon![
+            (x: 0., y: 0.),
+            (x: 4., y: 0.),
+            (x: 4., y: 6.),
+            (x: 6., y: 6.),
+            (x: 6., y: 0.),
+            (x: 10., y: 0.),
+            (x: 10., y: 10.),
+            (x: 0., y: 10.),
+        ]);
+        for (x, y, expected) in [
+            (2., 5., true),   // left arm
+            (5., 8., true),   // base
+            (5., 3., false),  // notch
+            (12., 5., false), // outside
+            (5., 6., true),   // notch bottom edge
+            (4., 0., true),   // vertex
+            (0., 5., true),   // left edge
+            (2., 0., true),   // top edge, level with the ray of vertices
+            (5., 0., false),  // level with two vertices, inside the notch
+        ] {
+            assert_eq!(
+                polygon.contains_point(&geo::point! { x: x, y: y }),
+                expected,
+                "({x}, {y})"
+            );
+        }
+
+        let bbox = |x1, y1, x2, y2| Bbox::default().with_xyxy(x1, y1, x2, y2);
+        assert!(polygon.contains_bbox(&bbox(1., 1., 3., 9.)));
+        assert!(polygon.contains_bbox(&bbox(0., 6., 10., 10.)));
+        assert!(!polygon.contains_bbox(&bbox(3., 1., 7., 9.))); // corners in, notch through
+        assert!(!polygon.contains_bbox(&bbox(8., 8., 11., 9.)));
+    }
+
+    #[test]
+    fn is_degenerate() {
+        let contour = |pixels: &[(u32, u32)]| {
+            let mut mask = image::GrayImage::new(8, 8);
+            for &(x, y) in pixels {
+                mask.put_pixel(x, y, image::Luma([255]));
+            }
+            let contours = imageproc::contours::find_contours_with_threshold::<i32>(&mask, 0);
+            Polygon::default().with_points_imageproc(&contours[0].points)
+        };
+        assert!(contour(&[(3, 3)]).is_degenerate());
+        assert!(contour(&[(2, 3), (3, 3), (4, 3)]).is_degenerate());
+        assert!(!contour(&[(2, 2), (3, 2), (2, 3), (3, 3)]).is_degenerate());
+        assert!(Polygon::default().is_degenerate());
+    }
+}
@@ -1,5 +1,6 @@
 /// Probabilities for classification
 #[derive(Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Prob {
     probs: Vec<f32>,
     names: Option<Vec<String>>,
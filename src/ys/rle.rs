@@ -0,0 +1,227 @@
+use anyhow::{bail, Result};
+use image::GrayImage;
+use serde_json::{json, Value};
+
+use crate::Bbox;
+
+/// Run-length encoded binary mask in the COCO layout: pixels are read column by column
+/// and `counts` alternate runs of background and foreground, starting with background.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rle {
+    counts: Vec<u32>,
+    height: u32,
+    width: u32,
+}
+
+impl Rle {
+    /// Encode a mask, any non-zero pixel is foreground
+    pub fn encode(mask: &GrayImage) -> Self {
+        let (width, height) = mask.dimensions();
+        let mut counts = Vec::new();
+        let (mut value, mut run) = (false, 0u32);
+        for x in 0..width {
+            for y in 0..height {
+                let v = mask.get_pixel(x, y).0[0] > 0;
+                if v != value {
+                    counts.push(run);
+                    (value, run) = (v, 0);
+                }
+                run += 1;
+            }
+        }
+        counts.push(run);
+        Self {
+            counts,
+            height,
+            width,
+        }
+    }
+
+    /// Binary mask with foreground at 255
+    pub fn decode(&self) -> GrayImage {
+        let mut mask = GrayImage::new(self.width, self.height);
+        let (mut i, mut value) = (0usize, false);
+        for &run in self.counts.iter() {
+            for _ in 0..run {
+                let (x, y) = (i / self.height as usize, i % self.height as usize);
+                if value && x < self.width as usize {
+                    mask.put_pixel(x as u32, y as u32, image::Luma([255]));
+                }
+                i += 1;
+            }
+            value = !value;
+        }
+        mask
+    }
+
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// `[height, width]` as in COCO
+    pub fn size(&self) -> [u32; 2] {
+        [self.height, self.width]
+    }
+
+    /// Foreground pixels
+    pub fn area(&self) -> u32 {
+        self.counts.iter().skip(1).step_by(2).sum()
+    }
+
+    /// Tight box around the foreground, `None` for an empty mask
+    pub fn bbox(&self) -> Option<Bbox> {
+        let (mut x1, mut y1, mut x2, mut y2) = (u32::MAX, u32::MAX, 0, 0);
+        let mut i = 0u64;
+        let h = self.height.max(1) as u64;
+        for (k, &run) in self.counts.iter().enumerate() {
+            if k % 2 == 1 && run > 0 {
+                let (start, end) = (i, i + run as u64 - 1);
+                let (xs, xe) = ((start / h) as u32, (end / h) as u32);
+                x1 = x1.min(xs);
+                x2 = x2.max(xe + 1);
+                // a run over two or more columns reaches both the top and the bottom row
+                if xs == xe {
+                    y1 = y1.min((start % h) as u32);
+                    y2 = y2.max((end % h) as u32 + 1);
+                } else {
+                    (y1, y2) = (0, self.height);
+                }
+            }
+            i += run as u64;
+        }
+        (x2 > 0).then(|| Bbox::default().with_xyxy(x1 as f32, y1 as f32, x2 as f32, y2 as f32))
+    }
+
+    /// Counts as the compressed string of the COCO API
+    pub fn to_compressed(&self) -> String {
+        let mut s = String::new();
+        for i in 0..self.counts.len() {
+            let mut x = self.counts[i] as i64;
+            if i > 2 {
+                x -= self.counts[i - 2] as i64;
+            }
+            loop {
+                let mut c = (x & 0x1f) as u8;
+                x >>= 5;
+                let more = if c & 0x10 != 0 { x != -1 } else { x != 0 };
+                if more {
+                    c |= 0x20;
+                }
+                s.push((c + 48) as char);
+                if !more {
+                    break;
+                }
+            }
+        }
+        s
+    }
+
+    /// Parse the compressed string of the COCO API
+    pub fn from_compressed(s: &str, height: u32, width: u32) -> Result<Self> {
+        let bytes = s.as_bytes();
+        let mut counts: Vec<u32> = Vec::new();
+        let mut p = 0;
+        while p < bytes.len() {
+            let (mut x, mut k) = (0i64, 0);
+            loop {
+                let Some(c) = bytes.get(p).and_then(|c| c.checked_sub(48)) else {
+                    bail!("Invalid RLE string: {s:?}");
+                };
+                x |= ((c & 0x1f) as i64) << (5 * k);
+                p += 1;
+                k += 1;
+                if c & 0x20 == 0 {
+                    if c & 0x10 != 0 {
+                        x |= -1i64 << (5 * k);
+                    }
+                    break;
+                }
+            }
+            if counts.len() > 2 {
+                x += counts[counts.len() - 2] as i64;
+            }
+            match u32::try_from(x) {
+                Ok(x) => counts.push(x),
+                Err(_) => bail!("Invalid RLE string: {s:?}"),
+            }
+        }
+        if counts.iter().map(|&x| x as u64).sum::<u64>() != height as u64 * width as u64 {
+            bail!("RLE counts do not add up to {height}x{width}");
+        }
+        Ok(Self {
+            counts,
+            height,
+            width,
+        })
+    }
+
+    /// `{"size": [h, w], "counts": ...}`, counts as the compressed string or as numbers
+    pub fn to_json(&self, compressed: bool) -> Value {
+        if compressed {
+            json!({"size": self.size(), "counts": self.to_compressed()})
+        } else {
+            json!({"size": self.size(), "counts": self.counts})
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_rle {
+    use super::Rle;
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn encode() {
+        // 0 1 1
+        // 0 1 0
+        let mut mask = GrayImage::new(3, 2);
+        for (x, y) in [(1, 0), (2, 0), (1, 1)] {
+            mask.put_pixel(x, y, Luma([1]));
+        }
+        let rle = Rle::encode(&mask);
+        assert_eq!(rle.counts(), [2, 3, 1]);
+        assert_eq!(rle.size(), [2, 3]);
+        assert_eq!(rle.area(), 3);
+        assert_eq!(rle.to_compressed(), "231");
+        assert_eq!(Rle::from_compressed("231", 2, 3).unwrap(), rle);
+        assert_eq!(
+            rle.decode()
+                .pixels()
+                .map(|x| x.0[0] > 0)
+                .collect::<Vec<_>>(),
+            mask.pixels().map(|x| x.0[0] > 0).collect::<Vec<_>>()
+        );
+
+        let bbox = rle.bbox().unwrap();
+        assert_eq!(
+            [bbox.xmin(), bbox.ymin(), bbox.xmax(), bbox.ymax()],
+            [1., 0., 3., 2.]
+        );
+        let mut mask = GrayImage::new(3, 3);
+        mask.put_pixel(1, 1, Luma([1]));
+        let bbox = Rle::encode(&mask).bbox().unwrap();
+        assert_eq!(
+            [bbox.xmin(), bbox.ymin(), bbox.xmax(), bbox.ymax()],
+            [1., 1., 2., 2.]
+        );
+        assert!(Rle::encode(&GrayImage::new(2, 2)).bbox().is_none());
+
+        assert_eq!(Rle::encode(&GrayImage::new(2, 2)).counts(), [4]);
+        let ones = Rle::encode(&GrayImage::from_pixel(2, 2, Luma([255])));
+        assert_eq!(ones.counts(), [0, 4]);
+        assert_eq!(ones.decode(), GrayImage::from_pixel(2, 2, Luma([255])));
+
+        // deltas beyond the first two counts
+        let rle = Rle {
+            counts: vec![100, 3, 200, 5, 1000],
+            height: 1308,
+            width: 1,
+        };
+        assert_eq!(
+            Rle::from_compressed(&rle.to_compressed(), 1308, 1).unwrap(),
+            rle
+        );
+        assert!(Rle::from_compressed("231", 3, 3).is_err());
+    }
+}
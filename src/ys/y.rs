@@ -1,6 +1,61 @@
-use crate::{Bbox, Embedding, Keypoint, Mask, Mbr, Polygon, Prob};
+use anyhow::Result;
+use geo::MapCoords;
+use image::{imageops::FilterType, DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use ndarray::{Array, Ix2};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::{
+    uuid_v4_with, Bbox, DynConf, Embedding, Keypoint, Mask, MaskPriority, Mbr, ModelRng, Polygon,
+    Prob, ResizeInfo, Winding,
+};
+
+/// How overlapping boxes are suppressed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NmsMethod {
+    /// Drop boxes overlapping a kept box above the IoU threshold
+    #[default]
+    Hard,
+    /// Soft-NMS, scale the confidence by `1 - iou` above the IoU threshold
+    SoftLinear,
+    /// Soft-NMS, scale the confidence by `exp(-iou^2 / sigma)`
+    SoftGaussian,
+    /// Drop boxes whose DIoU with a kept box is above the IoU threshold, so close boxes
+    /// with distant centers (e.g. occluded neighbours) survive
+    Diou,
+}
+
+/// One object of a `Y`: its bbox and the polygon, mask and keypoints produced for it
+#[derive(Debug, Default)]
+struct Instance<'a> {
+    bbox: Option<&'a Bbox>,
+    polygon: Option<&'a Polygon>,
+    mask: Option<&'a Mask>,
+    keypoints: Option<&'a Vec<Keypoint>>,
+}
+
+impl Instance<'_> {
+    fn id(&self) -> isize {
+        self.bbox.map(|x| x.id()).unwrap_or_else(|| {
+            self.polygon
+                .map(|x| x.id())
+                .or(self.mask.map(|x| x.id()))
+                .unwrap_or(-1)
+        })
+    }
+
+    fn confidence(&self) -> f32 {
+        self.bbox.map(|x| x.confidence()).unwrap_or_else(|| {
+            self.polygon
+                .map(|x| x.confidence())
+                .or(self.mask.map(|x| x.confidence()))
+                .unwrap_or(0.)
+        })
+    }
+}
 
 #[derive(Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Y {
     probs: Option<Prob>,
     bboxes: Option<Vec<Bbox>>,
@@ -10,6 +65,8 @@ pub struct Y {
     texts: Option<Vec<String>>,
     masks: Option<Vec<Mask>>,
     embedding: Option<Embedding>,
+    heatmap: Option<Array<f32, Ix2>>, // (height, width), values in [0, 1]
+    depth: Option<Array<f32, Ix2>>,   // (height, width), raw model values at image size
 }
 
 impl std::fmt::Debug for Y {
@@ -51,6 +108,12 @@ impl std::fmt::Debug for Y {
         if let Some(x) = &self.embedding {
             f.field("Embedding", &x);
         }
+        if let Some(x) = &self.heatmap {
+            f.field("Heatmap", &x.dim());
+        }
+        if let Some(x) = &self.depth {
+            f.field("Depth", &x.dim());
+        }
         f.finish()
     }
 }
@@ -96,6 +159,27 @@ impl Y {
         self
     }
 
+    /// Per-pixel confidence map in [0, 1] of shape (height, width), at any resolution
+    pub fn with_heatmap(mut self, x: Array<f32, Ix2>) -> Self {
+        self.heatmap = Some(x);
+        self
+    }
+
+    pub fn heatmap(&self) -> Option<&Array<f32, Ix2>> {
+        self.heatmap.as_ref()
+    }
+
+    /// Per-pixel depth of shape (height, width), in the units of the model (relative
+    /// inverse depth for Depth-Anything, larger is closer)
+    pub fn with_depth(mut self, x: Array<f32, Ix2>) -> Self {
+        self.depth = Some(x);
+        self
+    }
+
+    pub fn depth(&self) -> Option<&Array<f32, Ix2>> {
+        self.depth.as_ref()
+    }
+
     pub fn masks(&self) -> Option<&Vec<Mask>> {
         self.masks.as_ref()
     }
@@ -138,37 +222,764 @@ impl Y {
         }
     }
 
-    pub fn apply_mbrs_nms(mut self, iou_threshold: f32) -> Self {
-        match &mut self.mbrs {
-            None => self,
-            Some(ref mut mbrs) => {
-                mbrs.sort_by(|b1, b2| {
-                    b2.confidence()
-                        .partial_cmp(&b1.confidence())
-                        .unwrap_or(std::cmp::Ordering::Equal)
+    /// Plain JSON of bboxes (xyxy), keypoints, mbrs, polygons, texts and probs, absent fields omitted.
+    pub fn to_json(&self) -> Value {
+        let mut x = serde_json::Map::new();
+        if let Some(bboxes) = self.bboxes() {
+            let v: Vec<Value> = bboxes
+                .iter()
+                .map(|b| {
+                    let mut v = json!({
+                        "xyxy": [b.xmin(), b.ymin(), b.xmax(), b.ymax()],
+                        "id": b.id(),
+                        "name": b.name(),
+                        "confidence": b.confidence(),
+                    });
+                    if let Some(uuid) = b.uuid() {
+                        v["uuid"] = json!(uuid);
+                    }
+                    if let Some(track_id) = b.track_id() {
+                        v["track_id"] = json!(track_id);
+                    }
+                    v
+                })
+                .collect();
+            x.insert("bboxes".into(), json!(v));
+        }
+        if let Some(keypoints) = self.keypoints() {
+            let v: Vec<Vec<Value>> = keypoints
+                .iter()
+                .map(|kpts| {
+                    kpts.iter()
+                        .map(|k| {
+                            let mut v = json!({ "xy": [k.x(), k.y()], "id": k.id(), "name": k.name(), "confidence": k.confidence() });
+                            if let Some(z) = k.z() {
+                                v["z"] = json!(z);
+                            }
+                            v
+                        })
+                        .collect()
+                })
+                .collect();
+            x.insert("keypoints".into(), json!(v));
+        }
+        if let Some(mbrs) = self.mbrs() {
+            let v: Vec<Value> = mbrs
+                .iter()
+                .map(|m| {
+                    json!({
+                        "vertices": m.vertices().iter().map(|c| [c.x, c.y]).collect::<Vec<_>>(),
+                        "id": m.id(),
+                        "name": m.name(),
+                        "confidence": m.confidence(),
+                    })
+                })
+                .collect();
+            x.insert("mbrs".into(), json!(v));
+        }
+        if let Some(polygons) = self.polygons() {
+            let v: Vec<Value> = polygons
+                .iter()
+                .map(|p| {
+                    json!({
+                        "points": p.polygon().exterior().coords().map(|c| [c.x, c.y]).collect::<Vec<_>>(),
+                        "id": p.id(),
+                        "name": p.name(),
+                        "confidence": p.confidence(),
+                    })
+                })
+                .collect();
+            x.insert("polygons".into(), json!(v));
+        }
+        if let Some(texts) = self.texts() {
+            x.insert("texts".into(), json!(texts));
+        }
+        if let Some(probs) = self.probs() {
+            x.insert("probs".into(), json!(probs.probs()));
+        }
+        Value::Object(x)
+    }
+
+    /// Group the results of each object: instance `i` is bbox `i` with the first unused
+    /// polygon and mask of the same class and confidence overlapping it, and keypoints `i`
+    /// for pose results. Polygons and masks without a bbox follow as instances of their own.
+    fn instances(&self) -> Vec<Instance<'_>> {
+        let bboxes = self.bboxes().map_or(&[][..], |x| x.as_slice());
+        let polygons = self.polygons().map_or(&[][..], |x| x.as_slice());
+        let masks = self.masks().map_or(&[][..], |x| x.as_slice());
+        let keypoints = self
+            .keypoints()
+            .filter(|x| x.len() == bboxes.len())
+            .map_or(&[][..], |x| x.as_slice());
+        let claim = |used: &mut [bool], f: &dyn Fn(usize) -> bool| {
+            let i = (0..used.len()).find(|&i| !used[i] && f(i))?;
+            used[i] = true;
+            Some(i)
+        };
+        let (mut used_polygons, mut used_masks) =
+            (vec![false; polygons.len()], vec![false; masks.len()]);
+
+        let mut instances = Vec::new();
+        for (i, bbox) in bboxes.iter().enumerate() {
+            let same =
+                |id: isize, confidence: f32| id == bbox.id() && confidence == bbox.confidence();
+            let polygon = claim(&mut used_polygons, &|j| {
+                let x = &polygons[j];
+                same(x.id(), x.confidence()) && x.bbox().is_some_and(|b| b.intersect(bbox) > 0.)
+            });
+            let mask = claim(&mut used_masks, &|j| {
+                same(masks[j].id(), masks[j].confidence())
+            });
+            instances.push(Instance {
+                bbox: Some(bbox),
+                polygon: polygon.map(|j| &polygons[j]),
+                mask: mask.map(|j| &masks[j]),
+                keypoints: keypoints.get(i),
+            });
+        }
+        for (polygon, _) in polygons.iter().zip(used_polygons).filter(|x| !x.1) {
+            let mask = claim(&mut used_masks, &|k| {
+                masks[k].id() == polygon.id() && masks[k].confidence() == polygon.confidence()
+            });
+            instances.push(Instance {
+                polygon: Some(polygon),
+                mask: mask.map(|k| &masks[k]),
+                ..Default::default()
+            });
+        }
+        for (mask, _) in masks.iter().zip(used_masks).filter(|x| !x.1) {
+            instances.push(Instance {
+                mask: Some(mask),
+                ..Default::default()
+            });
+        }
+        instances
+    }
+
+    /// Pascal VOC `<annotation>` with one `<object>` per bbox, coordinates rounded to pixels.
+    pub fn to_voc_xml(&self, filename: &str, image_w: u32, image_h: u32, depth: u32) -> String {
+        let escape = |x: &str| {
+            x.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        };
+        let mut xml = format!(
+            "<annotation>\n\t<filename>{}</filename>\n\t<size>\n\t\t<width>{image_w}</width>\n\t\t<height>{image_h}</height>\n\t\t<depth>{depth}</depth>\n\t</size>\n\t<segmented>0</segmented>\n",
+            escape(filename)
+        );
+        for bbox in self.bboxes.iter().flatten() {
+            let name = bbox
+                .name()
+                .cloned()
+                .unwrap_or_else(|| bbox.id().to_string());
+            xml.push_str(&format!(
+                "\t<object>\n\t\t<name>{}</name>\n\t\t<pose>Unspecified</pose>\n\t\t<truncated>0</truncated>\n\t\t<difficult>0</difficult>\n\t\t<bndbox>\n\t\t\t<xmin>{}</xmin>\n\t\t\t<ymin>{}</ymin>\n\t\t\t<xmax>{}</xmax>\n\t\t\t<ymax>{}</ymax>\n\t\t</bndbox>\n\t</object>\n",
+                escape(&name),
+                bbox.xmin().round() as i64,
+                bbox.ymin().round() as i64,
+                bbox.xmax().round() as i64,
+                bbox.ymax().round() as i64,
+            ));
+        }
+        xml.push_str("</annotation>\n");
+        xml
+    }
+
+    /// YOLO `.txt` labels of a `image_w` x `image_h` image, coordinates normalized to [0, 1].
+    /// With polygons, one `class x1 y1 x2 y2 ...` segment per polygon; otherwise one
+    /// `class cx cy w h` line per bbox, followed by `x y v` per keypoint for pose results.
+    /// Negative class ids are skipped.
+    pub fn to_yolo_txt(&self, image_w: u32, image_h: u32) -> String {
+        let (w, h) = (image_w.max(1) as f32, image_h.max(1) as f32);
+        let mut lines = Vec::new();
+        if let Some(polygons) = self.polygons() {
+            for polygon in polygons.iter().filter(|x| x.id() >= 0) {
+                let mut coords: Vec<_> = polygon.polygon().exterior().coords().collect();
+                if coords.len() > 1 && coords.first() == coords.last() {
+                    coords.pop();
+                }
+                let mut line = polygon.id().to_string();
+                for c in coords {
+                    line.push_str(&format!(" {:.6} {:.6}", c.x as f32 / w, c.y as f32 / h));
+                }
+                lines.push(line);
+            }
+        } else {
+            for (idx, bbox) in self.bboxes().into_iter().flatten().enumerate() {
+                if bbox.id() < 0 {
+                    continue;
+                }
+                let mut line = format!(
+                    "{} {:.6} {:.6} {:.6} {:.6}",
+                    bbox.id(),
+                    bbox.cx() / w,
+                    bbox.cy() / h,
+                    bbox.width() / w,
+                    bbox.height() / h
+                );
+                for k in self
+                    .keypoints()
+                    .and_then(|x| x.get(idx))
+                    .into_iter()
+                    .flatten()
+                {
+                    if k.confidence() > 0. && !k.is_origin() {
+                        line.push_str(&format!(" {:.6} {:.6} 2", k.x() / w, k.y() / h));
+                    } else {
+                        line.push_str(" 0 0 0");
+                    }
+                }
+                lines.push(line);
+            }
+        }
+        lines.iter().map(|x| format!("{x}\n")).collect()
+    }
+
+    /// Parse YOLO `.txt` labels of a `image_w` x `image_h` image back to pixels. Lines of 5
+    /// values are bboxes, `5 + 2 * nk` or `5 + 3 * nk` values a bbox with `nk` keypoints
+    /// (`x y` or `x y v`), any other odd count a segment, read as a polygon and its bbox.
+    /// Confidences are 1.
+    pub fn from_yolo_txt(s: &str, image_w: u32, image_h: u32, nk: usize) -> Result<Self> {
+        let (w, h) = (image_w as f32, image_h as f32);
+        let (mut bboxes, mut kpts, mut polygons) = (Vec::new(), Vec::new(), Vec::new());
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let xs: Vec<f32> = line
+                .split_whitespace()
+                .map(|x| x.parse::<f32>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|err| anyhow::anyhow!("Line {}: {err}: {line:?}", n + 1))?;
+            let id = xs[0] as isize;
+            let bbox = || {
+                Bbox::default()
+                    .with_xywh(
+                        (xs[1] - xs[3] / 2.) * w,
+                        (xs[2] - xs[4] / 2.) * h,
+                        xs[3] * w,
+                        xs[4] * h,
+                    )
+                    .with_id(id)
+                    .with_confidence(1.)
+            };
+            match xs.len() {
+                5 => bboxes.push(bbox()),
+                len if nk > 0 && (len == 5 + 2 * nk || len == 5 + 3 * nk) => {
+                    bboxes.push(bbox());
+                    kpts.push(
+                        xs[5..]
+                            .chunks_exact((len - 5) / nk)
+                            .enumerate()
+                            .map(|(i, k)| match k.get(2).map_or(true, |&v| v > 0.) {
+                                true => Keypoint::default()
+                                    .with_id(i as isize)
+                                    .with_confidence(1.)
+                                    .with_xy(k[0] * w, k[1] * h),
+                                false => Keypoint::default(),
+                            })
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                len if len > 5 && len % 2 == 1 => {
+                    let coords: Vec<(f64, f64)> = xs[1..]
+                        .chunks_exact(2)
+                        .map(|c| ((c[0] * w) as f64, (c[1] * h) as f64))
+                        .collect();
+                    let polygon = Polygon::default()
+                        .with_polygon(geo::Polygon::new(coords.into(), vec![]))
+                        .with_id(id)
+                        .with_confidence(1.);
+                    if let Some(bbox) = polygon.bbox() {
+                        bboxes.push(bbox.with_id(id).with_confidence(1.));
+                    }
+                    polygons.push(polygon);
+                }
+                _ => anyhow::bail!(
+                    "Line {}: unexpected number of values {}: {line:?}",
+                    n + 1,
+                    xs.len()
+                ),
+            }
+        }
+        let mut y = Y::default();
+        if !bboxes.is_empty() {
+            y = y.with_bboxes(&bboxes);
+        }
+        if !kpts.is_empty() {
+            y = y.with_keypoints(&kpts);
+        }
+        if !polygons.is_empty() {
+            y = y.with_polygons(&polygons);
+        }
+        Ok(y)
+    }
+
+    pub fn apply_polygons_winding(mut self, winding: Winding) -> Self {
+        if let Some(polygons) = self.polygons.take() {
+            self.polygons = Some(
+                polygons
+                    .into_iter()
+                    .map(|x| x.ensure_winding(winding))
+                    .collect(),
+            );
+        }
+        self
+    }
+
+    /// Rescale all geometry (bboxes, keypoints, mbrs, polygons and masks) from a
+    /// `from_w` x `from_h` image to a `new_w` x `new_h` one, e.g. for drawing on a thumbnail.
+    pub fn scale_to(self, new_w: u32, new_h: u32, from_w: u32, from_h: u32) -> Self {
+        let sx = new_w as f32 / from_w.max(1) as f32;
+        let sy = new_h as f32 / from_h.max(1) as f32;
+        self.map_geometry(sx, sy, 0., 0., |mask| {
+            mask.resize_exact(new_w, new_h, FilterType::Nearest)
+        })
+    }
+
+    /// Image patches of all bboxes, in order, see [`Bbox::crop`]. Fails if any box is
+    /// entirely outside `img`.
+    pub fn crops(&self, img: &DynamicImage) -> Result<Vec<DynamicImage>> {
+        self.bboxes
+            .iter()
+            .flatten()
+            .map(|bbox| bbox.crop(img))
+            .collect()
+    }
+
+    /// Upright patches of all mbrs, in order, see [`Mbr::crop`]
+    pub fn mbr_crops(&self, img: &DynamicImage) -> Result<Vec<DynamicImage>> {
+        self.mbrs
+            .iter()
+            .flatten()
+            .map(|mbr| mbr.crop(img))
+            .collect()
+    }
+
+    /// Shift bboxes, keypoints, mbrs and polygons by `(dx, dy)`, e.g. from a crop back to
+    /// the full image. Masks are left as they are.
+    pub fn translate(self, dx: f32, dy: f32) -> Self {
+        self.map_geometry(1., 1., dx, dy, |mask| mask.clone())
+    }
+
+    /// Map results from the model input back to the original image, see [`ResizeInfo`].
+    /// Masks are expected at the model input size.
+    pub fn unresize(self, info: &ResizeInfo) -> Self {
+        let (sx, sy) = (1. / info.scale_x, 1. / info.scale_y);
+        let (tx, ty) = (-info.pad_x * sx, -info.pad_y * sy);
+        self.map_geometry(sx, sy, tx, ty, |mask| {
+            // the original image area in the model input, padding excluded
+            let (w, h) = (
+                ((info.width0 as f32 * info.scale_x).round() as u32).max(1),
+                ((info.height0 as f32 * info.scale_y).round() as u32).max(1),
+            );
+            let mut canvas = DynamicImage::new(w, h, mask.color());
+            image::imageops::replace(&mut canvas, mask, -info.pad_x as i64, -info.pad_y as i64);
+            canvas.resize_exact(info.width0, info.height0, FilterType::Nearest)
+        })
+    }
+
+    /// Clamp bboxes and keypoints into a `width` x `height` image. Boxes outside the
+    /// image are kept with zero area, so they still line up with their keypoints or masks.
+    pub fn clip(mut self, width: f32, height: f32) -> Self {
+        if let Some(bboxes) = self.bboxes.as_mut() {
+            for bbox in bboxes.iter_mut() {
+                *bbox = bbox.clip(width, height);
+            }
+        }
+        if let Some(keypoints) = self.keypoints.as_mut() {
+            for kpt in keypoints.iter_mut().flatten() {
+                *kpt = kpt.clip(width, height, false);
+            }
+        }
+        self
+    }
+
+    /// `(x, y) -> (x * sx + tx, y * sy + ty)` on every geometry, masks through `mask_fn`
+    fn map_geometry<F: Fn(&DynamicImage) -> DynamicImage>(
+        mut self,
+        sx: f32,
+        sy: f32,
+        tx: f32,
+        ty: f32,
+        mask_fn: F,
+    ) -> Self {
+        if let Some(bboxes) = self.bboxes.as_mut() {
+            for bbox in bboxes.iter_mut() {
+                let (x1, y1, x2, y2) = (
+                    bbox.xmin() * sx + tx,
+                    bbox.ymin() * sy + ty,
+                    bbox.xmax() * sx + tx,
+                    bbox.ymax() * sy + ty,
+                );
+                *bbox = std::mem::take(bbox).with_xyxy(x1, y1, x2, y2);
+            }
+        }
+        if let Some(keypoints) = self.keypoints.as_mut() {
+            for kpt in keypoints.iter_mut().flatten() {
+                let (x, y) = (kpt.x() * sx + tx, kpt.y() * sy + ty);
+                *kpt = std::mem::take(kpt).with_xy(x, y);
+            }
+        }
+        let (sx, sy, tx, ty) = (sx as f64, sy as f64, tx as f64, ty as f64);
+        if let Some(mbrs) = self.mbrs.as_mut() {
+            for mbr in mbrs.iter_mut() {
+                let ls: geo::LineString = mbr
+                    .vertices()
+                    .iter()
+                    .map(|c| geo::coord! { x: c.x * sx + tx, y: c.y * sy + ty })
+                    .collect();
+                *mbr = Mbr::from_line_string(ls)
+                    .with_id(mbr.id())
+                    .with_name(mbr.name().cloned())
+                    .with_confidence(mbr.confidence());
+            }
+        }
+        if let Some(polygons) = self.polygons.as_mut() {
+            for polygon in polygons.iter_mut() {
+                let x = polygon.polygon().map_coords(|c| {
+                    geo::coord! {
+                        x: c.x * sx + tx,
+                        y: c.y * sy + ty,
+                    }
                 });
-                let mut current_index = 0;
-                for index in 0..mbrs.len() {
-                    let mut drop = false;
-                    for prev_index in 0..current_index {
-                        let iou = mbrs[prev_index].iou(&mbrs[index]);
-                        if iou > iou_threshold {
-                            drop = true;
-                            break;
+                *polygon = std::mem::take(polygon).with_polygon(x);
+            }
+        }
+        if let Some(masks) = self.masks.as_mut() {
+            for mask in masks.iter_mut() {
+                let x = mask_fn(mask.mask());
+                *mask = std::mem::take(mask).with_mask(x);
+            }
+        }
+        self
+    }
+
+    pub fn apply_polygons_simplify_adaptive(mut self, base_eps: f64) -> Self {
+        if let Some(polygons) = self.polygons.take() {
+            self.polygons = Some(
+                polygons
+                    .into_iter()
+                    .map(|x| x.simplify_adaptive(base_eps))
+                    .collect(),
+            );
+        }
+        self
+    }
+
+    pub fn apply_mbrs_nms(mut self, iou_threshold: f32) -> Self {
+        if let Some(ref mut mbrs) = &mut self.mbrs {
+            Self::nms_mbrs_by(mbrs, |kept, x| kept.iou(x) > iou_threshold);
+        }
+        self
+    }
+
+    /// Rotated NMS with the probabilistic IoU, see [`Mbr::probiou`]
+    pub fn apply_mbrs_nms_probiou(mut self, iou_threshold: f32) -> Self {
+        if let Some(ref mut mbrs) = &mut self.mbrs {
+            Self::nms_mbrs_by(mbrs, |kept, x| kept.probiou(x) > iou_threshold);
+        }
+        self
+    }
+
+    fn nms_mbrs_by<F: Fn(&Mbr, &Mbr) -> bool>(mbrs: &mut Vec<Mbr>, suppress: F) {
+        mbrs.sort_by(|b1, b2| {
+            b2.confidence()
+                .partial_cmp(&b1.confidence())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut current_index = 0;
+        for index in 0..mbrs.len() {
+            let mut drop = false;
+            for prev_index in 0..current_index {
+                if suppress(&mbrs[prev_index], &mbrs[index]) {
+                    drop = true;
+                    break;
+                }
+            }
+            if !drop {
+                mbrs.swap(current_index, index);
+                current_index += 1;
+            }
+        }
+        mbrs.truncate(current_index);
+    }
+
+    /// Drop bboxes (and their keypoints) smaller than their class's `(min_w, min_h)`;
+    /// the last entry applies to classes beyond the list, so a single entry covers all.
+    pub fn apply_min_box_size(mut self, sizes: &[(f32, f32)]) -> Self {
+        if sizes.is_empty() {
+            return self;
+        }
+        if let Some(bboxes) = self.bboxes.take() {
+            let mut kpts = self.keypoints.take().map(|x| x.into_iter());
+            let (mut bboxes_, mut kpts_) = (Vec::new(), Vec::new());
+            for bbox in bboxes {
+                let kpt = kpts.as_mut().and_then(|x| x.next());
+                let (w, h) = sizes[(bbox.id().max(0) as usize).min(sizes.len() - 1)];
+                if bbox.width() < w || bbox.height() < h {
+                    continue;
+                }
+                bboxes_.push(bbox);
+                kpts_.extend(kpt);
+            }
+            self.bboxes = Some(bboxes_);
+            if kpts.is_some() {
+                self.keypoints = Some(kpts_);
+            }
+        }
+        self
+    }
+
+    /// Rewrite ids and names of bboxes, mbrs and polygons via `map` (old id -> (new id, new name)).
+    /// Unmapped detections are dropped if `strict`, otherwise kept as is.
+    /// Keypoints follow their bboxes.
+    pub fn remap_classes(mut self, map: &HashMap<usize, (usize, String)>, strict: bool) -> Self {
+        let lookup = |id: isize| usize::try_from(id).ok().and_then(|id| map.get(&id));
+        if let Some(bboxes) = self.bboxes.take() {
+            let mut kpts = self.keypoints.take().map(|x| x.into_iter());
+            let (mut bboxes_, mut kpts_) = (Vec::new(), Vec::new());
+            for bbox in bboxes {
+                let kpt = kpts.as_mut().and_then(|x| x.next());
+                let bbox = match lookup(bbox.id()) {
+                    Some((id, name)) => bbox.with_id(*id as isize).with_name(Some(name.clone())),
+                    None if strict => continue,
+                    None => bbox,
+                };
+                bboxes_.push(bbox);
+                kpts_.extend(kpt);
+            }
+            self.bboxes = Some(bboxes_);
+            if kpts.is_some() {
+                self.keypoints = Some(kpts_);
+            }
+        }
+        if let Some(mbrs) = self.mbrs.take() {
+            self.mbrs = Some(
+                mbrs.into_iter()
+                    .filter_map(|x| match lookup(x.id()) {
+                        Some((id, name)) => {
+                            Some(x.with_id(*id as isize).with_name(Some(name.clone())))
+                        }
+                        None if strict => None,
+                        None => Some(x),
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(polygons) = self.polygons.take() {
+            self.polygons = Some(
+                polygons
+                    .into_iter()
+                    .filter_map(|x| match lookup(x.id()) {
+                        Some((id, name)) => {
+                            Some(x.with_id(*id as isize).with_name(Some(name.clone())))
                         }
+                        None if strict => None,
+                        None => Some(x),
+                    })
+                    .collect(),
+            );
+        }
+        self
+    }
+
+    /// Give every bbox a random UUID, to reference detections across a pipeline
+    pub fn apply_detection_ids(self) -> Self {
+        self.apply_detection_ids_with(&ModelRng::default())
+    }
+
+    pub(crate) fn apply_detection_ids_with(mut self, rng: &ModelRng) -> Self {
+        if let Some(ref mut bboxes) = &mut self.bboxes {
+            for bbox in bboxes.iter_mut() {
+                *bbox = std::mem::take(bbox).with_uuid(Some(uuid_v4_with(rng)));
+            }
+        }
+        self
+    }
+
+    pub fn apply_bboxes_nms_per_class(mut self, ious: &DynConf) -> Self {
+        if let Some(ref mut bboxes) = &mut self.bboxes {
+            Self::nms_bboxes_per_class(bboxes, ious);
+        }
+        self
+    }
+
+    pub fn nms_bboxes(bboxes: &mut Vec<Bbox>, iou_threshold: f32) {
+        Self::nms_bboxes_by(bboxes, |kept, x| kept.iou(x) > iou_threshold);
+    }
+
+    /// Class-aware NMS, boxes only suppress boxes of their own class with that class's threshold
+    pub fn nms_bboxes_per_class(bboxes: &mut Vec<Bbox>, ious: &DynConf) {
+        if ious.is_empty() {
+            return;
+        }
+        Self::nms_bboxes_by(bboxes, |kept, x| {
+            kept.id() == x.id() && kept.iou(x) > ious[(x.id().max(0) as usize).min(ious.len() - 1)]
+        });
+    }
+
+    /// Soft-NMS: instead of dropping overlapping boxes, decay their confidences and drop
+    /// the ones falling below `confs`. With `ious`, boxes only decay boxes of their own
+    /// class and `SoftLinear` uses that class's threshold. `Hard` and `Diou` drop boxes.
+    pub fn apply_bboxes_soft_nms(
+        mut self,
+        method: NmsMethod,
+        iou_threshold: f32,
+        ious: Option<&DynConf>,
+        sigma: f32,
+        confs: &DynConf,
+    ) -> Self {
+        if let Some(ref mut bboxes) = &mut self.bboxes {
+            Self::soft_nms_bboxes(bboxes, method, iou_threshold, ious, sigma, confs);
+        }
+        self
+    }
+
+    pub fn soft_nms_bboxes(
+        bboxes: &mut Vec<Bbox>,
+        method: NmsMethod,
+        iou_threshold: f32,
+        ious: Option<&DynConf>,
+        sigma: f32,
+        confs: &DynConf,
+    ) {
+        let threshold = |x: &Bbox| match ious {
+            Some(ious) if !ious.is_empty() => ious[(x.id().max(0) as usize).min(ious.len() - 1)],
+            _ => iou_threshold,
+        };
+        let weight = |kept: &Bbox, x: &Bbox| {
+            if ious.is_some() && kept.id() != x.id() {
+                return 1.;
+            }
+            let iou = kept.iou(x);
+            match method {
+                NmsMethod::Hard => {
+                    if iou > threshold(x) {
+                        0.
+                    } else {
+                        1.
                     }
-                    if !drop {
-                        mbrs.swap(current_index, index);
-                        current_index += 1;
+                }
+                NmsMethod::SoftLinear => {
+                    if iou > threshold(x) {
+                        1. - iou
+                    } else {
+                        1.
+                    }
+                }
+                NmsMethod::SoftGaussian => (-iou * iou / sigma.max(f32::EPSILON)).exp(),
+                NmsMethod::Diou => {
+                    if kept.diou(x) > threshold(x) {
+                        0.
+                    } else {
+                        1.
                     }
                 }
-                mbrs.truncate(current_index);
-                self
             }
+        };
+        let conf_threshold = |x: &Bbox| {
+            if confs.is_empty() {
+                0.
+            } else {
+                confs[(x.id().max(0) as usize).min(confs.len() - 1)]
+            }
+        };
+
+        let mut pending = std::mem::take(bboxes);
+        while let Some(i) = pending
+            .iter()
+            .enumerate()
+            .max_by(|(i, a), (j, b)| {
+                a.confidence()
+                    .total_cmp(&b.confidence())
+                    .then_with(|| j.cmp(i))
+            })
+            .map(|(i, _)| i)
+        {
+            let kept = pending.remove(i);
+            pending = pending
+                .into_iter()
+                .filter_map(|x| {
+                    // boxes that aren't decayed are kept as they are, e.g. pose scores
+                    // below the detection threshold
+                    let w = weight(&kept, &x);
+                    if w == 1. {
+                        return Some(x);
+                    }
+                    let confidence = x.confidence() * w;
+                    (confidence > 0. && confidence >= conf_threshold(&x))
+                        .then(|| x.with_confidence(confidence))
+                })
+                .collect();
+            bboxes.push(kept);
         }
     }
 
-    pub fn nms_bboxes(bboxes: &mut Vec<Bbox>, iou_threshold: f32) {
+    /// Composite masks and polygons into one `width` x `height` label map, pixel value is
+    /// class id + 1 (0 for background), overlaps resolved by `priority`.
+    pub fn flatten_masks(
+        &self,
+        width: u32,
+        height: u32,
+        priority: &MaskPriority,
+    ) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        // (id, confidence, binary layer)
+        let mut layers: Vec<(isize, f32, GrayImage)> = Vec::new();
+        if let Some(masks) = self.masks() {
+            for mask in masks.iter() {
+                let mut layer = mask.mask().to_luma8();
+                if layer.dimensions() != (width, height) {
+                    layer = image::imageops::resize(
+                        &layer,
+                        width,
+                        height,
+                        image::imageops::FilterType::Nearest,
+                    );
+                }
+                layers.push((mask.id(), mask.confidence(), layer));
+            }
+        }
+        if let Some(polygons) = self.polygons() {
+            for polygon in polygons.iter() {
+                let mut points: Vec<imageproc::point::Point<i32>> = polygon
+                    .polygon()
+                    .exterior()
+                    .coords()
+                    .map(|c| imageproc::point::Point::new(c.x.round() as i32, c.y.round() as i32))
+                    .collect();
+                if points.len() > 1 && points.first() == points.last() {
+                    points.pop();
+                }
+                if points.len() < 3 {
+                    continue;
+                }
+                let mut layer = GrayImage::new(width, height);
+                imageproc::drawing::draw_polygon_mut(&mut layer, &points, Luma([255u8]));
+                layers.push((polygon.id(), polygon.confidence(), layer));
+            }
+        }
+
+        // paint from the lowest priority to the highest
+        let rank = |id: isize| match priority {
+            MaskPriority::Confidence => 0,
+            MaskPriority::Classes(ids) => ids.iter().position(|x| *x == id).unwrap_or(ids.len()),
+        };
+        layers.sort_by(|a, b| rank(b.0).cmp(&rank(a.0)).then(a.1.total_cmp(&b.1)));
+        let mut labels = ImageBuffer::new(width, height);
+        for (id, _, layer) in layers.iter().filter(|(id, _, _)| *id >= 0) {
+            for (x, y, v) in layer.enumerate_pixels() {
+                if v.0[0] > 0 {
+                    labels.put_pixel(x, y, Luma([(*id + 1) as u16]));
+                }
+            }
+        }
+        labels
+    }
+
+    fn nms_bboxes_by<F: Fn(&Bbox, &Bbox) -> bool>(bboxes: &mut Vec<Bbox>, suppress: F) {
         bboxes.sort_by(|b1, b2| {
             b2.confidence()
                 .partial_cmp(&b1.confidence())
@@ -178,8 +989,7 @@ impl Y {
         for index in 0..bboxes.len() {
             let mut drop = false;
             for prev_index in 0..current_index {
-                let iou = bboxes[prev_index].iou(&bboxes[index]);
-                if iou > iou_threshold {
+                if suppress(&bboxes[prev_index], &bboxes[index]) {
                     drop = true;
                     break;
                 }
@@ -192,3 +1002,985 @@ impl Y {
         bboxes.truncate(current_index);
     }
 }
+
+/// Batch of results, one `Y` per image.
+#[derive(Clone, PartialEq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ys(pub Vec<Y>);
+
+impl From<Vec<Y>> for Ys {
+    fn from(ys: Vec<Y>) -> Self {
+        Self(ys)
+    }
+}
+
+impl std::ops::Deref for Ys {
+    type Target = Vec<Y>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Ys {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Ys {
+    /// Remap class ids and names of every result, see [`Y::remap_classes`].
+    pub fn remap_classes(self, map: &HashMap<usize, (usize, String)>, strict: bool) -> Self {
+        Self(
+            self.0
+                .into_iter()
+                .map(|y| y.remap_classes(map, strict))
+                .collect(),
+        )
+    }
+
+    /// Write one `<stem>.xml` per image into `dir`. `sizes` are (width, height, depth) per image.
+    pub fn save_voc<P: AsRef<std::path::Path>>(
+        &self,
+        dir: P,
+        stems: &[&str],
+        sizes: &[(u32, u32, u32)],
+    ) -> anyhow::Result<()> {
+        if stems.len() != self.len() || sizes.len() != self.len() {
+            anyhow::bail!(
+                "Expect {} stems and sizes, got {} and {}",
+                self.len(),
+                stems.len(),
+                sizes.len()
+            );
+        }
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for ((y, stem), &(w, h, d)) in self.iter().zip(stems).zip(sizes) {
+            let xml = y.to_voc_xml(stem, w, h, d);
+            std::fs::write(dir.join(format!("{stem}.xml")), xml)?;
+        }
+        Ok(())
+    }
+
+    /// Write one `<stem>.txt` of YOLO labels per image into `dir`, see [`Y::to_yolo_txt`].
+    /// `sizes` are (width, height) per image.
+    pub fn save_yolo_txt<P: AsRef<std::path::Path>>(
+        &self,
+        dir: P,
+        stems: &[&str],
+        sizes: &[(u32, u32)],
+    ) -> anyhow::Result<()> {
+        if stems.len() != self.len() || sizes.len() != self.len() {
+            anyhow::bail!(
+                "Expect {} stems and sizes, got {} and {}",
+                self.len(),
+                stems.len(),
+                sizes.len()
+            );
+        }
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for ((y, stem), &(w, h)) in self.iter().zip(stems).zip(sizes) {
+            std::fs::write(dir.join(format!("{stem}.txt")), y.to_yolo_txt(w, h))?;
+        }
+        Ok(())
+    }
+
+    /// Read `<stem>.txt` YOLO labels from `dir`, see [`Y::from_yolo_txt`]. A missing file is
+    /// a background image and gives an empty `Y`.
+    pub fn load_yolo_txt<P: AsRef<std::path::Path>>(
+        dir: P,
+        stems: &[&str],
+        sizes: &[(u32, u32)],
+        nk: usize,
+    ) -> anyhow::Result<Self> {
+        if stems.len() != sizes.len() {
+            anyhow::bail!(
+                "Expect as many sizes as stems, got {} and {}",
+                sizes.len(),
+                stems.len()
+            );
+        }
+        let dir = dir.as_ref();
+        let mut ys = Vec::with_capacity(stems.len());
+        for (stem, &(w, h)) in stems.iter().zip(sizes) {
+            let path = dir.join(format!("{stem}.txt"));
+            match std::fs::read_to_string(&path) {
+                Ok(s) => ys.push(
+                    Y::from_yolo_txt(&s, w, h, nk)
+                        .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?,
+                ),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => ys.push(Y::default()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(Self(ys))
+    }
+
+    /// Save every bbox crop of `images` (same order) as a `size` x `size` PNG, resized to fit
+    /// and padded with `pad_color` instead of being distorted. Crops are grouped into one
+    /// sub-directory per class name (or id), as `{image index}-{bbox index}.png`.
+    pub fn save_crops_square<P: AsRef<std::path::Path>>(
+        &self,
+        images: &[DynamicImage],
+        dir: P,
+        size: u32,
+        pad_color: [u8; 3],
+    ) -> anyhow::Result<()> {
+        if images.len() != self.len() {
+            anyhow::bail!("Expect {} images, got {}", self.len(), images.len());
+        }
+        if size == 0 {
+            anyhow::bail!("Crop size must be greater than 0");
+        }
+        let dir = dir.as_ref();
+        for (i, (y, image)) in self.iter().zip(images).enumerate() {
+            let bboxes = match y.bboxes() {
+                None => continue,
+                Some(bboxes) => bboxes,
+            };
+            for (j, bbox) in bboxes.iter().enumerate() {
+                let x0 = (bbox.xmin().max(0.) as u32).min(image.width());
+                let y0 = (bbox.ymin().max(0.) as u32).min(image.height());
+                let x1 = (bbox.xmax().ceil().max(0.) as u32).min(image.width());
+                let y1 = (bbox.ymax().ceil().max(0.) as u32).min(image.height());
+                if x1 <= x0 || y1 <= y0 {
+                    continue;
+                }
+                let crop = image.crop_imm(x0, y0, x1 - x0, y1 - y0).to_rgb8();
+                let r = size as f32 / crop.width().max(crop.height()) as f32;
+                let (w, h) = (
+                    ((crop.width() as f32 * r).round() as u32).clamp(1, size),
+                    ((crop.height() as f32 * r).round() as u32).clamp(1, size),
+                );
+                let crop = image::imageops::resize(&crop, w, h, FilterType::Triangle);
+                let mut canvas = RgbImage::from_pixel(size, size, Rgb(pad_color));
+                image::imageops::replace(
+                    &mut canvas,
+                    &crop,
+                    ((size - w) / 2) as i64,
+                    ((size - h) / 2) as i64,
+                );
+                let class = match bbox.name() {
+                    Some(name) => name.replace(['/', '\\'], "_"),
+                    None => bbox.id().to_string(),
+                };
+                let sub = dir.join(class);
+                std::fs::create_dir_all(&sub)?;
+                canvas.save(sub.join(format!("{i}-{j}.png")))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Histogram of bbox confidences over `bins` equal-width bins in [0, 1].
+    pub fn confidence_histogram(&self, bins: usize) -> Vec<usize> {
+        let mut hist = vec![0; bins];
+        if bins == 0 {
+            return hist;
+        }
+        for bbox in self.iter().filter_map(|y| y.bboxes()).flatten() {
+            let i = (bbox.confidence().clamp(0., 1.) * bins as f32) as usize;
+            hist[i.min(bins - 1)] += 1;
+        }
+        hist
+    }
+
+    /// Precision, recall and F1 of bboxes against ground truth `gts` (same image order)
+    /// at `steps` confidence thresholds evenly spaced in [0, 1), as `(thresh, p, r, f1)`.
+    ///
+    /// Predictions are greedily matched by descending confidence to unmatched ground truths
+    /// of the same class with IoU >= `iou_threshold`.
+    pub fn threshold_sweep(
+        &self,
+        gts: &Ys,
+        iou_threshold: f32,
+        steps: usize,
+    ) -> Vec<(f32, f32, f32, f32)> {
+        // greedy matching of the whole set is valid for every threshold: the predictions
+        // kept at a threshold are a prefix of the confidence order
+        let mut matches: Vec<(f32, bool)> = Vec::new();
+        let mut num_gts = 0;
+        for (y, gt) in self.iter().zip(gts.iter()) {
+            let gt = gt.bboxes().map_or(&[][..], |x| x.as_slice());
+            num_gts += gt.len();
+            let mut preds: Vec<&Bbox> = y.bboxes().map_or(vec![], |x| x.iter().collect());
+            preds.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+            let mut used = vec![false; gt.len()];
+            for pred in preds {
+                let best = gt
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, x)| !used[*i] && x.id() == pred.id())
+                    .map(|(i, x)| (i, pred.iou(x)))
+                    .filter(|(_, iou)| *iou >= iou_threshold)
+                    .max_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, _)) = best {
+                    used[i] = true;
+                }
+                matches.push((pred.confidence(), best.is_some()));
+            }
+        }
+
+        (0..steps)
+            .map(|i| {
+                let thresh = i as f32 / steps as f32;
+                let kept = matches.iter().filter(|(c, _)| *c >= thresh);
+                let (n, tp) = kept.fold((0, 0), |(n, tp), (_, m)| (n + 1, tp + *m as usize));
+                let p = if n == 0 { 1. } else { tp as f32 / n as f32 };
+                let r = if num_gts == 0 {
+                    1.
+                } else {
+                    tp as f32 / num_gts as f32
+                };
+                let f1 = if p + r == 0. {
+                    0.
+                } else {
+                    2. * p * r / (p + r)
+                };
+                (thresh, p, r, f1)
+            })
+            .collect()
+    }
+
+    /// Entry of `threshold_sweep` with the highest F1, the highest threshold on ties.
+    pub fn best_threshold(sweep: &[(f32, f32, f32, f32)]) -> Option<(f32, f32, f32, f32)> {
+        sweep.iter().copied().max_by(|a, b| a.3.total_cmp(&b.3))
+    }
+
+    /// `Y::flatten_masks` for every image, `sizes` are the images' (width, height).
+    pub fn flatten_masks(
+        &self,
+        sizes: &[(u32, u32)],
+        priority: &MaskPriority,
+    ) -> Vec<ImageBuffer<Luma<u16>, Vec<u16>>> {
+        self.iter()
+            .zip(sizes.iter())
+            .map(|(y, (w, h))| y.flatten_masks(*w, *h, priority))
+            .collect()
+    }
+
+    /// COCO `results` JSON: one `{image_id, category_id, bbox: [x, y, w, h], score}` entry
+    /// per instance (a bbox with the polygon, mask and keypoints of the same object), with its
+    /// mask as a compressed RLE `segmentation` (or its polygon when there is no mask) and
+    /// `keypoints` as `[x, y, v, ...]` for pose results. `image_ids` follow the order of
+    /// `self`; classes missing from `category_map` are skipped.
+    pub fn to_coco_json(&self, image_ids: &[u64], category_map: &HashMap<usize, u64>) -> String {
+        let results = self.coco_results(image_ids, category_map);
+        Value::Array(results.into_iter().map(|x| x.2).collect()).to_string()
+    }
+
+    /// Write [`Ys::to_coco_json`] to `path`, ready for `COCO.loadRes` of pycocotools.
+    pub fn save_coco_json<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        image_ids: &[u64],
+        category_map: &HashMap<usize, u64>,
+    ) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_coco_json(image_ids, category_map))?;
+        Ok(())
+    }
+
+    /// COCO annotation file (`images`, `categories`, `annotations`) as loaded by CVAT or used
+    /// as ground truth, one annotation per instance like [`Ys::to_coco_json`]. `images` are
+    /// `(id, file_name, width, height)` in the order of `self`, `category_map` maps class
+    /// ids to `(category id, name)`. Instances are `iscrowd: 0` except the
+    /// `(image index, bbox index)` listed in `crowd`.
+    pub fn to_coco_dataset(
+        &self,
+        images: &[(u64, &str, u32, u32)],
+        category_map: &HashMap<usize, (u64, String)>,
+        crowd: &[(usize, usize)],
+    ) -> Result<Value> {
+        if images.len() != self.len() {
+            anyhow::bail!("Expect {} images, got {}", self.len(), images.len());
+        }
+        let ids: HashMap<usize, u64> = category_map.iter().map(|(&k, v)| (k, v.0)).collect();
+        let image_ids: Vec<u64> = images.iter().map(|x| x.0).collect();
+        let annotations: Vec<Value> = self
+            .coco_results(&image_ids, &ids)
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (idx_image, idx_instance, mut x, area))| {
+                let x = x.as_object_mut().unwrap();
+                x.remove("score");
+                x.entry("segmentation").or_insert(json!([]));
+                x.insert("id".into(), json!(idx + 1));
+                x.insert("area".into(), json!(area));
+                let iscrowd = crowd.contains(&(idx_image, idx_instance));
+                x.insert("iscrowd".into(), json!(iscrowd as u8));
+                Value::Object(x.to_owned())
+            })
+            .collect();
+
+        let mut categories: Vec<(&u64, &String)> =
+            category_map.values().map(|(id, name)| (id, name)).collect();
+        categories.sort();
+        categories.dedup_by_key(|x| x.0);
+        Ok(json!({
+            "images": images
+                .iter()
+                .map(|(id, file_name, width, height)| json!({
+                    "id": id,
+                    "file_name": file_name,
+                    "width": width,
+                    "height": height,
+                }))
+                .collect::<Vec<_>>(),
+            "categories": categories
+                .iter()
+                .map(|(id, name)| json!({ "id": id, "name": name }))
+                .collect::<Vec<_>>(),
+            "annotations": annotations,
+        }))
+    }
+
+    /// Write [`Ys::to_coco_dataset`] to `path`.
+    pub fn save_coco_dataset<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        images: &[(u64, &str, u32, u32)],
+        category_map: &HashMap<usize, (u64, String)>,
+        crowd: &[(usize, usize)],
+    ) -> anyhow::Result<()> {
+        let dataset = self.to_coco_dataset(images, category_map, crowd)?;
+        std::fs::write(path, dataset.to_string())?;
+        Ok(())
+    }
+
+    /// COCO results entries, one per instance of `Y::instances`, with the index of their
+    /// image and instance and their areas
+    fn coco_results(
+        &self,
+        image_ids: &[u64],
+        category_map: &HashMap<usize, u64>,
+    ) -> Vec<(usize, usize, Value, f64)> {
+        let category = |id: isize| -> Option<u64> {
+            usize::try_from(id)
+                .ok()
+                .and_then(|id| category_map.get(&id).copied())
+        };
+        let round = |x: f32| (x as f64 * 100.).round() / 100.;
+        let mut results = Vec::new();
+        for (idx_image, (y, &image_id)) in self.iter().zip(image_ids).enumerate() {
+            for (idx, instance) in y.instances().iter().enumerate() {
+                let Some(category_id) = category(instance.id()) else {
+                    continue;
+                };
+                let rle = instance.mask.map(|x| x.to_rle());
+                let bbox = match (instance.bbox, instance.polygon, &rle) {
+                    (Some(bbox), ..) => Some(bbox.to_owned()),
+                    (None, Some(polygon), _) => polygon.bbox(),
+                    (None, None, Some(rle)) => rle.bbox(),
+                    _ => None,
+                };
+                let Some(bbox) = bbox else {
+                    continue;
+                };
+                let mut x = json!({
+                    "image_id": image_id,
+                    "category_id": category_id,
+                    "bbox": [round(bbox.xmin()), round(bbox.ymin()), round(bbox.width()), round(bbox.height())],
+                    "score": instance.confidence(),
+                });
+                let mut area = bbox.area() as f64;
+                if let Some(rle) = &rle {
+                    x["segmentation"] = rle.to_json(true);
+                    area = rle.area() as f64;
+                } else if let Some(polygon) = instance.polygon {
+                    let segmentation: Vec<f64> = polygon
+                        .polygon()
+                        .exterior()
+                        .coords()
+                        .flat_map(|c| [(c.x * 100.).round() / 100., (c.y * 100.).round() / 100.])
+                        .collect();
+                    x["segmentation"] = json!([segmentation]);
+                    area = polygon.area();
+                }
+                // v = 2 visible, 0 missing
+                if let Some(kpts) = instance.keypoints {
+                    let visible = |k: &Keypoint| k.confidence() > 0. && !k.is_origin();
+                    let keypoints: Vec<f64> = kpts
+                        .iter()
+                        .flat_map(|k| match visible(k) {
+                            true => [round(k.x()), round(k.y()), 2.],
+                            false => [0., 0., 0.],
+                        })
+                        .collect();
+                    x["keypoints"] = json!(keypoints);
+                    x["num_keypoints"] = json!(kpts.iter().filter(|k| visible(k)).count());
+                }
+                results.push((idx_image, idx, x, area));
+            }
+        }
+        results
+    }
+
+    /// Export bboxes, mbrs and polygons as a GeoJSON `FeatureCollection`.
+    ///
+    /// `transform` is a GDAL-style geotransform `[x0, dx/dcol, dx/drow, y0, dy/dcol, dy/drow]`
+    /// mapping pixel `(col, row)` to geo coordinates. Pixel coordinates are kept if `None`.
+    pub fn to_geojson(&self, transform: Option<[f64; 6]>) -> Value {
+        let project = |x: f64, y: f64| -> [f64; 2] {
+            match transform {
+                None => [x, y],
+                Some(t) => [t[0] + x * t[1] + y * t[2], t[3] + x * t[4] + y * t[5]],
+            }
+        };
+        let feature = |ring: Vec<[f64; 2]>,
+                       image: usize,
+                       kind: &str,
+                       id: isize,
+                       name: Option<&String>,
+                       confidence: f32| {
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "Polygon", "coordinates": [ring] },
+                "properties": {
+                    "image": image,
+                    "kind": kind,
+                    "id": id,
+                    "name": name,
+                    "confidence": confidence,
+                },
+            })
+        };
+        let close = |mut ring: Vec<[f64; 2]>| {
+            if let (Some(first), Some(last)) = (ring.first(), ring.last()) {
+                if first != last {
+                    ring.push(*first);
+                }
+            }
+            ring
+        };
+
+        let mut features = Vec::new();
+        for (idx, y) in self.iter().enumerate() {
+            if let Some(bboxes) = y.bboxes() {
+                for bbox in bboxes.iter() {
+                    let (x1, y1, x2, y2) = (
+                        bbox.xmin() as f64,
+                        bbox.ymin() as f64,
+                        bbox.xmax() as f64,
+                        bbox.ymax() as f64,
+                    );
+                    let ring = close(vec![
+                        project(x1, y1),
+                        project(x2, y1),
+                        project(x2, y2),
+                        project(x1, y2),
+                    ]);
+                    let mut f =
+                        feature(ring, idx, "bbox", bbox.id(), bbox.name(), bbox.confidence());
+                    if let Some(uuid) = bbox.uuid() {
+                        f["properties"]["uuid"] = json!(uuid);
+                    }
+                    features.push(f);
+                }
+            }
+            if let Some(mbrs) = y.mbrs() {
+                for mbr in mbrs.iter() {
+                    let ring = close(mbr.vertices().iter().map(|c| project(c.x, c.y)).collect());
+                    features.push(feature(
+                        ring,
+                        idx,
+                        "mbr",
+                        mbr.id(),
+                        mbr.name(),
+                        mbr.confidence(),
+                    ));
+                }
+            }
+            if let Some(polygons) = y.polygons() {
+                for polygon in polygons.iter() {
+                    let ring = close(
+                        polygon
+                            .polygon()
+                            .exterior()
+                            .coords()
+                            .map(|c| project(c.x, c.y))
+                            .collect(),
+                    );
+                    features.push(feature(
+                        ring,
+                        idx,
+                        "polygon",
+                        polygon.id(),
+                        polygon.name(),
+                        polygon.confidence(),
+                    ));
+                }
+            }
+        }
+
+        json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests_y {
+    use super::{NmsMethod, Ys, Y};
+    use crate::{Bbox, DynConf, Keypoint, Mask, MaskPriority, ModelRng, Polygon};
+    use geo::polygon;
+    use image::DynamicImage;
+    use std::collections::HashMap;
+
+    #[test]
+    fn threshold_sweep() {
+        let gts = Ys::from(vec![Y::default().with_bboxes(&[
+            Bbox::from((0., 0., 10., 10., 0, 1.)),
+            Bbox::from((20., 20., 10., 10., 0, 1.)),
+        ])]);
+        let preds = Ys::from(vec![Y::default().with_bboxes(&[
+            Bbox::from((0., 0., 10., 10., 0, 0.9)),   // tp
+            Bbox::from((50., 50., 10., 10., 0, 0.6)), // fp
+            Bbox::from((20., 20., 10., 10., 0, 0.3)), // tp
+        ])]);
+        assert_eq!(preds.confidence_histogram(2), vec![1, 2]);
+
+        let sweep = preds.threshold_sweep(&gts, 0.5, 10);
+        assert_eq!(sweep.len(), 10);
+        assert_eq!(sweep[0], (0., 2. / 3., 1., 0.8));
+        assert_eq!(sweep[5], (0.5, 0.5, 0.5, 0.5));
+        assert_eq!(sweep[7], (0.7, 1., 0.5, 2. / 3.));
+        // ties resolve to the highest threshold
+        assert_eq!(Ys::best_threshold(&sweep).unwrap().0, 0.3);
+    }
+
+    #[test]
+    fn to_voc_xml() {
+        let y = Y::default().with_bboxes(&[
+            Bbox::default()
+                .with_xyxy(10.4, 20.6, 110.2, 220.5)
+                .with_name(Some("a<b".to_string())),
+            Bbox::default().with_xyxy(0., 0., 5., 5.).with_id(3),
+        ]);
+        let xml = y.to_voc_xml("bus.jpg", 640, 480, 3);
+        assert!(xml.starts_with("<annotation>"));
+        assert!(xml.contains("<width>640</width>"));
+        assert!(xml.contains("<name>a&lt;b</name>"));
+        assert!(xml.contains("<name>3</name>"));
+
+        // round trip the integer coordinates
+        let values = |tag: &str| -> Vec<i64> {
+            xml.split(&format!("<{tag}>"))
+                .skip(1)
+                .map(|x| x.split('<').next().unwrap().parse().unwrap())
+                .collect()
+        };
+        assert_eq!(values("xmin"), vec![10, 0]);
+        assert_eq!(values("ymin"), vec![21, 0]);
+        assert_eq!(values("xmax"), vec![110, 5]);
+        assert_eq!(values("ymax"), vec![221, 5]);
+    }
+
+    #[test]
+    fn nms_bboxes_per_class() {
+        let bboxes = vec![
+            Bbox::from((0., 0., 10., 10., 0, 0.9)),
+            Bbox::from((0., 0., 10., 8., 0, 0.8)), // iou 0.8 with the first
+            Bbox::from((0., 0., 10., 8., 1, 0.7)), // other class
+        ];
+
+        let mut xs = bboxes.clone();
+        Y::nms_bboxes(&mut xs, 0.5);
+        assert_eq!(xs.len(), 1);
+
+        let mut xs = bboxes.clone();
+        Y::nms_bboxes_per_class(&mut xs, &DynConf::new(&[0.5], 2));
+        assert_eq!(xs.len(), 2);
+
+        let mut xs = bboxes.clone();
+        Y::nms_bboxes_per_class(&mut xs, &DynConf::new(&[0.85, 0.5], 2));
+        assert_eq!(xs.len(), 3);
+
+        let empty = DynConf::new(&[], 0);
+        let mut xs = bboxes.clone();
+        Y::nms_bboxes_per_class(&mut xs, &empty);
+        assert_eq!(xs.len(), 3);
+        let mut xs = bboxes;
+        Y::soft_nms_bboxes(&mut xs, NmsMethod::Hard, 0.5, Some(&empty), 0.5, &empty);
+        assert_eq!(xs.len(), 2);
+    }
+
+    #[test]
+    fn detection_ids() {
+        let y = Y::default()
+            .with_bboxes(&[
+                Bbox::from((0., 0., 10., 10., 0, 0.9)),
+                Bbox::from((5., 5., 10., 10., 1, 0.8)),
+            ])
+            .apply_detection_ids();
+        let bboxes = y.bboxes().unwrap();
+        let (a, b) = (
+            bboxes[0].uuid().unwrap().clone(),
+            bboxes[1].uuid().unwrap().clone(),
+        );
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+
+        let geojson = Ys::from(vec![y]).to_geojson(None);
+        assert_eq!(geojson["features"][0]["properties"]["uuid"], a.as_str());
+
+        // models seeded alike draw the same ids, whatever the other draws in between
+        let (rng_a, rng_b) = (ModelRng::new(Some(7)), ModelRng::new(Some(7)));
+        let y = Y::default().with_bboxes(&[Bbox::from((0., 0., 10., 10., 0, 0.9))]);
+        let a = y.clone().apply_detection_ids_with(&rng_a);
+        let _ = y.clone().apply_detection_ids();
+        let b = y.apply_detection_ids_with(&rng_b);
+        assert_eq!(a.bboxes().unwrap()[0].uuid(), b.bboxes().unwrap()[0].uuid());
+    }
+
+    #[test]
+    fn flatten_masks() {
+        let y = Y::default().with_polygons(&[
+            Polygon::default()
+                .with_polygon(
+                    polygon![(x: 0., y: 0.), (x: 6., y: 0.), (x: 6., y: 6.), (x: 0., y: 6.)],
+                )
+                .with_id(0)
+                .with_confidence(0.9),
+            Polygon::default()
+                .with_polygon(
+                    polygon![(x: 4., y: 4.), (x: 9., y: 4.), (x: 9., y: 9.), (x: 4., y: 9.)],
+                )
+                .with_id(2)
+                .with_confidence(0.5),
+        ]);
+        let labels = y.flatten_masks(10, 10, &MaskPriority::Confidence);
+        assert_eq!(labels.get_pixel(1, 1).0[0], 1);
+        assert_eq!(labels.get_pixel(5, 5).0[0], 1);
+        assert_eq!(labels.get_pixel(8, 8).0[0], 3);
+        assert_eq!(labels.get_pixel(1, 8).0[0], 0);
+
+        let labels = y.flatten_masks(10, 10, &MaskPriority::Classes(vec![2]));
+        assert_eq!(labels.get_pixel(5, 5).0[0], 3);
+    }
+
+    #[test]
+    fn scale_to() {
+        let y = Y::default()
+            .with_bboxes(&[Bbox::from((10., 20., 30., 40., 0, 0.9))])
+            .with_keypoints(&[vec![Keypoint::from((50., 60.))]])
+            .with_polygons(&[Polygon::default()
+                .with_polygon(polygon![(x: 0., y: 0.), (x: 64., y: 0.), (x: 64., y: 48.)])]);
+        let thumbnail = y.clone().scale_to(160, 120, 640, 480);
+        let bbox = &thumbnail.bboxes().unwrap()[0];
+        assert_eq!((bbox.xmin(), bbox.ymin(), bbox.width()), (2.5, 5., 7.5));
+        assert_eq!(thumbnail.keypoints().unwrap()[0][0].x(), 12.5);
+        assert_eq!(thumbnail.polygons().unwrap()[0].area(), 96.);
+        assert_eq!(thumbnail.scale_to(640, 480, 160, 120), y);
+    }
+
+    #[test]
+    fn apply_min_box_size() {
+        let y = Y::default()
+            .with_bboxes(&[
+                Bbox::from((0., 0., 4., 4., 0, 0.9)),
+                Bbox::from((0., 0., 40., 80., 0, 0.9)),
+                Bbox::from((0., 0., 4., 4., 1, 0.9)),
+            ])
+            .with_keypoints(&[
+                vec![Keypoint::from((1., 1.))],
+                vec![Keypoint::from((2., 2.))],
+                vec![Keypoint::from((3., 3.))],
+            ]);
+        let y_ = y.clone().apply_min_box_size(&[(20., 40.), (2., 2.)]);
+        assert_eq!(y_.bboxes().unwrap().len(), 2);
+        assert_eq!(y_.keypoints().unwrap()[0][0].x(), 2.);
+        assert_eq!(y_.keypoints().unwrap()[1][0].x(), 3.);
+
+        let y_ = y.apply_min_box_size(&[(10., 10.)]);
+        assert_eq!(y_.bboxes().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn remap_classes() {
+        let ys = Ys::from(vec![Y::default()
+            .with_bboxes(&[
+                Bbox::from((0., 0., 1., 1., 0, 0.9)),
+                Bbox::from((0., 0., 1., 1., 7, 0.8)),
+            ])
+            .with_keypoints(&[
+                vec![Keypoint::default().with_id(0)],
+                vec![Keypoint::default().with_id(1)],
+            ])]);
+        let map = HashMap::from([(7, (1, "car".to_string()))]);
+
+        let y = &ys.clone().remap_classes(&map, false)[0];
+        let bboxes = y.bboxes().unwrap();
+        assert_eq!(bboxes.len(), 2);
+        assert_eq!(bboxes[0].id(), 0);
+        assert_eq!(bboxes[1].id(), 1);
+        assert_eq!(bboxes[1].name(), Some(&"car".to_string()));
+
+        let y = &ys.remap_classes(&map, true)[0];
+        assert_eq!(y.bboxes().unwrap().len(), 1);
+        assert_eq!(y.bboxes().unwrap()[0].id(), 1);
+        assert_eq!(y.keypoints().unwrap().len(), 1);
+        assert_eq!(y.keypoints().unwrap()[0][0].id(), 1);
+    }
+
+    #[test]
+    fn to_geojson() {
+        let polygon = Polygon::default()
+            .with_polygon(polygon![(x: 0., y: 0.), (x: 2., y: 0.), (x: 2., y: 1.)])
+            .with_id(3)
+            .with_name(Some("field".to_string()))
+            .with_confidence(0.5);
+        let ys = Ys::from(vec![Y::default()
+            .with_polygons(&[polygon])
+            .with_bboxes(&[Bbox::from((0., 0., 1., 1., 1, 0.9))])]);
+
+        let geojson = ys.to_geojson(None);
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert_eq!(geojson["features"].as_array().unwrap().len(), 2);
+        let bbox = &geojson["features"][0];
+        assert_eq!(bbox["properties"]["kind"], "bbox");
+        assert_eq!(
+            bbox["geometry"]["coordinates"],
+            serde_json::json!([[[0., 0.], [1., 0.], [1., 1.], [0., 1.], [0., 0.]]])
+        );
+        let polygon = &geojson["features"][1];
+        assert_eq!(polygon["properties"]["name"], "field");
+        assert_eq!(polygon["properties"]["id"], 3);
+        assert_eq!(polygon["properties"]["confidence"], 0.5);
+        assert_eq!(
+            polygon["geometry"]["coordinates"],
+            serde_json::json!([[[0., 0.], [2., 0.], [2., 1.], [0., 0.]]])
+        );
+
+        // 10 units per pixel, north-up with origin at (100, 50)
+        let geojson = ys.to_geojson(Some([100., 10., 0., 50., 0., -10.]));
+        assert_eq!(
+            geojson["features"][1]["geometry"]["coordinates"],
+            serde_json::json!([[[100., 50.], [120., 50.], [120., 40.], [100., 50.]]])
+        );
+    }
+
+    #[test]
+    fn to_json() {
+        let y = Y::default()
+            .with_bboxes(&[Bbox::from((1., 2., 3., 4., 0, 0.5))])
+            .with_texts(&["hi".to_string()]);
+        let json = y.to_json();
+        assert_eq!(
+            json["bboxes"][0]["xyxy"],
+            serde_json::json!([1., 2., 4., 6.])
+        );
+        assert_eq!(json["texts"], serde_json::json!(["hi"]));
+        assert!(json.get("polygons").is_none());
+    }
+
+    #[test]
+    fn soft_nms_gaussian() {
+        let bboxes = vec![
+            Bbox::from((0., 0., 10., 10., 0, 0.9)),
+            Bbox::from((0., 0., 10., 8., 0, 0.8)), // iou 0.8 with the first
+        ];
+        let confs = DynConf::new(&[0.1], 1);
+
+        let mut xs = bboxes.clone();
+        Y::soft_nms_bboxes(&mut xs, NmsMethod::Hard, 0.5, None, 0.5, &confs);
+        assert_eq!(xs.len(), 1);
+
+        let mut xs = bboxes;
+        Y::soft_nms_bboxes(&mut xs, NmsMethod::SoftGaussian, 0.5, None, 0.5, &confs);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].confidence(), 0.9);
+        let decayed = 0.8 * (-0.8f32 * 0.8 / 0.5).exp();
+        assert!((xs[1].confidence() - decayed).abs() < 1e-5);
+        assert!(xs[1].confidence() < 0.8);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let y = Y::default()
+            .with_bboxes(&[Bbox::from((1., 2., 3., 4., 5, 0.5)).with_name(Some("car".into()))])
+            .with_keypoints(&[vec![
+                Keypoint::from((1., 2.)).with_id(0).with_confidence(0.9),
+                Keypoint::default(),
+            ]])
+            .with_polygons(&[Polygon::default()
+                .with_polygon(polygon![(x: 0., y: 0.), (x: 4., y: 0.), (x: 4., y: 3.)])
+                .with_id(2)
+                .with_confidence(0.7)]);
+        let s = serde_json::to_string(&y).unwrap();
+        assert_eq!(serde_json::from_str::<Y>(&s).unwrap(), y);
+
+        let ys = Ys::from(vec![y.clone(), Y::default()]);
+        let s = serde_json::to_string(&ys).unwrap();
+        assert_eq!(serde_json::from_str::<Ys>(&s).unwrap(), ys);
+    }
+
+    #[test]
+    fn to_coco_json() {
+        let ys = Ys::from(vec![Y::default()
+            .with_bboxes(&[
+                Bbox::from((10., 20., 30., 40., 0, 0.9)),
+                Bbox::from((0., 0., 1., 1., 7, 0.5)), // not in the map
+            ])
+            .with_polygons(&[Polygon::default()
+                .with_polygon(polygon![(x: 0., y: 0.), (x: 4., y: 0.), (x: 4., y: 3.)])
+                .with_id(0)
+                .with_confidence(0.8)])]);
+        let map = HashMap::from([(0, 1)]);
+        let json: serde_json::Value = serde_json::from_str(&ys.to_coco_json(&[42], &map)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(
+            json[0],
+            serde_json::json!({"image_id": 42, "category_id": 1, "bbox": [10., 20., 30., 40.], "score": json[0]["score"]})
+        );
+        assert!((json[0]["score"].as_f64().unwrap() - 0.9).abs() < 1e-6);
+        assert_eq!(json[1]["bbox"], serde_json::json!([0., 0., 4., 3.]));
+        assert_eq!(
+            json[1]["segmentation"],
+            serde_json::json!([[0., 0., 4., 0., 4., 3., 0., 0.]])
+        );
+
+        // a segmented object is a single result, its mask as the segmentation
+        let polygon = Polygon::default()
+            .with_polygon(polygon![(x: 0., y: 0.), (x: 4., y: 0.), (x: 4., y: 3.), (x: 0., y: 3.)])
+            .with_id(0)
+            .with_confidence(0.6);
+        let mask =
+            image::GrayImage::from_fn(5, 4, |x, y| image::Luma([255 * (x < 4 && y < 3) as u8]));
+        let ys = Ys::from(vec![Y::default()
+            .with_bboxes(&[Bbox::from((0., 0., 4., 3., 0, 0.6))])
+            .with_polygons(&[polygon])
+            .with_masks(&[Mask::default()
+                .with_mask(DynamicImage::ImageLuma8(mask))
+                .with_id(0)
+                .with_confidence(0.6)])]);
+        let json: serde_json::Value = serde_json::from_str(&ys.to_coco_json(&[43], &map)).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["bbox"], serde_json::json!([0., 0., 4., 3.]));
+        assert_eq!(json[0]["segmentation"]["size"], serde_json::json!([4, 5]));
+    }
+
+    #[test]
+    fn yolo_txt() {
+        let y = Y::default()
+            .with_bboxes(&[Bbox::from((16., 32., 32., 64., 2, 0.9))])
+            .with_keypoints(&[vec![
+                Keypoint::from((32., 64.)).with_confidence(0.8),
+                Keypoint::default(),
+            ]]);
+        let txt = y.to_yolo_txt(128, 256);
+        assert_eq!(
+            txt,
+            "2 0.250000 0.250000 0.250000 0.250000 0.250000 0.250000 2 0 0 0\n"
+        );
+        let y = Y::from_yolo_txt(&txt, 128, 256, 2).unwrap();
+        let bbox = &y.bboxes().unwrap()[0];
+        assert_eq!(
+            (
+                bbox.id(),
+                bbox.xmin(),
+                bbox.ymin(),
+                bbox.width(),
+                bbox.height()
+            ),
+            (2, 16., 32., 32., 64.)
+        );
+        let kpts = &y.keypoints().unwrap()[0];
+        assert_eq!((kpts[0].x(), kpts[0].y()), (32., 64.));
+        assert!(kpts[1].is_origin());
+
+        let y = Y::default().with_polygons(&[Polygon::default()
+            .with_polygon(polygon![(x: 0., y: 0.), (x: 50., y: 0.), (x: 50., y: 100.)])
+            .with_id(1)]);
+        let txt = y.to_yolo_txt(100, 200);
+        assert_eq!(
+            txt,
+            "1 0.000000 0.000000 0.500000 0.000000 0.500000 0.500000\n"
+        );
+        let y = Y::from_yolo_txt(&txt, 100, 200, 0).unwrap();
+        assert_eq!(y.polygons().unwrap()[0].area(), 2500.);
+        assert_eq!(y.bboxes().unwrap()[0].xmax(), 50.);
+        assert!(Y::from_yolo_txt("0 0.5 0.5", 10, 10, 0).is_err());
+
+        let dir = std::env::temp_dir().join(format!("usls-yolo-{}", std::process::id()));
+        Ys::from(vec![y])
+            .save_yolo_txt(&dir, &["a"], &[(100, 200)])
+            .unwrap();
+        let ys = Ys::load_yolo_txt(&dir, &["a", "missing"], &[(100, 200), (10, 10)], 0).unwrap();
+        assert_eq!(ys[0].polygons().unwrap().len(), 1);
+        assert_eq!(ys[1], Y::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn to_coco_dataset() {
+        // a segmented person with keypoints, then a box marked as crowd
+        let ys = Ys::from(vec![Y::default()
+            .with_bboxes(&[
+                Bbox::from((10., 20., 30., 40., 0, 0.9)),
+                Bbox::from((100., 100., 10., 10., 0, 0.8)),
+            ])
+            .with_keypoints(&[
+                vec![
+                    Keypoint::from((12., 24.)).with_confidence(0.8),
+                    Keypoint::default(),
+                ],
+                vec![Keypoint::default(); 2],
+            ])
+            .with_polygons(&[Polygon::default()
+                .with_polygon(polygon![(x: 10., y: 20.), (x: 40., y: 20.), (x: 40., y: 60.)])
+                .with_id(0)
+                .with_confidence(0.9)])]);
+        let map = HashMap::from([(0, (1, "person".to_string()))]);
+        let images = [(42, "a.jpg", 640, 480)];
+        let json = ys.to_coco_dataset(&images, &map, &[(0, 1)]).unwrap();
+        assert_eq!(
+            json["images"],
+            serde_json::json!([{"id": 42, "file_name": "a.jpg", "width": 640, "height": 480}])
+        );
+        assert_eq!(
+            json["categories"],
+            serde_json::json!([{"id": 1, "name": "person"}])
+        );
+        let anns = json["annotations"].as_array().unwrap();
+        assert_eq!(anns.len(), 2);
+        assert_eq!(
+            (anns[0]["id"].clone(), anns[1]["id"].clone()),
+            (1.into(), 2.into())
+        );
+        assert_eq!(anns[0]["bbox"], serde_json::json!([10., 20., 30., 40.]));
+        assert_eq!(anns[0]["area"], 600.);
+        assert_eq!(
+            anns[0]["segmentation"],
+            serde_json::json!([[10., 20., 40., 20., 40., 60., 10., 20.]])
+        );
+        assert_eq!(
+            anns[0]["keypoints"],
+            serde_json::json!([12., 24., 2., 0., 0., 0.])
+        );
+        assert_eq!(anns[0]["num_keypoints"], 1);
+        assert_eq!(anns[0]["iscrowd"], 0);
+        assert_eq!(
+            (anns[1]["area"].clone(), anns[1]["iscrowd"].clone()),
+            (100.0.into(), 1.into())
+        );
+        assert_eq!(anns[1]["segmentation"], serde_json::json!([]));
+        assert!(anns[0].get("score").is_none());
+        assert!(ys.to_coco_dataset(&[], &map, &[]).is_err());
+    }
+
+    #[test]
+    fn save_crops_square_zero_size() {
+        let ys = Ys::from(vec![
+            Y::default().with_bboxes(&[Bbox::from((0., 0., 10., 10., 0, 0.9))])
+        ]);
+        let images = vec![DynamicImage::new_rgb8(20, 20)];
+        let dir = std::env::temp_dir().join("usls-save-crops-square-zero");
+        assert!(ys.save_crops_square(&images, &dir, 0, [0; 3]).is_err());
+        assert!(!dir.exists());
+    }
+}